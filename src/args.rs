@@ -13,6 +13,10 @@ pub struct Arguments {
     pub verbosity_level: u32,
     pub arguments: Vec<String>,
     pub flags: CPUFlags,
+    /// `rumipsem disasm ELF_BINARY` prints disassembly instead of running the binary
+    pub disassemble: bool,
+    pub start_address: Option<u64>,
+    pub stop_address: Option<u64>,
 }
 
 pub fn parse_arguments() -> Arguments {
@@ -24,7 +28,12 @@ pub fn parse_arguments() -> Arguments {
         stack_pointer: None,
         arguments: Vec::new(),
         flags: CPUFlags::default(),
+        disassemble: false,
+        start_address: None,
+        stop_address: None,
     };
+    let mut start_address: Option<String> = None;
+    let mut stop_address: Option<String> = None;
 
     {
         // this block limits scope of borrows by ap.refer() method
@@ -35,7 +44,7 @@ pub fn parse_arguments() -> Arguments {
         ap.refer(&mut args.executable).add_argument(
             "ELF binary",
             Store,
-            "ELF binary to run.",
+            "ELF binary to run. Use `disasm ELF_BINARY` to disassemble it instead.",
         );
         ap.refer(&mut args.arguments).add_argument(
             "args",
@@ -62,10 +71,49 @@ pub fn parse_arguments() -> Arguments {
             StoreOption,
             "Check flow of the emulation using this tracefile.",
         );
+        ap.refer(&mut args.flags.fpu_64bit).add_option(
+            &["--fpu-64bit-registers"],
+            StoreTrue,
+            "Run the FPU in FR=1 mode with 64 bit registers instead of even-odd register pairs.",
+        );
+        ap.refer(&mut args.flags.isa_revision).add_option(
+            &["--isa-revision"],
+            StoreOption,
+            "Optional. Emulate given MIPS32 revision (R1, R2, R5 or R6) instead of the one \
+             detected from the ELF header.",
+        );
+        ap.refer(&mut args.flags.jit).add_option(
+            &["--jit"],
+            StoreTrue,
+            "Translate hot basic blocks to x86-64 code instead of interpreting them.",
+        );
+        ap.refer(&mut args.flags.jit_lockstep).add_option(
+            &["--jit-lockstep"],
+            StoreTrue,
+            "With --jit and --tracefile, translate single instructions, so that the trace is \
+             checked after every one of them.",
+        );
+        ap.refer(&mut start_address).add_option(
+            &["--start-address"],
+            StoreOption,
+            "Optional. With disasm, start disassembling at this (hexadecimal) address.",
+        );
+        ap.refer(&mut stop_address).add_option(
+            &["--stop-address"],
+            StoreOption,
+            "Optional. With disasm, stop disassembling at this (hexadecimal) address.",
+        );
         ap.refer(&mut args.stack_pointer)
             .add_option(&["-s", "--stack-pointer"], StoreOption,
                         "Optional. Specify stack pointer. This will prevent the emulator from creating its own stack. Use with coredumps.");
 
+        ap.refer(&mut args.flags.syscalls_conf.sysroot)
+            .add_option(
+                &["-L", "--sysroot"],
+                StoreOption,
+                "Optional. Look up absolute paths of the program in this directory first, e.g. \
+                 the dynamic linker and libraries in an extracted root filesystem.",
+            );
         ap.refer(&mut args.flags.syscalls_conf.sys_fake_root)
             .add_option(
                 &["--fake-root"],
@@ -107,6 +155,13 @@ pub fn parse_arguments() -> Arguments {
         ap.parse_args_or_exit();
     }
 
+    if args.executable == "disasm" && !args.arguments.is_empty() {
+        args.disassemble = true;
+        args.executable = args.arguments.remove(0);
+    }
+    args.start_address = start_address.map(|a| parse_address(&a));
+    args.stop_address = stop_address.map(|a| parse_address(&a));
+
     if args.executable.len() == 0 {
         eprintln!("No executable specified! Can't do anything!");
         ::std::process::exit(1);
@@ -114,3 +169,11 @@ pub fn parse_arguments() -> Arguments {
 
     args
 }
+
+fn parse_address(address: &str) -> u64 {
+    let digits = address.trim_start_matches("0x");
+    u64::from_str_radix(digits, 16).unwrap_or_else(|_| {
+        eprintln!("Invalid address {}", address);
+        ::std::process::exit(1);
+    })
+}
@@ -42,6 +42,12 @@ pub fn sign_extend(word: u32, length: u8) -> i32 {
     //(word | (0xFF_FF_FF_FF ^ (((word & (1 << (length - 1))) << 1) - 1))) as i32
 }
 
+/// Mask with the lowest `size` bits set, `size` can be anything from 0 to 32.
+pub fn bit_mask(size: u32) -> u32 {
+    assert!(size <= 32);
+    if size == 32 { 0xFF_FF_FF_FF } else { (1 << size) - 1 }
+}
+
 #[test]
 fn test_apply_offset() {
     assert_eq!(add_signed_offset(0, 10), 10);
@@ -60,6 +66,13 @@ fn test_sign_extend() {
     assert_eq!(sign_extend(0x00_FF_FF_FF, 24), -1);
 }
 
+#[test]
+fn test_bit_mask() {
+    assert_eq!(bit_mask(0), 0);
+    assert_eq!(bit_mask(5), 0b11111);
+    assert_eq!(bit_mask(32), 0xFF_FF_FF_FF);
+}
+
 #[test]
 fn test_add_to_upper_bits() {
     assert_eq!(add_to_upper_bits(0x0000_0000, 0x7F_FF), 0x7F_FF_00_00);
@@ -0,0 +1,165 @@
+//! Cache of decoded basic blocks. Instructions are fetched and decoded once per block, the CPU
+//! loop then just walks the decoded instructions. Blocks never cross a code page, so stores into
+//! a page (see `Memory::invalidate_code_page`) drop exactly the blocks decoded from it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cpu::decoder::{decode, DecodeError, Instruction};
+use cpu::instructions_constants::is_control_transfer;
+use cpu::isa::Isa;
+use cpu::jit::Translation;
+use memory::{Memory, CODE_PAGE_SIZE};
+
+/// Blocks end earlier on a control transfer instruction or at the end of a page
+const MAX_BLOCK_LENGTH: usize = 64;
+
+#[derive(Clone, Copy)]
+pub struct DecodedInstruction {
+    pub raw: u32,
+    pub decoded: Result<Instruction, DecodeError>,
+}
+
+/// Straight sequence of instructions ending with a control transfer and its delay slot
+pub struct BasicBlock {
+    start: u32,
+    instructions: Vec<DecodedInstruction>,
+    /// Translated code, when the JIT is enabled
+    pub translation: RefCell<Translation>,
+}
+
+impl BasicBlock {
+    fn decode(start: u32, memory: &Memory, isa: Isa) -> BasicBlock {
+        let mut instructions = Vec::with_capacity(MAX_BLOCK_LENGTH);
+        let mut address = start;
+        let mut delay_slot = false;
+        loop {
+            let raw = memory.fetch_instruction(address);
+            instructions.push(DecodedInstruction {
+                raw,
+                decoded: decode(raw, isa),
+            });
+            address = address.wrapping_add(4);
+            if delay_slot || instructions.len() == MAX_BLOCK_LENGTH ||
+                address & (CODE_PAGE_SIZE - 1) == 0
+            {
+                break;
+            }
+            delay_slot = is_control_transfer(raw);
+        }
+        BasicBlock {
+            start,
+            instructions,
+            translation: RefCell::new(Translation::Cold(0)),
+        }
+    }
+
+    pub fn get_start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn get_instructions(&self) -> &[DecodedInstruction] {
+        &self.instructions
+    }
+
+    /// Decoded instruction at the address, when the block contains it
+    pub fn get(&self, address: u32) -> Option<&DecodedInstruction> {
+        self.instructions.get(
+            (address.wrapping_sub(self.start) / 4) as usize,
+        )
+    }
+}
+
+pub struct BlockCache {
+    blocks: HashMap<u32, Rc<BasicBlock>>,
+    /// Incremented whenever blocks are dropped, blocks held from older generations may be stale
+    generation: u64,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache {
+            blocks: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Block starting at the address, it is decoded first when it is not in the cache.
+    pub fn get_block(&mut self, address: u32, memory: &mut Memory, isa: Isa) -> Rc<BasicBlock> {
+        if let Some(block) = self.blocks.get(&address) {
+            return block.clone();
+        }
+
+        let block = Rc::new(BasicBlock::decode(address, memory, isa));
+        memory.mark_code_page(address);
+        self.blocks.insert(address, block.clone());
+        block
+    }
+
+    /// Drops blocks from pages, that were written to since the last call.
+    pub fn sync(&mut self, memory: &mut Memory) {
+        self.generation += 1;
+        match memory.take_modified_code() {
+            None => {
+                debug!("Instruction cache flushed");
+                self.blocks.clear();
+            }
+            Some(pages) => {
+                for page in pages {
+                    debug!("Code modified in page 0x{:08x}", page);
+                    self.blocks.retain(|start, _| start & !(CODE_PAGE_SIZE - 1) != page);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_block_invalidation() {
+    use cpu::isa::{Abi, IsaRevision};
+    use memory::{Endianness, PROT_EXEC, PROT_READ};
+
+    let isa = Isa {
+        revision: IsaRevision::R2,
+        compressed: None,
+        abi: Abi::O32,
+    };
+    let mut memory = Memory::new(Endianness::BigEndian);
+    memory.map(0x400000, 0x1000, PROT_READ | PROT_EXEC);
+    // addiu, jr ra, its delay slot and an instruction of the next block
+    memory.write_word(0x400000, 0x27BDFFE0);
+    memory.write_word(0x400004, 0x03E00008);
+    memory.write_word(0x400008, 0x00000000);
+    memory.write_word(0x40000C, 0x8FBF001C);
+
+    let mut cache = BlockCache::new();
+    let block = cache.get_block(0x400000, &mut memory, isa);
+    assert_eq!(block.get(0x400008).unwrap().raw, 0);
+    assert!(block.get(0x40000C).is_none());
+    assert!(!memory.is_code_modified());
+
+    memory.write_word(0x400000, 0x27BD0020);
+    assert!(memory.is_code_modified());
+    cache.sync(&mut memory);
+    let block = cache.get_block(0x400000, &mut memory, isa);
+    assert_eq!(block.get(0x400000).unwrap().raw, 0x27BD0020);
+
+    // the CPU loop checks the execute permission only on entry to a block, so changing the
+    // protection of the page or unmapping it must make held blocks stale
+    let generation = cache.get_generation();
+    memory.protect(0x400000, 0x1000, PROT_READ);
+    assert!(memory.is_code_modified());
+    cache.sync(&mut memory);
+    assert!(cache.get_generation() != generation);
+    assert!(cache.blocks.is_empty());
+    cache.get_block(0x400000, &mut memory, isa);
+    memory.unmap(0x400000, 0x1000);
+    assert!(memory.is_code_modified());
+    cache.sync(&mut memory);
+    assert!(cache.blocks.is_empty());
+}
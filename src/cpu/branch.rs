@@ -0,0 +1,49 @@
+//! Conditions shared by conditional branches and trap instructions.
+
+/// Relation between two register values (or a register and an immediate or zero), which decides
+/// whether a branch is taken or a trap is raised.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BranchCondition {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+    LessThanUnsigned,
+    GreaterEqualUnsigned,
+    /// Signed addition of the values overflows (R6 BOVC)
+    Overflow,
+    /// Signed addition of the values does not overflow (R6 BNVC)
+    NoOverflow,
+}
+
+impl BranchCondition {
+    /// Compares full registers. 32 bit values are sign extended in them, so the result is the
+    /// same as on a 32 bit CPU.
+    pub fn evaluate(self, a: u64, b: u64) -> bool {
+        let (sa, sb) = (a as i64, b as i64);
+        match self {
+            BranchCondition::Equal => a == b,
+            BranchCondition::NotEqual => a != b,
+            BranchCondition::LessThan => sa < sb,
+            BranchCondition::LessEqual => sa <= sb,
+            BranchCondition::GreaterThan => sa > sb,
+            BranchCondition::GreaterEqual => sa >= sb,
+            BranchCondition::LessThanUnsigned => a < b,
+            BranchCondition::GreaterEqualUnsigned => a >= b,
+            BranchCondition::Overflow => (a as i32).overflowing_add(b as i32).1,
+            BranchCondition::NoOverflow => !(a as i32).overflowing_add(b as i32).1,
+        }
+    }
+}
+
+#[test]
+fn test_branch_conditions() {
+    let minus_one = -1i64 as u64;
+    assert!(BranchCondition::LessThan.evaluate(minus_one, 0));
+    assert!(!BranchCondition::LessThanUnsigned.evaluate(minus_one, 0));
+    assert!(BranchCondition::GreaterEqual.evaluate(0, 0));
+    assert!(BranchCondition::Overflow.evaluate(0x7fff_ffff, 1));
+    assert!(BranchCondition::NoOverflow.evaluate(minus_one, 1));
+}
@@ -0,0 +1,156 @@
+//! Compressed instruction sets, MIPS16e and microMIPS. The CPU executes them while the lowest bit
+//! of the program counter is set. Instructions with a MIPS32 equivalent are encoded as MIPS32 and
+//! evaluated by `eval_instruction`, the rest is evaluated by the ISA specific modules.
+
+use cpu::event::{CPUEvent, ExceptionKind};
+use cpu::isa::CompressedIsa;
+use cpu::micromips::eval_micromips_instruction;
+use cpu::mips16::eval_mips16_instruction;
+use cpu::registers::RegisterFile;
+use memory::Memory;
+use syscalls::System;
+
+/// ISA mode bit of the program counter, jump targets and return addresses
+pub const ISA_MODE_BIT: u32 = 1;
+
+// MIPS32 opcodes used by the translation
+pub const OP_SPECIAL: u32 = 0b000000;
+pub const OP_REGIMM: u32 = 0b000001;
+pub const OP_ADDI: u32 = 0b001000;
+pub const OP_ADDIU: u32 = 0b001001;
+pub const OP_SLTI: u32 = 0b001010;
+pub const OP_SLTIU: u32 = 0b001011;
+pub const OP_ANDI: u32 = 0b001100;
+pub const OP_ORI: u32 = 0b001101;
+pub const OP_XORI: u32 = 0b001110;
+pub const OP_LUI: u32 = 0b001111;
+pub const OP_SPECIAL2: u32 = 0b011100;
+pub const OP_SPECIAL3: u32 = 0b011111;
+pub const OP_LB: u32 = 0b100000;
+pub const OP_LH: u32 = 0b100001;
+pub const OP_LWL: u32 = 0b100010;
+pub const OP_LW: u32 = 0b100011;
+pub const OP_LBU: u32 = 0b100100;
+pub const OP_LHU: u32 = 0b100101;
+pub const OP_LWR: u32 = 0b100110;
+pub const OP_SB: u32 = 0b101000;
+pub const OP_SH: u32 = 0b101001;
+pub const OP_SWL: u32 = 0b101010;
+pub const OP_SW: u32 = 0b101011;
+pub const OP_SWR: u32 = 0b101110;
+pub const OP_LL: u32 = 0b110000;
+pub const OP_LWC1: u32 = 0b110001;
+pub const OP_LDC1: u32 = 0b110101;
+pub const OP_SC: u32 = 0b111000;
+pub const OP_SWC1: u32 = 0b111001;
+pub const OP_SDC1: u32 = 0b111101;
+
+// SPECIAL functs used by the translation
+pub const FUNCT_SLL: u32 = 0b000000;
+pub const FUNCT_SRL: u32 = 0b000010;
+pub const FUNCT_SRA: u32 = 0b000011;
+pub const FUNCT_SLLV: u32 = 0b000100;
+pub const FUNCT_SRLV: u32 = 0b000110;
+pub const FUNCT_SRAV: u32 = 0b000111;
+pub const FUNCT_MOVZ: u32 = 0b001010;
+pub const FUNCT_MOVN: u32 = 0b001011;
+pub const FUNCT_SYSCALL: u32 = 0b001100;
+pub const FUNCT_BREAK: u32 = 0b001101;
+pub const FUNCT_SYNC: u32 = 0b001111;
+pub const FUNCT_MFHI: u32 = 0b010000;
+pub const FUNCT_MTHI: u32 = 0b010001;
+pub const FUNCT_MFLO: u32 = 0b010010;
+pub const FUNCT_MTLO: u32 = 0b010011;
+pub const FUNCT_MULT: u32 = 0b011000;
+pub const FUNCT_MULTU: u32 = 0b011001;
+pub const FUNCT_DIV: u32 = 0b011010;
+pub const FUNCT_DIVU: u32 = 0b011011;
+pub const FUNCT_ADD: u32 = 0b100000;
+pub const FUNCT_ADDU: u32 = 0b100001;
+pub const FUNCT_SUB: u32 = 0b100010;
+pub const FUNCT_SUBU: u32 = 0b100011;
+pub const FUNCT_AND: u32 = 0b100100;
+pub const FUNCT_OR: u32 = 0b100101;
+pub const FUNCT_XOR: u32 = 0b100110;
+pub const FUNCT_NOR: u32 = 0b100111;
+pub const FUNCT_SLT: u32 = 0b101010;
+pub const FUNCT_SLTU: u32 = 0b101011;
+pub const FUNCT_TGE: u32 = 0b110000;
+pub const FUNCT_TGEU: u32 = 0b110001;
+pub const FUNCT_TLT: u32 = 0b110010;
+pub const FUNCT_TLTU: u32 = 0b110011;
+pub const FUNCT_TEQ: u32 = 0b110100;
+pub const FUNCT_TNE: u32 = 0b110110;
+
+// SPECIAL2 and SPECIAL3 functs used by the translation
+pub const FUNCT_MADD: u32 = 0b000000;
+pub const FUNCT_MADDU: u32 = 0b000001;
+pub const FUNCT_MUL: u32 = 0b000010;
+pub const FUNCT_MSUB: u32 = 0b000100;
+pub const FUNCT_MSUBU: u32 = 0b000101;
+pub const FUNCT_CLZ: u32 = 0b100000;
+pub const FUNCT_CLO: u32 = 0b100001;
+pub const FUNCT_EXT: u32 = 0b000000;
+pub const FUNCT_INS: u32 = 0b000100;
+pub const FUNCT_BSHFL: u32 = 0b100000;
+pub const FUNCT_RDHWR: u32 = 0b111011;
+
+// BSHFL operations in the shift amount field
+pub const BSHFL_WSBH: u32 = 0b00010;
+pub const BSHFL_SEB: u32 = 0b10000;
+pub const BSHFL_SEH: u32 = 0b11000;
+
+/// Evaluates compressed instruction at the given address. Returns the event together with size of
+/// the instruction in bytes.
+pub fn eval_compressed_instruction(
+    address: u32,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> (CPUEvent, u32) {
+    match registers.get_compressed_isa() {
+        Some(CompressedIsa::Mips16e) => {
+            eval_mips16_instruction(address, registers, memory, system)
+        }
+        Some(CompressedIsa::MicroMips) => {
+            eval_micromips_instruction(address, registers, memory, system)
+        }
+        // instruction fetch from an odd address
+        None => (CPUEvent::Exception(ExceptionKind::AddressError(address | ISA_MODE_BIT)), 2),
+    }
+}
+
+/// Encodes MIPS32 instruction with 16 bit immediate
+pub fn encode_immediate(opcode: u32, rs: u32, rt: u32, immediate: u32) -> u32 {
+    (opcode << 26) | (rs << 21) | (rt << 16) | (immediate & 0xFFFF)
+}
+
+/// Encodes MIPS32 instruction with register operands, like those in SPECIAL
+pub fn encode_register(opcode: u32, rs: u32, rt: u32, rd: u32, shift: u32, funct: u32) -> u32 {
+    (opcode << 26) | (rs << 21) | (rt << 16) | (rd << 11) | (shift << 6) | funct
+}
+
+/// Evaluates the halfwords placed at `TEST_CODE` of `test_machine` by the given compressed ISA
+#[cfg(test)]
+pub fn eval_halfwords(
+    eval: fn(u32, &mut RegisterFile, &mut Memory, &mut System) -> (CPUEvent, u32),
+    halfwords: &[u32],
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> (CPUEvent, u32) {
+    use cpu::instructions::TEST_CODE;
+
+    for (i, h) in halfwords.iter().enumerate() {
+        memory.write_halfword(TEST_CODE + i as u32 * 2, *h);
+    }
+    eval(TEST_CODE, registers, memory, system)
+}
+
+#[test]
+fn test_encode() {
+    assert_eq!(encode_immediate(OP_ADDIU, 29, 29, -8i32 as u32), 0x27BD_FFF8);
+    assert_eq!(encode_register(OP_SPECIAL, 0, 0, 0, 0, 0), 0);
+    assert_eq!(encode_register(OP_SPECIAL, 4, 5, 2, 0, FUNCT_ADDU), 0x0085_1021);
+    assert_eq!(encode_register(OP_SPECIAL3, 0, 5, 2, BSHFL_SEB, FUNCT_BSHFL), 0x7C05_1420);
+}
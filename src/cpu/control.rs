@@ -1,18 +1,30 @@
 //! CPU control logic. Orchestrates all components together to simulate the CPU.
 
-use cpu::event::CPUEvent;
-use cpu::instructions::eval_instruction;
-use cpu::registers::RegisterFile;
+use cpu::block_cache::{BasicBlock, BlockCache};
+use cpu::compressed::{eval_compressed_instruction, ISA_MODE_BIT};
+use cpu::event::{CPUEvent, ExceptionKind};
+use cpu::instructions::eval_decoded_instruction;
+use cpu::instructions_constants::is_control_transfer;
+use cpu::isa::{Abi, CompressedIsa, IsaRevision};
+use cpu::jit::{Jit, JitExit};
+use cpu::registers::{RegisterFile, A0, RETURN_ADDRESS, STACK_POINTER};
 use cpu::watchdog::Watchdog;
-use memory::Memory;
+use memory::{Access, Memory};
 use std::collections::VecDeque;
 use std::io;
 use std::io::Read;
-use syscalls::System;
+use std::rc::Rc;
+use syscalls::{SignalInfo, System};
 
 #[derive(Debug)]
 pub struct CPUFlags {
     pub tracefile: Option<String>,
+    pub fpu_64bit: bool,
+    pub isa_revision: Option<IsaRevision>,
+    pub compressed_isa: Option<CompressedIsa>,
+    pub abi: Abi,
+    pub jit: bool,
+    pub jit_lockstep: bool,
     pub syscalls_conf: CPUFlagsSyscalls,
     pub watchdog_conf: CPUFlagsWatchdog,
 }
@@ -32,17 +44,26 @@ pub struct CPUFlagsSyscalls {
     pub sys_fake_root_directory: bool,
     pub sys_block_ioctl_on_stdio: bool,
     pub sys_ioctl_fail_always: bool,
+    /// Directory, where absolute paths of the emulated program are looked up first
+    pub sysroot: Option<String>,
 }
 
 impl CPUFlags {
     pub fn default() -> CPUFlags {
         CPUFlags {
             tracefile: None,
+            fpu_64bit: false,
+            isa_revision: None,
+            compressed_isa: None,
+            abi: Abi::O32,
+            jit: false,
+            jit_lockstep: false,
             syscalls_conf: CPUFlagsSyscalls {
                 sys_fake_root: false,
                 sys_fake_root_directory: false,
                 sys_block_ioctl_on_stdio: false,
                 sys_ioctl_fail_always: false,
+                sysroot: None,
             },
             watchdog_conf: CPUFlagsWatchdog {
                 trace_checked_register_reads: true,
@@ -60,21 +81,49 @@ pub struct EmulatorContext {
     system: System,
     watchdog: Watchdog,
     registers: RegisterFile<'static>,
+    block_cache: BlockCache,
+    jit: Option<Jit>,
 }
 
+/// Return address of functions called using `run_function`. It is in the kernel segment, so the
+/// program can't reach it by any other means.
+const FUNCTION_RETURN_ADDRESS: u32 = 0xFFFF_FFF0;
+
 static mut EMULATOR_STATE: Option<EmulatorContext> = None;
 
 impl EmulatorContext {
     pub fn start(memory: Memory, stack_pointer: u32, entry_point: u32, flags: CPUFlags) {
+        let jit = if !flags.jit {
+            None
+        } else if !cfg!(target_arch = "x86_64") {
+            warn!("JIT is available only on x86-64 hosts, the program will be interpreted");
+            None
+        } else if flags.abi == Abi::N64 {
+            warn!("JIT does not support the n64 ABI, the program will be interpreted");
+            None
+        } else if flags.tracefile.is_some() && !flags.jit_lockstep {
+            info!("Traced runs are interpreted, use --jit-lockstep to check translated code");
+            None
+        } else {
+            Some(Jit::new(memory.is_big_endian(), flags.jit_lockstep))
+        };
+
         let watchdog = Watchdog::new(flags.tracefile, flags.watchdog_conf);
-        let registers = RegisterFile::new(stack_pointer);
-        let system = System::new(flags.syscalls_conf);
+        let mut registers = RegisterFile::new(stack_pointer);
+        registers.set_fpu_64bit(flags.fpu_64bit);
+        registers.set_isa_revision(flags.isa_revision.expect("ISA revision not selected"));
+        registers.set_compressed_isa(flags.compressed_isa);
+        registers.set_abi(flags.abi, memory.get_address_base());
+        registers.write_address(STACK_POINTER, stack_pointer);
+        let system = System::new(flags.syscalls_conf, flags.abi);
 
         let state = EmulatorContext {
             memory,
             system,
             watchdog,
             registers,
+            block_cache: BlockCache::new(),
+            jit,
         };
 
         let state = unsafe {
@@ -128,11 +177,18 @@ impl EmulatorContext {
         let memory = &mut self.memory;
         let system = &mut self.system;
         let watchdog = &mut self.watchdog;
+        let block_cache = &mut self.block_cache;
+        let jit = &self.jit;
 
         let mut program_counter = program_counter;
+        // block containing the last executed instruction and the cache generation it is from
+        let mut block: Option<(Rc<BasicBlock>, u64)> = None;
 
         let mut debug_mode = false;
+        let mut forbidden_slot = false;
 
+        // nested loops run functions called by the emulator itself, e.g. signal handlers
+        let nested = register_file.is_some();
         let register_file = if let Some(r) = register_file {
             r
         } else {
@@ -142,38 +198,165 @@ impl EmulatorContext {
         // work loop
         loop {
             let pc = program_counter.pop_front().unwrap();
-            register_file.set_pc(pc);
+            if nested && pc == FUNCTION_RETURN_ADDRESS {
+                break;
+            }
 
-            watchdog.run_cpu_watchdogs(register_file, memory, true);
+            // the lowest bit of the program counter selects the compressed ISA
+            let compressed = pc & ISA_MODE_BIT != 0;
+            let address = pc & !ISA_MODE_BIT;
+            register_file.set_pc(address);
+
+            if (!compressed && address & 0b11 != 0) ||
+                (compressed && register_file.get_compressed_isa().is_none())
+            {
+                // misaligned instruction fetch or the CPU has no compressed ISA
+                raise_exception(ExceptionKind::AddressError(pc), pc, &mut program_counter, system);
+                continue;
+            }
+            if memory.is_code_modified() {
+                block_cache.sync(memory);
+            }
 
-            let instruction = memory.fetch_instruction(pc);
-            let instruction_result = eval_instruction(instruction, register_file, memory, system);
+            // the execute permission is checked on entry to a block. Blocks never cross a page and
+            // changing the protection of a page drops its blocks, so the held block stays
+            // executable until the generation changes.
+            let generation = block_cache.get_generation();
+            let held = !compressed &&
+                match block {
+                    Some((ref block, g)) => g == generation && block.get(address).is_some(),
+                    None => false,
+                };
+            let fetch_size = if compressed { 2 } else { 4 };
+            if !held {
+                if let Err(fault) = memory.check_access(address, fetch_size, Access::Execute) {
+                    raise_exception(fault, pc, &mut program_counter, system);
+                    continue;
+                }
+            }
+
+            // hot blocks are run by the JIT, which takes over at jump targets and block boundaries.
+            // Delay slots and forbidden slots are always interpreted.
+            if let Some(ref jit) = *jit {
+                if !compressed && !held && !forbidden_slot && program_counter.is_empty() {
+                    let new_block = block_cache.get_block(address, memory, register_file.get_isa());
+                    block = Some((new_block.clone(), generation));
+                    if jit.prepare(&new_block) {
+                        let first = new_block.get(address).unwrap().raw;
+                        watchdog.run_cpu_watchdogs(register_file, first, true);
+                        match jit.run(&new_block, register_file, memory) {
+                            JitExit::Continue(npc) => {
+                                block = None;
+                                program_counter.push_back(npc);
+                            }
+                            JitExit::Interpret(npc) => program_counter.push_back(npc),
+                            JitExit::DelaySlot(npc, target) => {
+                                program_counter.push_back(npc);
+                                program_counter.push_back(target);
+                            }
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            // size of the instruction, compressed ones are 16 or 32 bits wide
+            let (instruction_result, size) = if compressed {
+                watchdog.run_cpu_watchdogs(register_file, memory.fetch_instruction(address), true);
+                eval_compressed_instruction(address, register_file, memory, system)
+            } else {
+                let instruction = match block {
+                    Some((ref block, _)) if held => *block.get(address).unwrap(),
+                    _ => {
+                        let new_block =
+                            block_cache.get_block(address, memory, register_file.get_isa());
+                        let instruction = *new_block.get(address).unwrap();
+                        block = Some((new_block, generation));
+                        instruction
+                    }
+                };
+
+                watchdog.run_cpu_watchdogs(register_file, instruction.raw, true);
+                // R6 reserves control transfers in delay slots as well, the jump target is still
+                // queued when the delay slot runs
+                let delay_slot = !program_counter.is_empty();
+                if forbidden_slot || (delay_slot && register_file.get_isa_revision().is_r6()) {
+                    if let Err(exception) = check_forbidden_slot(instruction.raw) {
+                        raise_exception(exception, pc, &mut program_counter, system);
+                        continue;
+                    }
+                }
+
+                let result = eval_decoded_instruction(
+                    instruction.raw,
+                    instruction.decoded,
+                    register_file,
+                    memory,
+                    system,
+                );
+                (result, 4)
+            };
+            forbidden_slot = instruction_result == CPUEvent::ForbiddenSlotAhead;
 
             // instruction result handling
             match instruction_result {
+                CPUEvent::Exit if nested => {
+                    // there is no way to unwind the host signal handler, the exit status is still
+                    // in the argument register of the syscall
+                    info!("Program exited from a nested function");
+                    ::std::process::exit(register_file.read_register(A0) as i32);
+                }
                 CPUEvent::Exit => break,
                 CPUEvent::AtomicLoadModifyWriteBegan => {
                     watchdog.trace_gap_ahead();
 
                     if program_counter.len() == 0 {
-                        program_counter.push_back(pc + 4);
+                        program_counter.push_back(pc + size);
                     }
                 }
                 CPUEvent::FlowChangeImmediate(npc) => {
                     if program_counter.len() == 0 {
                         program_counter.push_back(npc);
                     } else {
-                        panic!("Flow control failed. Multiple jumps at once.");
+                        // a jump in a delay slot is UNPREDICTABLE before R6
+                        raise_exception(
+                            ExceptionKind::ReservedInstruction,
+                            pc,
+                            &mut program_counter,
+                            system,
+                        );
                     }
                 }
                 CPUEvent::FlowChangeDelayed(npc) => {
                     if program_counter.len() == 0 {
-                        program_counter.push_back(pc + 4);
+                        program_counter.push_back(pc + size);
                         program_counter.push_back(npc);
                     } else {
-                        panic!("Flow control failed. Multiple jumps at once.");
+                        // a jump in a delay slot is UNPREDICTABLE before R6
+                        raise_exception(
+                            ExceptionKind::ReservedInstruction,
+                            pc,
+                            &mut program_counter,
+                            system,
+                        );
+                    }
+                }
+                CPUEvent::DelaySlotNullified => {
+                    if program_counter.len() == 0 {
+                        program_counter.push_back(pc + 8);
+                    } else {
+                        // a branch likely in a delay slot is UNPREDICTABLE
+                        raise_exception(
+                            ExceptionKind::ReservedInstruction,
+                            pc,
+                            &mut program_counter,
+                            system,
+                        );
                     }
                 }
+                CPUEvent::Exception(kind) => {
+                    raise_exception(kind, pc, &mut program_counter, system);
+                }
                 CPUEvent::Fork(return_val) => {
                     if return_val == 0 {
                         info!("Parent process!")
@@ -206,12 +389,12 @@ impl EmulatorContext {
                     }
                     if program_counter.len() == 0 {
                         info!("pc=0x{:x}", pc);
-                        program_counter.push_back(pc + 4);
+                        program_counter.push_back(pc + size);
                     }
                 }
                 _ => {
                     if program_counter.len() == 0 {
-                        program_counter.push_back(pc + 4);
+                        program_counter.push_back(pc + size);
                     }
                 }
             }
@@ -235,16 +418,14 @@ impl EmulatorContext {
     }
 
     pub fn run_function(&mut self, func: u32, arguments: &[u32]) {
-        let mut register_file = RegisterFile::new(
-            self.registers.read_register(
-                ::cpu::registers::STACK_POINTER,
-            ) - 16 - arguments.len() as u32,
-        ); //shifted the stack pointer a bit more just to be sure
+        let stack_pointer = self.registers.read_register(STACK_POINTER) - 16 -
+            arguments.len() as u32; //shifted the stack pointer a bit more just to be sure
+        let mut register_file = self.function_registers(stack_pointer);
 
         // initialize stack
         {
             let memory = &mut self.memory;
-            let sp = register_file.read_register(::cpu::registers::STACK_POINTER);
+            let sp = register_file.read_register(STACK_POINTER);
             for (i, a) in arguments.iter().enumerate() {
                 let i = i as u32;
                 memory.write_word(sp + i * 4, *a);
@@ -254,10 +435,272 @@ impl EmulatorContext {
             }
         }
 
+        self.call_function(func, register_file);
+    }
+
+    /// Runs a SA_SIGINFO signal handler. Its `siginfo_t` and `ucontext_t` are placed below the
+    /// stack of the interrupted code, the context holds its program counter, general purpose
+    /// registers, HI, LO and FCSR. For synchronous signals, `pc` is the faulting instruction and
+    /// like sigreturn does, the registers are restored from the context after the handler returns.
+    /// The program counter to resume at is returned then.
+    pub fn run_siginfo_handler(
+        &mut self,
+        func: u32,
+        info: SignalInfo,
+        pc: Option<u32>,
+    ) -> Option<u32> {
+        let abi = self.registers.get_abi();
+        let frame = (self.registers.read_register(STACK_POINTER) - SIGNAL_FRAME_SIZE) & !0xF;
+        let siginfo = frame + 32;
+        let ucontext = siginfo + SIGINFO_SIZE;
+        for i in 0..(SIGINFO_SIZE + UCONTEXT_SIZE) / 4 {
+            self.memory.write_word(siginfo + i * 4, 0);
+        }
+        self.memory.write_word(siginfo, info.signo);
+        self.memory.write_word(siginfo + 4, info.code);
+        let address_offset = if abi == Abi::N64 { 16 } else { 12 };
+        self.memory.write_pointer(siginfo + address_offset, info.address, abi);
+
+        let context = SigcontextLayout::new(abi, ucontext);
+        let interrupted_pc = pc.unwrap_or_else(|| self.registers.get_pc());
+        self.memory.write_doubleword(context.pc, interrupted_pc as i32 as i64 as u64);
+        for i in 0..32 {
+            let value = self.registers.read_register64(i);
+            self.memory.write_doubleword(context.regs + i * 8, value);
+        }
+        self.memory.write_doubleword(context.hi, self.registers.read_hi64(0));
+        self.memory.write_doubleword(context.lo, self.registers.read_lo64(0));
+        self.memory.write_word(context.fcsr, self.registers.read_fcsr());
+
+        let mut register_file = self.function_registers(frame);
+        register_file.write_register(A0, info.signo);
+        register_file.write_address(A0 + 1, siginfo);
+        register_file.write_address(A0 + 2, ucontext);
+        self.call_function(func, register_file);
+
+        // asynchronous signals interrupt the emulator anywhere, the registers can't change then
+        pc?;
+        for i in 1..32 {
+            let value = self.memory.read_doubleword(context.regs + i * 8);
+            self.registers.write_register64(i, value);
+        }
+        self.registers.write_hi64(0, self.memory.read_doubleword(context.hi));
+        self.registers.write_lo64(0, self.memory.read_doubleword(context.lo));
+        self.registers.write_fcsr(self.memory.read_word(context.fcsr));
+        Some(self.memory.read_doubleword(context.pc) as u32)
+    }
+
+    /// Registers for a function called by the emulator, returning to `FUNCTION_RETURN_ADDRESS`
+    fn function_registers(&self, stack_pointer: u32) -> RegisterFile<'static> {
+        let mut register_file = RegisterFile::new(stack_pointer);
+        register_file.set_abi(self.registers.get_abi(), self.memory.get_address_base());
+        register_file.write_address(STACK_POINTER, stack_pointer);
+        register_file.write_address(RETURN_ADDRESS, FUNCTION_RETURN_ADDRESS);
+        register_file.set_fpu_64bit(self.registers.is_fpu_64bit());
+        register_file.set_isa_revision(self.registers.get_isa_revision());
+        register_file.set_compressed_isa(self.registers.get_compressed_isa());
+        let fcsr = self.registers.read_fcsr();
+        register_file.write_fcsr(fcsr);
+        register_file.set_user_local(self.registers.get_user_local());
+        register_file
+    }
+
+    fn call_function(&mut self, func: u32, register_file: RegisterFile<'static>) {
+        let mut register_file = register_file;
+
+        // the interrupted code loses its link, the same happens on return from the function
+        self.registers.clear_link();
+        self.memory.clear_monitor();
+
         // initialize flow control
         let mut program_counter: VecDeque<u32> = VecDeque::with_capacity(3);
         program_counter.push_back(func);
 
-        self.cpu_loop(program_counter, Some(&mut register_file))
+        self.cpu_loop(program_counter, Some(&mut register_file));
+        self.memory.clear_monitor();
+    }
+}
+
+// MIPS signal numbers, `force_signal` translates them to the host ones
+const SIGILL: u32 = 4;
+const SIGTRAP: u32 = 5;
+const SIGFPE: u32 = 8;
+const SIGBUS: u32 = 10;
+const SIGSEGV: u32 = 11;
+
+// si_code values of the synchronous signals
+const ILL_ILLOPC: u32 = 1;
+const FPE_INTOVF: u32 = 2;
+const FPE_FLTDIV: u32 = 3;
+const FPE_FLTOVF: u32 = 4;
+const FPE_FLTUND: u32 = 5;
+const FPE_FLTRES: u32 = 6;
+const FPE_FLTINV: u32 = 7;
+const SEGV_MAPERR: u32 = 1;
+const SEGV_ACCERR: u32 = 2;
+const BUS_ADRALN: u32 = 1;
+const SI_KERNEL: u32 = 0x80;
+
+// sizes of the structures passed to SA_SIGINFO handlers, the context is a bit larger than needed
+const SIGINFO_SIZE: u32 = 128;
+const UCONTEXT_SIZE: u32 = 1024;
+const SIGNAL_FRAME_SIZE: u32 = 32 + SIGINFO_SIZE + UCONTEXT_SIZE + 16;
+
+/// Addresses of the fields of `uc_mcontext` in a `ucontext_t`. O32 has its own `struct
+/// sigcontext`, n32 and n64 share the 64 bit one, which follows a larger `uc_stack` in n64.
+struct SigcontextLayout {
+    pc: u32,
+    regs: u32,
+    hi: u32,
+    lo: u32,
+    fcsr: u32,
+}
+
+impl SigcontextLayout {
+    fn new(abi: Abi, ucontext: u32) -> SigcontextLayout {
+        match abi {
+            Abi::O32 => {
+                let mcontext = ucontext + 24;
+                SigcontextLayout {
+                    pc: mcontext + 8,
+                    regs: mcontext + 16,
+                    hi: mcontext + 552,
+                    lo: mcontext + 560,
+                    fcsr: mcontext + 532,
+                }
+            }
+            Abi::N32 | Abi::N64 => {
+                let mcontext = ucontext + if abi == Abi::N64 { 40 } else { 24 };
+                SigcontextLayout {
+                    pc: mcontext + 576,
+                    regs: mcontext,
+                    hi: mcontext + 512,
+                    lo: mcontext + 544,
+                    fcsr: mcontext + 584,
+                }
+            }
+        }
+    }
+}
+
+/// Jumps and branches in the forbidden slot of a R6 compact branch are reserved instructions
+fn check_forbidden_slot(instruction: u32) -> Result<(), ExceptionKind> {
+    if is_control_transfer(instruction) {
+        Err(ExceptionKind::ReservedInstruction)
+    } else {
+        Ok(())
+    }
+}
+
+/// Delivers synchronous exception to the emulated program. When the program has no handler
+/// installed, default action of the host signal terminates the emulator the same way. The faulting
+/// instruction at `pc` is restarted after the handler returns, unless the handler moved the
+/// program counter in its context.
+fn raise_exception(
+    kind: ExceptionKind,
+    pc: u32,
+    program_counter: &mut VecDeque<u32>,
+    system: &System,
+) {
+    let (signo, code, address) = match kind {
+        ExceptionKind::FloatingPoint(cause) => {
+            info!("Floating point exception, cause=0b{:06b}", cause);
+            // the most severe of the causes wins, as in the kernel
+            let code = if cause & 0b10000 != 0 {
+                FPE_FLTINV
+            } else if cause & 0b01000 != 0 {
+                FPE_FLTDIV
+            } else if cause & 0b00100 != 0 {
+                FPE_FLTOVF
+            } else if cause & 0b00010 != 0 {
+                FPE_FLTUND
+            } else {
+                FPE_FLTRES
+            };
+            (SIGFPE, code, 0)
+        }
+        ExceptionKind::IntegerOverflow => {
+            info!("Integer overflow exception");
+            (SIGFPE, FPE_INTOVF, 0)
+        }
+        ExceptionKind::Trap => {
+            info!("Trap instruction condition met");
+            (SIGTRAP, SI_KERNEL, 0)
+        }
+        ExceptionKind::Breakpoint => {
+            info!("Breakpoint instruction reached");
+            (SIGTRAP, SI_KERNEL, 0)
+        }
+        ExceptionKind::ReservedInstruction => {
+            info!("Reserved instruction exception");
+            (SIGILL, ILL_ILLOPC, 0)
+        }
+        ExceptionKind::AddressError(address) => {
+            info!("Address error exception, address=0x{:08x}", address);
+            (SIGBUS, BUS_ADRALN, address)
+        }
+        ExceptionKind::SegmentationFault(address) => {
+            info!("Segmentation fault, unmapped address=0x{:08x}", address);
+            (SIGSEGV, SEGV_MAPERR, address)
+        }
+        ExceptionKind::ProtectionFault(address) => {
+            info!("Segmentation fault, access denied at address=0x{:08x}", address);
+            (SIGSEGV, SEGV_ACCERR, address)
+        }
+    };
+    program_counter.push_front(pc);
+    let resumed = system.force_signal(SignalInfo { signo, code, address }, pc);
+    if resumed != pc {
+        program_counter.clear();
+        program_counter.push_back(resumed);
     }
 }
+
+#[test]
+fn test_forbidden_slot() {
+    use cpu::instructions::{eval_instruction, test_machine};
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    registers.set_isa_revision(IsaRevision::R6);
+    registers.write_register(A0, 1);
+    // beqzc a0,0x40 is not taken, the next instruction is in its forbidden slot
+    assert!(
+        eval_instruction(0xD880_0010, &mut registers, &mut memory, &mut system) ==
+            CPUEvent::ForbiddenSlotAhead
+    );
+    // bc 0x40
+    assert_eq!(check_forbidden_slot(0xC800_0010), Err(ExceptionKind::ReservedInstruction));
+    // nop
+    assert_eq!(check_forbidden_slot(0), Ok(()));
+}
+
+#[test]
+fn test_signal_handler_link() {
+    use cpu::instructions::{eval_instruction, test_machine, TEST_CODE, TEST_DATA};
+    use memory::{PROT_READ, PROT_WRITE};
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    memory.map(0x7fef_0000, 0x10000, PROT_READ | PROT_WRITE);
+    // jr ra; nop
+    memory.write_word(TEST_CODE, 0x03E0_0008);
+    memory.write_word(TEST_CODE + 4, 0);
+    registers.write_register(A0, TEST_DATA);
+    // ll v0,0(a0)
+    eval_instruction(0xC082_0000, &mut registers, &mut memory, &mut system);
+    let mut context = EmulatorContext {
+        memory,
+        system,
+        watchdog: Watchdog::new(None, CPUFlags::default().watchdog_conf),
+        registers,
+        block_cache: BlockCache::new(),
+        jit: None,
+    };
+    let info = SignalInfo { signo: SIGSEGV, code: 0, address: 0 };
+    let resumed = context.run_siginfo_handler(TEST_CODE, info, Some(TEST_CODE + 8));
+    assert_eq!(resumed, Some(TEST_CODE + 8));
+    // sc v0,0(a0)
+    context.registers.write_register(2, 5);
+    let EmulatorContext { ref mut registers, ref mut memory, ref mut system, .. } = context;
+    eval_instruction(0xE082_0000, registers, memory, system);
+    assert_eq!((registers.read_register(2), memory.read_word(TEST_DATA)), (0, 0));
+}
@@ -0,0 +1,1084 @@
+//! Decode stage. Raw instruction words are turned into an `Instruction` with typed operands, which
+//! `eval_instruction` then executes. DSP ASE and MIPS64 instructions are decoded into the nested
+//! enums of the modules evaluating them.
+
+use cpu::bitutils::*;
+use cpu::doubleword::{
+    decode_doubleword_instruction, is_doubleword_instruction, DoublewordInstruction,
+};
+use cpu::dsp::{decode_dsp_instruction, DspInstruction, DSP_FUNCTS};
+use cpu::float::{FMT_D, FMT_L, FMT_PS, FMT_S, FMT_W};
+use cpu::instructions_constants::{translate_opcode, InstructionOpcode};
+use cpu::isa::{Isa, IsaRevision};
+use memory::Access;
+
+/// Reason why an instruction word could not be decoded
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DecodeError {
+    /// Encoding is not defined in the selected ISA, the CPU raises reserved instruction exception
+    Reserved,
+    /// Valid instruction, that the emulator does not implement
+    Unsupported,
+}
+
+/// Decoded instruction. Registers are identified by their numbers, signed immediates are already
+/// sign extended and branch offsets are in bytes relative to the instruction after the branch.
+/// FPU formats are the `FMT_*` constants.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Instruction {
+    // shifts
+    Sll { rd: u32, rt: u32, shift: u32 },
+    Srl { rd: u32, rt: u32, shift: u32 },
+    Sra { rd: u32, rt: u32, shift: u32 },
+    Rotr { rd: u32, rt: u32, shift: u32 },
+    Sllv { rd: u32, rt: u32, rs: u32 },
+    Srlv { rd: u32, rt: u32, rs: u32 },
+    Srav { rd: u32, rt: u32, rs: u32 },
+    Rotrv { rd: u32, rt: u32, rs: u32 },
+
+    // ALU operations with registers
+    Add { rd: u32, rs: u32, rt: u32 },
+    Addu { rd: u32, rs: u32, rt: u32 },
+    Sub { rd: u32, rs: u32, rt: u32 },
+    Subu { rd: u32, rs: u32, rt: u32 },
+    And { rd: u32, rs: u32, rt: u32 },
+    Or { rd: u32, rs: u32, rt: u32 },
+    Xor { rd: u32, rs: u32, rt: u32 },
+    Nor { rd: u32, rs: u32, rt: u32 },
+    Slt { rd: u32, rs: u32, rt: u32 },
+    Sltu { rd: u32, rs: u32, rt: u32 },
+    Movz { rd: u32, rs: u32, rt: u32 },
+    Movn { rd: u32, rs: u32, rt: u32 },
+    Movf { rd: u32, rs: u32, cc: u32 },
+    Movt { rd: u32, rs: u32, cc: u32 },
+    Seleqz { rd: u32, rs: u32, rt: u32 },
+    Selnez { rd: u32, rs: u32, rt: u32 },
+    Lsa { rd: u32, rs: u32, rt: u32, shift: u32 },
+    Clz { rd: u32, rs: u32 },
+    Clo { rd: u32, rs: u32 },
+
+    // multiplication and division, pre-R6 ones use the accumulators
+    Mult { ac: u32, rs: u32, rt: u32 },
+    Multu { ac: u32, rs: u32, rt: u32 },
+    Div { rs: u32, rt: u32 },
+    Divu { rs: u32, rt: u32 },
+    Madd { ac: u32, rs: u32, rt: u32 },
+    Maddu { ac: u32, rs: u32, rt: u32 },
+    Msub { ac: u32, rs: u32, rt: u32 },
+    Msubu { ac: u32, rs: u32, rt: u32 },
+    Mfhi { rd: u32, ac: u32 },
+    Mflo { rd: u32, ac: u32 },
+    Mthi { rs: u32, ac: u32 },
+    Mtlo { rs: u32, ac: u32 },
+    Mul { rd: u32, rs: u32, rt: u32 },
+    Muh { rd: u32, rs: u32, rt: u32 },
+    Mulu { rd: u32, rs: u32, rt: u32 },
+    Muhu { rd: u32, rs: u32, rt: u32 },
+    DivR6 { rd: u32, rs: u32, rt: u32 },
+    Mod { rd: u32, rs: u32, rt: u32 },
+    DivuR6 { rd: u32, rs: u32, rt: u32 },
+    Modu { rd: u32, rs: u32, rt: u32 },
+
+    // ALU operations with immediates
+    Addi { rt: u32, rs: u32, immediate: i16 },
+    Addiu { rt: u32, rs: u32, immediate: i16 },
+    Slti { rt: u32, rs: u32, immediate: i16 },
+    Sltiu { rt: u32, rs: u32, immediate: i16 },
+    Andi { rt: u32, rs: u32, immediate: u16 },
+    Ori { rt: u32, rs: u32, immediate: u16 },
+    Xori { rt: u32, rs: u32, immediate: u16 },
+    Lui { rt: u32, immediate: u16 },
+    Aui { rt: u32, rs: u32, immediate: u16 },
+
+    // bit field operations
+    Ext { rt: u32, rs: u32, pos: u32, size: u32 },
+    Ins { rt: u32, rs: u32, pos: u32, size: u32 },
+    Wsbh { rd: u32, rt: u32 },
+    Seb { rd: u32, rt: u32 },
+    Seh { rd: u32, rt: u32 },
+    Bitswap { rd: u32, rt: u32 },
+    Align { rd: u32, rs: u32, rt: u32, bp: u32 },
+
+    // jumps and branches with a delay slot
+    J { target: u32 },
+    Jal { target: u32 },
+    Jalx { target: u32 },
+    Jr { rs: u32 },
+    Jalr { rd: u32, rs: u32 },
+    Beq { rs: u32, rt: u32, offset: i32 },
+    Bne { rs: u32, rt: u32, offset: i32 },
+    Blez { rs: u32, offset: i32 },
+    Bgtz { rs: u32, offset: i32 },
+    Beql { rs: u32, rt: u32, offset: i32 },
+    Bnel { rs: u32, rt: u32, offset: i32 },
+    Blezl { rs: u32, offset: i32 },
+    Bgtzl { rs: u32, offset: i32 },
+    Bltz { rs: u32, offset: i32 },
+    Bgez { rs: u32, offset: i32 },
+    Bltzl { rs: u32, offset: i32 },
+    Bgezl { rs: u32, offset: i32 },
+    Bltzal { rs: u32, offset: i32 },
+    Bgezal { rs: u32, offset: i32 },
+    Bltzall { rs: u32, offset: i32 },
+    Bgezall { rs: u32, offset: i32 },
+    Nal { offset: i32 },
+    Bal { offset: i32 },
+    Bposge32 { offset: i32 },
+
+    // compact branches (R6)
+    Bc { offset: i32 },
+    Balc { offset: i32 },
+    Jic { rt: u32, offset: i16 },
+    Jialc { rt: u32, offset: i16 },
+    Beqzc { rs: u32, offset: i32 },
+    Bnezc { rs: u32, offset: i32 },
+    Blezc { rt: u32, offset: i32 },
+    Bgezc { rt: u32, offset: i32 },
+    Bgtzc { rt: u32, offset: i32 },
+    Bltzc { rt: u32, offset: i32 },
+    Blezalc { rt: u32, offset: i32 },
+    Bgezalc { rt: u32, offset: i32 },
+    Bgtzalc { rt: u32, offset: i32 },
+    Bltzalc { rt: u32, offset: i32 },
+    Beqzalc { rt: u32, offset: i32 },
+    Bnezalc { rt: u32, offset: i32 },
+    Beqc { rs: u32, rt: u32, offset: i32 },
+    Bnec { rs: u32, rt: u32, offset: i32 },
+    Bgec { rs: u32, rt: u32, offset: i32 },
+    Bltc { rs: u32, rt: u32, offset: i32 },
+    Bgeuc { rs: u32, rt: u32, offset: i32 },
+    Bltuc { rs: u32, rt: u32, offset: i32 },
+    Bovc { rs: u32, rt: u32, offset: i32 },
+    Bnvc { rs: u32, rt: u32, offset: i32 },
+
+    // PC relative (R6)
+    Addiupc { rs: u32, offset: i32 },
+    Lwpc { rs: u32, offset: i32 },
+    Lwupc { rs: u32, offset: i32 },
+    Auipc { rs: u32, immediate: u16 },
+    Aluipc { rs: u32, immediate: u16 },
+
+    // loads and stores
+    Lb { rt: u32, base: u32, offset: i16 },
+    Lbu { rt: u32, base: u32, offset: i16 },
+    Lh { rt: u32, base: u32, offset: i16 },
+    Lhu { rt: u32, base: u32, offset: i16 },
+    Lw { rt: u32, base: u32, offset: i16 },
+    Lwl { rt: u32, base: u32, offset: i16 },
+    Lwr { rt: u32, base: u32, offset: i16 },
+    Ll { rt: u32, base: u32, offset: i16 },
+    Sb { rt: u32, base: u32, offset: i16 },
+    Sh { rt: u32, base: u32, offset: i16 },
+    Sw { rt: u32, base: u32, offset: i16 },
+    Swl { rt: u32, base: u32, offset: i16 },
+    Swr { rt: u32, base: u32, offset: i16 },
+    Sc { rt: u32, base: u32, offset: i16 },
+    Pref { hint: u32, base: u32, offset: i16 },
+
+    // traps, system and miscellaneous instructions
+    Tge { rs: u32, rt: u32 },
+    Tgeu { rs: u32, rt: u32 },
+    Tlt { rs: u32, rt: u32 },
+    Tltu { rs: u32, rt: u32 },
+    Teq { rs: u32, rt: u32 },
+    Tne { rs: u32, rt: u32 },
+    Tgei { rs: u32, immediate: i16 },
+    Tgeiu { rs: u32, immediate: i16 },
+    Tlti { rs: u32, immediate: i16 },
+    Tltiu { rs: u32, immediate: i16 },
+    Teqi { rs: u32, immediate: i16 },
+    Tnei { rs: u32, immediate: i16 },
+    Syscall { code: u32 },
+    Break { code: u32 },
+    Sdbbp { code: u32 },
+    Sync { stype: u32 },
+    Synci { base: u32, offset: i16 },
+    Cache { op: u32, base: u32, offset: i16 },
+    Rdhwr { rt: u32, rd: u32 },
+
+    // FPU moves, loads and stores
+    Mfc1 { rt: u32, fs: u32 },
+    Mfhc1 { rt: u32, fs: u32 },
+    Mtc1 { rt: u32, fs: u32 },
+    Mthc1 { rt: u32, fs: u32 },
+    Cfc1 { rt: u32, fs: u32 },
+    Ctc1 { rt: u32, fs: u32 },
+    Lwc1 { ft: u32, base: u32, offset: i16 },
+    Ldc1 { ft: u32, base: u32, offset: i16 },
+    Swc1 { ft: u32, base: u32, offset: i16 },
+    Sdc1 { ft: u32, base: u32, offset: i16 },
+    Lwxc1 { fd: u32, base: u32, index: u32 },
+    Ldxc1 { fd: u32, base: u32, index: u32 },
+    Luxc1 { fd: u32, base: u32, index: u32 },
+    Swxc1 { fs: u32, base: u32, index: u32 },
+    Sdxc1 { fs: u32, base: u32, index: u32 },
+    Suxc1 { fs: u32, base: u32, index: u32 },
+    Prefx { hint: u32, base: u32, index: u32 },
+
+    // FPU branches
+    Bc1f { cc: u32, offset: i32 },
+    Bc1t { cc: u32, offset: i32 },
+    Bc1fl { cc: u32, offset: i32 },
+    Bc1tl { cc: u32, offset: i32 },
+    Bc1eqz { ft: u32, offset: i32 },
+    Bc1nez { ft: u32, offset: i32 },
+
+    // FPU arithmetic
+    AddFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    SubFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MulFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    DivFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    SqrtFmt { fmt: u32, fd: u32, fs: u32 },
+    AbsFmt { fmt: u32, fd: u32, fs: u32 },
+    MovFmt { fmt: u32, fd: u32, fs: u32 },
+    NegFmt { fmt: u32, fd: u32, fs: u32 },
+    RecipFmt { fmt: u32, fd: u32, fs: u32 },
+    RsqrtFmt { fmt: u32, fd: u32, fs: u32 },
+    RintFmt { fmt: u32, fd: u32, fs: u32 },
+    ClassFmt { fmt: u32, fd: u32, fs: u32 },
+    MaddfFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MsubfFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MinFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MinaFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MaxFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MaxaFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MaddFmt { fmt: u32, fd: u32, fr: u32, fs: u32, ft: u32 },
+    MsubFmt { fmt: u32, fd: u32, fr: u32, fs: u32, ft: u32 },
+    NmaddFmt { fmt: u32, fd: u32, fr: u32, fs: u32, ft: u32 },
+    NmsubFmt { fmt: u32, fd: u32, fr: u32, fs: u32, ft: u32 },
+
+    // FPU conversions
+    RoundL { fmt: u32, fd: u32, fs: u32 },
+    TruncL { fmt: u32, fd: u32, fs: u32 },
+    CeilL { fmt: u32, fd: u32, fs: u32 },
+    FloorL { fmt: u32, fd: u32, fs: u32 },
+    RoundW { fmt: u32, fd: u32, fs: u32 },
+    TruncW { fmt: u32, fd: u32, fs: u32 },
+    CeilW { fmt: u32, fd: u32, fs: u32 },
+    FloorW { fmt: u32, fd: u32, fs: u32 },
+    CvtS { fmt: u32, fd: u32, fs: u32 },
+    CvtD { fmt: u32, fd: u32, fs: u32 },
+    CvtW { fmt: u32, fd: u32, fs: u32 },
+    CvtL { fmt: u32, fd: u32, fs: u32 },
+
+    // FPU comparisons and conditional moves
+    CCond { cond: u32, fmt: u32, cc: u32, fs: u32, ft: u32 },
+    CmpCond { cond: u32, fmt: u32, fd: u32, fs: u32, ft: u32 },
+    SelFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    SeleqzFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    SelnezFmt { fmt: u32, fd: u32, fs: u32, ft: u32 },
+    MovfFmt { fmt: u32, fd: u32, fs: u32, cc: u32 },
+    MovtFmt { fmt: u32, fd: u32, fs: u32, cc: u32 },
+    MovzFmt { fmt: u32, fd: u32, fs: u32, rt: u32 },
+    MovnFmt { fmt: u32, fd: u32, fs: u32, rt: u32 },
+
+    /// DSP ASE instruction, evaluated by `eval_dsp_instruction`
+    Dsp(DspInstruction),
+    /// MIPS64 instruction, evaluated by `eval_doubleword_instruction`
+    Doubleword(DoublewordInstruction),
+}
+
+/// Memory accessed by a load or store, `size` bytes at `base` register plus `offset`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MemoryOperand {
+    pub base: u32,
+    pub offset: i16,
+    pub size: u32,
+    pub access: Access,
+}
+
+impl Instruction {
+    /// Memory operand of loads and stores of the GPRs and FPRs with base and offset addressing.
+    /// Unaligned LWL, LWR, SWL and SWR access just one byte here, the rest of the aligned word
+    /// they touch is always in the same page.
+    pub fn memory_operand(&self) -> Option<MemoryOperand> {
+        let (base, offset, size, access) = match *self {
+            Instruction::Lb { base, offset, .. } |
+            Instruction::Lbu { base, offset, .. } |
+            Instruction::Lwl { base, offset, .. } |
+            Instruction::Lwr { base, offset, .. } => (base, offset, 1, Access::Read),
+            Instruction::Lh { base, offset, .. } |
+            Instruction::Lhu { base, offset, .. } => (base, offset, 2, Access::Read),
+            Instruction::Lw { base, offset, .. } |
+            Instruction::Ll { base, offset, .. } |
+            Instruction::Lwc1 { base, offset, .. } => (base, offset, 4, Access::Read),
+            Instruction::Ldc1 { base, offset, .. } => (base, offset, 8, Access::Read),
+            Instruction::Sb { base, offset, .. } |
+            Instruction::Swl { base, offset, .. } |
+            Instruction::Swr { base, offset, .. } => (base, offset, 1, Access::Write),
+            Instruction::Sh { base, offset, .. } => (base, offset, 2, Access::Write),
+            Instruction::Sw { base, offset, .. } |
+            Instruction::Sc { base, offset, .. } |
+            Instruction::Swc1 { base, offset, .. } => (base, offset, 4, Access::Write),
+            Instruction::Sdc1 { base, offset, .. } => (base, offset, 8, Access::Write),
+            _ => return None,
+        };
+        Some(MemoryOperand {
+            base,
+            offset,
+            size,
+            access,
+        })
+    }
+}
+
+/// Decodes a MIPS32 instruction word. Decoding depends on the ISA revision, the ABI (MIPS64
+/// instructions are reserved on 32 bit CPUs) and the compressed ISA (JALX is reserved without one).
+pub fn decode(instruction: u32, isa: Isa) -> Result<Instruction, DecodeError> {
+    let revision = isa.revision;
+    let opcode = translate_opcode(get_opcode(instruction), revision);
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let immediate = get_offset(instruction);
+    let offset = sign_extend((immediate as u32) << 2, 18);
+    let target = (instruction & 0x03_FF_FF_FF) << 2;
+
+    if is_doubleword_instruction(&opcode, instruction, revision) {
+        return if isa.abi.is_64bit() {
+            decode_doubleword_instruction(&opcode, instruction, revision)
+                .map(Instruction::Doubleword)
+        } else {
+            Err(DecodeError::Reserved)
+        };
+    }
+
+    macro_rules! load_store {
+        ($variant:ident) => (
+            Instruction::$variant { rt, base: rs, offset: immediate as i16 }
+        );
+    }
+
+    let decoded = match opcode {
+        InstructionOpcode::SPECIAL => return decode_special(instruction, revision),
+        InstructionOpcode::REGIMM => return decode_regimm(instruction, revision),
+        InstructionOpcode::SPECIAL2 => return decode_special2(instruction),
+        InstructionOpcode::SPECIAL3 => return decode_special3(instruction, revision),
+        InstructionOpcode::COP1 => return decode_cop1(instruction, revision),
+        InstructionOpcode::COP1X => return decode_cop1x(instruction, revision),
+        InstructionOpcode::PCREL => return decode_pcrel(instruction),
+        InstructionOpcode::ADDI => Instruction::Addi { rt, rs, immediate: immediate as i16 },
+        InstructionOpcode::ADDIU => Instruction::Addiu { rt, rs, immediate: immediate as i16 },
+        InstructionOpcode::SLTI => Instruction::Slti { rt, rs, immediate: immediate as i16 },
+        InstructionOpcode::SLTIU => Instruction::Sltiu { rt, rs, immediate: immediate as i16 },
+        InstructionOpcode::ANDI => Instruction::Andi { rt, rs, immediate },
+        InstructionOpcode::ORI => Instruction::Ori { rt, rs, immediate },
+        InstructionOpcode::XORI => Instruction::Xori { rt, rs, immediate },
+        // LUI is AUI with $zero, AUI itself exists only in R6
+        InstructionOpcode::AUI if rs == 0 => Instruction::Lui { rt, immediate },
+        InstructionOpcode::AUI if revision.is_r6() => Instruction::Aui { rt, rs, immediate },
+        // POP06: BLEZALC, BGEZALC, BGEUC (R6)
+        // POP07: BGTZALC, BLTZALC, BLTUC (R6)
+        InstructionOpcode::BLEZ |
+        InstructionOpcode::BGTZ if rt != 0 && revision.is_r6() => {
+            match (opcode == InstructionOpcode::BLEZ, rs) {
+                (true, 0) => Instruction::Blezalc { rt, offset },
+                (false, 0) => Instruction::Bgtzalc { rt, offset },
+                (true, _) if rs == rt => Instruction::Bgezalc { rt, offset },
+                (false, _) if rs == rt => Instruction::Bltzalc { rt, offset },
+                (true, _) => Instruction::Bgeuc { rs, rt, offset },
+                (false, _) => Instruction::Bltuc { rs, rt, offset },
+            }
+        }
+        InstructionOpcode::BEQ => Instruction::Beq { rs, rt, offset },
+        InstructionOpcode::BNE => Instruction::Bne { rs, rt, offset },
+        InstructionOpcode::BEQL => Instruction::Beql { rs, rt, offset },
+        InstructionOpcode::BNEL => Instruction::Bnel { rs, rt, offset },
+        InstructionOpcode::BLEZ if rt == 0 => Instruction::Blez { rs, offset },
+        InstructionOpcode::BGTZ if rt == 0 => Instruction::Bgtz { rs, offset },
+        InstructionOpcode::BLEZL if rt == 0 => Instruction::Blezl { rs, offset },
+        InstructionOpcode::BGTZL if rt == 0 => Instruction::Bgtzl { rs, offset },
+        // POP10: BOVC, BEQZALC, BEQC (R6)
+        // POP30: BNVC, BNEZALC, BNEC (R6)
+        InstructionOpcode::POP10 |
+        InstructionOpcode::POP30 => {
+            match opcode == InstructionOpcode::POP10 {
+                true if rs >= rt => Instruction::Bovc { rs, rt, offset },
+                false if rs >= rt => Instruction::Bnvc { rs, rt, offset },
+                true if rs == 0 => Instruction::Beqzalc { rt, offset },
+                false if rs == 0 => Instruction::Bnezalc { rt, offset },
+                true => Instruction::Beqc { rs, rt, offset },
+                false => Instruction::Bnec { rs, rt, offset },
+            }
+        }
+        // POP26: BLEZC, BGEZC, BGEC (R6)
+        // POP27: BGTZC, BLTZC, BLTC (R6)
+        InstructionOpcode::POP26 |
+        InstructionOpcode::POP27 if rt != 0 => {
+            match (opcode == InstructionOpcode::POP26, rs) {
+                (true, 0) => Instruction::Blezc { rt, offset },
+                (false, 0) => Instruction::Bgtzc { rt, offset },
+                (true, _) if rs == rt => Instruction::Bgezc { rt, offset },
+                (false, _) if rs == rt => Instruction::Bltzc { rt, offset },
+                (true, _) => Instruction::Bgec { rs, rt, offset },
+                (false, _) => Instruction::Bltc { rs, rt, offset },
+            }
+        }
+        // POP66: BEQZC, JIC (R6)
+        // POP76: BNEZC, JIALC (R6)
+        InstructionOpcode::POP66 |
+        InstructionOpcode::POP76 => {
+            let equal = opcode == InstructionOpcode::POP66;
+            let offset = sign_extend((instruction & 0x1F_FF_FF) << 2, 23);
+            match (equal, rs) {
+                (true, 0) => Instruction::Jic { rt, offset: immediate as i16 },
+                (false, 0) => Instruction::Jialc { rt, offset: immediate as i16 },
+                (true, _) => Instruction::Beqzc { rs, offset },
+                (false, _) => Instruction::Bnezc { rs, offset },
+            }
+        }
+        InstructionOpcode::BC |
+        InstructionOpcode::BALC => {
+            let offset = sign_extend((instruction & 0x03_FF_FF_FF) << 2, 28);
+            if opcode == InstructionOpcode::BC {
+                Instruction::Bc { offset }
+            } else {
+                Instruction::Balc { offset }
+            }
+        }
+        InstructionOpcode::J => Instruction::J { target },
+        InstructionOpcode::JAL => Instruction::Jal { target },
+        InstructionOpcode::JALX if isa.compressed.is_some() => Instruction::Jalx { target },
+        InstructionOpcode::LB => load_store!(Lb),
+        InstructionOpcode::LBU => load_store!(Lbu),
+        InstructionOpcode::LH => load_store!(Lh),
+        InstructionOpcode::LHU => load_store!(Lhu),
+        InstructionOpcode::LW => load_store!(Lw),
+        InstructionOpcode::LWL => load_store!(Lwl),
+        InstructionOpcode::LWR => load_store!(Lwr),
+        InstructionOpcode::LL => load_store!(Ll),
+        InstructionOpcode::SB => load_store!(Sb),
+        InstructionOpcode::SH => load_store!(Sh),
+        InstructionOpcode::SW => load_store!(Sw),
+        InstructionOpcode::SWL => load_store!(Swl),
+        InstructionOpcode::SWR => load_store!(Swr),
+        InstructionOpcode::SC => load_store!(Sc),
+        InstructionOpcode::PREF => Instruction::Pref { hint: rt, base: rs, offset: immediate as i16 },
+        InstructionOpcode::LWC1 => Instruction::Lwc1 { ft: rt, base: rs, offset: immediate as i16 },
+        InstructionOpcode::LDC1 => Instruction::Ldc1 { ft: rt, base: rs, offset: immediate as i16 },
+        InstructionOpcode::SWC1 => Instruction::Swc1 { ft: rt, base: rs, offset: immediate as i16 },
+        InstructionOpcode::SDC1 => Instruction::Sdc1 { ft: rt, base: rs, offset: immediate as i16 },
+        InstructionOpcode::COP0 |
+        InstructionOpcode::COP2 |
+        InstructionOpcode::LWC2 |
+        InstructionOpcode::LDC2 |
+        InstructionOpcode::SWC2 |
+        InstructionOpcode::SDC2 => return Err(DecodeError::Unsupported),
+        InstructionOpcode::CACHE => Instruction::Cache { op: rt, base: rs, offset: immediate as i16 },
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+fn decode_special(instruction: u32, revision: IsaRevision) -> Result<Instruction, DecodeError> {
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let rd = get_rd(instruction);
+    let shift = get_shift(instruction);
+    let code = (instruction >> 6) & 0xF_FF_FF;
+    let r6 = revision.is_r6();
+
+    let decoded = match get_funct(instruction) {
+        0b000000 if rs == 0 => Instruction::Sll { rd, rt, shift },
+        0b000010 if rs == 0 => Instruction::Srl { rd, rt, shift },
+        0b000010 if rs == 1 => Instruction::Rotr { rd, rt, shift },
+        0b000011 if rs == 0 => Instruction::Sra { rd, rt, shift },
+        0b000100 => Instruction::Sllv { rd, rt, rs },
+        0b000110 if shift == 0 => Instruction::Srlv { rd, rt, rs },
+        0b000110 if shift == 1 => Instruction::Rotrv { rd, rt, rs },
+        0b000111 if shift == 0 => Instruction::Srav { rd, rt, rs },
+        0b100000 => Instruction::Add { rd, rs, rt },
+        0b100001 => Instruction::Addu { rd, rs, rt },
+        0b100010 => Instruction::Sub { rd, rs, rt },
+        0b100011 => Instruction::Subu { rd, rs, rt },
+        0b100100 => Instruction::And { rd, rs, rt },
+        0b100101 => Instruction::Or { rd, rs, rt },
+        0b100110 => Instruction::Xor { rd, rs, rt },
+        0b100111 => Instruction::Nor { rd, rs, rt },
+        0b101010 => Instruction::Slt { rd, rs, rt },
+        0b101011 => Instruction::Sltu { rd, rs, rt },
+        0b001000 => Instruction::Jr { rs },
+        0b001001 => Instruction::Jalr { rd, rs },
+        // conditional moves were removed in R6, SELEQZ and SELNEZ replace them
+        0b000001 if !r6 && rt & 1 == 0 => Instruction::Movf { rd, rs, cc: rt >> 2 },
+        0b000001 if !r6 => Instruction::Movt { rd, rs, cc: rt >> 2 },
+        0b001010 if !r6 && shift == 0 => Instruction::Movz { rd, rs, rt },
+        0b001011 if !r6 && shift == 0 => Instruction::Movn { rd, rs, rt },
+        // MULT, MULTU, DIV, DIVU (pre-R6), multiplications select the accumulator in the DSP ASE
+        0b011000 | 0b011001 if shift == 0 && !r6 && rd < 4 => {
+            if get_funct(instruction) == 0b011000 {
+                Instruction::Mult { ac: rd, rs, rt }
+            } else {
+                Instruction::Multu { ac: rd, rs, rt }
+            }
+        }
+        0b011010 if shift == 0 && !r6 && rd == 0 => Instruction::Div { rs, rt },
+        0b011011 if shift == 0 && !r6 && rd == 0 => Instruction::Divu { rs, rt },
+        // SOP30, SOP31, SOP32, SOP33 (R6)
+        0b011000 if shift == 0b00010 && r6 => Instruction::Mul { rd, rs, rt },
+        0b011000 if shift == 0b00011 && r6 => Instruction::Muh { rd, rs, rt },
+        0b011001 if shift == 0b00010 && r6 => Instruction::Mulu { rd, rs, rt },
+        0b011001 if shift == 0b00011 && r6 => Instruction::Muhu { rd, rs, rt },
+        0b011010 if shift == 0b00010 && r6 => Instruction::DivR6 { rd, rs, rt },
+        0b011010 if shift == 0b00011 && r6 => Instruction::Mod { rd, rs, rt },
+        0b011011 if shift == 0b00010 && r6 => Instruction::DivuR6 { rd, rs, rt },
+        0b011011 if shift == 0b00011 && r6 => Instruction::Modu { rd, rs, rt },
+        0b110000 => Instruction::Tge { rs, rt },
+        0b110001 => Instruction::Tgeu { rs, rt },
+        0b110010 => Instruction::Tlt { rs, rt },
+        0b110011 => Instruction::Tltu { rs, rt },
+        0b110100 => Instruction::Teq { rs, rt },
+        0b110110 => Instruction::Tne { rs, rt },
+        // CLZ, CLO (R6)
+        0b010000 if r6 && shift == 0b00001 => Instruction::Clz { rd, rs },
+        0b010001 if r6 && shift == 0b00001 => Instruction::Clo { rd, rs },
+        0b001110 if r6 => Instruction::Sdbbp { code },
+        // HI and LO were removed in R6, the accumulator is in rs for the moves from them and in
+        // rd for the moves to them
+        0b010000 if !r6 && rt == 0 && rs < 4 && shift == 0 => Instruction::Mfhi { rd, ac: rs },
+        0b010010 if !r6 && rt == 0 && rs < 4 && shift == 0 => Instruction::Mflo { rd, ac: rs },
+        0b010001 if !r6 && rt == 0 && rd < 4 && shift == 0 => Instruction::Mthi { rs, ac: rd },
+        0b010011 if !r6 && rt == 0 && rd < 4 && shift == 0 => Instruction::Mtlo { rs, ac: rd },
+        0b001100 => Instruction::Syscall { code },
+        0b001101 => Instruction::Break { code },
+        0b001111 => Instruction::Sync { stype: shift },
+        // SELEQZ, SELNEZ and LSA (R6), the shift amount of LSA is encoded decremented by one
+        0b110101 if r6 && shift == 0 => Instruction::Seleqz { rd, rs, rt },
+        0b110111 if r6 && shift == 0 => Instruction::Selnez { rd, rs, rt },
+        0b000101 if r6 && shift >> 2 == 0 => Instruction::Lsa { rd, rs, rt, shift: shift + 1 },
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+fn decode_regimm(instruction: u32, revision: IsaRevision) -> Result<Instruction, DecodeError> {
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let immediate = get_offset(instruction) as i16;
+    let offset = sign_extend((get_offset(instruction) as u32) << 2, 18);
+
+    let decoded = match rt {
+        // BLTZAL and BGEZAL with $zero are the NAL and BAL encodings, the only ones left in R6
+        0b10000 if rs == 0 => Instruction::Nal { offset },
+        0b10001 if rs == 0 => Instruction::Bal { offset },
+        0b00000 => Instruction::Bltz { rs, offset },
+        0b00001 => Instruction::Bgez { rs, offset },
+        0b00010 if !revision.is_r6() => Instruction::Bltzl { rs, offset },
+        0b00011 if !revision.is_r6() => Instruction::Bgezl { rs, offset },
+        0b10000 if !revision.is_r6() => Instruction::Bltzal { rs, offset },
+        0b10001 if !revision.is_r6() => Instruction::Bgezal { rs, offset },
+        0b10010 if !revision.is_r6() => Instruction::Bltzall { rs, offset },
+        0b10011 if !revision.is_r6() => Instruction::Bgezall { rs, offset },
+        0b01000 if !revision.is_r6() => Instruction::Tgei { rs, immediate },
+        0b01001 if !revision.is_r6() => Instruction::Tgeiu { rs, immediate },
+        0b01010 if !revision.is_r6() => Instruction::Tlti { rs, immediate },
+        0b01011 if !revision.is_r6() => Instruction::Tltiu { rs, immediate },
+        0b01100 if !revision.is_r6() => Instruction::Teqi { rs, immediate },
+        0b01110 if !revision.is_r6() => Instruction::Tnei { rs, immediate },
+        0b11100 if rs == 0 => Instruction::Bposge32 { offset },
+        0b11111 => Instruction::Synci { base: rs, offset: immediate },
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+fn decode_pcrel(instruction: u32) -> Result<Instruction, DecodeError> {
+    let rs = get_rs(instruction);
+    let immediate = get_offset(instruction);
+    let offset = sign_extend((instruction & 0x7_FF_FF) << 2, 21);
+
+    let decoded = match get_rt(instruction) {
+        0b00000..=0b00111 => Instruction::Addiupc { rs, offset },
+        0b01000..=0b01111 => Instruction::Lwpc { rs, offset },
+        0b10000..=0b10111 => Instruction::Lwupc { rs, offset },
+        0b11110 => Instruction::Auipc { rs, immediate },
+        0b11111 => Instruction::Aluipc { rs, immediate },
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+/// SPECIAL2 exists only before R6, the opcode is translated differently there
+fn decode_special2(instruction: u32) -> Result<Instruction, DecodeError> {
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let rd = get_rd(instruction);
+    let shift = get_shift(instruction);
+
+    let decoded = match get_funct(instruction) {
+        0b000010 if shift == 0 => Instruction::Mul { rd, rs, rt },
+        // the accumulator is in rd
+        0b000000 if shift == 0 && rd < 4 => Instruction::Madd { ac: rd, rs, rt },
+        0b000001 if shift == 0 && rd < 4 => Instruction::Maddu { ac: rd, rs, rt },
+        0b000100 if shift == 0 && rd < 4 => Instruction::Msub { ac: rd, rs, rt },
+        0b000101 if shift == 0 && rd < 4 => Instruction::Msubu { ac: rd, rs, rt },
+        0b100000 if shift == 0 => Instruction::Clz { rd, rs },
+        0b100001 if shift == 0 => Instruction::Clo { rd, rs },
+        0b111111 => Instruction::Sdbbp { code: (instruction >> 6) & 0xF_FF_FF },
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+fn decode_special3(instruction: u32, revision: IsaRevision) -> Result<Instruction, DecodeError> {
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let rd = get_rd(instruction);
+    let shift = get_shift(instruction);
+    let funct = get_funct(instruction);
+    let r6 = revision.is_r6();
+
+    let decoded = match funct {
+        // the field is described by its size in EXT and by its msb in INS
+        0b000000 if shift + rd < 32 => Instruction::Ext { rt, rs, pos: shift, size: rd + 1 },
+        0b000100 if rd >= shift => Instruction::Ins { rt, rs, pos: shift, size: rd + 1 - shift },
+        // BSHFL, only ALIGN has a second source register
+        0b100000 if rs == 0 || shift & 0b11100 == 0b01000 => {
+            match shift {
+                0b00000 if r6 => Instruction::Bitswap { rd, rt },
+                0b00010 => Instruction::Wsbh { rd, rt },
+                0b01000..=0b01011 if r6 => Instruction::Align { rd, rs, rt, bp: shift & 0b11 },
+                0b10000 => Instruction::Seb { rd, rt },
+                0b11000 => Instruction::Seh { rd, rt },
+                _ => return Err(DecodeError::Reserved),
+            }
+        }
+        // CPUNum, SYNCI_Step, CC, CCRes and UserLocal
+        0b111011 if rd <= 3 || rd == 29 => Instruction::Rdhwr { rt, rd },
+        // LL, SC, PREF, CACHE with 9 bit offset (R6)
+        0b110110 | 0b100110 | 0b110101 | 0b100101 if r6 && instruction & 0x40 == 0 => {
+            let offset = sign_extend((instruction >> 7) & 0x1FF, 9) as i16;
+            match funct {
+                0b110110 => Instruction::Ll { rt, base: rs, offset },
+                0b100110 => Instruction::Sc { rt, base: rs, offset },
+                0b100101 => Instruction::Cache { op: rt, base: rs, offset },
+                _ => Instruction::Pref { hint: rt, base: rs, offset },
+            }
+        }
+        _ if DSP_FUNCTS.contains(&funct) => {
+            return decode_dsp_instruction(instruction).map(Instruction::Dsp)
+        }
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+fn decode_cop1(instruction: u32, revision: IsaRevision) -> Result<Instruction, DecodeError> {
+    let fmt = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let ft = rt;
+    let fs = get_rd(instruction);
+    let fd = get_shift(instruction);
+    let funct = get_funct(instruction);
+    let offset = sign_extend((get_offset(instruction) as u32) << 2, 18);
+    let r6 = revision.is_r6();
+
+    let decoded = match fmt {
+        0b00000 => Instruction::Mfc1 { rt, fs },
+        0b00010 => Instruction::Cfc1 { rt, fs },
+        0b00011 => Instruction::Mfhc1 { rt, fs },
+        0b00100 => Instruction::Mtc1 { rt, fs },
+        0b00110 => Instruction::Ctc1 { rt, fs },
+        0b00111 => Instruction::Mthc1 { rt, fs },
+        // BC1F, BC1T, BC1FL, BC1TL (pre-R6), the condition code is in the upper bits of rt
+        0b01000 if !r6 => {
+            let cc = rt >> 2;
+            match rt & 0b11 {
+                0b00 => Instruction::Bc1f { cc, offset },
+                0b01 => Instruction::Bc1t { cc, offset },
+                0b10 => Instruction::Bc1fl { cc, offset },
+                _ => Instruction::Bc1tl { cc, offset },
+            }
+        }
+        0b01001 if r6 => Instruction::Bc1eqz { ft, offset },
+        0b01101 if r6 => Instruction::Bc1nez { ft, offset },
+        // CMP.cond.fmt (R6), the W and L format encodings are reused for S and D
+        FMT_W | FMT_L if funct & 0b100000 == 0 && r6 => {
+            let fmt = if fmt == FMT_W { FMT_S } else { FMT_D };
+            Instruction::CmpCond { cond: funct, fmt, fd, fs, ft }
+        }
+        FMT_S | FMT_D | FMT_W | FMT_L | FMT_PS => {
+            // formats each operation is defined for, the other combinations are reserved.
+            // R6 removed the paired single format.
+            let float = fmt == FMT_S || fmt == FMT_D;
+            let paired = fmt == FMT_PS && !r6;
+            let valid = match funct {
+                // MOVF, MOVT, MOVZ, MOVN and C.cond (pre-R6)
+                0b010001..=0b010011 | 0b110000..=0b111111 => (float || paired) && !r6,
+                // SEL, SELEQZ, SELNEZ, MADDF, MSUBF, RINT, CLASS, MIN, MAX, MINA, MAXA (R6)
+                0b010000 | 0b010100 | 0b010111 | 0b011000..=0b011111 => float && r6,
+                // ADD, SUB, MUL, ABS, MOV and NEG
+                0b000000..=0b000010 | 0b000101..=0b000111 => float || paired,
+                // CVT.S (CVT.S.PU for PS) and CVT.D
+                0b100000 => fmt != FMT_S && (fmt != FMT_PS || paired),
+                0b100001 => fmt == FMT_S || fmt == FMT_W || fmt == FMT_L,
+                0b000011 | 0b000100 | 0b001000..=0b001111 | 0b010101 | 0b010110 |
+                0b100100 | 0b100101 => float,
+                _ => fmt != FMT_PS || paired,
+            };
+            if !valid {
+                return Err(DecodeError::Reserved);
+            }
+            // the paired single MOVF, MOVT and C.cond use the condition codes cc and cc + 1,
+            // so cc has to be even
+            let cc = if funct == 0b010001 { ft >> 2 } else { fd >> 2 };
+            if fmt == FMT_PS && (funct == 0b010001 || funct >= 0b110000) && cc & 1 != 0 {
+                return Err(DecodeError::Reserved);
+            }
+            match funct {
+                0b000000 => Instruction::AddFmt { fmt, fd, fs, ft },
+                0b000001 => Instruction::SubFmt { fmt, fd, fs, ft },
+                0b000010 => Instruction::MulFmt { fmt, fd, fs, ft },
+                0b000011 => Instruction::DivFmt { fmt, fd, fs, ft },
+                0b000100 => Instruction::SqrtFmt { fmt, fd, fs },
+                0b000101 => Instruction::AbsFmt { fmt, fd, fs },
+                0b000110 => Instruction::MovFmt { fmt, fd, fs },
+                0b000111 => Instruction::NegFmt { fmt, fd, fs },
+                0b001000 => Instruction::RoundL { fmt, fd, fs },
+                0b001001 => Instruction::TruncL { fmt, fd, fs },
+                0b001010 => Instruction::CeilL { fmt, fd, fs },
+                0b001011 => Instruction::FloorL { fmt, fd, fs },
+                0b001100 => Instruction::RoundW { fmt, fd, fs },
+                0b001101 => Instruction::TruncW { fmt, fd, fs },
+                0b001110 => Instruction::CeilW { fmt, fd, fs },
+                0b001111 => Instruction::FloorW { fmt, fd, fs },
+                0b010000 => Instruction::SelFmt { fmt, fd, fs, ft },
+                0b010001 if ft & 1 == 0 => Instruction::MovfFmt { fmt, fd, fs, cc: ft >> 2 },
+                0b010001 => Instruction::MovtFmt { fmt, fd, fs, cc: ft >> 2 },
+                0b010010 => Instruction::MovzFmt { fmt, fd, fs, rt },
+                0b010011 => Instruction::MovnFmt { fmt, fd, fs, rt },
+                0b010100 => Instruction::SeleqzFmt { fmt, fd, fs, ft },
+                0b010101 => Instruction::RecipFmt { fmt, fd, fs },
+                0b010110 => Instruction::RsqrtFmt { fmt, fd, fs },
+                0b010111 => Instruction::SelnezFmt { fmt, fd, fs, ft },
+                0b011000 => Instruction::MaddfFmt { fmt, fd, fs, ft },
+                0b011001 => Instruction::MsubfFmt { fmt, fd, fs, ft },
+                0b011010 => Instruction::RintFmt { fmt, fd, fs },
+                0b011011 => Instruction::ClassFmt { fmt, fd, fs },
+                0b011100 => Instruction::MinFmt { fmt, fd, fs, ft },
+                0b011101 => Instruction::MinaFmt { fmt, fd, fs, ft },
+                0b011110 => Instruction::MaxFmt { fmt, fd, fs, ft },
+                0b011111 => Instruction::MaxaFmt { fmt, fd, fs, ft },
+                0b100000 => Instruction::CvtS { fmt, fd, fs },
+                0b100001 => Instruction::CvtD { fmt, fd, fs },
+                0b100100 => Instruction::CvtW { fmt, fd, fs },
+                0b100101 => Instruction::CvtL { fmt, fd, fs },
+                // C.cond.fmt (pre-R6), the condition code is in the upper bits of fd
+                0b110000..=0b111111 => Instruction::CCond { cond: funct & 0b1111, fmt, cc: fd >> 2, fs, ft },
+                _ => return Err(DecodeError::Unsupported),
+            }
+        }
+        _ => return Err(DecodeError::Unsupported),
+    };
+    Ok(decoded)
+}
+
+/// COP1X instructions (indexed loads and stores, MADD.fmt and others) were removed in R6
+fn decode_cop1x(instruction: u32, revision: IsaRevision) -> Result<Instruction, DecodeError> {
+    let fr = get_rs(instruction);
+    let ft = get_rt(instruction);
+    let fs = get_rd(instruction);
+    let fd = get_shift(instruction);
+    let funct = get_funct(instruction);
+    let (base, index) = (fr, ft);
+    if revision.is_r6() {
+        return Err(DecodeError::Reserved);
+    }
+
+    let decoded = match funct {
+        0b000000 => Instruction::Lwxc1 { fd, base, index },
+        0b000001 => Instruction::Ldxc1 { fd, base, index },
+        0b000101 => Instruction::Luxc1 { fd, base, index },
+        0b001000 => Instruction::Swxc1 { fs, base, index },
+        0b001001 => Instruction::Sdxc1 { fs, base, index },
+        0b001101 => Instruction::Suxc1 { fs, base, index },
+        0b001111 => Instruction::Prefx { hint: fs, base, index },
+        // MADD.fmt, MSUB.fmt, NMADD.fmt, NMSUB.fmt
+        0b100000..=0b111111 => {
+            let fmt = match funct & 0b111 {
+                0 => FMT_S,
+                1 => FMT_D,
+                6 => FMT_PS,
+                _ => return Err(DecodeError::Reserved),
+            };
+            match funct >> 3 {
+                0b100 => Instruction::MaddFmt { fmt, fd, fr, fs, ft },
+                0b101 => Instruction::MsubFmt { fmt, fd, fr, fs, ft },
+                0b110 => Instruction::NmaddFmt { fmt, fd, fr, fs, ft },
+                _ => Instruction::NmsubFmt { fmt, fd, fr, fs, ft },
+            }
+        }
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+#[test]
+fn test_decode() {
+    use cpu::isa::{Abi, CompressedIsa};
+
+    let r2 = Isa {
+        revision: IsaRevision::R2,
+        compressed: Some(CompressedIsa::Mips16e),
+        abi: Abi::O32,
+    };
+    let r6 = Isa {
+        revision: IsaRevision::R6,
+        compressed: None,
+        abi: Abi::O32,
+    };
+    // addiu sp,sp,-32
+    assert_eq!(
+        decode(0x27BD_FFE0, r2),
+        Ok(Instruction::Addiu { rt: 29, rs: 29, immediate: -32 })
+    );
+    // lw ra,28(sp)
+    assert_eq!(
+        decode(0x8FBF_001C, r2),
+        Ok(Instruction::Lw { rt: 31, base: 29, offset: 28 })
+    );
+    // beq a0,zero,. - 4
+    assert_eq!(
+        decode(0x1080_FFFE, r2),
+        Ok(Instruction::Beq { rs: 4, rt: 0, offset: -8 })
+    );
+    // ext v0,a0,3,5
+    assert_eq!(
+        decode(0x7C82_20C0, r2),
+        Ok(Instruction::Ext { rt: 2, rs: 4, pos: 3, size: 5 })
+    );
+    // mul v0,a0,a1 has different encodings before and in R6
+    assert_eq!(decode(0x7085_1002, r2), Ok(Instruction::Mul { rd: 2, rs: 4, rt: 5 }));
+    assert_eq!(decode(0x0085_1098, r6), Ok(Instruction::Mul { rd: 2, rs: 4, rt: 5 }));
+    // bnezc a0,. + 8 and jalx without a compressed ISA
+    assert_eq!(decode(0xF880_0001, r6), Ok(Instruction::Bnezc { rs: 4, offset: 4 }));
+    assert_eq!(decode(0x7410_0000, r2), Ok(Instruction::Jalx { target: 0x0040_0000 }));
+    assert_eq!(decode(0x7410_0000, r6), Err(DecodeError::Reserved));
+    // movz v0,a0,a1 was removed in R6
+    assert_eq!(decode(0x0085_100A, r2), Ok(Instruction::Movz { rd: 2, rs: 4, rt: 5 }));
+    assert_eq!(decode(0x0085_100A, r6), Err(DecodeError::Reserved));
+    // seleqz v0,a0,a1 and lsa v0,a0,a1,2 replace them
+    assert_eq!(decode(0x0085_1035, r6), Ok(Instruction::Seleqz { rd: 2, rs: 4, rt: 5 }));
+    assert_eq!(decode(0x0085_1035, r2), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x0085_1045, r6), Ok(Instruction::Lsa { rd: 2, rs: 4, rt: 5, shift: 2 }));
+    // add.d $f0,$f2,$f4
+    assert_eq!(
+        decode(0x4624_1000, r2),
+        Ok(Instruction::AddFmt { fmt: FMT_D, fd: 0, fs: 2, ft: 4 })
+    );
+    // add.ps $f0,$f2,$f4, but there is no add.w, div.ps, sqrt.w or cvt.d.ps
+    assert_eq!(
+        decode(0x46C4_1000, r2),
+        Ok(Instruction::AddFmt { fmt: FMT_PS, fd: 0, fs: 2, ft: 4 })
+    );
+    assert_eq!(decode(0x4684_1000, r2), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x46C4_1003, r2), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x4680_1004, r2), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x46C0_1021, r2), Err(DecodeError::Reserved));
+    // movz.d $f0,$f2,a0, c.eq.d $f2,$f4, lwxc1 $f0,a1(a0) and add.ps were removed in R6
+    assert_eq!(
+        decode(0x4624_1012, r2),
+        Ok(Instruction::MovzFmt { fmt: FMT_D, fd: 0, fs: 2, rt: 4 })
+    );
+    assert_eq!(decode(0x4624_1012, r6), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x4624_1032, r6), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x4C85_0000, r2), Ok(Instruction::Lwxc1 { fd: 0, base: 4, index: 5 }));
+    assert_eq!(decode(0x4C85_0000, r6), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x46C4_1000, r6), Err(DecodeError::Reserved));
+    // c.eq.ps $fcc6,$f2,$f4 and movt.ps $f0,$f2,$fcc2, but not $fcc7 or $fcc3
+    assert_eq!(
+        decode(0x46C4_1632, r2),
+        Ok(Instruction::CCond { cond: 2, fmt: FMT_PS, cc: 6, fs: 2, ft: 4 })
+    );
+    assert_eq!(decode(0x46C4_1732, r2), Err(DecodeError::Reserved));
+    assert_eq!(
+        decode(0x46C9_1011, r2),
+        Ok(Instruction::MovtFmt { fmt: FMT_PS, fd: 0, fs: 2, cc: 2 })
+    );
+    assert_eq!(decode(0x46CD_1011, r2), Err(DecodeError::Reserved));
+    // maddf.d $f0,$f2,$f4 was added in R6
+    assert_eq!(
+        decode(0x4624_1018, r6),
+        Ok(Instruction::MaddfFmt { fmt: FMT_D, fd: 0, fs: 2, ft: 4 })
+    );
+    assert_eq!(decode(0x4624_1018, r2), Err(DecodeError::Reserved));
+    // daddu v0,a0,a1 needs a 64 bit ABI
+    assert_eq!(decode(0x0085_102D, r2), Err(DecodeError::Reserved));
+    assert_eq!(
+        decode(0x0085_102D, Isa { abi: Abi::N64, ..r2 }),
+        Ok(Instruction::Doubleword(DoublewordInstruction::Daddu { rd: 2, rs: 4, rt: 5 }))
+    );
+}
+
+#[test]
+fn test_decode_nested() {
+    use cpu::isa::Abi;
+
+    let dsp = Isa {
+        revision: IsaRevision::R2,
+        compressed: None,
+        abi: Abi::O32,
+    };
+    let r2 = Isa { abi: Abi::N64, ..dsp };
+    let r6 = Isa { revision: IsaRevision::R6, ..r2 };
+    // encodings from llvm-mc -show-encoding -mattr=+dsp
+    let dsp_encodings = [
+        // lwx v0,a1(a0)
+        (0x7C85_100A, DspInstruction::Lwx { rd: 2, base: 4, index: 5 }),
+        // insv v0,a0
+        (0x7C82_000C, DspInstruction::Insv { rt: 2, rs: 4 }),
+        // addu_s.qb v0,a0,a1
+        (0x7C85_1110, DspInstruction::AdduSQb { rd: 2, rs: 4, rt: 5 }),
+        // raddu.w.qb v0,a0
+        (0x7C80_1510, DspInstruction::RadduWQb { rd: 2, rs: 4 }),
+        // cmpu.lt.qb a0,a1
+        (0x7C85_0051, DspInstruction::CmpuLtQb { rs: 4, rt: 5 }),
+        // pick.ph v0,a0,a1
+        (0x7C85_12D1, DspInstruction::PickPh { rd: 2, rs: 4, rt: 5 }),
+        // repl.qb v0,0xab
+        (0x7CAB_1092, DspInstruction::ReplQb { rd: 2, immediate: 0xAB }),
+        // repl.ph v0,-3
+        (0x7FFD_1292, DspInstruction::ReplPh { rd: 2, immediate: -3 }),
+        // shll.ph v0,a1,13
+        (0x7DA5_1213, DspInstruction::ShllPh { rd: 2, rt: 5, shift: 13 }),
+        // shrav_r.w v0,a1,a0
+        (0x7C85_15D3, DspInstruction::ShravRW { rd: 2, rt: 5, rs: 4 }),
+        // dpaq_sa.l.w $ac1,a0,a1
+        (0x7C85_0B30, DspInstruction::DpaqSaLW { ac: 1, rs: 4, rt: 5 }),
+        // extr_rs.w v0,$ac2,17
+        (0x7E22_11B8, DspInstruction::ExtrRsW { rt: 2, ac: 2, shift: 17 }),
+        // extpdpv v0,$ac3,a0
+        (0x7C82_1AF8, DspInstruction::Extpdpv { rt: 2, ac: 3, rs: 4 }),
+        // shilo $ac1,-5
+        (0x7FB0_0EB8, DspInstruction::Shilo { ac: 1, shift: -5 }),
+        // rddsp v0,0x3f
+        (0x7C3F_14B8, DspInstruction::Rddsp { rd: 2, mask: 0x3F }),
+        // wrdsp a0,0x11
+        (0x7C80_8CF8, DspInstruction::Wrdsp { rs: 4, mask: 0x11 }),
+        // mthlip a0,$ac1
+        (0x7C80_0FF8, DspInstruction::Mthlip { rs: 4, ac: 1 }),
+        // revision 2, from llvm-mc -show-encoding -mattr=+dspr2
+        // addu.ph v0,a0,a1
+        (0x7C85_1210, DspInstruction::AdduPh { rd: 2, rs: 4, rt: 5 }),
+        // subu.ph v0,a0,a1
+        (0x7C85_1250, DspInstruction::SubuPh { rd: 2, rs: 4, rt: 5 }),
+        // addu_s.ph v0,a0,a1
+        (0x7C85_1310, DspInstruction::AdduSPh { rd: 2, rs: 4, rt: 5 }),
+        // subu_s.ph v0,a0,a1
+        (0x7C85_1350, DspInstruction::SubuSPh { rd: 2, rs: 4, rt: 5 }),
+        // mulq_s.ph v0,a0,a1
+        (0x7C85_1790, DspInstruction::MulqSPh { rd: 2, rs: 4, rt: 5 }),
+        // adduh.qb v0,a0,a1
+        (0x7C85_1018, DspInstruction::AdduhQb { rd: 2, rs: 4, rt: 5 }),
+        // adduh_r.qb v0,a0,a1
+        (0x7C85_1098, DspInstruction::AdduhRQb { rd: 2, rs: 4, rt: 5 }),
+        // subuh.qb v0,a0,a1
+        (0x7C85_1058, DspInstruction::SubuhQb { rd: 2, rs: 4, rt: 5 }),
+        // subuh_r.qb v0,a0,a1
+        (0x7C85_10D8, DspInstruction::SubuhRQb { rd: 2, rs: 4, rt: 5 }),
+        // addqh.ph v0,a0,a1
+        (0x7C85_1218, DspInstruction::AddqhPh { rd: 2, rs: 4, rt: 5 }),
+        // addqh_r.ph v0,a0,a1
+        (0x7C85_1298, DspInstruction::AddqhRPh { rd: 2, rs: 4, rt: 5 }),
+        // subqh.ph v0,a0,a1
+        (0x7C85_1258, DspInstruction::SubqhPh { rd: 2, rs: 4, rt: 5 }),
+        // subqh_r.ph v0,a0,a1
+        (0x7C85_12D8, DspInstruction::SubqhRPh { rd: 2, rs: 4, rt: 5 }),
+        // addqh.w v0,a0,a1
+        (0x7C85_1418, DspInstruction::AddqhW { rd: 2, rs: 4, rt: 5 }),
+        // addqh_r.w v0,a0,a1
+        (0x7C85_1498, DspInstruction::AddqhRW { rd: 2, rs: 4, rt: 5 }),
+        // subqh.w v0,a0,a1
+        (0x7C85_1458, DspInstruction::SubqhW { rd: 2, rs: 4, rt: 5 }),
+        // subqh_r.w v0,a0,a1
+        (0x7C85_14D8, DspInstruction::SubqhRW { rd: 2, rs: 4, rt: 5 }),
+        // mul.ph v0,a0,a1
+        (0x7C85_1318, DspInstruction::MulPh { rd: 2, rs: 4, rt: 5 }),
+        // mul_s.ph v0,a0,a1
+        (0x7C85_1398, DspInstruction::MulSPh { rd: 2, rs: 4, rt: 5 }),
+        // mulq_s.w v0,a0,a1
+        (0x7C85_1598, DspInstruction::MulqSW { rd: 2, rs: 4, rt: 5 }),
+        // mulq_rs.w v0,a0,a1
+        (0x7C85_15D8, DspInstruction::MulqRsW { rd: 2, rs: 4, rt: 5 }),
+        // cmpgdu.eq.qb v0,a0,a1
+        (0x7C85_1611, DspInstruction::CmpgduEqQb { rd: 2, rs: 4, rt: 5 }),
+        // cmpgdu.lt.qb v0,a0,a1
+        (0x7C85_1651, DspInstruction::CmpgduLtQb { rd: 2, rs: 4, rt: 5 }),
+        // cmpgdu.le.qb v0,a0,a1
+        (0x7C85_1691, DspInstruction::CmpgduLeQb { rd: 2, rs: 4, rt: 5 }),
+        // precr.qb.ph v0,a0,a1
+        (0x7C85_1351, DspInstruction::PrecrQbPh { rd: 2, rs: 4, rt: 5 }),
+        // precr_sra.ph.w v0,a0,7
+        (0x7C82_3F91, DspInstruction::PrecrSraPhW { rt: 2, rs: 4, shift: 7 }),
+        // precr_sra_r.ph.w v0,a0,31
+        (0x7C82_FFD1, DspInstruction::PrecrSraRPhW { rt: 2, rs: 4, shift: 31 }),
+        // absq_s.qb v0,a1
+        (0x7C05_1052, DspInstruction::AbsqSQb { rd: 2, rt: 5 }),
+        // shra.qb v0,a1,3
+        (0x7C65_1113, DspInstruction::ShraQb { rd: 2, rt: 5, shift: 3 }),
+        // shra_r.qb v0,a1,7
+        (0x7CE5_1153, DspInstruction::ShraRQb { rd: 2, rt: 5, shift: 7 }),
+        // shrav.qb v0,a1,a0
+        (0x7C85_1193, DspInstruction::ShravQb { rd: 2, rt: 5, rs: 4 }),
+        // shrav_r.qb v0,a1,a0
+        (0x7C85_11D3, DspInstruction::ShravRQb { rd: 2, rt: 5, rs: 4 }),
+        // shrl.ph v0,a1,13
+        (0x7DA5_1653, DspInstruction::ShrlPh { rd: 2, rt: 5, shift: 13 }),
+        // shrlv.ph v0,a1,a0
+        (0x7C85_16D3, DspInstruction::ShrlvPh { rd: 2, rt: 5, rs: 4 }),
+        // dpa.w.ph $ac1,a0,a1
+        (0x7C85_0830, DspInstruction::DpaWPh { ac: 1, rs: 4, rt: 5 }),
+        // dps.w.ph $ac1,a0,a1
+        (0x7C85_0870, DspInstruction::DpsWPh { ac: 1, rs: 4, rt: 5 }),
+        // dpax.w.ph $ac2,a0,a1
+        (0x7C85_1230, DspInstruction::DpaxWPh { ac: 2, rs: 4, rt: 5 }),
+        // dpsx.w.ph $ac2,a0,a1
+        (0x7C85_1270, DspInstruction::DpsxWPh { ac: 2, rs: 4, rt: 5 }),
+        // dpaqx_s.w.ph $ac3,a0,a1
+        (0x7C85_1E30, DspInstruction::DpaqxSWPh { ac: 3, rs: 4, rt: 5 }),
+        // dpaqx_sa.w.ph $ac3,a0,a1
+        (0x7C85_1EB0, DspInstruction::DpaqxSaWPh { ac: 3, rs: 4, rt: 5 }),
+        // dpsqx_s.w.ph $ac3,a0,a1
+        (0x7C85_1E70, DspInstruction::DpsqxSWPh { ac: 3, rs: 4, rt: 5 }),
+        // dpsqx_sa.w.ph $ac3,a0,a1
+        (0x7C85_1EF0, DspInstruction::DpsqxSaWPh { ac: 3, rs: 4, rt: 5 }),
+        // mulsa.w.ph $ac1,a0,a1
+        (0x7C85_08B0, DspInstruction::MulsaWPh { ac: 1, rs: 4, rt: 5 }),
+        // append v0,a0,7
+        (0x7C82_3831, DspInstruction::Append { rt: 2, rs: 4, shift: 7 }),
+        // prepend v0,a0,31
+        (0x7C82_F871, DspInstruction::Prepend { rt: 2, rs: 4, shift: 31 }),
+        // balign v0,a0,3
+        (0x7C82_1C31, DspInstruction::Balign { rt: 2, rs: 4, bp: 3 }),
+    ];
+    for &(instruction, expected) in dsp_encodings.iter() {
+        assert_eq!(decode(instruction, dsp), Ok(Instruction::Dsp(expected)));
+    }
+
+    // encodings from llvm-mc -show-encoding -mcpu=mips64r2 and -mcpu=mips64r6
+    let doubleword_encodings = [
+        // dsll32 v0,a0,3
+        (0x0004_10FC, r2, DoublewordInstruction::Dsll { rd: 2, rt: 4, shift: 35 }),
+        // dextm v0,a0,3,40
+        (0x7C82_38C1, r2, DoublewordInstruction::Dext { rt: 2, rs: 4, pos: 3, size: 40 }),
+        // ld v0,-8(sp)
+        (0xDFA2_FFF8, r2, DoublewordInstruction::Ld { rt: 2, base: 29, offset: -8 }),
+        // dmtc1 a0,$f2
+        (0x44A4_1000, r2, DoublewordInstruction::Dmtc1 { rt: 4, fs: 2 }),
+        // dsrav v0,a0,a1
+        (0x00A4_1017, r2, DoublewordInstruction::Dsrav { rd: 2, rt: 4, rs: 5 }),
+        // daui v0,a0,0x1234
+        (0x7482_1234, r6, DoublewordInstruction::Daui { rt: 2, rs: 4, immediate: 0x1234 }),
+        // dahi v0,0x8000
+        (0x0446_8000, r6, DoublewordInstruction::Dahi { rs: 2, immediate: 0x8000 }),
+        // dmuhu v0,a0,a1
+        (0x0085_10DD, r6, DoublewordInstruction::Dmuhu { rd: 2, rs: 4, rt: 5 }),
+        // ldpc v0,16
+        (0xEC58_0002, r6, DoublewordInstruction::Ldpc { rs: 2, offset: 16 }),
+    ];
+    for &(instruction, isa, expected) in doubleword_encodings.iter() {
+        assert_eq!(decode(instruction, isa), Ok(Instruction::Doubleword(expected)));
+    }
+    // the LX and ADDU.QB groups have no operations 2 and 19
+    assert_eq!(decode(0x7C85_108A, dsp), Err(DecodeError::Reserved));
+    assert_eq!(decode(0x7C85_14D0, dsp), Err(DecodeError::Reserved));
+}
@@ -0,0 +1,758 @@
+//! MIPS64 instructions operating on doublewords. They are available only to n32 and n64 programs,
+//! O32 ones run on a 32 bit CPU and get a reserved instruction exception instead.
+
+use cpu::bitutils::{get_funct, get_offset, get_rd, get_rs, get_rt, get_shift, sign_extend};
+use cpu::decoder::DecodeError;
+use cpu::event::{CPUEvent, ExceptionKind};
+use cpu::instructions_constants::InstructionOpcode;
+use cpu::isa::{Abi, IsaRevision};
+use cpu::registers::{get_register_name, RegisterFile};
+use memory::{Access, Memory, KERNEL_SEGMENT_START, LINK_LINE_SIZE};
+
+/// Mask with the lowest `size` bits set, `size` can be anything from 0 to 64.
+fn bit_mask64(size: u32) -> u64 {
+    assert!(size <= 64);
+    if size == 64 { u64::MAX } else { (1 << size) - 1 }
+}
+
+/// Decoded MIPS64 instruction, the operands follow the conventions of `Instruction`. Shifts by
+/// 32 or more (DSLL32, DSRL32, ...) are folded into `shift`, DEXTM, DEXTU, DINSM and DINSU into
+/// `pos` and `size` of DEXT and DINS.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DoublewordInstruction {
+    // ALU operations
+    Daddi { rt: u32, rs: u32, immediate: i16 },
+    Daddiu { rt: u32, rs: u32, immediate: i16 },
+    Daui { rt: u32, rs: u32, immediate: u16 },
+    Dahi { rs: u32, immediate: u16 },
+    Dati { rs: u32, immediate: u16 },
+    Dadd { rd: u32, rs: u32, rt: u32 },
+    Daddu { rd: u32, rs: u32, rt: u32 },
+    Dsub { rd: u32, rs: u32, rt: u32 },
+    Dsubu { rd: u32, rs: u32, rt: u32 },
+    Dlsa { rd: u32, rs: u32, rt: u32, shift: u32 },
+    Dclz { rd: u32, rs: u32 },
+    Dclo { rd: u32, rs: u32 },
+
+    // shifts
+    Dsll { rd: u32, rt: u32, shift: u32 },
+    Dsrl { rd: u32, rt: u32, shift: u32 },
+    Dsra { rd: u32, rt: u32, shift: u32 },
+    Drotr { rd: u32, rt: u32, shift: u32 },
+    Dsllv { rd: u32, rt: u32, rs: u32 },
+    Dsrlv { rd: u32, rt: u32, rs: u32 },
+    Dsrav { rd: u32, rt: u32, rs: u32 },
+    Drotrv { rd: u32, rt: u32, rs: u32 },
+
+    // multiplication and division, pre-R6 ones use HI and LO
+    Dmult { rs: u32, rt: u32 },
+    Dmultu { rs: u32, rt: u32 },
+    Ddiv { rs: u32, rt: u32 },
+    Ddivu { rs: u32, rt: u32 },
+    Dmul { rd: u32, rs: u32, rt: u32 },
+    Dmuh { rd: u32, rs: u32, rt: u32 },
+    Dmulu { rd: u32, rs: u32, rt: u32 },
+    Dmuhu { rd: u32, rs: u32, rt: u32 },
+    DdivR6 { rd: u32, rs: u32, rt: u32 },
+    Dmod { rd: u32, rs: u32, rt: u32 },
+    DdivuR6 { rd: u32, rs: u32, rt: u32 },
+    Dmodu { rd: u32, rs: u32, rt: u32 },
+
+    // bit field operations
+    Dext { rt: u32, rs: u32, pos: u32, size: u32 },
+    Dins { rt: u32, rs: u32, pos: u32, size: u32 },
+    Dbitswap { rd: u32, rt: u32 },
+    Dsbh { rd: u32, rt: u32 },
+    Dshd { rd: u32, rt: u32 },
+    Dalign { rd: u32, rs: u32, rt: u32, bp: u32 },
+
+    // loads and stores
+    Ld { rt: u32, base: u32, offset: i16 },
+    Lwu { rt: u32, base: u32, offset: i16 },
+    Ldl { rt: u32, base: u32, offset: i16 },
+    Ldr { rt: u32, base: u32, offset: i16 },
+    Lld { rt: u32, base: u32, offset: i16 },
+    Sd { rt: u32, base: u32, offset: i16 },
+    Sdl { rt: u32, base: u32, offset: i16 },
+    Sdr { rt: u32, base: u32, offset: i16 },
+    Scd { rt: u32, base: u32, offset: i16 },
+    Ldpc { rs: u32, offset: i32 },
+
+    // FPU moves
+    Dmfc1 { rt: u32, fs: u32 },
+    Dmtc1 { rt: u32, fs: u32 },
+}
+
+/// Checks whether the instruction is one of those decoded by `decode_doubleword_instruction`.
+pub fn is_doubleword_instruction(
+    opcode: &InstructionOpcode,
+    instruction: u32,
+    revision: IsaRevision,
+) -> bool {
+    let funct = get_funct(instruction);
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    match *opcode {
+        InstructionOpcode::DADDI |
+        InstructionOpcode::DADDIU |
+        InstructionOpcode::DAUI |
+        InstructionOpcode::LDL |
+        InstructionOpcode::LDR |
+        InstructionOpcode::LWU |
+        InstructionOpcode::SDL |
+        InstructionOpcode::SDR |
+        InstructionOpcode::LLD |
+        InstructionOpcode::SCD |
+        InstructionOpcode::LD |
+        InstructionOpcode::SD => true,
+        InstructionOpcode::SPECIAL => {
+            match funct {
+                0b010100 | 0b010110 | 0b010111 | 0b011100..=0b011111 | 0b101100..=0b101111 |
+                0b111000 | 0b111010 | 0b111011 | 0b111100 | 0b111110 | 0b111111 => true,
+                // DLSA, DCLZ, DCLO (R6)
+                0b010101 | 0b010010 | 0b010011 => revision.is_r6(),
+                _ => false,
+            }
+        }
+        // DCLZ, DCLO
+        InstructionOpcode::SPECIAL2 => funct == 0b100100 || funct == 0b100101,
+        InstructionOpcode::SPECIAL3 => {
+            match funct {
+                0b000001..=0b000011 | 0b000101..=0b000111 | 0b100100 => true,
+                // LLD, SCD (R6)
+                0b110111 | 0b100111 => revision.is_r6(),
+                _ => false,
+            }
+        }
+        // DAHI, DATI (R6)
+        InstructionOpcode::REGIMM => revision.is_r6() && (rt == 0b00110 || rt == 0b11110),
+        // LDPC
+        InstructionOpcode::PCREL => rt >> 2 == 0b110,
+        // DMFC1, DMTC1
+        InstructionOpcode::COP1 => rs == 0b00001 || rs == 0b00101,
+        _ => false,
+    }
+}
+
+/// Decodes a MIPS64 instruction recognized by `is_doubleword_instruction`
+pub fn decode_doubleword_instruction(
+    opcode: &InstructionOpcode,
+    instruction: u32,
+    revision: IsaRevision,
+) -> Result<DoublewordInstruction, DecodeError> {
+    let funct = get_funct(instruction);
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let rd = get_rd(instruction);
+    let sa = get_shift(instruction);
+    let immediate = get_offset(instruction);
+    let offset = immediate as i16;
+    let r6 = revision.is_r6();
+
+    macro_rules! load_store {
+        ($variant:ident) => (
+            DoublewordInstruction::$variant { rt, base: rs, offset }
+        );
+    }
+
+    let decoded = match *opcode {
+        InstructionOpcode::DADDI => DoublewordInstruction::Daddi { rt, rs, immediate: offset },
+        InstructionOpcode::DADDIU => DoublewordInstruction::Daddiu { rt, rs, immediate: offset },
+        // DAUI with rs zero is reserved (R6)
+        InstructionOpcode::DAUI if rs != 0 => DoublewordInstruction::Daui { rt, rs, immediate },
+        InstructionOpcode::LD => load_store!(Ld),
+        InstructionOpcode::LWU => load_store!(Lwu),
+        InstructionOpcode::LDL => load_store!(Ldl),
+        InstructionOpcode::LDR => load_store!(Ldr),
+        InstructionOpcode::LLD => load_store!(Lld),
+        InstructionOpcode::SD => load_store!(Sd),
+        InstructionOpcode::SDL => load_store!(Sdl),
+        InstructionOpcode::SDR => load_store!(Sdr),
+        InstructionOpcode::SCD => load_store!(Scd),
+        InstructionOpcode::SPECIAL => {
+            match funct {
+                0b010100 if sa == 0 => DoublewordInstruction::Dsllv { rd, rt, rs },
+                0b010110 if sa == 0 => DoublewordInstruction::Dsrlv { rd, rt, rs },
+                0b010110 if sa == 1 => DoublewordInstruction::Drotrv { rd, rt, rs },
+                0b010111 if sa == 0 => DoublewordInstruction::Dsrav { rd, rt, rs },
+                // DSLL, DSRL, DROTR, DSRA and their variants shifting by 32 more
+                0b111000..=0b111111 => {
+                    let shift = sa + if funct & 0b100 != 0 { 32 } else { 0 };
+                    match (funct & 0b11, rs) {
+                        (0b00, 0) => DoublewordInstruction::Dsll { rd, rt, shift },
+                        (0b10, 0) => DoublewordInstruction::Dsrl { rd, rt, shift },
+                        (0b10, 1) => DoublewordInstruction::Drotr { rd, rt, shift },
+                        (0b11, 0) => DoublewordInstruction::Dsra { rd, rt, shift },
+                        _ => return Err(DecodeError::Reserved),
+                    }
+                }
+                0b101100 => DoublewordInstruction::Dadd { rd, rs, rt },
+                0b101101 => DoublewordInstruction::Daddu { rd, rs, rt },
+                0b101110 => DoublewordInstruction::Dsub { rd, rs, rt },
+                0b101111 => DoublewordInstruction::Dsubu { rd, rs, rt },
+                // DMULT, DMULTU, DDIV, DDIVU (pre-R6)
+                0b011100..=0b011111 if sa == 0 && !r6 => {
+                    match funct {
+                        0b011100 => DoublewordInstruction::Dmult { rs, rt },
+                        0b011101 => DoublewordInstruction::Dmultu { rs, rt },
+                        0b011110 => DoublewordInstruction::Ddiv { rs, rt },
+                        _ => DoublewordInstruction::Ddivu { rs, rt },
+                    }
+                }
+                // DMUL, DMUH, DMULU, DMUHU, DDIV, DMOD, DDIVU, DMODU (R6)
+                0b011100..=0b011111 if (sa == 0b00010 || sa == 0b00011) && r6 => {
+                    match (funct, sa) {
+                        (0b011100, 0b00010) => DoublewordInstruction::Dmul { rd, rs, rt },
+                        (0b011100, _) => DoublewordInstruction::Dmuh { rd, rs, rt },
+                        (0b011101, 0b00010) => DoublewordInstruction::Dmulu { rd, rs, rt },
+                        (0b011101, _) => DoublewordInstruction::Dmuhu { rd, rs, rt },
+                        (0b011110, 0b00010) => DoublewordInstruction::DdivR6 { rd, rs, rt },
+                        (0b011110, _) => DoublewordInstruction::Dmod { rd, rs, rt },
+                        (_, 0b00010) => DoublewordInstruction::DdivuR6 { rd, rs, rt },
+                        _ => DoublewordInstruction::Dmodu { rd, rs, rt },
+                    }
+                }
+                // DLSA, DCLZ, DCLO (R6)
+                0b010101 => {
+                    let shift = ((instruction >> 6) & 0b11) + 1;
+                    DoublewordInstruction::Dlsa { rd, rs, rt, shift }
+                }
+                0b010010 if sa == 0b00001 => DoublewordInstruction::Dclz { rd, rs },
+                0b010011 if sa == 0b00001 => DoublewordInstruction::Dclo { rd, rs },
+                _ => return Err(DecodeError::Reserved),
+            }
+        }
+        // DCLZ, DCLO (pre-R6)
+        InstructionOpcode::SPECIAL2 if funct == 0b100100 => DoublewordInstruction::Dclz { rd, rs },
+        InstructionOpcode::SPECIAL2 => DoublewordInstruction::Dclo { rd, rs },
+        InstructionOpcode::SPECIAL3 => {
+            match funct {
+                // DEXTM, DEXTU, DEXT, fields not fitting into the register are reserved
+                0b000001..=0b000011 => {
+                    let (pos, size) = match funct {
+                        0b000001 => (sa, rd + 33),
+                        0b000010 => (sa + 32, rd + 1),
+                        _ => (sa, rd + 1),
+                    };
+                    if pos + size > 64 {
+                        return Err(DecodeError::Reserved);
+                    }
+                    DoublewordInstruction::Dext { rt, rs, pos, size }
+                }
+                // DINSM, DINSU, DINS
+                0b000101..=0b000111 => {
+                    let (lsb, msb) = match funct {
+                        0b000101 => (sa, rd + 32),
+                        0b000110 => (sa + 32, rd + 32),
+                        _ => (sa, rd),
+                    };
+                    if msb < lsb {
+                        return Err(DecodeError::Reserved);
+                    }
+                    DoublewordInstruction::Dins { rt, rs, pos: lsb, size: msb + 1 - lsb }
+                }
+                // DBSHFL
+                0b100100 => {
+                    match sa {
+                        0b00000 if r6 => DoublewordInstruction::Dbitswap { rd, rt },
+                        0b00010 => DoublewordInstruction::Dsbh { rd, rt },
+                        0b00101 => DoublewordInstruction::Dshd { rd, rt },
+                        0b01000..=0b01111 if r6 => {
+                            DoublewordInstruction::Dalign { rd, rs, rt, bp: sa & 0b111 }
+                        }
+                        _ => return Err(DecodeError::Reserved),
+                    }
+                }
+                // LLD, SCD with 9 bit offset (R6)
+                _ if instruction & 0x40 == 0 => {
+                    let offset = sign_extend((instruction >> 7) & 0x1FF, 9) as i16;
+                    if funct == 0b110111 {
+                        DoublewordInstruction::Lld { rt, base: rs, offset }
+                    } else {
+                        DoublewordInstruction::Scd { rt, base: rs, offset }
+                    }
+                }
+                _ => return Err(DecodeError::Reserved),
+            }
+        }
+        // DAHI, DATI (R6), rs is both the source and the destination
+        InstructionOpcode::REGIMM if rt == 0b00110 => DoublewordInstruction::Dahi { rs, immediate },
+        InstructionOpcode::REGIMM => DoublewordInstruction::Dati { rs, immediate },
+        // LDPC (R6)
+        InstructionOpcode::PCREL => {
+            let offset = sign_extend((instruction & 0x3_FF_FF) << 3, 21);
+            DoublewordInstruction::Ldpc { rs, offset }
+        }
+        InstructionOpcode::COP1 if rs == 0b00001 => DoublewordInstruction::Dmfc1 { rt, fs: rd },
+        InstructionOpcode::COP1 => DoublewordInstruction::Dmtc1 { rt, fs: rd },
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+/// LLD with the given effective address, the doubleword variant of LL
+fn load_linked(address: u32, rt: u32, registers: &mut RegisterFile, memory: &mut Memory) {
+    let r = memory.read_doubleword(address);
+    trace!(
+        "0x{:x}:\tlld\t{},0x{:x} - data=0x{:016x}",
+        registers.get_pc(),
+        get_register_name(rt),
+        address,
+        r
+    );
+    registers.write_register64(rt, r);
+    registers.set_link(address);
+    memory.monitor_line(address);
+}
+
+/// SCD with the given effective address, it succeeds under the same conditions as SC
+fn store_conditional(address: u32, rt: u32, registers: &mut RegisterFile, memory: &mut Memory) {
+    let linked = registers.get_link().is_some_and(|link| {
+        link & !(LINK_LINE_SIZE - 1) == address & !(LINK_LINE_SIZE - 1)
+    }) && memory.is_line_monitored(address);
+    trace!(
+        "0x{:x}:\tscd\t{},0x{:x} - data=0x{:016x} success={}",
+        registers.get_pc(),
+        get_register_name(rt),
+        address,
+        registers.read_register64(rt),
+        linked
+    );
+    if linked {
+        let value = registers.read_register64(rt);
+        memory.write_doubleword(address, value);
+    }
+    registers.clear_link();
+    memory.clear_monitor();
+    registers.write_register(rt, linked as u32);
+}
+
+/// Evaluates a MIPS64 instruction decoded by `decode_doubleword_instruction`.
+pub fn eval_doubleword_instruction(
+    decoded: DoublewordInstruction,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+) -> CPUEvent {
+    macro_rules! itrace {
+        ($fmt:expr, $($arg:tt)*) => (
+            trace!(concat!("0x{:x}:\t", $fmt), registers.get_pc(), $($arg)*);
+        );
+    }
+
+    let mut result_cpu_event = CPUEvent::Nothing;
+
+    // Checks, that the program may access `size` bytes at the address
+    macro_rules! check_access {
+        ($address:expr, $size:expr, $access:expr) => ({
+            let address = $address;
+            if address >= KERNEL_SEGMENT_START && registers.get_abi() != Abi::N64 {
+                itrace!("address error at 0x{:08x}", address);
+                return CPUEvent::Exception(ExceptionKind::AddressError(address));
+            }
+            if let Err(fault) = memory.check_access(address, $size, $access) {
+                itrace!("segmentation fault at 0x{:08x}", address);
+                return CPUEvent::Exception(fault);
+            }
+            address
+        });
+    }
+
+    // Effective address of a load or store. n32 programs can't access the kernel segment, n64
+    // ones only see their own address window.
+    macro_rules! effective_address {
+        ($base:expr, $offset:expr, $size:expr, $access:expr) => ({
+            check_access!(
+                registers.read_register($base).wrapping_add($offset as u32),
+                $size,
+                $access
+            )
+        });
+    }
+
+    match decoded {
+        // DADDI (pre-R6)
+        DoublewordInstruction::Daddi { rt, rs, immediate } => {
+            itrace!("daddi\t{},{},{}", get_register_name(rt), get_register_name(rs), immediate);
+            let a = registers.read_register64(rs);
+            let (r, overflow) = (a as i64).overflowing_add(immediate as i64);
+            if overflow {
+                return CPUEvent::Exception(ExceptionKind::IntegerOverflow);
+            }
+            registers.write_register64(rt, r as u64);
+        }
+        DoublewordInstruction::Daddiu { rt, rs, immediate } => {
+            let r = registers.read_register64(rs).wrapping_add(immediate as i64 as u64);
+            itrace!(
+                "daddiu\t{},{},{} - res=0x{:016x}",
+                get_register_name(rt),
+                get_register_name(rs),
+                immediate,
+                r
+            );
+            registers.write_register64(rt, r);
+        }
+        // DAUI, DAHI, DATI (R6)
+        DoublewordInstruction::Daui { rt, rs, immediate } => {
+            let r = registers.read_register64(rs).wrapping_add(
+                ((immediate as i16 as i64) << 16) as u64,
+            );
+            itrace!("daui\t{},{},0x{:x}", get_register_name(rt), get_register_name(rs), immediate);
+            registers.write_register64(rt, r);
+        }
+        DoublewordInstruction::Dahi { rs, immediate } |
+        DoublewordInstruction::Dati { rs, immediate } => {
+            let (name, shift) = match decoded {
+                DoublewordInstruction::Dahi { .. } => ("dahi", 32),
+                _ => ("dati", 48),
+            };
+            itrace!("{}\t{},0x{:x}", name, get_register_name(rs), immediate);
+            let r = registers.read_register64(rs).wrapping_add(
+                ((immediate as i16 as i64) << shift) as u64,
+            );
+            registers.write_register64(rs, r);
+        }
+        // DADD, DADDU, DSUB, DSUBU
+        DoublewordInstruction::Dadd { rd, rs, rt } |
+        DoublewordInstruction::Daddu { rd, rs, rt } |
+        DoublewordInstruction::Dsub { rd, rs, rt } |
+        DoublewordInstruction::Dsubu { rd, rs, rt } => {
+            let a = registers.read_register64(rs) as i64;
+            let b = registers.read_register64(rt) as i64;
+            let (name, (r, overflow)) = match decoded {
+                DoublewordInstruction::Dadd { .. } => ("dadd", a.overflowing_add(b)),
+                DoublewordInstruction::Daddu { .. } => ("daddu", (a.wrapping_add(b), false)),
+                DoublewordInstruction::Dsub { .. } => ("dsub", a.overflowing_sub(b)),
+                _ => ("dsubu", (a.wrapping_sub(b), false)),
+            };
+            itrace!(
+                "{}\t{},{},{} - res=0x{:016x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            if overflow {
+                return CPUEvent::Exception(ExceptionKind::IntegerOverflow);
+            }
+            registers.write_register64(rd, r as u64);
+        }
+        // DLSA (R6)
+        DoublewordInstruction::Dlsa { rd, rs, rt, shift } => {
+            let r = (registers.read_register64(rs) << shift)
+                .wrapping_add(registers.read_register64(rt));
+            itrace!(
+                "dlsa\t{},{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                shift
+            );
+            registers.write_register64(rd, r);
+        }
+        // DCLZ, DCLO
+        DoublewordInstruction::Dclz { rd, rs } |
+        DoublewordInstruction::Dclo { rd, rs } => {
+            let clz = matches!(decoded, DoublewordInstruction::Dclz { .. });
+            let a = registers.read_register64(rs);
+            let r = if clz { a.leading_zeros() } else { (!a).leading_zeros() };
+            itrace!(
+                "{}\t{},{} - res={}",
+                if clz { "dclz" } else { "dclo" },
+                get_register_name(rd),
+                get_register_name(rs),
+                r
+            );
+            registers.write_register(rd, r);
+        }
+        // DSLL, DSRL, DSRA, DROTR and their variants shifting by 32 more
+        DoublewordInstruction::Dsll { rd, rt, shift } |
+        DoublewordInstruction::Dsrl { rd, rt, shift } |
+        DoublewordInstruction::Dsra { rd, rt, shift } |
+        DoublewordInstruction::Drotr { rd, rt, shift } => {
+            let b = registers.read_register64(rt);
+            let (name, r) = match decoded {
+                DoublewordInstruction::Dsll { .. } => ("dsll", b << shift),
+                DoublewordInstruction::Dsrl { .. } => ("dsrl", b >> shift),
+                DoublewordInstruction::Dsra { .. } => ("dsra", ((b as i64) >> shift) as u64),
+                _ => ("drotr", b.rotate_right(shift)),
+            };
+            itrace!(
+                "{}\t{},{},{}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                shift
+            );
+            registers.write_register64(rd, r);
+        }
+        // DSLLV, DSRLV, DSRAV, DROTRV
+        DoublewordInstruction::Dsllv { rd, rt, rs } |
+        DoublewordInstruction::Dsrlv { rd, rt, rs } |
+        DoublewordInstruction::Dsrav { rd, rt, rs } |
+        DoublewordInstruction::Drotrv { rd, rt, rs } => {
+            let shift = (registers.read_register64(rs) & 0x3F) as u32;
+            let b = registers.read_register64(rt);
+            let (name, r) = match decoded {
+                DoublewordInstruction::Dsllv { .. } => ("dsllv", b << shift),
+                DoublewordInstruction::Dsrlv { .. } => ("dsrlv", b >> shift),
+                DoublewordInstruction::Dsrav { .. } => ("dsrav", ((b as i64) >> shift) as u64),
+                _ => ("drotrv", b.rotate_right(shift)),
+            };
+            itrace!(
+                "{}\t{},{},{}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                get_register_name(rs)
+            );
+            registers.write_register64(rd, r);
+        }
+        // DMULT, DMULTU, DDIV, DDIVU (pre-R6)
+        DoublewordInstruction::Dmult { rs, rt } |
+        DoublewordInstruction::Dmultu { rs, rt } |
+        DoublewordInstruction::Ddiv { rs, rt } |
+        DoublewordInstruction::Ddivu { rs, rt } => {
+            let (name, divide, unsigned) = match decoded {
+                DoublewordInstruction::Dmult { .. } => ("dmult", false, false),
+                DoublewordInstruction::Dmultu { .. } => ("dmultu", false, true),
+                DoublewordInstruction::Ddiv { .. } => ("ddiv", true, false),
+                _ => ("ddivu", true, true),
+            };
+            itrace!("{}\t{},{}", name, get_register_name(rs), get_register_name(rt));
+            let (a, b) = (registers.read_register64(rs), registers.read_register64(rt));
+            if !divide {
+                let r = if unsigned {
+                    (a as u128) * (b as u128)
+                } else {
+                    ((a as i64 as i128) * (b as i64 as i128)) as u128
+                };
+                registers.write_hi64(0, (r >> 64) as u64);
+                registers.write_lo64(0, r as u64);
+            } else {
+                // division by zero leaves HI and LO untouched, as with DIV
+                let result = if unsigned {
+                    a.checked_div(b).zip(a.checked_rem(b))
+                } else if b != 0 {
+                    let (a, b) = (a as i64, b as i64);
+                    Some((a.wrapping_div(b) as u64, a.wrapping_rem(b) as u64))
+                } else {
+                    None
+                };
+                if let Some((quotient, remainder)) = result {
+                    registers.write_hi64(0, remainder);
+                    registers.write_lo64(0, quotient);
+                }
+            }
+        }
+        // DMUL, DMUH, DMULU, DMUHU, DDIV, DMOD, DDIVU, DMODU (R6)
+        DoublewordInstruction::Dmul { rd, rs, rt } |
+        DoublewordInstruction::Dmuh { rd, rs, rt } |
+        DoublewordInstruction::Dmulu { rd, rs, rt } |
+        DoublewordInstruction::Dmuhu { rd, rs, rt } |
+        DoublewordInstruction::DdivR6 { rd, rs, rt } |
+        DoublewordInstruction::Dmod { rd, rs, rt } |
+        DoublewordInstruction::DdivuR6 { rd, rs, rt } |
+        DoublewordInstruction::Dmodu { rd, rs, rt } => {
+            let (a, b) = (registers.read_register64(rs), registers.read_register64(rt));
+            let (sa, sb) = (a as i64, b as i64);
+            let (name, r) = match decoded {
+                DoublewordInstruction::Dmul { .. } => ("dmul", sa.wrapping_mul(sb) as u64),
+                DoublewordInstruction::Dmuh { .. } => {
+                    ("dmuh", ((sa as i128 * sb as i128) >> 64) as u64)
+                }
+                DoublewordInstruction::Dmulu { .. } => ("dmulu", a.wrapping_mul(b)),
+                DoublewordInstruction::Dmuhu { .. } => {
+                    ("dmuhu", ((a as u128 * b as u128) >> 64) as u64)
+                }
+                // results of division by zero are UNPREDICTABLE, zero is used
+                DoublewordInstruction::DdivR6 { .. } if b == 0 => ("ddiv", 0),
+                DoublewordInstruction::Dmod { .. } if b == 0 => ("dmod", 0),
+                DoublewordInstruction::DdivuR6 { .. } if b == 0 => ("ddivu", 0),
+                DoublewordInstruction::Dmodu { .. } if b == 0 => ("dmodu", 0),
+                DoublewordInstruction::DdivR6 { .. } => ("ddiv", sa.wrapping_div(sb) as u64),
+                DoublewordInstruction::Dmod { .. } => ("dmod", sa.wrapping_rem(sb) as u64),
+                DoublewordInstruction::DdivuR6 { .. } => ("ddivu", a / b),
+                _ => ("dmodu", a % b),
+            };
+            itrace!(
+                "{}\t{},{},{} - res=0x{:016x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            registers.write_register64(rd, r);
+        }
+        // DEXT, DEXTM, DEXTU
+        DoublewordInstruction::Dext { rt, rs, pos, size } => {
+            itrace!(
+                "dext\t{},{},pos={},size={}",
+                get_register_name(rt),
+                get_register_name(rs),
+                pos,
+                size
+            );
+            let a = registers.read_register64(rs);
+            registers.write_register64(rt, (a >> pos) & bit_mask64(size));
+        }
+        // DINS, DINSM, DINSU
+        DoublewordInstruction::Dins { rt, rs, pos, size } => {
+            itrace!(
+                "dins\t{},{},pos={},size={}",
+                get_register_name(rt),
+                get_register_name(rs),
+                pos,
+                size
+            );
+            let (a, b) = (registers.read_register64(rs), registers.read_register64(rt));
+            let mask = bit_mask64(size) << pos;
+            registers.write_register64(rt, (b & !mask) | ((a << pos) & mask));
+        }
+        // DBITSWAP (R6), DSBH, DSHD
+        DoublewordInstruction::Dbitswap { rd, rt } |
+        DoublewordInstruction::Dsbh { rd, rt } |
+        DoublewordInstruction::Dshd { rd, rt } => {
+            let b = registers.read_register64(rt);
+            let (name, r) = match decoded {
+                DoublewordInstruction::Dbitswap { .. } => {
+                    let r = (0..8).fold(0, |r, i| {
+                        r | ((((b >> (8 * i)) as u8).reverse_bits() as u64) << (8 * i))
+                    });
+                    ("dbitswap", r)
+                }
+                DoublewordInstruction::Dsbh { .. } => {
+                    let mask = 0x00FF_00FF_00FF_00FF;
+                    ("dsbh", ((b & mask) << 8) | ((b >> 8) & mask))
+                }
+                _ => {
+                    let mask = 0x0000_FFFF_0000_FFFF;
+                    ("dshd", (((b & mask) << 16) | ((b >> 16) & mask)).rotate_left(32))
+                }
+            };
+            itrace!("{}\t{},{}", name, get_register_name(rd), get_register_name(rt));
+            registers.write_register64(rd, r);
+        }
+        // DALIGN (R6)
+        DoublewordInstruction::Dalign { rd, rs, rt, bp } => {
+            itrace!(
+                "dalign\t{},{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                bp
+            );
+            let (a, b) = (registers.read_register64(rs), registers.read_register64(rt));
+            let r = if bp == 0 { b } else { (b << (8 * bp)) | (a >> (64 - 8 * bp)) };
+            registers.write_register64(rd, r);
+        }
+        // LD, LWU
+        DoublewordInstruction::Ld { rt, base, offset } |
+        DoublewordInstruction::Lwu { rt, base, offset } => {
+            let doubleword = matches!(decoded, DoublewordInstruction::Ld { .. });
+            let size = if doubleword { 8 } else { 4 };
+            let address = effective_address!(base, offset, size, Access::Read);
+            let (name, r) = if doubleword {
+                ("ld", memory.read_doubleword(address))
+            } else {
+                ("lwu", memory.read_word(address) as u64)
+            };
+            itrace!(
+                "{}\t{},0x{:x} - data=0x{:016x}",
+                name,
+                get_register_name(rt),
+                address,
+                r
+            );
+            registers.write_register64(rt, r);
+        }
+        DoublewordInstruction::Sd { rt, base, offset } => {
+            let address = effective_address!(base, offset, 8, Access::Write);
+            let b = registers.read_register64(rt);
+            itrace!("sd\t{},0x{:x} - data=0x{:016x}", get_register_name(rt), address, b);
+            memory.write_doubleword(address, b);
+        }
+        // LDL, LDR (pre-R6)
+        DoublewordInstruction::Ldl { rt, base, offset } |
+        DoublewordInstruction::Ldr { rt, base, offset } => {
+            let address = effective_address!(base, offset, 1, Access::Read);
+            let b = registers.read_register64(rt);
+            let (name, r) = if matches!(decoded, DoublewordInstruction::Ldl { .. }) {
+                ("ldl", memory.read_doubleword_unaligned_ldl(address, b))
+            } else {
+                ("ldr", memory.read_doubleword_unaligned_ldr(address, b))
+            };
+            itrace!(
+                "{}\t{},0x{:x} - res=0x{:016x}",
+                name,
+                get_register_name(rt),
+                address,
+                r
+            );
+            registers.write_register64(rt, r);
+        }
+        // SDL, SDR (pre-R6)
+        DoublewordInstruction::Sdl { rt, base, offset } |
+        DoublewordInstruction::Sdr { rt, base, offset } => {
+            let address = effective_address!(base, offset, 1, Access::Write);
+            let left = matches!(decoded, DoublewordInstruction::Sdl { .. });
+            let b = registers.read_register64(rt);
+            itrace!(
+                "{}\t{},0x{:x} - data=0x{:016x} (only part of the data will be stored)",
+                if left { "sdl" } else { "sdr" },
+                get_register_name(rt),
+                address,
+                b
+            );
+            if left {
+                memory.write_doubleword_unaligned_sdl(address, b);
+            } else {
+                memory.write_doubleword_unaligned_sdr(address, b);
+            }
+        }
+        DoublewordInstruction::Lld { rt, base, offset } => {
+            load_linked(effective_address!(base, offset, 8, Access::Read), rt, registers, memory);
+            result_cpu_event = CPUEvent::AtomicLoadModifyWriteBegan;
+        }
+        DoublewordInstruction::Scd { rt, base, offset } => {
+            let address = effective_address!(base, offset, 8, Access::Write);
+            store_conditional(address, rt, registers, memory);
+        }
+        // LDPC (R6)
+        DoublewordInstruction::Ldpc { rs, offset } => {
+            let address = check_access!(
+                (registers.get_pc() & !0b111).wrapping_add(offset as u32),
+                8,
+                Access::Read
+            );
+            let r = memory.read_doubleword(address);
+            itrace!(
+                "ldpc\t{},mem[0x{:x}] - data=0x{:016x}",
+                get_register_name(rs),
+                address,
+                r
+            );
+            registers.write_register64(rs, r);
+        }
+        DoublewordInstruction::Dmfc1 { rt, fs } => {
+            let r = registers.read_fpr_doubleword(fs);
+            itrace!("dmfc1\t{},f{} - data=0x{:016x}", get_register_name(rt), fs, r);
+            registers.write_register64(rt, r);
+        }
+        DoublewordInstruction::Dmtc1 { rt, fs } => {
+            let b = registers.read_register64(rt);
+            itrace!("dmtc1\t{},f{} - data=0x{:016x}", get_register_name(rt), fs, b);
+            registers.write_fpr_doubleword(fs, b);
+        }
+    }
+
+    result_cpu_event
+}
+
+#[test]
+fn test_bit_mask64() {
+    assert_eq!(bit_mask64(0), 0);
+    assert_eq!(bit_mask64(33), 0x1_FFFF_FFFF);
+    assert_eq!(bit_mask64(64), u64::MAX);
+}
@@ -0,0 +1,1925 @@
+//! DSP ASE revision 2 as implemented by the 74Kc. Instructions operate on SIMD vectors held in
+//! GPRs, either two Q15 or unsigned halfwords (PH) or four unsigned bytes (QB), and on the
+//! accumulators ac0-ac3.
+//! Overflows, comparison results and the bit position used by extractions are kept in the
+//! DSPControl register.
+
+use cpu::bitutils::{bit_mask, get_funct, get_rd, get_rs, get_rt, get_shift, sign_extend};
+use cpu::decoder::DecodeError;
+use cpu::event::{CPUEvent, ExceptionKind};
+use cpu::registers::{get_register_name, RegisterFile};
+use memory::{Access, Memory, KERNEL_SEGMENT_START};
+
+// SPECIAL3 functs of the DSP instruction groups
+const FUNCT_LX: u32 = 0b001010;
+const FUNCT_INSV: u32 = 0b001100;
+const FUNCT_ADDU_QB: u32 = 0b010000;
+const FUNCT_CMPU_EQ_QB: u32 = 0b010001;
+const FUNCT_ABSQ_S_PH: u32 = 0b010010;
+const FUNCT_SHLL_QB: u32 = 0b010011;
+const FUNCT_ADDUH_QB: u32 = 0b011000;
+const FUNCT_DPA_W_PH: u32 = 0b110000;
+const FUNCT_APPEND: u32 = 0b110001;
+const FUNCT_EXTR_W: u32 = 0b111000;
+
+/// SPECIAL3 functs decoded by `decode_dsp_instruction`
+pub const DSP_FUNCTS: [u32; 10] = [
+    FUNCT_LX,
+    FUNCT_INSV,
+    FUNCT_ADDU_QB,
+    FUNCT_CMPU_EQ_QB,
+    FUNCT_ABSQ_S_PH,
+    FUNCT_SHLL_QB,
+    FUNCT_ADDUH_QB,
+    FUNCT_DPA_W_PH,
+    FUNCT_APPEND,
+    FUNCT_EXTR_W,
+];
+
+// DSPControl fields
+pub const DSP_CONTROL_POS: u32 = 0x3F;
+const SCOUNT_SHIFT: u32 = 7;
+const SCOUNT: u32 = 0x3F << SCOUNT_SHIFT;
+const CARRY: u32 = 1 << 13;
+const EFI: u32 = 1 << 14;
+const OUFLAG: u32 = 0xFF << 16;
+const CCOND_SHIFT: u32 = 24;
+const CCOND: u32 = 0xF << CCOND_SHIFT;
+
+/// DSPControl fields in the order of RDDSP and WRDSP mask bits
+const MASK_FIELDS: [u32; 6] = [DSP_CONTROL_POS, SCOUNT, CARRY, OUFLAG, CCOND, EFI];
+
+// ouflag bits, accumulator overflows are at 16 + ac
+const OUFLAG_ACCUMULATOR: u32 = 16;
+const OUFLAG_ADD: u32 = 20;
+const OUFLAG_MULTIPLY: u32 = 21;
+const OUFLAG_SHIFT: u32 = 22;
+const OUFLAG_EXTRACT: u32 = 23;
+
+/// Decoded DSP ASE instruction. `ac` is one of the accumulators ac0-ac3, the V variants of
+/// shifts and extractions take the amount from the `rs` register instead of an immediate. The
+/// halving additions (`h`), MUL and the cross dot products (`x`) are new in revision 2.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DspInstruction {
+    // indexed loads and INSV
+    Lwx { rd: u32, base: u32, index: u32 },
+    Lhx { rd: u32, base: u32, index: u32 },
+    Lbux { rd: u32, base: u32, index: u32 },
+    Insv { rt: u32, rs: u32 },
+
+    // additions, subtractions and multiplications writing a GPR
+    AdduQb { rd: u32, rs: u32, rt: u32 },
+    SubuQb { rd: u32, rs: u32, rt: u32 },
+    AdduSQb { rd: u32, rs: u32, rt: u32 },
+    SubuSQb { rd: u32, rs: u32, rt: u32 },
+    MuleuSPhQbl { rd: u32, rs: u32, rt: u32 },
+    MuleuSPhQbr { rd: u32, rs: u32, rt: u32 },
+    AddqPh { rd: u32, rs: u32, rt: u32 },
+    SubqPh { rd: u32, rs: u32, rt: u32 },
+    AddqSPh { rd: u32, rs: u32, rt: u32 },
+    SubqSPh { rd: u32, rs: u32, rt: u32 },
+    Addsc { rd: u32, rs: u32, rt: u32 },
+    Addwc { rd: u32, rs: u32, rt: u32 },
+    Modsub { rd: u32, rs: u32, rt: u32 },
+    AddqSW { rd: u32, rs: u32, rt: u32 },
+    SubqSW { rd: u32, rs: u32, rt: u32 },
+    MuleqSWPhl { rd: u32, rs: u32, rt: u32 },
+    MuleqSWPhr { rd: u32, rs: u32, rt: u32 },
+    MulqRsPh { rd: u32, rs: u32, rt: u32 },
+    RadduWQb { rd: u32, rs: u32 },
+    AdduPh { rd: u32, rs: u32, rt: u32 },
+    SubuPh { rd: u32, rs: u32, rt: u32 },
+    AdduSPh { rd: u32, rs: u32, rt: u32 },
+    SubuSPh { rd: u32, rs: u32, rt: u32 },
+    MulqSPh { rd: u32, rs: u32, rt: u32 },
+    AdduhQb { rd: u32, rs: u32, rt: u32 },
+    AdduhRQb { rd: u32, rs: u32, rt: u32 },
+    SubuhQb { rd: u32, rs: u32, rt: u32 },
+    SubuhRQb { rd: u32, rs: u32, rt: u32 },
+    AddqhPh { rd: u32, rs: u32, rt: u32 },
+    AddqhRPh { rd: u32, rs: u32, rt: u32 },
+    SubqhPh { rd: u32, rs: u32, rt: u32 },
+    SubqhRPh { rd: u32, rs: u32, rt: u32 },
+    AddqhW { rd: u32, rs: u32, rt: u32 },
+    AddqhRW { rd: u32, rs: u32, rt: u32 },
+    SubqhW { rd: u32, rs: u32, rt: u32 },
+    SubqhRW { rd: u32, rs: u32, rt: u32 },
+    MulPh { rd: u32, rs: u32, rt: u32 },
+    MulSPh { rd: u32, rs: u32, rt: u32 },
+    MulqSW { rd: u32, rs: u32, rt: u32 },
+    MulqRsW { rd: u32, rs: u32, rt: u32 },
+
+    // comparisons, picks and precision reductions
+    CmpuEqQb { rs: u32, rt: u32 },
+    CmpuLtQb { rs: u32, rt: u32 },
+    CmpuLeQb { rs: u32, rt: u32 },
+    CmpEqPh { rs: u32, rt: u32 },
+    CmpLtPh { rs: u32, rt: u32 },
+    CmpLePh { rs: u32, rt: u32 },
+    CmpguEqQb { rd: u32, rs: u32, rt: u32 },
+    CmpguLtQb { rd: u32, rs: u32, rt: u32 },
+    CmpguLeQb { rd: u32, rs: u32, rt: u32 },
+    PickQb { rd: u32, rs: u32, rt: u32 },
+    PickPh { rd: u32, rs: u32, rt: u32 },
+    PrecrqQbPh { rd: u32, rs: u32, rt: u32 },
+    PackrlPh { rd: u32, rs: u32, rt: u32 },
+    PrecrquSQbPh { rd: u32, rs: u32, rt: u32 },
+    PrecrqPhW { rd: u32, rs: u32, rt: u32 },
+    PrecrqRsPhW { rd: u32, rs: u32, rt: u32 },
+    CmpgduEqQb { rd: u32, rs: u32, rt: u32 },
+    CmpgduLtQb { rd: u32, rs: u32, rt: u32 },
+    CmpgduLeQb { rd: u32, rs: u32, rt: u32 },
+    PrecrQbPh { rd: u32, rs: u32, rt: u32 },
+    PrecrSraPhW { rt: u32, rs: u32, shift: u32 },
+    PrecrSraRPhW { rt: u32, rs: u32, shift: u32 },
+
+    // absolute values, replications, precision expansions and BITREV
+    ReplQb { rd: u32, immediate: u32 },
+    ReplPh { rd: u32, immediate: i16 },
+    ReplvQb { rd: u32, rt: u32 },
+    ReplvPh { rd: u32, rt: u32 },
+    PrecequPhQbl { rd: u32, rt: u32 },
+    PrecequPhQbr { rd: u32, rt: u32 },
+    PrecequPhQbla { rd: u32, rt: u32 },
+    PrecequPhQbra { rd: u32, rt: u32 },
+    PreceuPhQbl { rd: u32, rt: u32 },
+    PreceuPhQbr { rd: u32, rt: u32 },
+    PreceuPhQbla { rd: u32, rt: u32 },
+    PreceuPhQbra { rd: u32, rt: u32 },
+    PreceqWPhl { rd: u32, rt: u32 },
+    PreceqWPhr { rd: u32, rt: u32 },
+    AbsqSPh { rd: u32, rt: u32 },
+    AbsqSW { rd: u32, rt: u32 },
+    Bitrev { rd: u32, rt: u32 },
+    AbsqSQb { rd: u32, rt: u32 },
+
+    // shifts
+    ShllQb { rd: u32, rt: u32, shift: u32 },
+    ShrlQb { rd: u32, rt: u32, shift: u32 },
+    ShllvQb { rd: u32, rt: u32, rs: u32 },
+    ShrlvQb { rd: u32, rt: u32, rs: u32 },
+    ShllPh { rd: u32, rt: u32, shift: u32 },
+    ShraPh { rd: u32, rt: u32, shift: u32 },
+    ShllSPh { rd: u32, rt: u32, shift: u32 },
+    ShraRPh { rd: u32, rt: u32, shift: u32 },
+    ShllvPh { rd: u32, rt: u32, rs: u32 },
+    ShravPh { rd: u32, rt: u32, rs: u32 },
+    ShllvSPh { rd: u32, rt: u32, rs: u32 },
+    ShravRPh { rd: u32, rt: u32, rs: u32 },
+    ShllSW { rd: u32, rt: u32, shift: u32 },
+    ShraRW { rd: u32, rt: u32, shift: u32 },
+    ShllvSW { rd: u32, rt: u32, rs: u32 },
+    ShravRW { rd: u32, rt: u32, rs: u32 },
+    ShraQb { rd: u32, rt: u32, shift: u32 },
+    ShraRQb { rd: u32, rt: u32, shift: u32 },
+    ShravQb { rd: u32, rt: u32, rs: u32 },
+    ShravRQb { rd: u32, rt: u32, rs: u32 },
+    ShrlPh { rd: u32, rt: u32, shift: u32 },
+    ShrlvPh { rd: u32, rt: u32, rs: u32 },
+
+    // dot products and multiply-accumulate
+    DpauHQbl { ac: u32, rs: u32, rt: u32 },
+    DpauHQbr { ac: u32, rs: u32, rt: u32 },
+    DpsuHQbl { ac: u32, rs: u32, rt: u32 },
+    DpsuHQbr { ac: u32, rs: u32, rt: u32 },
+    DpaqSWPh { ac: u32, rs: u32, rt: u32 },
+    DpsqSWPh { ac: u32, rs: u32, rt: u32 },
+    MulsaqSWPh { ac: u32, rs: u32, rt: u32 },
+    DpaqSaLW { ac: u32, rs: u32, rt: u32 },
+    DpsqSaLW { ac: u32, rs: u32, rt: u32 },
+    MaqSaWPhl { ac: u32, rs: u32, rt: u32 },
+    MaqSaWPhr { ac: u32, rs: u32, rt: u32 },
+    MaqSWPhl { ac: u32, rs: u32, rt: u32 },
+    MaqSWPhr { ac: u32, rs: u32, rt: u32 },
+    DpaWPh { ac: u32, rs: u32, rt: u32 },
+    DpsWPh { ac: u32, rs: u32, rt: u32 },
+    DpaxWPh { ac: u32, rs: u32, rt: u32 },
+    DpsxWPh { ac: u32, rs: u32, rt: u32 },
+    DpaqxSWPh { ac: u32, rs: u32, rt: u32 },
+    DpaqxSaWPh { ac: u32, rs: u32, rt: u32 },
+    DpsqxSWPh { ac: u32, rs: u32, rt: u32 },
+    DpsqxSaWPh { ac: u32, rs: u32, rt: u32 },
+    MulsaWPh { ac: u32, rs: u32, rt: u32 },
+
+    // bit field appends and byte alignment
+    Append { rt: u32, rs: u32, shift: u32 },
+    Prepend { rt: u32, rs: u32, shift: u32 },
+    Balign { rt: u32, rs: u32, bp: u32 },
+
+    // extractions from the accumulators and DSPControl access
+    ExtrW { rt: u32, ac: u32, shift: u32 },
+    ExtrRW { rt: u32, ac: u32, shift: u32 },
+    ExtrRsW { rt: u32, ac: u32, shift: u32 },
+    ExtrSH { rt: u32, ac: u32, shift: u32 },
+    ExtrvW { rt: u32, ac: u32, rs: u32 },
+    ExtrvRW { rt: u32, ac: u32, rs: u32 },
+    ExtrvRsW { rt: u32, ac: u32, rs: u32 },
+    ExtrvSH { rt: u32, ac: u32, rs: u32 },
+    Extp { rt: u32, ac: u32, size: u32 },
+    Extpdp { rt: u32, ac: u32, size: u32 },
+    Extpv { rt: u32, ac: u32, rs: u32 },
+    Extpdpv { rt: u32, ac: u32, rs: u32 },
+    Shilo { ac: u32, shift: i32 },
+    Shilov { ac: u32, rs: u32 },
+    Mthlip { rs: u32, ac: u32 },
+    Rddsp { rd: u32, mask: u32 },
+    Wrdsp { rs: u32, mask: u32 },
+}
+
+impl DspInstruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            DspInstruction::Lwx { .. } => "lwx",
+            DspInstruction::Lhx { .. } => "lhx",
+            DspInstruction::Lbux { .. } => "lbux",
+            DspInstruction::Insv { .. } => "insv",
+            DspInstruction::AdduQb { .. } => "addu.qb",
+            DspInstruction::SubuQb { .. } => "subu.qb",
+            DspInstruction::AdduSQb { .. } => "addu_s.qb",
+            DspInstruction::SubuSQb { .. } => "subu_s.qb",
+            DspInstruction::MuleuSPhQbl { .. } => "muleu_s.ph.qbl",
+            DspInstruction::MuleuSPhQbr { .. } => "muleu_s.ph.qbr",
+            DspInstruction::AddqPh { .. } => "addq.ph",
+            DspInstruction::SubqPh { .. } => "subq.ph",
+            DspInstruction::AddqSPh { .. } => "addq_s.ph",
+            DspInstruction::SubqSPh { .. } => "subq_s.ph",
+            DspInstruction::Addsc { .. } => "addsc",
+            DspInstruction::Addwc { .. } => "addwc",
+            DspInstruction::Modsub { .. } => "modsub",
+            DspInstruction::AddqSW { .. } => "addq_s.w",
+            DspInstruction::SubqSW { .. } => "subq_s.w",
+            DspInstruction::MuleqSWPhl { .. } => "muleq_s.w.phl",
+            DspInstruction::MuleqSWPhr { .. } => "muleq_s.w.phr",
+            DspInstruction::MulqRsPh { .. } => "mulq_rs.ph",
+            DspInstruction::RadduWQb { .. } => "raddu.w.qb",
+            DspInstruction::AdduPh { .. } => "addu.ph",
+            DspInstruction::SubuPh { .. } => "subu.ph",
+            DspInstruction::AdduSPh { .. } => "addu_s.ph",
+            DspInstruction::SubuSPh { .. } => "subu_s.ph",
+            DspInstruction::MulqSPh { .. } => "mulq_s.ph",
+            DspInstruction::AdduhQb { .. } => "adduh.qb",
+            DspInstruction::AdduhRQb { .. } => "adduh_r.qb",
+            DspInstruction::SubuhQb { .. } => "subuh.qb",
+            DspInstruction::SubuhRQb { .. } => "subuh_r.qb",
+            DspInstruction::AddqhPh { .. } => "addqh.ph",
+            DspInstruction::AddqhRPh { .. } => "addqh_r.ph",
+            DspInstruction::SubqhPh { .. } => "subqh.ph",
+            DspInstruction::SubqhRPh { .. } => "subqh_r.ph",
+            DspInstruction::AddqhW { .. } => "addqh.w",
+            DspInstruction::AddqhRW { .. } => "addqh_r.w",
+            DspInstruction::SubqhW { .. } => "subqh.w",
+            DspInstruction::SubqhRW { .. } => "subqh_r.w",
+            DspInstruction::MulPh { .. } => "mul.ph",
+            DspInstruction::MulSPh { .. } => "mul_s.ph",
+            DspInstruction::MulqSW { .. } => "mulq_s.w",
+            DspInstruction::MulqRsW { .. } => "mulq_rs.w",
+            DspInstruction::CmpuEqQb { .. } => "cmpu.eq.qb",
+            DspInstruction::CmpuLtQb { .. } => "cmpu.lt.qb",
+            DspInstruction::CmpuLeQb { .. } => "cmpu.le.qb",
+            DspInstruction::CmpEqPh { .. } => "cmp.eq.ph",
+            DspInstruction::CmpLtPh { .. } => "cmp.lt.ph",
+            DspInstruction::CmpLePh { .. } => "cmp.le.ph",
+            DspInstruction::CmpguEqQb { .. } => "cmpgu.eq.qb",
+            DspInstruction::CmpguLtQb { .. } => "cmpgu.lt.qb",
+            DspInstruction::CmpguLeQb { .. } => "cmpgu.le.qb",
+            DspInstruction::PickQb { .. } => "pick.qb",
+            DspInstruction::PickPh { .. } => "pick.ph",
+            DspInstruction::PrecrqQbPh { .. } => "precrq.qb.ph",
+            DspInstruction::PackrlPh { .. } => "packrl.ph",
+            DspInstruction::PrecrquSQbPh { .. } => "precrqu_s.qb.ph",
+            DspInstruction::PrecrqPhW { .. } => "precrq.ph.w",
+            DspInstruction::PrecrqRsPhW { .. } => "precrq_rs.ph.w",
+            DspInstruction::CmpgduEqQb { .. } => "cmpgdu.eq.qb",
+            DspInstruction::CmpgduLtQb { .. } => "cmpgdu.lt.qb",
+            DspInstruction::CmpgduLeQb { .. } => "cmpgdu.le.qb",
+            DspInstruction::PrecrQbPh { .. } => "precr.qb.ph",
+            DspInstruction::PrecrSraPhW { .. } => "precr_sra.ph.w",
+            DspInstruction::PrecrSraRPhW { .. } => "precr_sra_r.ph.w",
+            DspInstruction::ReplQb { .. } => "repl.qb",
+            DspInstruction::ReplPh { .. } => "repl.ph",
+            DspInstruction::ReplvQb { .. } => "replv.qb",
+            DspInstruction::ReplvPh { .. } => "replv.ph",
+            DspInstruction::PrecequPhQbl { .. } => "precequ.ph.qbl",
+            DspInstruction::PrecequPhQbr { .. } => "precequ.ph.qbr",
+            DspInstruction::PrecequPhQbla { .. } => "precequ.ph.qbla",
+            DspInstruction::PrecequPhQbra { .. } => "precequ.ph.qbra",
+            DspInstruction::PreceuPhQbl { .. } => "preceu.ph.qbl",
+            DspInstruction::PreceuPhQbr { .. } => "preceu.ph.qbr",
+            DspInstruction::PreceuPhQbla { .. } => "preceu.ph.qbla",
+            DspInstruction::PreceuPhQbra { .. } => "preceu.ph.qbra",
+            DspInstruction::PreceqWPhl { .. } => "preceq.w.phl",
+            DspInstruction::PreceqWPhr { .. } => "preceq.w.phr",
+            DspInstruction::AbsqSPh { .. } => "absq_s.ph",
+            DspInstruction::AbsqSW { .. } => "absq_s.w",
+            DspInstruction::Bitrev { .. } => "bitrev",
+            DspInstruction::AbsqSQb { .. } => "absq_s.qb",
+            DspInstruction::ShllQb { .. } => "shll.qb",
+            DspInstruction::ShrlQb { .. } => "shrl.qb",
+            DspInstruction::ShllvQb { .. } => "shllv.qb",
+            DspInstruction::ShrlvQb { .. } => "shrlv.qb",
+            DspInstruction::ShllPh { .. } => "shll.ph",
+            DspInstruction::ShraPh { .. } => "shra.ph",
+            DspInstruction::ShllSPh { .. } => "shll_s.ph",
+            DspInstruction::ShraRPh { .. } => "shra_r.ph",
+            DspInstruction::ShllvPh { .. } => "shllv.ph",
+            DspInstruction::ShravPh { .. } => "shrav.ph",
+            DspInstruction::ShllvSPh { .. } => "shllv_s.ph",
+            DspInstruction::ShravRPh { .. } => "shrav_r.ph",
+            DspInstruction::ShllSW { .. } => "shll_s.w",
+            DspInstruction::ShraRW { .. } => "shra_r.w",
+            DspInstruction::ShllvSW { .. } => "shllv_s.w",
+            DspInstruction::ShravRW { .. } => "shrav_r.w",
+            DspInstruction::ShraQb { .. } => "shra.qb",
+            DspInstruction::ShraRQb { .. } => "shra_r.qb",
+            DspInstruction::ShravQb { .. } => "shrav.qb",
+            DspInstruction::ShravRQb { .. } => "shrav_r.qb",
+            DspInstruction::ShrlPh { .. } => "shrl.ph",
+            DspInstruction::ShrlvPh { .. } => "shrlv.ph",
+            DspInstruction::DpauHQbl { .. } => "dpau.h.qbl",
+            DspInstruction::DpauHQbr { .. } => "dpau.h.qbr",
+            DspInstruction::DpsuHQbl { .. } => "dpsu.h.qbl",
+            DspInstruction::DpsuHQbr { .. } => "dpsu.h.qbr",
+            DspInstruction::DpaqSWPh { .. } => "dpaq_s.w.ph",
+            DspInstruction::DpsqSWPh { .. } => "dpsq_s.w.ph",
+            DspInstruction::MulsaqSWPh { .. } => "mulsaq_s.w.ph",
+            DspInstruction::DpaqSaLW { .. } => "dpaq_sa.l.w",
+            DspInstruction::DpsqSaLW { .. } => "dpsq_sa.l.w",
+            DspInstruction::MaqSaWPhl { .. } => "maq_sa.w.phl",
+            DspInstruction::MaqSaWPhr { .. } => "maq_sa.w.phr",
+            DspInstruction::MaqSWPhl { .. } => "maq_s.w.phl",
+            DspInstruction::MaqSWPhr { .. } => "maq_s.w.phr",
+            DspInstruction::DpaWPh { .. } => "dpa.w.ph",
+            DspInstruction::DpsWPh { .. } => "dps.w.ph",
+            DspInstruction::DpaxWPh { .. } => "dpax.w.ph",
+            DspInstruction::DpsxWPh { .. } => "dpsx.w.ph",
+            DspInstruction::DpaqxSWPh { .. } => "dpaqx_s.w.ph",
+            DspInstruction::DpaqxSaWPh { .. } => "dpaqx_sa.w.ph",
+            DspInstruction::DpsqxSWPh { .. } => "dpsqx_s.w.ph",
+            DspInstruction::DpsqxSaWPh { .. } => "dpsqx_sa.w.ph",
+            DspInstruction::MulsaWPh { .. } => "mulsa.w.ph",
+            DspInstruction::Append { .. } => "append",
+            DspInstruction::Prepend { .. } => "prepend",
+            DspInstruction::Balign { .. } => "balign",
+            DspInstruction::ExtrW { .. } => "extr.w",
+            DspInstruction::ExtrRW { .. } => "extr_r.w",
+            DspInstruction::ExtrRsW { .. } => "extr_rs.w",
+            DspInstruction::ExtrSH { .. } => "extr_s.h",
+            DspInstruction::ExtrvW { .. } => "extrv.w",
+            DspInstruction::ExtrvRW { .. } => "extrv_r.w",
+            DspInstruction::ExtrvRsW { .. } => "extrv_rs.w",
+            DspInstruction::ExtrvSH { .. } => "extrv_s.h",
+            DspInstruction::Extp { .. } => "extp",
+            DspInstruction::Extpdp { .. } => "extpdp",
+            DspInstruction::Extpv { .. } => "extpv",
+            DspInstruction::Extpdpv { .. } => "extpdpv",
+            DspInstruction::Shilo { .. } => "shilo",
+            DspInstruction::Shilov { .. } => "shilov",
+            DspInstruction::Mthlip { .. } => "mthlip",
+            DspInstruction::Rddsp { .. } => "rddsp",
+            DspInstruction::Wrdsp { .. } => "wrdsp",
+        }
+    }
+}
+
+/// Decodes a SPECIAL3 instruction with one of the `DSP_FUNCTS`
+pub fn decode_dsp_instruction(instruction: u32) -> Result<DspInstruction, DecodeError> {
+    let funct = get_funct(instruction);
+    let op = get_shift(instruction);
+    let rs = get_rs(instruction);
+    let rt = get_rt(instruction);
+    let rd = get_rd(instruction);
+    // accumulator of the DPA and EXTR groups
+    let ac = rd & 0b11;
+
+    let decoded = match (funct, op) {
+        // LWX, LHX, LBUX, base is in rs and index in rt
+        (FUNCT_LX, 0b00000) => DspInstruction::Lwx { rd, base: rs, index: rt },
+        (FUNCT_LX, 0b00100) => DspInstruction::Lhx { rd, base: rs, index: rt },
+        (FUNCT_LX, 0b00110) => DspInstruction::Lbux { rd, base: rs, index: rt },
+        (FUNCT_INSV, _) => DspInstruction::Insv { rt, rs },
+
+        (FUNCT_ADDU_QB, 0b00000) => DspInstruction::AdduQb { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b00001) => DspInstruction::SubuQb { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b00100) => DspInstruction::AdduSQb { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b00101) => DspInstruction::SubuSQb { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b00110) => DspInstruction::MuleuSPhQbl { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b00111) => DspInstruction::MuleuSPhQbr { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01010) => DspInstruction::AddqPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01011) => DspInstruction::SubqPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01110) => DspInstruction::AddqSPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01111) => DspInstruction::SubqSPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b10000) => DspInstruction::Addsc { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b10001) => DspInstruction::Addwc { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b10010) => DspInstruction::Modsub { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b10100) => DspInstruction::RadduWQb { rd, rs },
+        (FUNCT_ADDU_QB, 0b10110) => DspInstruction::AddqSW { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b10111) => DspInstruction::SubqSW { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b11100) => DspInstruction::MuleqSWPhl { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b11101) => DspInstruction::MuleqSWPhr { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b11111) => DspInstruction::MulqRsPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01000) => DspInstruction::AdduPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01001) => DspInstruction::SubuPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01100) => DspInstruction::AdduSPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b01101) => DspInstruction::SubuSPh { rd, rs, rt },
+        (FUNCT_ADDU_QB, 0b11110) => DspInstruction::MulqSPh { rd, rs, rt },
+
+        (FUNCT_ADDUH_QB, 0b00000) => DspInstruction::AdduhQb { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b00010) => DspInstruction::AdduhRQb { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b00001) => DspInstruction::SubuhQb { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b00011) => DspInstruction::SubuhRQb { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b01000) => DspInstruction::AddqhPh { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b01010) => DspInstruction::AddqhRPh { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b01001) => DspInstruction::SubqhPh { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b01011) => DspInstruction::SubqhRPh { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b10000) => DspInstruction::AddqhW { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b10010) => DspInstruction::AddqhRW { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b10001) => DspInstruction::SubqhW { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b10011) => DspInstruction::SubqhRW { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b01100) => DspInstruction::MulPh { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b01110) => DspInstruction::MulSPh { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b10110) => DspInstruction::MulqSW { rd, rs, rt },
+        (FUNCT_ADDUH_QB, 0b10111) => DspInstruction::MulqRsW { rd, rs, rt },
+
+        (FUNCT_CMPU_EQ_QB, 0b00000) => DspInstruction::CmpuEqQb { rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b00001) => DspInstruction::CmpuLtQb { rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b00010) => DspInstruction::CmpuLeQb { rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b00011) => DspInstruction::PickQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b00100) => DspInstruction::CmpguEqQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b00101) => DspInstruction::CmpguLtQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b00110) => DspInstruction::CmpguLeQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01000) => DspInstruction::CmpEqPh { rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01001) => DspInstruction::CmpLtPh { rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01010) => DspInstruction::CmpLePh { rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01011) => DspInstruction::PickPh { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01100) => DspInstruction::PrecrqQbPh { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01110) => DspInstruction::PackrlPh { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01111) => DspInstruction::PrecrquSQbPh { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b10100) => DspInstruction::PrecrqPhW { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b10101) => DspInstruction::PrecrqRsPhW { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b11000) => DspInstruction::CmpgduEqQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b11001) => DspInstruction::CmpgduLtQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b11010) => DspInstruction::CmpgduLeQb { rd, rs, rt },
+        (FUNCT_CMPU_EQ_QB, 0b01101) => DspInstruction::PrecrQbPh { rd, rs, rt },
+        // the shift amount immediate is in rd, the result is written to rt
+        (FUNCT_CMPU_EQ_QB, 0b11110) => DspInstruction::PrecrSraPhW { rt, rs, shift: rd },
+        (FUNCT_CMPU_EQ_QB, 0b11111) => DspInstruction::PrecrSraRPhW { rt, rs, shift: rd },
+
+        (FUNCT_ABSQ_S_PH, 0b00010) => {
+            DspInstruction::ReplQb { rd, immediate: (instruction >> 16) & 0xFF }
+        }
+        (FUNCT_ABSQ_S_PH, 0b01010) => {
+            let immediate = sign_extend((instruction >> 16) & 0x3FF, 10) as i16;
+            DspInstruction::ReplPh { rd, immediate }
+        }
+        (FUNCT_ABSQ_S_PH, 0b00011) => DspInstruction::ReplvQb { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b01011) => DspInstruction::ReplvPh { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b00100) => DspInstruction::PrecequPhQbl { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b00101) => DspInstruction::PrecequPhQbr { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b00110) => DspInstruction::PrecequPhQbla { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b00111) => DspInstruction::PrecequPhQbra { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b11100) => DspInstruction::PreceuPhQbl { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b11101) => DspInstruction::PreceuPhQbr { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b11110) => DspInstruction::PreceuPhQbla { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b11111) => DspInstruction::PreceuPhQbra { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b01100) => DspInstruction::PreceqWPhl { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b01101) => DspInstruction::PreceqWPhr { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b01001) => DspInstruction::AbsqSPh { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b10001) => DspInstruction::AbsqSW { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b11011) => DspInstruction::Bitrev { rd, rt },
+        (FUNCT_ABSQ_S_PH, 0b00001) => DspInstruction::AbsqSQb { rd, rt },
+
+        // the shift amount immediate is in rs, 3 bits for QB, 4 for PH and 5 for W
+        (FUNCT_SHLL_QB, 0b00000) => DspInstruction::ShllQb { rd, rt, shift: rs & 0b111 },
+        (FUNCT_SHLL_QB, 0b00001) => DspInstruction::ShrlQb { rd, rt, shift: rs & 0b111 },
+        (FUNCT_SHLL_QB, 0b00010) => DspInstruction::ShllvQb { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b00011) => DspInstruction::ShrlvQb { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b01000) => DspInstruction::ShllPh { rd, rt, shift: rs & 0b1111 },
+        (FUNCT_SHLL_QB, 0b01001) => DspInstruction::ShraPh { rd, rt, shift: rs & 0b1111 },
+        (FUNCT_SHLL_QB, 0b01010) => DspInstruction::ShllvPh { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b01011) => DspInstruction::ShravPh { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b01100) => DspInstruction::ShllSPh { rd, rt, shift: rs & 0b1111 },
+        (FUNCT_SHLL_QB, 0b01101) => DspInstruction::ShraRPh { rd, rt, shift: rs & 0b1111 },
+        (FUNCT_SHLL_QB, 0b01110) => DspInstruction::ShllvSPh { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b01111) => DspInstruction::ShravRPh { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b10100) => DspInstruction::ShllSW { rd, rt, shift: rs },
+        (FUNCT_SHLL_QB, 0b10101) => DspInstruction::ShraRW { rd, rt, shift: rs },
+        (FUNCT_SHLL_QB, 0b10110) => DspInstruction::ShllvSW { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b10111) => DspInstruction::ShravRW { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b00100) => DspInstruction::ShraQb { rd, rt, shift: rs & 0b111 },
+        (FUNCT_SHLL_QB, 0b00101) => DspInstruction::ShraRQb { rd, rt, shift: rs & 0b111 },
+        (FUNCT_SHLL_QB, 0b00110) => DspInstruction::ShravQb { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b00111) => DspInstruction::ShravRQb { rd, rt, rs },
+        (FUNCT_SHLL_QB, 0b11001) => DspInstruction::ShrlPh { rd, rt, shift: rs & 0b1111 },
+        (FUNCT_SHLL_QB, 0b11011) => DspInstruction::ShrlvPh { rd, rt, rs },
+
+        (FUNCT_DPA_W_PH, 0b00011) => DspInstruction::DpauHQbl { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00111) => DspInstruction::DpauHQbr { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b01011) => DspInstruction::DpsuHQbl { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b01111) => DspInstruction::DpsuHQbr { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00100) => DspInstruction::DpaqSWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00101) => DspInstruction::DpsqSWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00110) => DspInstruction::MulsaqSWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b01100) => DspInstruction::DpaqSaLW { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b01101) => DspInstruction::DpsqSaLW { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b10000) => DspInstruction::MaqSaWPhl { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b10010) => DspInstruction::MaqSaWPhr { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b10100) => DspInstruction::MaqSWPhl { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b10110) => DspInstruction::MaqSWPhr { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00000) => DspInstruction::DpaWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00001) => DspInstruction::DpsWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b01000) => DspInstruction::DpaxWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b01001) => DspInstruction::DpsxWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b11000) => DspInstruction::DpaqxSWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b11010) => DspInstruction::DpaqxSaWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b11001) => DspInstruction::DpsqxSWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b11011) => DspInstruction::DpsqxSaWPh { ac, rs, rt },
+        (FUNCT_DPA_W_PH, 0b00010) => DspInstruction::MulsaWPh { ac, rs, rt },
+
+        // the shift amount and byte position immediates are in rd
+        (FUNCT_APPEND, 0b00000) => DspInstruction::Append { rt, rs, shift: rd },
+        (FUNCT_APPEND, 0b00001) => DspInstruction::Prepend { rt, rs, shift: rd },
+        (FUNCT_APPEND, 0b10000) => DspInstruction::Balign { rt, rs, bp: rd & 0b11 },
+
+        // the shift amount and size immediates are in rs
+        (FUNCT_EXTR_W, 0b00000) => DspInstruction::ExtrW { rt, ac, shift: rs },
+        (FUNCT_EXTR_W, 0b00100) => DspInstruction::ExtrRW { rt, ac, shift: rs },
+        (FUNCT_EXTR_W, 0b00110) => DspInstruction::ExtrRsW { rt, ac, shift: rs },
+        (FUNCT_EXTR_W, 0b01110) => DspInstruction::ExtrSH { rt, ac, shift: rs },
+        (FUNCT_EXTR_W, 0b00001) => DspInstruction::ExtrvW { rt, ac, rs },
+        (FUNCT_EXTR_W, 0b00101) => DspInstruction::ExtrvRW { rt, ac, rs },
+        (FUNCT_EXTR_W, 0b00111) => DspInstruction::ExtrvRsW { rt, ac, rs },
+        (FUNCT_EXTR_W, 0b01111) => DspInstruction::ExtrvSH { rt, ac, rs },
+        (FUNCT_EXTR_W, 0b00010) => DspInstruction::Extp { rt, ac, size: rs },
+        (FUNCT_EXTR_W, 0b01010) => DspInstruction::Extpdp { rt, ac, size: rs },
+        (FUNCT_EXTR_W, 0b00011) => DspInstruction::Extpv { rt, ac, rs },
+        (FUNCT_EXTR_W, 0b01011) => DspInstruction::Extpdpv { rt, ac, rs },
+        (FUNCT_EXTR_W, 0b11010) => {
+            let shift = sign_extend((instruction >> 20) & 0x3F, 6);
+            DspInstruction::Shilo { ac, shift }
+        }
+        (FUNCT_EXTR_W, 0b11011) => DspInstruction::Shilov { ac, rs },
+        (FUNCT_EXTR_W, 0b11111) => DspInstruction::Mthlip { rs, ac },
+        (FUNCT_EXTR_W, 0b10010) => {
+            DspInstruction::Rddsp { rd, mask: (instruction >> 16) & 0x3FF }
+        }
+        (FUNCT_EXTR_W, 0b10011) => {
+            DspInstruction::Wrdsp { rs, mask: (instruction >> 11) & 0x3FF }
+        }
+        _ => return Err(DecodeError::Reserved),
+    };
+    Ok(decoded)
+}
+
+/// Applies the operation to both signed halfwords of the operands
+fn map_ph<F: FnMut(i32, i32) -> i32>(a: u32, b: u32, mut f: F) -> u32 {
+    let high = f((a >> 16) as i16 as i32, (b >> 16) as i16 as i32) as u32;
+    let low = f(a as i16 as i32, b as i16 as i32) as u32;
+    (high << 16) | (low & 0xFFFF)
+}
+
+/// Applies the operation to all four unsigned bytes of the operands
+fn map_qb<F: FnMut(u32, u32) -> u32>(a: u32, b: u32, mut f: F) -> u32 {
+    let mut r = 0;
+    for lane in 0..4 {
+        let shift = lane * 8;
+        r |= (f((a >> shift) & 0xFF, (b >> shift) & 0xFF) & 0xFF) << shift;
+    }
+    r
+}
+
+/// Byte of the QB vector, byte 0 is the rightmost one
+fn byte(value: u32, lane: u32) -> u32 {
+    (value >> (lane * 8)) & 0xFF
+}
+
+/// Compares all four unsigned bytes, bit n of the result is set when the condition holds for
+/// byte n
+fn compare_qb<F: Fn(u32, u32) -> bool>(a: u32, b: u32, condition: F) -> u32 {
+    (0..4).filter(|&lane| condition(byte(a, lane), byte(b, lane))).map(|lane| 1 << lane).sum()
+}
+
+/// Compares both signed halfwords, bit 1 of the result is for the left one
+fn compare_ph<F: Fn(i16, i16) -> bool>(a: u32, b: u32, condition: F) -> u32 {
+    (0..2)
+        .filter(|&lane| condition((a >> (lane * 16)) as i16, (b >> (lane * 16)) as i16))
+        .map(|lane| 1 << lane)
+        .sum()
+}
+
+/// Clamps the value to the signed range of the given width and reports whether it did not fit
+fn saturate(value: i64, bits: u32, overflow: &mut bool) -> i64 {
+    let max = (1i64 << (bits - 1)) - 1;
+    let min = -(1i64 << (bits - 1));
+    if value > max || value < min {
+        *overflow = true;
+        if value > max { max } else { min }
+    } else {
+        value
+    }
+}
+
+/// Q15 multiplication producing Q31, -1.0 * -1.0 saturates to the largest Q31 value
+fn multiply_q15(a: i32, b: i32, overflow: &mut bool) -> i32 {
+    if a == -0x8000 && b == -0x8000 {
+        *overflow = true;
+        0x7FFF_FFFF
+    } else {
+        (a * b) << 1
+    }
+}
+
+/// Q31 multiplication producing Q63, -1.0 * -1.0 saturates to the largest Q63 value
+fn multiply_q31(a: i32, b: i32, overflow: &mut bool) -> i64 {
+    if a == i32::MIN && b == i32::MIN {
+        *overflow = true;
+        i64::MAX
+    } else {
+        (a as i64 * b as i64) << 1
+    }
+}
+
+/// Left shift of a signed value of the given width. Overflow is reported when bits other than
+/// copies of the sign are shifted out, the result then either wraps or saturates.
+fn shift_left(value: i64, shift: u32, bits: u32, saturated: bool, overflow: &mut bool) -> i64 {
+    let mut lost = false;
+    let r = saturate(value << shift, bits, &mut lost);
+    *overflow |= lost;
+    if lost && !saturated {
+        (value << shift) & bit_mask(bits) as i64
+    } else {
+        r
+    }
+}
+
+/// Arithmetic right shift, optionally rounded by adding the last bit shifted out
+fn shift_right(value: i64, shift: u32, rounded: bool) -> i64 {
+    if rounded && shift > 0 {
+        ((value >> (shift - 1)) + 1) >> 1
+    } else {
+        value >> shift
+    }
+}
+
+/// Evaluates an instruction of the DSP ASE decoded by `decode_dsp_instruction`
+pub fn eval_dsp_instruction(
+    decoded: DspInstruction,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+) -> CPUEvent {
+    macro_rules! itrace {
+        ($fmt:expr, $($arg:tt)*) => (
+            trace!(concat!("0x{:x}:\t", $fmt), registers.get_pc(), $($arg)*);
+        );
+    }
+
+    let name = decoded.mnemonic();
+    let mut control = registers.read_dsp_control();
+    let mut overflow = false;
+
+    match decoded {
+        DspInstruction::Lwx { rd, base, index } |
+        DspInstruction::Lhx { rd, base, index } |
+        DspInstruction::Lbux { rd, base, index } => {
+            let address = registers
+                .read_register(base)
+                .wrapping_add(registers.read_register(index));
+            if address >= KERNEL_SEGMENT_START {
+                itrace!("address error at 0x{:08x}", address);
+                return CPUEvent::Exception(ExceptionKind::AddressError(address));
+            }
+            let size = match decoded {
+                DspInstruction::Lwx { .. } => 4,
+                DspInstruction::Lhx { .. } => 2,
+                _ => 1,
+            };
+            if let Err(fault) = memory.check_access(address, size, Access::Read) {
+                itrace!("segmentation fault at 0x{:08x}", address);
+                return CPUEvent::Exception(fault);
+            }
+            let r = match size {
+                4 => memory.read_word(address),
+                2 => memory.read_halfword(address) as i16 as u32,
+                _ => memory.read_byte(address),
+            };
+            itrace!(
+                "{}\t{},{}({}) - address=0x{:x} data=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(index),
+                get_register_name(base),
+                address,
+                r
+            );
+            registers.write_register(rd, r);
+        }
+        // INSV, position and size are taken from DSPControl
+        DspInstruction::Insv { rt, rs } => {
+            let pos = control & DSP_CONTROL_POS;
+            let size = (control & SCOUNT) >> SCOUNT_SHIFT;
+            itrace!(
+                "insv\t{},{} - pos={} size={}",
+                get_register_name(rt),
+                get_register_name(rs),
+                pos,
+                size
+            );
+            // the result is UNPREDICTABLE for fields not fitting into the register
+            if size > 0 && pos + size <= 32 {
+                let mask = bit_mask(size) << pos;
+                let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+                registers.write_register(rt, (b & !mask) | ((a << pos) & mask));
+            }
+        }
+        // additions, subtractions and multiplications writing a GPR
+        DspInstruction::AdduQb { rd, rs, rt } |
+        DspInstruction::SubuQb { rd, rs, rt } |
+        DspInstruction::AdduSQb { rd, rs, rt } |
+        DspInstruction::SubuSQb { rd, rs, rt } |
+        DspInstruction::MuleuSPhQbl { rd, rs, rt } |
+        DspInstruction::MuleuSPhQbr { rd, rs, rt } |
+        DspInstruction::AddqPh { rd, rs, rt } |
+        DspInstruction::SubqPh { rd, rs, rt } |
+        DspInstruction::AddqSPh { rd, rs, rt } |
+        DspInstruction::SubqSPh { rd, rs, rt } |
+        DspInstruction::Addsc { rd, rs, rt } |
+        DspInstruction::Addwc { rd, rs, rt } |
+        DspInstruction::Modsub { rd, rs, rt } |
+        DspInstruction::AddqSW { rd, rs, rt } |
+        DspInstruction::SubqSW { rd, rs, rt } |
+        DspInstruction::MuleqSWPhl { rd, rs, rt } |
+        DspInstruction::MuleqSWPhr { rd, rs, rt } |
+        DspInstruction::MulqRsPh { rd, rs, rt } |
+        DspInstruction::AdduPh { rd, rs, rt } |
+        DspInstruction::SubuPh { rd, rs, rt } |
+        DspInstruction::AdduSPh { rd, rs, rt } |
+        DspInstruction::SubuSPh { rd, rs, rt } |
+        DspInstruction::MulqSPh { rd, rs, rt } |
+        DspInstruction::AdduhQb { rd, rs, rt } |
+        DspInstruction::AdduhRQb { rd, rs, rt } |
+        DspInstruction::SubuhQb { rd, rs, rt } |
+        DspInstruction::SubuhRQb { rd, rs, rt } |
+        DspInstruction::AddqhPh { rd, rs, rt } |
+        DspInstruction::AddqhRPh { rd, rs, rt } |
+        DspInstruction::SubqhPh { rd, rs, rt } |
+        DspInstruction::SubqhRPh { rd, rs, rt } |
+        DspInstruction::AddqhW { rd, rs, rt } |
+        DspInstruction::AddqhRW { rd, rs, rt } |
+        DspInstruction::SubqhW { rd, rs, rt } |
+        DspInstruction::SubqhRW { rd, rs, rt } |
+        DspInstruction::MulPh { rd, rs, rt } |
+        DspInstruction::MulSPh { rd, rs, rt } |
+        DspInstruction::MulqSW { rd, rs, rt } |
+        DspInstruction::MulqRsW { rd, rs, rt } => {
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            // halving additions and subtractions round by adding 1 before the shift
+            let round = matches!(
+                decoded,
+                DspInstruction::AdduhRQb { .. } |
+                    DspInstruction::SubuhRQb { .. } |
+                    DspInstruction::AddqhRPh { .. } |
+                    DspInstruction::SubqhRPh { .. } |
+                    DspInstruction::AddqhRW { .. } |
+                    DspInstruction::SubqhRW { .. }
+            ) as i32;
+            let r = match decoded {
+                DspInstruction::AdduQb { .. } => map_qb(a, b, |x, y| {
+                    overflow |= x + y > 0xFF;
+                    x + y
+                }),
+                DspInstruction::SubuQb { .. } => map_qb(a, b, |x, y| {
+                    overflow |= x < y;
+                    x.wrapping_sub(y)
+                }),
+                DspInstruction::AdduSQb { .. } => map_qb(a, b, |x, y| if x + y > 0xFF {
+                    overflow = true;
+                    0xFF
+                } else {
+                    x + y
+                }),
+                DspInstruction::SubuSQb { .. } => map_qb(a, b, |x, y| if x < y {
+                    overflow = true;
+                    0
+                } else {
+                    x - y
+                }),
+                DspInstruction::MuleuSPhQbl { .. } | DspInstruction::MuleuSPhQbr { .. } => {
+                    let lane = if let DspInstruction::MuleuSPhQbl { .. } = decoded {
+                        2
+                    } else {
+                        0
+                    };
+                    let mut multiply = |x: u32, y: u32| if x * y > 0xFFFF {
+                        overflow = true;
+                        0xFFFF
+                    } else {
+                        x * y
+                    };
+                    let high = multiply(byte(a, lane + 1), b >> 16);
+                    let low = multiply(byte(a, lane), b & 0xFFFF);
+                    (high << 16) | low
+                }
+                DspInstruction::AddqPh { .. } |
+                DspInstruction::SubqPh { .. } |
+                DspInstruction::AddqSPh { .. } |
+                DspInstruction::SubqSPh { .. } => {
+                    let subtract = matches!(
+                        decoded,
+                        DspInstruction::SubqPh { .. } | DspInstruction::SubqSPh { .. }
+                    );
+                    let saturated = matches!(
+                        decoded,
+                        DspInstruction::AddqSPh { .. } | DspInstruction::SubqSPh { .. }
+                    );
+                    map_ph(a, b, |x, y| {
+                        let r = if subtract { x - y } else { x + y };
+                        let mut lost = false;
+                        let s = saturate(r as i64, 16, &mut lost) as i32;
+                        overflow |= lost;
+                        if saturated { s } else { r }
+                    })
+                }
+                DspInstruction::Addsc { .. } => {
+                    let (r, carry) = a.overflowing_add(b);
+                    control = if carry { control | CARRY } else { control & !CARRY };
+                    r
+                }
+                DspInstruction::Addwc { .. } => {
+                    let carry = if control & CARRY != 0 { 1 } else { 0 };
+                    let r = a as i32 as i64 + b as i32 as i64 + carry;
+                    overflow = r != r as i32 as i64;
+                    r as u32
+                }
+                DspInstruction::Modsub { .. } => if a == 0 {
+                    (b >> 8) & 0xFFFF
+                } else {
+                    a.wrapping_sub(b & 0xFF)
+                },
+                DspInstruction::AddqSW { .. } | DspInstruction::SubqSW { .. } => {
+                    let (x, y) = (a as i32 as i64, b as i32 as i64);
+                    let r = if let DspInstruction::AddqSW { .. } = decoded {
+                        x + y
+                    } else {
+                        x - y
+                    };
+                    saturate(r, 32, &mut overflow) as u32
+                }
+                DspInstruction::MuleqSWPhl { .. } | DspInstruction::MuleqSWPhr { .. } => {
+                    let shift = if let DspInstruction::MuleqSWPhl { .. } = decoded {
+                        16
+                    } else {
+                        0
+                    };
+                    multiply_q15(
+                        (a >> shift) as i16 as i32,
+                        (b >> shift) as i16 as i32,
+                        &mut overflow,
+                    ) as u32
+                }
+                // unsigned halfwords
+                DspInstruction::AdduPh { .. } => map_ph(a, b, |x, y| {
+                    let r = (x & 0xFFFF) + (y & 0xFFFF);
+                    overflow |= r > 0xFFFF;
+                    r
+                }),
+                DspInstruction::SubuPh { .. } => map_ph(a, b, |x, y| {
+                    let r = (x & 0xFFFF) - (y & 0xFFFF);
+                    overflow |= r < 0;
+                    r
+                }),
+                DspInstruction::AdduSPh { .. } => map_ph(a, b, |x, y| {
+                    let r = (x & 0xFFFF) + (y & 0xFFFF);
+                    if r > 0xFFFF {
+                        overflow = true;
+                        0xFFFF
+                    } else {
+                        r
+                    }
+                }),
+                DspInstruction::SubuSPh { .. } => map_ph(a, b, |x, y| {
+                    let r = (x & 0xFFFF) - (y & 0xFFFF);
+                    if r < 0 {
+                        overflow = true;
+                        0
+                    } else {
+                        r
+                    }
+                }),
+                DspInstruction::MulqSPh { .. } => {
+                    map_ph(a, b, |x, y| multiply_q15(x, y, &mut overflow) >> 16)
+                }
+                DspInstruction::AdduhQb { .. } | DspInstruction::AdduhRQb { .. } => {
+                    map_qb(a, b, |x, y| (x + y + round as u32) >> 1)
+                }
+                DspInstruction::SubuhQb { .. } | DspInstruction::SubuhRQb { .. } => {
+                    map_qb(a, b, |x, y| ((x as i32 - y as i32 + round) >> 1) as u32)
+                }
+                DspInstruction::AddqhPh { .. } | DspInstruction::AddqhRPh { .. } => {
+                    map_ph(a, b, |x, y| (x + y + round) >> 1)
+                }
+                DspInstruction::SubqhPh { .. } | DspInstruction::SubqhRPh { .. } => {
+                    map_ph(a, b, |x, y| (x - y + round) >> 1)
+                }
+                DspInstruction::AddqhW { .. } | DspInstruction::AddqhRW { .. } => {
+                    ((a as i32 as i64 + b as i32 as i64 + round as i64) >> 1) as u32
+                }
+                DspInstruction::SubqhW { .. } | DspInstruction::SubqhRW { .. } => {
+                    ((a as i32 as i64 - b as i32 as i64 + round as i64) >> 1) as u32
+                }
+                DspInstruction::MulPh { .. } | DspInstruction::MulSPh { .. } => {
+                    let saturated = matches!(decoded, DspInstruction::MulSPh { .. });
+                    map_ph(a, b, |x, y| {
+                        let mut lost = false;
+                        let s = saturate((x * y) as i64, 16, &mut lost) as i32;
+                        overflow |= lost;
+                        if saturated { s } else { x * y }
+                    })
+                }
+                DspInstruction::MulqSW { .. } => {
+                    (multiply_q31(a as i32, b as i32, &mut overflow) >> 32) as u32
+                }
+                DspInstruction::MulqRsW { .. } => {
+                    let product = multiply_q31(a as i32, b as i32, &mut overflow);
+                    if product == i64::MAX {
+                        0x7FFF_FFFF
+                    } else {
+                        ((product + 0x8000_0000) >> 32) as u32
+                    }
+                }
+                _ => map_ph(a, b, |x, y| if x == -0x8000 && y == -0x8000 {
+                    overflow = true;
+                    0x7FFF
+                } else {
+                    (((x * y) << 1) + 0x8000) >> 16
+                }),
+            };
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            registers.write_register(rd, r);
+            if overflow {
+                let bit = if name.starts_with("mul") {
+                    OUFLAG_MULTIPLY
+                } else {
+                    OUFLAG_ADD
+                };
+                control |= 1 << bit;
+            }
+        }
+        DspInstruction::RadduWQb { rd, rs } => {
+            let a = registers.read_register(rs);
+            let r = (0..4).map(|lane| byte(a, lane)).sum::<u32>();
+            itrace!(
+                "raddu.w.qb\t{},{} - result=0x{:08x}",
+                get_register_name(rd),
+                get_register_name(rs),
+                r
+            );
+            registers.write_register(rd, r);
+        }
+        // comparisons setting the ccond bits
+        DspInstruction::CmpuEqQb { rs, rt } |
+        DspInstruction::CmpuLtQb { rs, rt } |
+        DspInstruction::CmpuLeQb { rs, rt } |
+        DspInstruction::CmpEqPh { rs, rt } |
+        DspInstruction::CmpLtPh { rs, rt } |
+        DspInstruction::CmpLePh { rs, rt } => {
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let bits = match decoded {
+                DspInstruction::CmpuEqQb { .. } => compare_qb(a, b, |x, y| x == y),
+                DspInstruction::CmpuLtQb { .. } => compare_qb(a, b, |x, y| x < y),
+                DspInstruction::CmpuLeQb { .. } => compare_qb(a, b, |x, y| x <= y),
+                DspInstruction::CmpEqPh { .. } => compare_ph(a, b, |x, y| x == y),
+                DspInstruction::CmpLtPh { .. } => compare_ph(a, b, |x, y| x < y),
+                _ => compare_ph(a, b, |x, y| x <= y),
+            };
+            control = (control & !CCOND) | (bits << CCOND_SHIFT);
+            itrace!(
+                "{}\t{},{} - ccond=0b{:04b}",
+                name,
+                get_register_name(rs),
+                get_register_name(rt),
+                bits
+            );
+        }
+        // comparisons writing a GPR, picks and precision reductions
+        DspInstruction::CmpguEqQb { rd, rs, rt } |
+        DspInstruction::CmpguLtQb { rd, rs, rt } |
+        DspInstruction::CmpguLeQb { rd, rs, rt } |
+        DspInstruction::PickQb { rd, rs, rt } |
+        DspInstruction::PickPh { rd, rs, rt } |
+        DspInstruction::PrecrqQbPh { rd, rs, rt } |
+        DspInstruction::PackrlPh { rd, rs, rt } |
+        DspInstruction::PrecrquSQbPh { rd, rs, rt } |
+        DspInstruction::PrecrqPhW { rd, rs, rt } |
+        DspInstruction::PrecrqRsPhW { rd, rs, rt } |
+        DspInstruction::CmpgduEqQb { rd, rs, rt } |
+        DspInstruction::CmpgduLtQb { rd, rs, rt } |
+        DspInstruction::CmpgduLeQb { rd, rs, rt } |
+        DspInstruction::PrecrQbPh { rd, rs, rt } => {
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let ccond = (control & CCOND) >> CCOND_SHIFT;
+            let r = match decoded {
+                DspInstruction::CmpguEqQb { .. } | DspInstruction::CmpgduEqQb { .. } => {
+                    compare_qb(a, b, |x, y| x == y)
+                }
+                DspInstruction::CmpguLtQb { .. } | DspInstruction::CmpgduLtQb { .. } => {
+                    compare_qb(a, b, |x, y| x < y)
+                }
+                DspInstruction::CmpguLeQb { .. } | DspInstruction::CmpgduLeQb { .. } => {
+                    compare_qb(a, b, |x, y| x <= y)
+                }
+                DspInstruction::PickQb { .. } => (0..4)
+                    .map(|lane| {
+                        let source = if ccond & (1 << lane) != 0 { a } else { b };
+                        byte(source, lane) << (lane * 8)
+                    })
+                    .sum::<u32>(),
+                DspInstruction::PickPh { .. } => {
+                    let high = if ccond & 0b10 != 0 { a } else { b };
+                    let low = if ccond & 0b01 != 0 { a } else { b };
+                    (high & 0xFFFF_0000) | (low & 0xFFFF)
+                }
+                DspInstruction::PrecrqQbPh { .. } => {
+                    (byte(a, 3) << 24) | (byte(a, 1) << 16) | (byte(b, 3) << 8) | byte(b, 1)
+                }
+                DspInstruction::PrecrQbPh { .. } => {
+                    (byte(a, 2) << 24) | (byte(a, 0) << 16) | (byte(b, 2) << 8) | byte(b, 0)
+                }
+                DspInstruction::PackrlPh { .. } => (a << 16) | (b >> 16),
+                DspInstruction::PrecrquSQbPh { .. } => {
+                    // Q15 to unsigned byte, negative values saturate to zero
+                    let mut reduce = |x: u32| {
+                        let x = x & 0xFFFF;
+                        if x & 0x8000 != 0 {
+                            overflow = true;
+                            0
+                        } else if x > 0x7F80 {
+                            overflow = true;
+                            0xFF
+                        } else {
+                            (x >> 7) & 0xFF
+                        }
+                    };
+                    (reduce(a >> 16) << 24) | (reduce(a) << 16) | (reduce(b >> 16) << 8) |
+                        reduce(b)
+                }
+                DspInstruction::PrecrqPhW { .. } => (a & 0xFFFF_0000) | (b >> 16),
+                _ => {
+                    // Q31 to Q15 with rounding
+                    let mut reduce = |x: u32| if x as i32 > 0x7FFF_7FFF {
+                        overflow = true;
+                        0x7FFF
+                    } else {
+                        (x.wrapping_add(0x8000) >> 16) & 0xFFFF
+                    };
+                    (reduce(a) << 16) | reduce(b)
+                }
+            };
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            registers.write_register(rd, r);
+            // CMPGDU sets the ccond bits as well
+            if matches!(
+                decoded,
+                DspInstruction::CmpgduEqQb { .. } |
+                    DspInstruction::CmpgduLtQb { .. } |
+                    DspInstruction::CmpgduLeQb { .. }
+            )
+            {
+                control = (control & !CCOND) | (r << CCOND_SHIFT);
+            }
+            if overflow {
+                control |= 1 << OUFLAG_SHIFT;
+            }
+        }
+        // PRECR_SRA.PH.W, PRECR_SRA_R.PH.W, the halfwords of rt and rs are reduced into rt
+        DspInstruction::PrecrSraPhW { rt, rs, shift } |
+        DspInstruction::PrecrSraRPhW { rt, rs, shift } => {
+            let rounded = matches!(decoded, DspInstruction::PrecrSraRPhW { .. });
+            let reduce = |x: u32| shift_right(x as i32 as i64, shift, rounded) as u32 & 0xFFFF;
+            let r = (reduce(registers.read_register(rt)) << 16) |
+                reduce(registers.read_register(rs));
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rt),
+                get_register_name(rs),
+                shift,
+                r
+            );
+            registers.write_register(rt, r);
+        }
+        DspInstruction::ReplQb { rd, immediate } => {
+            let r = immediate * 0x0101_0101;
+            itrace!("repl.qb\t{},0x{:x} - result=0x{:08x}", get_register_name(rd), immediate, r);
+            registers.write_register(rd, r);
+        }
+        DspInstruction::ReplPh { rd, immediate } => {
+            let r = immediate as u16 as u32 * 0x0001_0001;
+            itrace!("repl.ph\t{},{} - result=0x{:08x}", get_register_name(rd), immediate, r);
+            registers.write_register(rd, r);
+        }
+        // absolute values, replications, precision expansions and BITREV
+        DspInstruction::ReplvQb { rd, rt } |
+        DspInstruction::ReplvPh { rd, rt } |
+        DspInstruction::PrecequPhQbl { rd, rt } |
+        DspInstruction::PrecequPhQbr { rd, rt } |
+        DspInstruction::PrecequPhQbla { rd, rt } |
+        DspInstruction::PrecequPhQbra { rd, rt } |
+        DspInstruction::PreceuPhQbl { rd, rt } |
+        DspInstruction::PreceuPhQbr { rd, rt } |
+        DspInstruction::PreceuPhQbla { rd, rt } |
+        DspInstruction::PreceuPhQbra { rd, rt } |
+        DspInstruction::PreceqWPhl { rd, rt } |
+        DspInstruction::PreceqWPhr { rd, rt } |
+        DspInstruction::AbsqSPh { rd, rt } |
+        DspInstruction::AbsqSW { rd, rt } |
+        DspInstruction::Bitrev { rd, rt } |
+        DspInstruction::AbsqSQb { rd, rt } => {
+            let b = registers.read_register(rt);
+            let expand = |high: u32, low: u32, shift: u32| {
+                (byte(b, high) << (16 + shift)) | (byte(b, low) << shift)
+            };
+            let r = match decoded {
+                DspInstruction::ReplvQb { .. } => (b & 0xFF) * 0x0101_0101,
+                DspInstruction::ReplvPh { .. } => (b & 0xFFFF) * 0x0001_0001,
+                DspInstruction::PrecequPhQbl { .. } => expand(3, 2, 7),
+                DspInstruction::PrecequPhQbr { .. } => expand(1, 0, 7),
+                DspInstruction::PrecequPhQbla { .. } => expand(3, 1, 7),
+                DspInstruction::PrecequPhQbra { .. } => expand(2, 0, 7),
+                DspInstruction::PreceuPhQbl { .. } => expand(3, 2, 0),
+                DspInstruction::PreceuPhQbr { .. } => expand(1, 0, 0),
+                DspInstruction::PreceuPhQbla { .. } => expand(3, 1, 0),
+                DspInstruction::PreceuPhQbra { .. } => expand(2, 0, 0),
+                DspInstruction::PreceqWPhl { .. } => b & 0xFFFF_0000,
+                DspInstruction::PreceqWPhr { .. } => b << 16,
+                DspInstruction::AbsqSPh { .. } => map_ph(b, 0, |x, _| if x == -0x8000 {
+                    overflow = true;
+                    0x7FFF
+                } else {
+                    x.abs()
+                }),
+                DspInstruction::AbsqSW { .. } => {
+                    let r = if b as i32 == i32::MIN {
+                        overflow = true;
+                        i32::MAX
+                    } else {
+                        (b as i32).abs()
+                    };
+                    r as u32
+                }
+                DspInstruction::AbsqSQb { .. } => map_qb(b, 0, |x, _| if x == 0x80 {
+                    overflow = true;
+                    0x7F
+                } else {
+                    (x as i8).unsigned_abs() as u32
+                }),
+                _ => (b as u16).reverse_bits() as u32,
+            };
+            itrace!(
+                "{}\t{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                r
+            );
+            registers.write_register(rd, r);
+            if overflow {
+                control |= 1 << OUFLAG_ADD;
+            }
+        }
+        // SHLL.QB, SHRL.QB, SHLLV.QB, SHRLV.QB
+        DspInstruction::ShllQb { rd, rt, .. } |
+        DspInstruction::ShrlQb { rd, rt, .. } |
+        DspInstruction::ShllvQb { rd, rt, .. } |
+        DspInstruction::ShrlvQb { rd, rt, .. } => {
+            let b = registers.read_register(rt);
+            let shift = match decoded {
+                DspInstruction::ShllvQb { rs, .. } | DspInstruction::ShrlvQb { rs, .. } => {
+                    registers.read_register(rs) & 0b111
+                }
+                DspInstruction::ShllQb { shift, .. } | DspInstruction::ShrlQb { shift, .. } => {
+                    shift
+                }
+                _ => unreachable!(),
+            };
+            let left = matches!(
+                decoded,
+                DspInstruction::ShllQb { .. } | DspInstruction::ShllvQb { .. }
+            );
+            let r = map_qb(b, 0, |x, _| if left {
+                overflow |= (x << shift) > 0xFF;
+                x << shift
+            } else {
+                x >> shift
+            });
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                shift,
+                r
+            );
+            registers.write_register(rd, r);
+            if overflow {
+                control |= 1 << OUFLAG_SHIFT;
+            }
+        }
+        // SHRA.QB, SHRAV.QB and their rounding variants
+        DspInstruction::ShraQb { rd, rt, .. } |
+        DspInstruction::ShraRQb { rd, rt, .. } |
+        DspInstruction::ShravQb { rd, rt, .. } |
+        DspInstruction::ShravRQb { rd, rt, .. } => {
+            let b = registers.read_register(rt);
+            let shift = match decoded {
+                DspInstruction::ShravQb { rs, .. } | DspInstruction::ShravRQb { rs, .. } => {
+                    registers.read_register(rs) & 0b111
+                }
+                DspInstruction::ShraQb { shift, .. } | DspInstruction::ShraRQb { shift, .. } => {
+                    shift
+                }
+                _ => unreachable!(),
+            };
+            let rounded = matches!(
+                decoded,
+                DspInstruction::ShraRQb { .. } | DspInstruction::ShravRQb { .. }
+            );
+            let r = map_qb(b, 0, |x, _| shift_right(x as i8 as i64, shift, rounded) as u32);
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                shift,
+                r
+            );
+            registers.write_register(rd, r);
+        }
+        // SHLL.PH, SHRA.PH, SHRL.PH, their V variants and the saturating and rounding variants
+        DspInstruction::ShllPh { rd, rt, .. } |
+        DspInstruction::ShraPh { rd, rt, .. } |
+        DspInstruction::ShllSPh { rd, rt, .. } |
+        DspInstruction::ShraRPh { rd, rt, .. } |
+        DspInstruction::ShllvPh { rd, rt, .. } |
+        DspInstruction::ShravPh { rd, rt, .. } |
+        DspInstruction::ShllvSPh { rd, rt, .. } |
+        DspInstruction::ShravRPh { rd, rt, .. } |
+        DspInstruction::ShrlPh { rd, rt, .. } |
+        DspInstruction::ShrlvPh { rd, rt, .. } => {
+            let b = registers.read_register(rt);
+            let shift = match decoded {
+                DspInstruction::ShllvPh { rs, .. } |
+                DspInstruction::ShravPh { rs, .. } |
+                DspInstruction::ShllvSPh { rs, .. } |
+                DspInstruction::ShravRPh { rs, .. } |
+                DspInstruction::ShrlvPh { rs, .. } => registers.read_register(rs) & 0b1111,
+                DspInstruction::ShllPh { shift, .. } |
+                DspInstruction::ShraPh { shift, .. } |
+                DspInstruction::ShllSPh { shift, .. } |
+                DspInstruction::ShraRPh { shift, .. } |
+                DspInstruction::ShrlPh { shift, .. } => shift,
+                _ => unreachable!(),
+            };
+            let left = matches!(
+                decoded,
+                DspInstruction::ShllPh { .. } |
+                    DspInstruction::ShllSPh { .. } |
+                    DspInstruction::ShllvPh { .. } |
+                    DspInstruction::ShllvSPh { .. }
+            );
+            let modified = matches!(
+                decoded,
+                DspInstruction::ShllSPh { .. } |
+                    DspInstruction::ShraRPh { .. } |
+                    DspInstruction::ShllvSPh { .. } |
+                    DspInstruction::ShravRPh { .. }
+            );
+            let logical = matches!(
+                decoded,
+                DspInstruction::ShrlPh { .. } | DspInstruction::ShrlvPh { .. }
+            );
+            let r = map_ph(b, 0, |x, _| if left {
+                shift_left(x as i64, shift, 16, modified, &mut overflow) as i32
+            } else if logical {
+                (x & 0xFFFF) >> shift
+            } else {
+                shift_right(x as i64, shift, modified) as i32
+            });
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                shift,
+                r
+            );
+            registers.write_register(rd, r);
+            if overflow {
+                control |= 1 << OUFLAG_SHIFT;
+            }
+        }
+        // SHLL_S.W, SHRA_R.W, SHLLV_S.W, SHRAV_R.W
+        DspInstruction::ShllSW { rd, rt, .. } |
+        DspInstruction::ShraRW { rd, rt, .. } |
+        DspInstruction::ShllvSW { rd, rt, .. } |
+        DspInstruction::ShravRW { rd, rt, .. } => {
+            let b = registers.read_register(rt);
+            let shift = match decoded {
+                DspInstruction::ShllvSW { rs, .. } | DspInstruction::ShravRW { rs, .. } => {
+                    registers.read_register(rs) & 0b11111
+                }
+                DspInstruction::ShllSW { shift, .. } | DspInstruction::ShraRW { shift, .. } => {
+                    shift
+                }
+                _ => unreachable!(),
+            };
+            let left = matches!(
+                decoded,
+                DspInstruction::ShllSW { .. } | DspInstruction::ShllvSW { .. }
+            );
+            let r = if left {
+                shift_left(b as i32 as i64, shift, 32, true, &mut overflow)
+            } else {
+                shift_right(b as i32 as i64, shift, true)
+            } as u32;
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rt),
+                shift,
+                r
+            );
+            registers.write_register(rd, r);
+            if overflow {
+                control |= 1 << OUFLAG_SHIFT;
+            }
+        }
+        // dot products and multiply-accumulate
+        DspInstruction::DpauHQbl { ac, rs, rt } |
+        DspInstruction::DpauHQbr { ac, rs, rt } |
+        DspInstruction::DpsuHQbl { ac, rs, rt } |
+        DspInstruction::DpsuHQbr { ac, rs, rt } |
+        DspInstruction::DpaqSWPh { ac, rs, rt } |
+        DspInstruction::DpsqSWPh { ac, rs, rt } |
+        DspInstruction::MulsaqSWPh { ac, rs, rt } |
+        DspInstruction::DpaqSaLW { ac, rs, rt } |
+        DspInstruction::DpsqSaLW { ac, rs, rt } |
+        DspInstruction::MaqSaWPhl { ac, rs, rt } |
+        DspInstruction::MaqSaWPhr { ac, rs, rt } |
+        DspInstruction::MaqSWPhl { ac, rs, rt } |
+        DspInstruction::MaqSWPhr { ac, rs, rt } |
+        DspInstruction::DpaWPh { ac, rs, rt } |
+        DspInstruction::DpsWPh { ac, rs, rt } |
+        DspInstruction::DpaxWPh { ac, rs, rt } |
+        DspInstruction::DpsxWPh { ac, rs, rt } |
+        DspInstruction::DpaqxSWPh { ac, rs, rt } |
+        DspInstruction::DpaqxSaWPh { ac, rs, rt } |
+        DspInstruction::DpsqxSWPh { ac, rs, rt } |
+        DspInstruction::DpsqxSaWPh { ac, rs, rt } |
+        DspInstruction::MulsaWPh { ac, rs, rt } => {
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let acc = registers.read_accumulator(ac) as i64;
+            let high = |x: u32| (x >> 16) as i16 as i32;
+            let low = |x: u32| x as i16 as i32;
+            let r = match decoded {
+                DspInstruction::DpauHQbl { .. } |
+                DspInstruction::DpauHQbr { .. } |
+                DspInstruction::DpsuHQbl { .. } |
+                DspInstruction::DpsuHQbr { .. } => {
+                    let lane = match decoded {
+                        DspInstruction::DpauHQbl { .. } | DspInstruction::DpsuHQbl { .. } => 2,
+                        _ => 0,
+                    };
+                    let product = (byte(a, lane + 1) * byte(b, lane + 1) +
+                                       byte(a, lane) * byte(b, lane)) as i64;
+                    match decoded {
+                        DspInstruction::DpauHQbl { .. } | DspInstruction::DpauHQbr { .. } => {
+                            acc.wrapping_add(product)
+                        }
+                        _ => acc.wrapping_sub(product),
+                    }
+                }
+                DspInstruction::DpaqSWPh { .. } |
+                DspInstruction::DpsqSWPh { .. } |
+                DspInstruction::MulsaqSWPh { .. } => {
+                    let left = multiply_q15(high(a), high(b), &mut overflow) as i64;
+                    let right = multiply_q15(low(a), low(b), &mut overflow) as i64;
+                    match decoded {
+                        DspInstruction::DpaqSWPh { .. } => acc.wrapping_add(left + right),
+                        DspInstruction::DpsqSWPh { .. } => acc.wrapping_sub(left + right),
+                        _ => acc.wrapping_add(left - right),
+                    }
+                }
+                // integer products, the X variants cross the halfwords
+                DspInstruction::DpaWPh { .. } |
+                DspInstruction::DpsWPh { .. } |
+                DspInstruction::DpaxWPh { .. } |
+                DspInstruction::DpsxWPh { .. } |
+                DspInstruction::MulsaWPh { .. } => {
+                    let b = match decoded {
+                        DspInstruction::DpaxWPh { .. } | DspInstruction::DpsxWPh { .. } => {
+                            b.rotate_left(16)
+                        }
+                        _ => b,
+                    };
+                    let left = high(a) as i64 * high(b) as i64;
+                    let right = low(a) as i64 * low(b) as i64;
+                    match decoded {
+                        DspInstruction::DpaWPh { .. } | DspInstruction::DpaxWPh { .. } => {
+                            acc.wrapping_add(left + right)
+                        }
+                        DspInstruction::DpsWPh { .. } | DspInstruction::DpsxWPh { .. } => {
+                            acc.wrapping_sub(left + right)
+                        }
+                        _ => acc.wrapping_add(left - right),
+                    }
+                }
+                // Q15 cross products, the SA variants saturate the accumulator to Q31
+                DspInstruction::DpaqxSWPh { .. } |
+                DspInstruction::DpaqxSaWPh { .. } |
+                DspInstruction::DpsqxSWPh { .. } |
+                DspInstruction::DpsqxSaWPh { .. } => {
+                    let sum = multiply_q15(high(a), low(b), &mut overflow) as i64 +
+                        multiply_q15(low(a), high(b), &mut overflow) as i64;
+                    let r = match decoded {
+                        DspInstruction::DpaqxSWPh { .. } | DspInstruction::DpaqxSaWPh { .. } => {
+                            acc.wrapping_add(sum)
+                        }
+                        _ => acc.wrapping_sub(sum),
+                    };
+                    match decoded {
+                        DspInstruction::DpaqxSaWPh { .. } | DspInstruction::DpsqxSaWPh { .. } => {
+                            saturate(r, 32, &mut overflow)
+                        }
+                        _ => r,
+                    }
+                }
+                // the accumulation saturates too
+                DspInstruction::DpaqSaLW { .. } | DspInstruction::DpsqSaLW { .. } => {
+                    let product = multiply_q31(a as i32, b as i32, &mut overflow);
+                    let (r, lost) = if let DspInstruction::DpaqSaLW { .. } = decoded {
+                        acc.overflowing_add(product)
+                    } else {
+                        acc.overflowing_sub(product)
+                    };
+                    if lost {
+                        overflow = true;
+                        if r < 0 { i64::MAX } else { i64::MIN }
+                    } else {
+                        r
+                    }
+                }
+                _ => {
+                    let product = match decoded {
+                        DspInstruction::MaqSaWPhr { .. } | DspInstruction::MaqSWPhr { .. } => {
+                            multiply_q15(low(a), low(b), &mut overflow)
+                        }
+                        _ => multiply_q15(high(a), high(b), &mut overflow),
+                    };
+                    let r = acc.wrapping_add(product as i64);
+                    match decoded {
+                        DspInstruction::MaqSaWPhl { .. } | DspInstruction::MaqSaWPhr { .. } => {
+                            saturate(r, 32, &mut overflow)
+                        }
+                        _ => r,
+                    }
+                }
+            };
+            itrace!(
+                "{}\tac{},{},{} - acc=0x{:016x}",
+                name,
+                ac,
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            registers.write_accumulator(ac, r as u64);
+            if overflow {
+                control |= 1 << (OUFLAG_ACCUMULATOR + ac);
+            }
+        }
+        // EXTR.W, EXTR_R.W, EXTR_RS.W, EXTR_S.H and their V variants
+        DspInstruction::ExtrW { rt, ac, .. } |
+        DspInstruction::ExtrRW { rt, ac, .. } |
+        DspInstruction::ExtrRsW { rt, ac, .. } |
+        DspInstruction::ExtrSH { rt, ac, .. } |
+        DspInstruction::ExtrvW { rt, ac, .. } |
+        DspInstruction::ExtrvRW { rt, ac, .. } |
+        DspInstruction::ExtrvRsW { rt, ac, .. } |
+        DspInstruction::ExtrvSH { rt, ac, .. } => {
+            let acc = registers.read_accumulator(ac) as i64;
+            let shift = match decoded {
+                DspInstruction::ExtrvW { rs, .. } |
+                DspInstruction::ExtrvRW { rs, .. } |
+                DspInstruction::ExtrvRsW { rs, .. } |
+                DspInstruction::ExtrvSH { rs, .. } => registers.read_register(rs) & 0b11111,
+                DspInstruction::ExtrW { shift, .. } |
+                DspInstruction::ExtrRW { shift, .. } |
+                DspInstruction::ExtrRsW { shift, .. } |
+                DspInstruction::ExtrSH { shift, .. } => shift,
+                _ => unreachable!(),
+            };
+            let shifted = shift_right(acc, shift, false);
+            let rounded = shift_right(acc, shift, true);
+            let r = match decoded {
+                DspInstruction::ExtrSH { .. } | DspInstruction::ExtrvSH { .. } => {
+                    saturate(shifted, 16, &mut overflow)
+                }
+                _ => {
+                    saturate(shifted, 32, &mut overflow);
+                    saturate(rounded, 32, &mut overflow);
+                    match decoded {
+                        DspInstruction::ExtrRsW { .. } | DspInstruction::ExtrvRsW { .. } => {
+                            saturate(rounded, 32, &mut overflow)
+                        }
+                        DspInstruction::ExtrRW { .. } | DspInstruction::ExtrvRW { .. } => {
+                            rounded
+                        }
+                        _ => shifted,
+                    }
+                }
+            };
+            itrace!(
+                "{}\t{},ac{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rt),
+                ac,
+                shift,
+                r as u32
+            );
+            registers.write_register(rt, r as u32);
+            if overflow {
+                control |= 1 << OUFLAG_EXTRACT;
+            }
+        }
+        // EXTP, EXTPV, EXTPDP, EXTPDPV extract size + 1 bits ending at pos
+        DspInstruction::Extp { rt, ac, .. } |
+        DspInstruction::Extpdp { rt, ac, .. } |
+        DspInstruction::Extpv { rt, ac, .. } |
+        DspInstruction::Extpdpv { rt, ac, .. } => {
+            let acc = registers.read_accumulator(ac) as i64;
+            let size = match decoded {
+                DspInstruction::Extpv { rs, .. } | DspInstruction::Extpdpv { rs, .. } => {
+                    registers.read_register(rs) & 0b11111
+                }
+                DspInstruction::Extp { size, .. } | DspInstruction::Extpdp { size, .. } => size,
+                _ => unreachable!(),
+            };
+            let pos = control & DSP_CONTROL_POS;
+            let decrement = matches!(
+                decoded,
+                DspInstruction::Extpdp { .. } | DspInstruction::Extpdpv { .. }
+            );
+            itrace!(
+                "{}\t{},ac{},{} - pos={}",
+                name,
+                get_register_name(rt),
+                ac,
+                size,
+                pos
+            );
+            if pos < size {
+                // the result is UNPREDICTABLE, the register is left unchanged
+                control |= EFI;
+            } else {
+                control &= !EFI;
+                let r = (acc as u64 >> (pos - size)) as u32 & bit_mask(size + 1);
+                registers.write_register(rt, r);
+                if decrement {
+                    // pos equal to the size wraps around to 63
+                    let pos = pos.wrapping_sub(size + 1) & DSP_CONTROL_POS;
+                    control = (control & !DSP_CONTROL_POS) | pos;
+                }
+            }
+        }
+        // APPEND, PREPEND, BALIGN shift rt and fill it with bits of rs
+        DspInstruction::Append { rt, rs, .. } |
+        DspInstruction::Prepend { rt, rs, .. } |
+        DspInstruction::Balign { rt, rs, .. } => {
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let (shift, r) = match decoded {
+                DspInstruction::Append { shift, .. } if shift > 0 => {
+                    (shift, (b << shift) | (a & bit_mask(shift)))
+                }
+                DspInstruction::Prepend { shift, .. } if shift > 0 => {
+                    (shift, (a << (32 - shift)) | (b >> shift))
+                }
+                // byte positions 0 and 2 leave rt unchanged like on QEMU
+                DspInstruction::Balign { bp, .. } if bp & 1 != 0 => {
+                    (bp, (b << (8 * bp)) | (a >> (8 * (4 - bp))))
+                }
+                DspInstruction::Append { shift, .. } |
+                DspInstruction::Prepend { shift, .. } |
+                DspInstruction::Balign { bp: shift, .. } => (shift, b),
+                _ => unreachable!(),
+            };
+            itrace!(
+                "{}\t{},{},{} - result=0x{:08x}",
+                name,
+                get_register_name(rt),
+                get_register_name(rs),
+                shift,
+                r
+            );
+            registers.write_register(rt, r);
+        }
+        DspInstruction::Rddsp { rd, mask } => {
+            let r = MASK_FIELDS
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| mask & (1 << i) != 0)
+                .fold(0, |r, (_, field)| r | (control & field));
+            itrace!("rddsp\t{},0x{:x} - data=0x{:08x}", get_register_name(rd), mask, r);
+            registers.write_register(rd, r);
+        }
+        DspInstruction::Wrdsp { rs, mask } => {
+            let a = registers.read_register(rs);
+            let fields = MASK_FIELDS
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| mask & (1 << i) != 0)
+                .fold(0, |r, (_, field)| r | field);
+            itrace!("wrdsp\t{},0x{:x} - data=0x{:08x}", get_register_name(rs), mask, a);
+            control = (control & !fields) | (a & fields);
+        }
+        // SHILO, SHILOV, positive shift is to the right
+        DspInstruction::Shilo { ac, .. } | DspInstruction::Shilov { ac, .. } => {
+            let shift = match decoded {
+                DspInstruction::Shilov { rs, .. } => {
+                    sign_extend(registers.read_register(rs) & 0x3F, 6)
+                }
+                DspInstruction::Shilo { shift, .. } => shift,
+                _ => unreachable!(),
+            };
+            let acc = registers.read_accumulator(ac);
+            let r = if shift < 0 {
+                acc << -shift
+            } else {
+                acc >> shift
+            };
+            itrace!("{}\tac{},{} - acc=0x{:016x}", name, ac, shift, r);
+            registers.write_accumulator(ac, r);
+        }
+        DspInstruction::Mthlip { rs, ac } => {
+            let acc = registers.read_accumulator(ac);
+            let r = (acc << 32) | registers.read_register(rs) as u64;
+            let pos = ((control & DSP_CONTROL_POS) + 32) & DSP_CONTROL_POS;
+            itrace!("mthlip\t{},ac{} - acc=0x{:016x}", get_register_name(rs), ac, r);
+            registers.write_accumulator(ac, r);
+            control = (control & !DSP_CONTROL_POS) | pos;
+        }
+    }
+
+    registers.write_dsp_control(control);
+    CPUEvent::Nothing
+}
+
+#[test]
+fn test_lanes() {
+    let mut overflow = false;
+    assert_eq!(map_qb(0x01FF_8040, 0x0101_8040, |x, y| x + y), 0x0200_0080);
+    assert_eq!(map_ph(0x7FFF_0001, 0x0001_FFFF, |x, y| x + y), 0x8000_0000);
+    assert_eq!(saturate(0x8000, 16, &mut overflow), 0x7FFF);
+    assert!(overflow);
+    assert_eq!(multiply_q15(0x4000, 0x4000, &mut overflow), 0x2000_0000);
+    assert_eq!(shift_right(-3, 1, true), -1);
+}
+
+#[test]
+fn test_instructions() {
+    use cpu::instructions::test_machine;
+
+    let encode = |rs: u32, rt: u32, rd: u32, op: u32, funct: u32| {
+        0x7C00_0000 | (rs << 21) | (rt << 16) | (rd << 11) | (op << 6) | funct
+    };
+    let run = |instruction: u32, registers: &mut RegisterFile, memory: &mut Memory| {
+        let decoded = decode_dsp_instruction(instruction).unwrap();
+        assert!(eval_dsp_instruction(decoded, registers, memory) == CPUEvent::Nothing);
+    };
+    let (mut registers, mut memory, _) = test_machine();
+    let (r, m) = (&mut registers, &mut memory);
+
+    // addu_s.qb v0,a0,a1 saturates two of the bytes
+    r.write_register(4, 0xFF01_8040);
+    r.write_register(5, 0x0101_8040);
+    run(encode(4, 5, 2, 0b00100, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(2), 0xFF02_FF80);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_ADD);
+
+    // mulq_rs.ph v0,a0,a1 saturates -1.0 * -1.0
+    r.write_dsp_control(0);
+    r.write_register(4, 0x8000_4000);
+    r.write_register(5, 0x8000_4000);
+    run(encode(4, 5, 2, 0b11111, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(2), 0x7FFF_2000);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_MULTIPLY);
+
+    // addsc v0,a0,a1 sets the carry, addwc v1,a2,a3 adds it
+    r.write_dsp_control(0);
+    r.write_register(4, 0xFFFF_FFFF);
+    r.write_register(5, 2);
+    run(encode(4, 5, 2, 0b10000, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(2), 1);
+    assert_eq!(r.read_dsp_control(), CARRY);
+    r.write_register(6, 5);
+    r.write_register(7, 6);
+    run(encode(6, 7, 3, 0b10001, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(3), 12);
+    assert_eq!(r.read_dsp_control(), CARRY);
+    r.write_register(6, 0x7FFF_FFFF);
+    r.write_register(7, 0);
+    run(encode(6, 7, 3, 0b10001, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(3), 0x8000_0000);
+    assert_eq!(r.read_dsp_control(), CARRY | 1 << OUFLAG_ADD);
+
+    // dpaq_s.w.ph ac1,a0,a1 with the right product saturated
+    r.write_dsp_control(0);
+    r.write_register(4, 0x4000_8000);
+    r.write_register(5, 0x4000_8000);
+    r.write_accumulator(1, 0);
+    run(encode(4, 5, 1, 0b00100, FUNCT_DPA_W_PH), r, m);
+    assert_eq!(r.read_accumulator(1), 0x9FFF_FFFF);
+    assert_eq!(r.read_dsp_control(), 1 << (OUFLAG_ACCUMULATOR + 1));
+
+    // extr_r.w v0,ac1,4 rounds, extr_rs.w v0,ac1,0 saturates
+    r.write_dsp_control(0);
+    run(encode(4, 2, 1, 0b00100, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_register(2), 0x0A00_0000);
+    assert_eq!(r.read_dsp_control(), 0);
+    r.write_accumulator(1, 1 << 36);
+    run(encode(0, 2, 1, 0b00110, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_register(2), 0x7FFF_FFFF);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_EXTRACT);
+
+    // shilo ac1,-8 shifts to the left, shilo ac1,16 to the right
+    r.write_accumulator(1, 0x0000_0012_3456_7800);
+    run(0x7F80_0EB8, r, m);
+    assert_eq!(r.read_accumulator(1), 0x0000_1234_5678_0000);
+    run(0x7D00_0EB8, r, m);
+    assert_eq!(r.read_accumulator(1), 0x0000_0000_1234_5678);
+
+    // mthlip a0,ac1 shifts lo into hi and advances pos by 32
+    r.write_dsp_control(10);
+    r.write_register(4, 0x9ABC_DEF0);
+    run(encode(4, 0, 1, 0b11111, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_accumulator(1), 0x1234_5678_9ABC_DEF0);
+    assert_eq!(r.read_dsp_control(), 42);
+
+    // extp v0,ac1,7 extracts 8 bits ending at pos, extpdp v0,ac1,7 decrements pos too
+    run(encode(7, 2, 1, 0b00010, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_register(2), 0xCF);
+    assert_eq!(r.read_dsp_control(), 42);
+    r.write_register(2, 0);
+    run(encode(7, 2, 1, 0b01010, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_register(2), 0xCF);
+    assert_eq!(r.read_dsp_control(), 34);
+    // EFI is set when pos is lower than the size and cleared by the next extraction
+    r.write_dsp_control(3);
+    run(encode(7, 2, 1, 0b00010, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_register(2), 0xCF);
+    assert_eq!(r.read_dsp_control(), EFI | 3);
+    run(encode(3, 2, 1, 0b01010, FUNCT_EXTR_W), r, m);
+    assert_eq!(r.read_register(2), 0);
+    assert_eq!(r.read_dsp_control(), 63);
+
+    // revision 2: addu.ph v0,a0,a1 wraps, addu_s.ph v0,a0,a1 and subu_s.ph v0,a1,a0 saturate
+    r.write_dsp_control(0);
+    r.write_register(4, 0xFFFF_0001);
+    r.write_register(5, 0x0002_0003);
+    run(encode(4, 5, 2, 0b01000, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(2), 0x0001_0004);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_ADD);
+    run(encode(4, 5, 2, 0b01100, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(2), 0xFFFF_0004);
+    run(encode(5, 4, 2, 0b01101, FUNCT_ADDU_QB), r, m);
+    assert_eq!(r.read_register(2), 0x0000_0002);
+
+    // adduh_r.qb v0,a0,a1 rounds, subuh.qb v0,a1,a0 halves negative differences
+    r.write_dsp_control(0);
+    r.write_register(4, 0xFF01_0203);
+    r.write_register(5, 0x0100_0202);
+    run(encode(4, 5, 2, 0b00010, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x8001_0203);
+    run(encode(5, 4, 2, 0b00001, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x81FF_00FF);
+    // addqh_r.w v0,a0,a0 and subqh.w v0,a1,a0 can't overflow
+    r.write_register(4, 0x7FFF_FFFF);
+    r.write_register(5, 0x8000_0000);
+    run(encode(4, 4, 2, 0b10010, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x7FFF_FFFF);
+    run(encode(5, 4, 2, 0b10001, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x8000_0000);
+    assert_eq!(r.read_dsp_control(), 0);
+
+    // mul.ph v0,a0,a1 wraps and mul_s.ph v0,a0,a1 saturates 256 * 256
+    r.write_register(4, 0x0100_FFFE);
+    r.write_register(5, 0x0100_0003);
+    run(encode(4, 5, 2, 0b01100, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x0000_FFFA);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_MULTIPLY);
+    run(encode(4, 5, 2, 0b01110, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x7FFF_FFFA);
+    // mulq_rs.w v0,a0,a1 of 0.5 and 0.75, mulq_s.w v0,a1,a1 saturates -1.0 * -1.0
+    r.write_dsp_control(0);
+    r.write_register(4, 0x4000_0000);
+    r.write_register(5, 0x6000_0000);
+    run(encode(4, 5, 2, 0b10111, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x3000_0000);
+    assert_eq!(r.read_dsp_control(), 0);
+    r.write_register(5, 0x8000_0000);
+    run(encode(5, 5, 2, 0b10110, FUNCT_ADDUH_QB), r, m);
+    assert_eq!(r.read_register(2), 0x7FFF_FFFF);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_MULTIPLY);
+
+    // cmpgdu.lt.qb v0,a0,a1 writes both v0 and ccond
+    r.write_dsp_control(0);
+    r.write_register(4, 0x0102_0304);
+    r.write_register(5, 0x0202_0205);
+    run(encode(4, 5, 2, 0b11001, FUNCT_CMPU_EQ_QB), r, m);
+    assert_eq!(r.read_register(2), 0b1001);
+    assert_eq!(r.read_dsp_control(), 0b1001 << CCOND_SHIFT);
+
+    // precr.qb.ph v0,a0,a1 keeps the even bytes
+    r.write_dsp_control(0);
+    r.write_register(4, 0x1122_3344);
+    r.write_register(5, 0x5566_7788);
+    run(encode(4, 5, 2, 0b01101, FUNCT_CMPU_EQ_QB), r, m);
+    assert_eq!(r.read_register(2), 0x2244_6688);
+    // precr_sra.ph.w v0,a0,4 and precr_sra_r.ph.w v0,a0,4 reduce v0 into the left halfword
+    r.write_register(4, 0xFFFF_FFF8);
+    r.write_register(2, 0x0001_2348);
+    run(encode(4, 2, 4, 0b11110, FUNCT_CMPU_EQ_QB), r, m);
+    assert_eq!(r.read_register(2), 0x1234_FFFF);
+    r.write_register(2, 0x0001_2348);
+    run(encode(4, 2, 4, 0b11111, FUNCT_CMPU_EQ_QB), r, m);
+    assert_eq!(r.read_register(2), 0x1235_0000);
+
+    // absq_s.qb v0,a1 saturates -128
+    r.write_register(5, 0x80FF_7F01);
+    run(encode(0, 5, 2, 0b00001, FUNCT_ABSQ_S_PH), r, m);
+    assert_eq!(r.read_register(2), 0x7F01_7F01);
+    assert_eq!(r.read_dsp_control(), 1 << OUFLAG_ADD);
+
+    // shra.qb v0,a1,2 and shra_r.qb v0,a1,2 are arithmetic, shrl.ph v0,a1,4 is logical
+    r.write_dsp_control(0);
+    r.write_register(5, 0x80FE_0306);
+    run(encode(2, 5, 2, 0b00100, FUNCT_SHLL_QB), r, m);
+    assert_eq!(r.read_register(2), 0xE0FF_0001);
+    run(encode(2, 5, 2, 0b00101, FUNCT_SHLL_QB), r, m);
+    assert_eq!(r.read_register(2), 0xE000_0102);
+    r.write_register(5, 0x8000_FFF0);
+    run(encode(4, 5, 2, 0b11001, FUNCT_SHLL_QB), r, m);
+    assert_eq!(r.read_register(2), 0x0800_0FFF);
+    assert_eq!(r.read_dsp_control(), 0);
+
+    // dpax.w.ph ac2,a0,a1 crosses the halfwords, dps.w.ph ac2,a0,a1 does not
+    r.write_register(4, 0x0002_0003);
+    r.write_register(5, 0x0004_0005);
+    r.write_accumulator(2, 100);
+    run(encode(4, 5, 2, 0b01000, FUNCT_DPA_W_PH), r, m);
+    assert_eq!(r.read_accumulator(2), 122);
+    run(encode(4, 5, 2, 0b00001, FUNCT_DPA_W_PH), r, m);
+    assert_eq!(r.read_accumulator(2), 99);
+    // mulsa.w.ph ac2,a0,a1
+    run(encode(4, 5, 2, 0b00010, FUNCT_DPA_W_PH), r, m);
+    assert_eq!(r.read_accumulator(2), 92);
+    // dpaqx_sa.w.ph ac3,a0,a0 saturates the accumulator to Q31
+    r.write_register(4, 0x4000_4000);
+    r.write_accumulator(3, 0x7FFF_0000);
+    run(encode(4, 4, 3, 0b11010, FUNCT_DPA_W_PH), r, m);
+    assert_eq!(r.read_accumulator(3), 0x7FFF_FFFF);
+    assert_eq!(r.read_dsp_control(), 1 << (OUFLAG_ACCUMULATOR + 3));
+    // dpsqx_s.w.ph ac3,a1,a1 saturates the products only
+    r.write_dsp_control(0);
+    r.write_register(5, 0x8000_8000);
+    run(encode(5, 5, 3, 0b11001, FUNCT_DPA_W_PH), r, m);
+    assert_eq!(r.read_accumulator(3), 0xFFFF_FFFF_8000_0001);
+    assert_eq!(r.read_dsp_control(), 1 << (OUFLAG_ACCUMULATOR + 3));
+
+    // append v0,a0,8, prepend v0,a0,8 and balign v0,a0 with byte positions 1 and 2
+    r.write_register(4, 0xAABB_CCDD);
+    r.write_register(2, 0x1234_5678);
+    run(encode(4, 2, 8, 0b00000, FUNCT_APPEND), r, m);
+    assert_eq!(r.read_register(2), 0x3456_78DD);
+    r.write_register(2, 0x1234_5678);
+    run(encode(4, 2, 8, 0b00001, FUNCT_APPEND), r, m);
+    assert_eq!(r.read_register(2), 0xDD12_3456);
+    r.write_register(2, 0x1234_5678);
+    run(encode(4, 2, 1, 0b10000, FUNCT_APPEND), r, m);
+    assert_eq!(r.read_register(2), 0x3456_78AA);
+    run(encode(4, 2, 2, 0b10000, FUNCT_APPEND), r, m);
+    assert_eq!(r.read_register(2), 0x3456_78AA);
+}
@@ -0,0 +1,304 @@
+//! Minimal x86-64 machine code emitter used by the JIT. It knows just the handful of instruction
+//! forms the translator needs, operands are always registers, immediates or `[base + disp32]`.
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Reg {
+    Rax = 0,
+    Rcx = 1,
+    Rdx = 2,
+    Rbx = 3,
+    Rsp = 4,
+    Rbp = 5,
+    Rsi = 6,
+    Rdi = 7,
+    R12 = 12,
+    R13 = 13,
+    R14 = 14,
+}
+
+impl Reg {
+    fn low(self) -> u8 {
+        self as u8 & 0b111
+    }
+
+    fn high(self) -> u8 {
+        (self as u8 >> 3) & 1
+    }
+}
+
+/// Condition codes of Jcc, SETcc and CMOVcc
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Cond {
+    Below = 0x2,
+    Equal = 0x4,
+    NotEqual = 0x5,
+    NotSign = 0x9,
+    Less = 0xC,
+    GreaterEqual = 0xD,
+    LessEqual = 0xE,
+    Greater = 0xF,
+}
+
+/// Two operand ALU operations, the value is the /digit of the immediate form
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Alu {
+    Add = 0,
+    Or = 1,
+    And = 4,
+    Sub = 5,
+    Xor = 6,
+    Cmp = 7,
+}
+
+/// Shifts and rotations, the value is the /digit of the instruction
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Shift {
+    Rol = 0,
+    Ror = 1,
+    Shl = 4,
+    Shr = 5,
+    Sar = 7,
+}
+
+/// Width and extension of a load from `[base + index]`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Load {
+    ByteSigned,
+    ByteUnsigned,
+    Halfword,
+    Word,
+}
+
+pub struct Emitter {
+    code: Vec<u8>,
+}
+
+impl Emitter {
+    pub fn new() -> Emitter {
+        Emitter { code: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn append(&mut self, other: Emitter) {
+        self.code.extend(other.code);
+    }
+
+    fn byte(&mut self, b: u8) {
+        self.code.push(b);
+    }
+
+    fn dword(&mut self, d: u32) {
+        self.code.extend_from_slice(&[d as u8, (d >> 8) as u8, (d >> 16) as u8, (d >> 24) as u8]);
+    }
+
+    /// REX prefix, omitted when it would be empty
+    fn rex(&mut self, wide: bool, reg: u8, base: u8) {
+        let rex = 0x40 | ((wide as u8) << 3) | (reg << 2) | base;
+        if rex != 0x40 {
+            self.byte(rex);
+        }
+    }
+
+    /// Opcode with register-register ModRM, `reg` is a register or an opcode extension
+    fn op_rr(&mut self, wide: bool, opcode: &[u8], reg: u8, rm: Reg) {
+        self.rex(wide, (reg >> 3) & 1, rm.high());
+        self.code.extend_from_slice(opcode);
+        self.byte(0xC0 | ((reg & 0b111) << 3) | rm.low());
+    }
+
+    /// Opcode with `[base + disp32]` memory operand
+    fn op_mem(&mut self, wide: bool, opcode: &[u8], reg: u8, base: Reg, disp: i32) {
+        self.rex(wide, (reg >> 3) & 1, base.high());
+        self.code.extend_from_slice(opcode);
+        self.byte(0x80 | ((reg & 0b111) << 3) | base.low());
+        if base.low() == Reg::Rsp.low() {
+            self.byte(0x24);
+        }
+        self.dword(disp as u32);
+    }
+
+    pub fn push(&mut self, reg: Reg) {
+        self.rex(false, 0, reg.high());
+        self.byte(0x50 + reg.low());
+    }
+
+    pub fn pop(&mut self, reg: Reg) {
+        self.rex(false, 0, reg.high());
+        self.byte(0x58 + reg.low());
+    }
+
+    pub fn ret(&mut self) {
+        self.byte(0xC3);
+    }
+
+    /// `call reg`
+    pub fn call(&mut self, reg: Reg) {
+        self.op_rr(false, &[0xFF], 2, reg);
+    }
+
+    pub fn mov(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x89], src as u8, dst);
+    }
+
+    /// `mov dst32, imm32`, the upper half of the register is cleared
+    pub fn mov_imm32(&mut self, dst: Reg, imm: u32) {
+        self.rex(false, 0, dst.high());
+        self.byte(0xB8 + dst.low());
+        self.dword(imm);
+    }
+
+    pub fn mov_imm64(&mut self, dst: Reg, imm: u64) {
+        self.rex(true, 0, dst.high());
+        self.byte(0xB8 + dst.low());
+        self.dword(imm as u32);
+        self.dword((imm >> 32) as u32);
+    }
+
+    pub fn load(&mut self, wide: bool, dst: Reg, base: Reg, disp: i32) {
+        self.op_mem(wide, &[0x8B], dst as u8, base, disp);
+    }
+
+    pub fn store(&mut self, wide: bool, base: Reg, disp: i32, src: Reg) {
+        self.op_mem(wide, &[0x89], src as u8, base, disp);
+    }
+
+    /// Stores an immediate, 64 bit stores sign extend it
+    pub fn store_imm(&mut self, wide: bool, base: Reg, disp: i32, imm: u32) {
+        self.op_mem(wide, &[0xC7], 0, base, disp);
+        self.dword(imm);
+    }
+
+    /// Loads from `[base + index]` into the lower 32 bits of `dst`, bytes are extended to 32 bits
+    /// and halfwords zero extended. `base` must not be RBP or R13.
+    pub fn load_indexed(&mut self, load: Load, dst: Reg, base: Reg, index: Reg) {
+        let opcode: &[u8] = match load {
+            Load::ByteSigned => &[0x0F, 0xBE],
+            Load::ByteUnsigned => &[0x0F, 0xB6],
+            Load::Halfword => &[0x0F, 0xB7],
+            Load::Word => &[0x8B],
+        };
+        let rex = 0x40 | (dst.high() << 2) | (index.high() << 1) | base.high();
+        if rex != 0x40 {
+            self.byte(rex);
+        }
+        self.code.extend_from_slice(opcode);
+        self.byte((dst.low() << 3) | 0b100);
+        self.byte((index.low() << 3) | base.low());
+    }
+
+    pub fn alu(&mut self, op: Alu, wide: bool, dst: Reg, src: Reg) {
+        let opcode = ((op as u8) << 3) | 0x01;
+        self.op_rr(wide, &[opcode], src as u8, dst);
+    }
+
+    /// ALU operation with a sign extended immediate
+    pub fn alu_imm(&mut self, op: Alu, wide: bool, dst: Reg, imm: i32) {
+        self.op_rr(wide, &[0x81], op as u8, dst);
+        self.dword(imm as u32);
+    }
+
+    pub fn shift_imm(&mut self, op: Shift, wide: bool, dst: Reg, amount: u8) {
+        self.op_rr(wide, &[0xC1], op as u8, dst);
+        self.byte(amount);
+    }
+
+    /// Shift by CL, the amount is masked to the operand width by the CPU
+    pub fn shift_cl(&mut self, op: Shift, wide: bool, dst: Reg) {
+        self.op_rr(wide, &[0xD3], op as u8, dst);
+    }
+
+    /// Rotates the lower 16 bits by 8, i.e. swaps bytes of a halfword
+    pub fn swap16(&mut self, dst: Reg) {
+        self.byte(0x66);
+        self.op_rr(false, &[0xC1], Shift::Rol as u8, dst);
+        self.byte(8);
+    }
+
+    pub fn bswap32(&mut self, dst: Reg) {
+        self.rex(false, 0, dst.high());
+        self.byte(0x0F);
+        self.byte(0xC8 + dst.low());
+    }
+
+    pub fn not(&mut self, wide: bool, dst: Reg) {
+        self.op_rr(wide, &[0xF7], 2, dst);
+    }
+
+    pub fn test(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x85], src as u8, dst);
+    }
+
+    pub fn test_imm(&mut self, dst: Reg, imm: u32) {
+        self.op_rr(false, &[0xF7], 0, dst);
+        self.dword(imm);
+    }
+
+    /// `imul dst32, src32`
+    pub fn imul(&mut self, dst: Reg, src: Reg) {
+        self.op_rr(false, &[0x0F, 0xAF], dst as u8, src);
+    }
+
+    /// `movsxd dst64, src32`
+    pub fn sign_extend32(&mut self, dst: Reg, src: Reg) {
+        self.op_rr(true, &[0x63], dst as u8, src);
+    }
+
+    /// Sign extends the lower byte (8) or halfword (16) of `src` into 32 bit `dst`
+    pub fn sign_extend(&mut self, bits: u8, dst: Reg, src: Reg) {
+        let opcode = if bits == 8 { 0xBE } else { 0xBF };
+        self.op_rr(false, &[0x0F, opcode], dst as u8, src);
+    }
+
+    /// Zero extends the lower byte (8) or halfword (16) of `src` into 32 bit `dst`
+    pub fn zero_extend(&mut self, bits: u8, dst: Reg, src: Reg) {
+        let opcode = if bits == 8 { 0xB6 } else { 0xB7 };
+        self.op_rr(false, &[0x0F, opcode], dst as u8, src);
+    }
+
+    /// Sets the lowest byte of `dst` to the condition, the rest of the register is kept
+    pub fn set(&mut self, cond: Cond, dst: Reg) {
+        self.op_rr(false, &[0x0F, 0x90 + cond as u8], 0, dst);
+    }
+
+    pub fn cmov(&mut self, cond: Cond, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x0F, 0x40 + cond as u8], dst as u8, src);
+    }
+
+    /// Short conditional jump over the following `len` bytes
+    pub fn jump_over(&mut self, cond: Cond, len: usize) {
+        assert!(len < 128, "Conditional jump too far");
+        self.byte(0x70 + cond as u8);
+        self.byte(len as u8);
+    }
+}
+
+#[test]
+fn test_instruction_encoding() {
+    let mut e = Emitter::new();
+    e.push(Reg::R12);
+    e.load(true, Reg::Rax, Reg::Rbx, 8);
+    e.load_indexed(Load::Word, Reg::Rax, Reg::R12, Reg::Rax);
+    e.alu_imm(Alu::Add, false, Reg::Rax, -1);
+    e.sign_extend32(Reg::Rax, Reg::Rax);
+    e.mov(false, Reg::Rax, Reg::R14);
+    e.cmov(Cond::Equal, false, Reg::R14, Reg::Rdx);
+    assert_eq!(
+        e.code(),
+        &[
+            0x41, 0x54,
+            0x48, 0x8B, 0x83, 0x08, 0x00, 0x00, 0x00,
+            0x41, 0x8B, 0x04, 0x04,
+            0x81, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF,
+            0x48, 0x63, 0xC0,
+            0x44, 0x89, 0xF0,
+            0x44, 0x0F, 0x44, 0xF2,
+        ][..]
+    );
+}
@@ -9,4 +9,31 @@ pub enum CPUEvent {
     Fork(u32),
     FlowChangeImmediate(u32), // this is here to support compact branch
     FlowChangeDelayed(u32),
+    ForbiddenSlotAhead, // R6 compact branch was not taken
+    DelaySlotNullified, // branch likely was not taken, delay slot is skipped
+    Exception(ExceptionKind),
+}
+
+/// Synchronous exceptions which are delivered to the emulated program as signals. The faulting
+/// instruction has no effect and the signal handler sees its address.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ExceptionKind {
+    /// Enabled IEEE exception in FPU, contains the cause bits. Delivered as SIGFPE.
+    FloatingPoint(u32),
+    /// Signed integer overflow in ADD, ADDI or SUB. Delivered as SIGFPE.
+    IntegerOverflow,
+    /// Condition of a trap instruction was met. Delivered as SIGTRAP.
+    Trap,
+    /// BREAK instruction. Delivered as SIGTRAP.
+    Breakpoint,
+    /// Instruction encoding not defined in the selected ISA revision. Delivered as SIGILL.
+    ReservedInstruction,
+    /// Misaligned instruction fetch or access outside of user space, contains the bad address.
+    /// Delivered as SIGBUS.
+    AddressError(u32),
+    /// Access of an unmapped page, contains the bad address. Delivered as SIGSEGV.
+    SegmentationFault(u32),
+    /// Access, that the page protection does not allow, contains the bad address. Delivered as
+    /// SIGSEGV.
+    ProtectionFault(u32),
 }
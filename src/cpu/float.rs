@@ -1,74 +1,819 @@
 //! Float operations, mainly handling different float formats and calculating according to them.
+//!
+//! All arithmetic goes through `FloatEnv`, which carries the FCSR of the emulated FPU. The actual
+//! computation is done by the host FPU with the guest rounding mode applied, host exception flags
+//! are then translated into FCSR cause bits. MIPS specific behaviour (NaN encoding, default NaN
+//! values, flushing of subnormals) is handled here in software.
 
 use cpu::registers::RegisterFile;
-use std::ops::Add;
+use std::hint::black_box;
 
-#[derive(Debug)]
+pub const FMT_S: u32 = 0x10;
+pub const FMT_D: u32 = 0x11;
+pub const FMT_W: u32 = 0x14;
+pub const FMT_L: u32 = 0x15;
+pub const FMT_PS: u32 = 0x16;
+
+// FCSR fields
+pub const FCSR_RM_MASK: u32 = 0b11;
+pub const FCSR_FLAGS_SHIFT: u32 = 2;
+pub const FCSR_ENABLES_SHIFT: u32 = 7;
+pub const FCSR_CAUSE_SHIFT: u32 = 12;
+pub const FCSR_CAUSE_MASK: u32 = 0b111111 << FCSR_CAUSE_SHIFT;
+pub const FCSR_NAN2008: u32 = 1 << 18;
+pub const FCSR_ABS2008: u32 = 1 << 19;
+pub const FCSR_FS: u32 = 1 << 24;
+
+// IEEE exceptions as ordered in the Flags, Enables and Cause fields of FCSR
+pub const FPE_INEXACT: u32 = 1 << 0;
+pub const FPE_UNDERFLOW: u32 = 1 << 1;
+pub const FPE_OVERFLOW: u32 = 1 << 2;
+pub const FPE_DIVIDE_BY_ZERO: u32 = 1 << 3;
+pub const FPE_INVALID: u32 = 1 << 4;
+/// Unimplemented operation, present only in the Cause field and always enabled.
+pub const FPE_UNIMPLEMENTED: u32 = 1 << 5;
+
+/// Rounding modes as encoded in the RM field of FCSR.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RoundingMode {
+    Nearest,
+    Zero,
+    PlusInfinity,
+    MinusInfinity,
+}
+
+impl RoundingMode {
+    pub fn from_fcsr(fcsr: u32) -> RoundingMode {
+        match fcsr & FCSR_RM_MASK {
+            0 => RoundingMode::Nearest,
+            1 => RoundingMode::Zero,
+            2 => RoundingMode::PlusInfinity,
+            3 => RoundingMode::MinusInfinity,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Returns the cause bits of FCSR which should trap - those with the enable bit set and the
+/// unimplemented operation bit.
+pub fn enabled_exceptions(fcsr: u32) -> u32 {
+    let cause = (fcsr & FCSR_CAUSE_MASK) >> FCSR_CAUSE_SHIFT;
+    let enables = (fcsr >> FCSR_ENABLES_SHIFT) & 0b11111;
+    cause & (enables | FPE_UNIMPLEMENTED)
+}
+
+/// Floating point environment of a single FPU instruction. It is created from the current FCSR,
+/// collects exceptions raised during the computation and writes them back with `commit`.
+pub struct FloatEnv {
+    fcsr: u32,
+    exceptions: u32,
+}
+
+impl FloatEnv {
+    pub fn new(fcsr: u32) -> FloatEnv {
+        FloatEnv {
+            fcsr,
+            exceptions: 0,
+        }
+    }
+
+    pub fn rounding(&self) -> RoundingMode {
+        RoundingMode::from_fcsr(self.fcsr)
+    }
+
+    pub fn raise(&mut self, exceptions: u32) {
+        self.exceptions |= exceptions;
+    }
+
+    fn nan2008(&self) -> bool {
+        self.fcsr & FCSR_NAN2008 != 0
+    }
+
+    fn abs2008(&self) -> bool {
+        self.fcsr & FCSR_ABS2008 != 0
+    }
+
+    fn flush_to_zero(&self) -> bool {
+        self.fcsr & FCSR_FS != 0
+    }
+
+    /// Updates cause and flag bits in FCSR. When any of the raised exceptions is enabled, the
+    /// instruction must not write its result and `Err` with the cause bits is returned instead.
+    /// Like the Linux kernel, we leave the Cause field cleared when the trap is taken.
+    pub fn commit(self, registers: &mut RegisterFile) -> Result<(), u32> {
+        let fcsr = registers.read_fcsr() & !FCSR_CAUSE_MASK;
+        let cause = self.exceptions << FCSR_CAUSE_SHIFT;
+        if enabled_exceptions(fcsr | cause) != 0 {
+            registers.write_fcsr(fcsr);
+            Err(self.exceptions)
+        } else {
+            let flags = (self.exceptions & 0b11111) << FCSR_FLAGS_SHIFT;
+            registers.write_fcsr(fcsr | cause | flags);
+            Ok(())
+        }
+    }
+
+    /// Runs `op` on the host FPU with the guest rounding mode and collects raised exceptions.
+    fn host<A: Copy, T>(&mut self, args: A, op: impl FnOnce(A) -> T) -> T {
+        let (result, exceptions) = host_fenv::run(self.rounding(), args, op);
+        self.exceptions |= exceptions;
+        result
+    }
+
+    /// Handles NaN operands of an arithmetic operation. Signaling NaN raises invalid operation
+    /// and is replaced by a quiet NaN, which is the result of the whole operation.
+    fn propagate_nan<F: MipsFloat>(&mut self, operands: &[F]) -> Option<F> {
+        let nan2008 = self.nan2008();
+        if operands.iter().any(|x| x.is_signaling(nan2008)) {
+            self.raise(FPE_INVALID);
+        }
+        operands.iter().find(|x| x.is_nan()).map(|x| if !x.is_signaling(nan2008) {
+            *x
+        } else if nan2008 {
+            x.quieted()
+        } else {
+            F::default_nan(false)
+        })
+    }
+
+    fn flush_input<F: MipsFloat>(&self, v: F) -> F {
+        if self.flush_to_zero() && v.is_subnormal() {
+            v.signed_zero()
+        } else {
+            v
+        }
+    }
+
+    /// Replaces host generated NaNs with the MIPS default NaN and flushes subnormal results.
+    fn finish<F: MipsFloat>(&mut self, v: F) -> F {
+        if v.is_nan() {
+            F::default_nan(self.nan2008())
+        } else if self.flush_to_zero() && v.is_subnormal() {
+            self.raise(FPE_UNDERFLOW | FPE_INEXACT);
+            v.signed_zero()
+        } else {
+            v
+        }
+    }
+
+    fn unary<F: MipsFloat>(&mut self, a: F, op: fn(F) -> F) -> F {
+        if let Some(nan) = self.propagate_nan(&[a]) {
+            return nan;
+        }
+        let a = self.flush_input(a);
+        let r = self.host(a, op);
+        self.finish(r)
+    }
+
+    fn binary<F: MipsFloat>(&mut self, a: F, b: F, op: fn(F, F) -> F) -> F {
+        if let Some(nan) = self.propagate_nan(&[a, b]) {
+            return nan;
+        }
+        let args = (self.flush_input(a), self.flush_input(b));
+        let r = self.host(args, |(a, b)| op(a, b));
+        self.finish(r)
+    }
+
+    fn ternary<F: MipsFloat>(&mut self, a: F, b: F, c: F, op: fn(F, F, F) -> F) -> F {
+        if let Some(nan) = self.propagate_nan(&[a, b, c]) {
+            return nan;
+        }
+        let args = (self.flush_input(a), self.flush_input(b), self.flush_input(c));
+        let r = self.host(args, |(a, b, c)| op(a, b, c));
+        self.finish(r)
+    }
+
+    /// Float to integer conversion. Values which do not fit and NaNs raise invalid operation and
+    /// produce the default result, which depends on the NaN encoding mode.
+    fn convert_to_integer<F: MipsFloat>(
+        &mut self,
+        v: F,
+        rounding: RoundingMode,
+        bits: u32,
+    ) -> i64 {
+        let max = if bits == 32 { i32::MAX as i64 } else { i64::MAX };
+        let min = if bits == 32 { i32::MIN as i64 } else { i64::MIN };
+        let v = self.flush_input(v).to_f64();
+        if v.is_nan() {
+            self.raise(FPE_INVALID);
+            return if self.nan2008() { 0 } else { max };
+        }
+        let r = round_f64(v, rounding);
+        let limit = 2.0f64.powi(bits as i32 - 1);
+        if r >= limit || r < -limit {
+            self.raise(FPE_INVALID);
+            return if !self.nan2008() || r > 0.0 { max } else { min };
+        }
+        if r != v {
+            self.raise(FPE_INEXACT);
+        }
+        r as i64
+    }
+}
+
+/// Operations on host float types needed to emulate MIPS specific behaviour.
+trait MipsFloat: Copy {
+    fn is_nan(self) -> bool;
+    fn is_subnormal(self) -> bool;
+    fn quiet_bit_set(self) -> bool;
+    fn quieted(self) -> Self;
+    fn default_nan(nan2008: bool) -> Self;
+    fn signed_zero(self) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn is_signaling(self, nan2008: bool) -> bool {
+        self.is_nan() && self.quiet_bit_set() != nan2008
+    }
+}
+
+impl MipsFloat for f32 {
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+    fn is_subnormal(self) -> bool {
+        f32::is_subnormal(self)
+    }
+    fn quiet_bit_set(self) -> bool {
+        self.to_bits() & 0x0040_0000 != 0
+    }
+    fn quieted(self) -> f32 {
+        f32::from_bits(self.to_bits() | 0x0040_0000)
+    }
+    fn default_nan(nan2008: bool) -> f32 {
+        f32::from_bits(if nan2008 { 0x7fc0_0000 } else { 0x7fbf_ffff })
+    }
+    fn signed_zero(self) -> f32 {
+        if self.is_sign_negative() { -0.0 } else { 0.0 }
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl MipsFloat for f64 {
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+    fn is_subnormal(self) -> bool {
+        f64::is_subnormal(self)
+    }
+    fn quiet_bit_set(self) -> bool {
+        self.to_bits() & 0x0008_0000_0000_0000 != 0
+    }
+    fn quieted(self) -> f64 {
+        f64::from_bits(self.to_bits() | 0x0008_0000_0000_0000)
+    }
+    fn default_nan(nan2008: bool) -> f64 {
+        f64::from_bits(if nan2008 {
+            0x7ff8_0000_0000_0000
+        } else {
+            0x7ff7_ffff_ffff_ffff
+        })
+    }
+    fn signed_zero(self) -> f64 {
+        if self.is_sign_negative() { -0.0 } else { 0.0 }
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+/// Access to the host floating point environment through C99 `<fenv.h>` functions. The `libc`
+/// crate does not export them, so they are declared here with glibc's x86 constants.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod host_fenv {
+    use super::*;
+    use libc::c_int;
+
+    extern "C" {
+        fn fegetround() -> c_int;
+        fn fesetround(round: c_int) -> c_int;
+        fn feclearexcept(excepts: c_int) -> c_int;
+        fn fetestexcept(excepts: c_int) -> c_int;
+    }
+
+    const FE_INVALID: c_int = 0x01;
+    const FE_DIVBYZERO: c_int = 0x04;
+    const FE_OVERFLOW: c_int = 0x08;
+    const FE_UNDERFLOW: c_int = 0x10;
+    const FE_INEXACT: c_int = 0x20;
+    const FE_ALL_EXCEPT: c_int = FE_INVALID | FE_DIVBYZERO | FE_OVERFLOW | FE_UNDERFLOW |
+        FE_INEXACT;
+
+    const FE_TONEAREST: c_int = 0x000;
+    const FE_DOWNWARD: c_int = 0x400;
+    const FE_UPWARD: c_int = 0x800;
+    const FE_TOWARDZERO: c_int = 0xc00;
+
+    /// Evaluates `op` with the given rounding mode and returns the result together with raised
+    /// exceptions in FCSR bit order. Arguments and result are passed through `black_box`, so
+    /// the compiler can't move the computation outside of the modified environment.
+    pub fn run<A: Copy, T>(rounding: RoundingMode, args: A, op: impl FnOnce(A) -> T) -> (T, u32) {
+        let mode = match rounding {
+            RoundingMode::Nearest => FE_TONEAREST,
+            RoundingMode::Zero => FE_TOWARDZERO,
+            RoundingMode::PlusInfinity => FE_UPWARD,
+            RoundingMode::MinusInfinity => FE_DOWNWARD,
+        };
+
+        unsafe {
+            let previous = fegetround();
+            fesetround(mode);
+            feclearexcept(FE_ALL_EXCEPT);
+            let result = black_box(op(black_box(args)));
+            let raised = fetestexcept(FE_ALL_EXCEPT);
+            fesetround(previous);
+
+            let mut exceptions = 0;
+            if raised & FE_INEXACT != 0 {
+                exceptions |= FPE_INEXACT;
+            }
+            if raised & FE_UNDERFLOW != 0 {
+                exceptions |= FPE_UNDERFLOW;
+            }
+            if raised & FE_OVERFLOW != 0 {
+                exceptions |= FPE_OVERFLOW;
+            }
+            if raised & FE_DIVBYZERO != 0 {
+                exceptions |= FPE_DIVIDE_BY_ZERO;
+            }
+            if raised & FE_INVALID != 0 {
+                exceptions |= FPE_INVALID;
+            }
+            (result, exceptions)
+        }
+    }
+}
+
+/// Fallback for hosts without known `<fenv.h>` constants. Results are always rounded to nearest
+/// and no exceptions are reported.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+mod host_fenv {
+    use super::*;
+
+    pub fn run<A: Copy, T>(_rounding: RoundingMode, args: A, op: impl FnOnce(A) -> T) -> (T, u32) {
+        (black_box(op(black_box(args))), 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum FloatFmt {
     S(f32),
     D(f64),
-    W(f32),
-    L(f64),
+    W(i32),
+    L(i64),
     PS(f32, f32),
 }
 
 impl FloatFmt {
+    /// Reinterprets bits stored in the register as a value of the given format.
     pub fn from_raw(fmt: u32, id: u32, registers: &RegisterFile) -> FloatFmt {
-        /*
-        //FIXME no idea what to do with these format strings, but they appear in binaries. So this is guesswork.
-        let fmt = match fmt {
-            0x2 => 0x14,
-            _ => fmt,
-        };
-        */
-
         match fmt {
-            0x10 => FloatFmt::S(registers.read_fpr(id)),
-            0x11 => FloatFmt::D(
-                ((((registers.read_fpr(id + 1) as u32) as u64) << 32) |
-                     (registers.read_fpr(id) as u32) as u64) as f64,
-            ),
-            0x14 => FloatFmt::W(registers.read_fpr(id)),
-            0x15 => FloatFmt::L(
-                ((((registers.read_fpr(id + 1) as u32) as u64) << 32) |
-                     (registers.read_fpr(id) as u32) as u64) as f64,
-            ),
-            0x16 => FloatFmt::PS(registers.read_fpr(id), registers.read_fpr(id + 1)),
+            FMT_S => FloatFmt::S(f32::from_bits(registers.read_fpr_word(id))),
+            FMT_D => FloatFmt::D(f64::from_bits(registers.read_fpr_doubleword(id))),
+            FMT_W => FloatFmt::W(registers.read_fpr_word(id) as i32),
+            FMT_L => FloatFmt::L(registers.read_fpr_doubleword(id) as i64),
+            FMT_PS => {
+                let r = registers.read_fpr_doubleword(id);
+                FloatFmt::PS(f32::from_bits(r as u32), f32::from_bits((r >> 32) as u32))
+            }
             _ => panic!("Unknown float format 0x{:x}", fmt),
         }
     }
 
     pub fn save(self, id: u32, registers: &mut RegisterFile) {
         match self {
-            FloatFmt::S(a) => registers.write_fpr(id, a),
-            FloatFmt::W(a) => registers.write_fpr(id, a),
-            FloatFmt::D(a) => {
-                registers.write_fpr(id + 1, (((a as u64) >> 32) as u32) as f32);
-                registers.write_fpr(id, ((a as u64) as u32) as f32);
+            FloatFmt::S(a) => registers.write_fpr_word(id, a.to_bits()),
+            FloatFmt::W(a) => registers.write_fpr_word(id, a as u32),
+            FloatFmt::D(a) => registers.write_fpr_doubleword(id, a.to_bits()),
+            FloatFmt::L(a) => registers.write_fpr_doubleword(id, a as u64),
+            FloatFmt::PS(a, b) => {
+                registers.write_fpr_doubleword(
+                    id,
+                    ((b.to_bits() as u64) << 32) | a.to_bits() as u64,
+                )
             }
-            FloatFmt::L(a) => {
-                registers.write_fpr(id + 1, (((a as u64) >> 32) as u32) as f32);
-                registers.write_fpr(id, ((a as u64) as u32) as f32);
+        }
+    }
+
+    /// Short name of a format as used in instruction mnemonics.
+    pub fn format_name(fmt: u32) -> &'static str {
+        match fmt {
+            FMT_S => "s",
+            FMT_D => "d",
+            FMT_W => "w",
+            FMT_L => "l",
+            FMT_PS => "ps",
+            _ => "?",
+        }
+    }
+
+    fn arithmetic(
+        self,
+        other: FloatFmt,
+        env: &mut FloatEnv,
+        op32: fn(f32, f32) -> f32,
+        op64: fn(f64, f64) -> f64,
+    ) -> FloatFmt {
+        match (self, other) {
+            (FloatFmt::S(a), FloatFmt::S(b)) => FloatFmt::S(env.binary(a, b, op32)),
+            (FloatFmt::D(a), FloatFmt::D(b)) => FloatFmt::D(env.binary(a, b, op64)),
+            (FloatFmt::PS(a, b), FloatFmt::PS(c, d)) => {
+                FloatFmt::PS(env.binary(a, c, op32), env.binary(b, d, op32))
             }
+            _ => panic!("Incompatible float types {:?} and {:?}", self, other),
+        }
+    }
+
+    pub fn add(self, other: FloatFmt, env: &mut FloatEnv) -> FloatFmt {
+        self.arithmetic(other, env, |a, b| a + b, |a, b| a + b)
+    }
+
+    pub fn sub(self, other: FloatFmt, env: &mut FloatEnv) -> FloatFmt {
+        self.arithmetic(other, env, |a, b| a - b, |a, b| a - b)
+    }
+
+    pub fn mul(self, other: FloatFmt, env: &mut FloatEnv) -> FloatFmt {
+        self.arithmetic(other, env, |a, b| a * b, |a, b| a * b)
+    }
+
+    pub fn div(self, other: FloatFmt, env: &mut FloatEnv) -> FloatFmt {
+        match self {
+            FloatFmt::PS(_, _) => panic!("Division of paired single values"),
+            _ => self.arithmetic(other, env, |a, b| a / b, |a, b| a / b),
+        }
+    }
+
+    pub fn sqrt(self, env: &mut FloatEnv) -> FloatFmt {
+        match self {
+            FloatFmt::S(a) => FloatFmt::S(env.unary(a, f32::sqrt)),
+            FloatFmt::D(a) => FloatFmt::D(env.unary(a, f64::sqrt)),
+            _ => panic!("Square root of non-float format {:?}", self),
+        }
+    }
+
+    pub fn recip(self, env: &mut FloatEnv) -> FloatFmt {
+        match self {
+            FloatFmt::S(a) => FloatFmt::S(env.unary(a, |a| 1.0 / a)),
+            FloatFmt::D(a) => FloatFmt::D(env.unary(a, |a| 1.0 / a)),
+            _ => panic!("Reciprocal of non-float format {:?}", self),
+        }
+    }
+
+    pub fn rsqrt(self, env: &mut FloatEnv) -> FloatFmt {
+        match self {
+            FloatFmt::S(a) => FloatFmt::S(env.unary(a, |a| 1.0 / a.sqrt())),
+            FloatFmt::D(a) => FloatFmt::D(env.unary(a, |a| 1.0 / a.sqrt())),
+            _ => panic!("Reciprocal square root of non-float format {:?}", self),
+        }
+    }
+
+    /// ABS and NEG. With FCSR.ABS2008 set they only manipulate the sign bit, otherwise they are
+    /// arithmetic and NaN operands are handled like in any other operation.
+    fn sign_operation(self, env: &mut FloatEnv, op32: fn(f32) -> f32, op64: fn(f64) -> f64) -> FloatFmt {
+        if env.abs2008() {
+            return match self {
+                FloatFmt::S(a) => FloatFmt::S(op32(a)),
+                FloatFmt::D(a) => FloatFmt::D(op64(a)),
+                FloatFmt::PS(a, b) => FloatFmt::PS(op32(a), op32(b)),
+                _ => panic!("Sign operation on non-float format {:?}", self),
+            };
+        }
+
+        match self {
+            FloatFmt::S(a) => FloatFmt::S(env.propagate_nan(&[a]).unwrap_or_else(|| op32(a))),
+            FloatFmt::D(a) => FloatFmt::D(env.propagate_nan(&[a]).unwrap_or_else(|| op64(a))),
             FloatFmt::PS(a, b) => {
-                registers.write_fpr(id, a);
-                registers.write_fpr(id + 1, b);
+                FloatFmt::PS(
+                    env.propagate_nan(&[a]).unwrap_or_else(|| op32(a)),
+                    env.propagate_nan(&[b]).unwrap_or_else(|| op32(b)),
+                )
             }
+            _ => panic!("Sign operation on non-float format {:?}", self),
         }
     }
-}
 
-impl Add for FloatFmt {
-    type Output = FloatFmt;
+    pub fn abs(self, env: &mut FloatEnv) -> FloatFmt {
+        self.sign_operation(env, f32::abs, f64::abs)
+    }
+
+    pub fn neg(self, env: &mut FloatEnv) -> FloatFmt {
+        self.sign_operation(env, |a| -a, |a| -a)
+    }
+
+    /// Fused multiply-add `self + a * b` with a single rounding (R6 MADDF).
+    pub fn fused_mul_add(self, a: FloatFmt, b: FloatFmt, env: &mut FloatEnv) -> FloatFmt {
+        match (self, a, b) {
+            (FloatFmt::S(c), FloatFmt::S(a), FloatFmt::S(b)) => {
+                FloatFmt::S(env.ternary(c, a, b, |c, a, b| a.mul_add(b, c)))
+            }
+            (FloatFmt::D(c), FloatFmt::D(a), FloatFmt::D(b)) => {
+                FloatFmt::D(env.ternary(c, a, b, |c, a, b| a.mul_add(b, c)))
+            }
+            _ => panic!("Incompatible float types in fused multiply-add..."),
+        }
+    }
+
+    /// Rounds to an integral value, keeping the format (R6 RINT).
+    pub fn round_integral(self, rounding: RoundingMode, env: &mut FloatEnv) -> FloatFmt {
+        fn rint<F: MipsFloat>(a: F, rounding: RoundingMode, env: &mut FloatEnv) -> f64 {
+            let v = a.to_f64();
+            let r = round_f64(v, rounding);
+            if r != v {
+                env.raise(FPE_INEXACT);
+            }
+            r
+        }
+
+        match self {
+            FloatFmt::S(a) => {
+                FloatFmt::S(env.propagate_nan(&[a]).unwrap_or_else(
+                    || rint(a, rounding, env) as f32,
+                ))
+            }
+            FloatFmt::D(a) => {
+                FloatFmt::D(env.propagate_nan(&[a]).unwrap_or_else(
+                    || rint(a, rounding, env),
+                ))
+            }
+            _ => panic!("Rounding of non-float format {:?}", self),
+        }
+    }
+
+    /// Converts value into another format. Used by CVT, ROUND, TRUNC, CEIL and FLOOR.
+    pub fn convert(self, fmt: u32, rounding: RoundingMode, env: &mut FloatEnv) -> FloatFmt {
+        match fmt {
+            FMT_S => {
+                match self {
+                    FloatFmt::S(a) => FloatFmt::S(a),
+                    FloatFmt::D(a) => {
+                        let nan2008 = env.nan2008();
+                        match env.propagate_nan(&[a]) {
+                            // payload of a quiet NaN is kept in 2008 mode only
+                            Some(nan) if nan2008 => FloatFmt::S((nan as f32).quieted()),
+                            Some(_) => FloatFmt::S(f32::default_nan(false)),
+                            None => {
+                                let a = env.flush_input(a);
+                                let r = env.host(a, |a| a as f32);
+                                FloatFmt::S(env.finish(r))
+                            }
+                        }
+                    }
+                    FloatFmt::W(a) => FloatFmt::S(env.host(a, |a| a as f32)),
+                    FloatFmt::L(a) => FloatFmt::S(env.host(a, |a| a as f32)),
+                    FloatFmt::PS(_, _) => panic!("CVT.S.PS does not exist, use CVT.S.PL/PU"),
+                }
+            }
+            FMT_D => {
+                match self {
+                    FloatFmt::S(a) => {
+                        let nan2008 = env.nan2008();
+                        match env.propagate_nan(&[a]) {
+                            Some(nan) if nan2008 => FloatFmt::D((nan as f64).quieted()),
+                            Some(_) => FloatFmt::D(f64::default_nan(false)),
+                            None => FloatFmt::D(env.flush_input(a) as f64),
+                        }
+                    }
+                    FloatFmt::D(a) => FloatFmt::D(a),
+                    FloatFmt::W(a) => FloatFmt::D(a as f64),
+                    FloatFmt::L(a) => FloatFmt::D(env.host(a, |a| a as f64)),
+                    FloatFmt::PS(_, _) => panic!("CVT.D.PS is not a valid instruction"),
+                }
+            }
+            FMT_W | FMT_L => {
+                let bits = if fmt == FMT_W { 32 } else { 64 };
+                let r = match self {
+                    FloatFmt::S(a) => env.convert_to_integer(a, rounding, bits),
+                    FloatFmt::D(a) => env.convert_to_integer(a, rounding, bits),
+                    _ => panic!("Conversion of {:?} into integer format", self),
+                };
+                if fmt == FMT_W {
+                    FloatFmt::W(r as i32)
+                } else {
+                    FloatFmt::L(r)
+                }
+            }
+            _ => panic!("Conversion into unknown float format 0x{:x}", fmt),
+        }
+    }
+
+    /// Evaluates comparison predicate shared by pre-R6 `C.cond.fmt` and R6 `CMP.cond.fmt`.
+    /// Bit 0 of `cond` means unordered, bit 1 equal, bit 2 less than. Bit 4 (R6 only) negates
+    /// the result. Bit 3 selects the signaling variant, which raises invalid operation on any
+    /// NaN operand, the quiet one only on signaling NaNs.
+    pub fn compare(self, other: FloatFmt, cond: u32, env: &mut FloatEnv) -> bool {
+        fn cmp<F: MipsFloat + PartialOrd>(a: F, b: F, cond: u32, env: &mut FloatEnv) -> bool {
+            let nan2008 = env.nan2008();
+            let unordered = a.is_nan() || b.is_nan();
+            if (unordered && cond & 0b1000 != 0) || a.is_signaling(nan2008) ||
+                b.is_signaling(nan2008)
+            {
+                env.raise(FPE_INVALID);
+            }
+            let (a, b) = (env.flush_input(a), env.flush_input(b));
+            let res = (cond & 0b001 != 0 && unordered) || (cond & 0b010 != 0 && a == b) ||
+                (cond & 0b100 != 0 && a < b);
+            if cond & 0b10000 != 0 { !res } else { res }
+        }
+
+        match (self, other) {
+            (FloatFmt::S(a), FloatFmt::S(b)) => cmp(a, b, cond, env),
+            (FloatFmt::D(a), FloatFmt::D(b)) => cmp(a, b, cond, env),
+            _ => panic!("Incompatible float types comparison..."),
+        }
+    }
 
-    fn add(self, other: FloatFmt) -> FloatFmt {
+    /// Same as `compare`, but for both halves of paired single values.
+    pub fn compare_paired(self, other: FloatFmt, cond: u32, env: &mut FloatEnv) -> (bool, bool) {
         match (self, other) {
-            (FloatFmt::S(a), FloatFmt::S(b)) => FloatFmt::S(a + b),
-            (FloatFmt::D(a), FloatFmt::D(b)) => FloatFmt::D(a + b),
-            (FloatFmt::W(a), FloatFmt::W(b)) => FloatFmt::W(a + b),
-            (FloatFmt::L(a), FloatFmt::L(b)) => FloatFmt::L(a + b),
-            (FloatFmt::PS(a, b), FloatFmt::PS(c, d)) => FloatFmt::PS(a + c, b + d),
-            _ => panic!("Incompatible float types addition..."),
+            (FloatFmt::PS(a, b), FloatFmt::PS(c, d)) => {
+                (
+                    FloatFmt::S(a).compare(FloatFmt::S(c), cond, env),
+                    FloatFmt::S(b).compare(FloatFmt::S(d), cond, env),
+                )
+            }
+            _ => panic!("Paired comparison of non-paired values..."),
+        }
+    }
+
+    /// R6 MIN/MAX/MINA/MAXA. When exactly one of the operands is a quiet NaN, the other one is
+    /// returned.
+    pub fn min_max(self, other: FloatFmt, max: bool, magnitude: bool, env: &mut FloatEnv) -> FloatFmt {
+        fn pick<F: MipsFloat>(a: F, b: F, max: bool, magnitude: bool, env: &mut FloatEnv) -> F {
+            let nan2008 = env.nan2008();
+            if a.is_signaling(nan2008) || b.is_signaling(nan2008) || (a.is_nan() && b.is_nan()) {
+                return env.propagate_nan(&[a, b]).unwrap();
+            } else if a.is_nan() {
+                return b;
+            } else if b.is_nan() {
+                return a;
+            }
+            let (va, vb) = (a.to_f64(), b.to_f64());
+            let (ka, kb) = if magnitude { (va.abs(), vb.abs()) } else { (va, vb) };
+            if ka == kb {
+                // prefer the positive zero (or positive value) for max and the negative for min
+                if max == va.is_sign_negative() { b } else { a }
+            } else if (ka > kb) == max {
+                a
+            } else {
+                b
+            }
+        }
+
+        match (self, other) {
+            (FloatFmt::S(a), FloatFmt::S(b)) => FloatFmt::S(pick(a, b, max, magnitude, env)),
+            (FloatFmt::D(a), FloatFmt::D(b)) => FloatFmt::D(pick(a, b, max, magnitude, env)),
+            _ => panic!("Incompatible float types in min/max..."),
+        }
+    }
+
+    /// R6 CLASS. Returns bit mask describing the value. Release 6 uses IEEE 754-2008 NaN encoding,
+    /// so quiet NaNs have the most significant fraction bit set.
+    pub fn class(self) -> FloatFmt {
+        fn classify(negative: bool, class: ::std::num::FpCategory, signaling: bool) -> u32 {
+            use std::num::FpCategory::*;
+            let offset = if negative { 0 } else { 4 };
+            match class {
+                Nan => if signaling { 1 << 0 } else { 1 << 1 },
+                Infinite => 1 << (2 + offset),
+                Normal => 1 << (3 + offset),
+                Subnormal => 1 << (4 + offset),
+                Zero => 1 << (5 + offset),
+            }
+        }
+
+        match self {
+            FloatFmt::S(a) => {
+                let signaling = a.is_signaling(true);
+                FloatFmt::W(classify(a.is_sign_negative(), a.classify(), signaling) as i32)
+            }
+            FloatFmt::D(a) => {
+                let signaling = a.is_signaling(true);
+                FloatFmt::L(classify(a.is_sign_negative(), a.classify(), signaling) as i64)
+            }
+            _ => panic!("Classification of non-float format {:?}", self),
         }
     }
 }
+
+fn round_f64(v: f64, rounding: RoundingMode) -> f64 {
+    match rounding {
+        RoundingMode::Nearest => v.round_ties_even(),
+        RoundingMode::Zero => v.trunc(),
+        RoundingMode::PlusInfinity => v.ceil(),
+        RoundingMode::MinusInfinity => v.floor(),
+    }
+}
+
+#[test]
+fn test_compare() {
+    let mut env = FloatEnv::new(0);
+    let one = FloatFmt::D(1.0);
+    let two = FloatFmt::D(2.0);
+    // quiet NaN in the legacy encoding
+    let nan = FloatFmt::D(f64::default_nan(false));
+    // C.EQ, C.OLT, C.ULE
+    assert!(!one.compare(two, 0b0010, &mut env));
+    assert!(one.compare(two, 0b0100, &mut env));
+    assert!(one.compare(nan, 0b0111, &mut env));
+    assert!(!one.compare(nan, 0b0110, &mut env));
+    // CMP.NE and CMP.OR
+    assert!(one.compare(two, 0b10011, &mut env));
+    assert!(!one.compare(nan, 0b10001, &mut env));
+    assert_eq!(env.exceptions, 0);
+    // C.SEQ signals on quiet NaN
+    one.compare(nan, 0b1010, &mut env);
+    assert_eq!(env.exceptions, FPE_INVALID);
+}
+
+#[test]
+fn test_convert() {
+    let mut env = FloatEnv::new(0);
+    let v = FloatFmt::D(2.5);
+    match v.convert(FMT_W, RoundingMode::Nearest, &mut env) {
+        FloatFmt::W(2) => {}
+        x => panic!("{:?}", x),
+    }
+    match v.convert(FMT_W, RoundingMode::PlusInfinity, &mut env) {
+        FloatFmt::W(3) => {}
+        x => panic!("{:?}", x),
+    }
+    match FloatFmt::S(-1.5).convert(FMT_L, RoundingMode::Zero, &mut env) {
+        FloatFmt::L(-1) => {}
+        x => panic!("{:?}", x),
+    }
+    assert_eq!(env.exceptions, FPE_INEXACT);
+    match FloatFmt::D(1e20).convert(FMT_W, RoundingMode::Nearest, &mut env) {
+        FloatFmt::W(i32::MAX) => {}
+        x => panic!("{:?}", x),
+    }
+    assert_eq!(env.exceptions, FPE_INEXACT | FPE_INVALID);
+}
+
+#[test]
+fn test_rounding_and_exceptions() {
+    let third = |fcsr: u32| {
+        let mut env = FloatEnv::new(fcsr);
+        match FloatFmt::D(1.0).div(FloatFmt::D(3.0), &mut env) {
+            FloatFmt::D(r) => (r.to_bits(), env.exceptions),
+            x => panic!("{:?}", x),
+        }
+    };
+    let (nearest, exceptions) = third(0);
+    assert_eq!(exceptions, FPE_INEXACT);
+    assert_eq!(third(1).0, nearest);
+    assert_eq!(third(2).0, nearest + 1);
+    assert_eq!(third(3).0, nearest);
+
+    let mut env = FloatEnv::new(0);
+    match FloatFmt::S(1.0).div(FloatFmt::S(0.0), &mut env) {
+        FloatFmt::S(r) => assert!(r.is_infinite()),
+        x => panic!("{:?}", x),
+    }
+    assert_eq!(env.exceptions, FPE_DIVIDE_BY_ZERO);
+
+    // invalid operation produces the legacy default NaN
+    let mut env = FloatEnv::new(0);
+    match FloatFmt::S(0.0).div(FloatFmt::S(0.0), &mut env) {
+        FloatFmt::S(r) => assert_eq!(r.to_bits(), 0x7fbf_ffff),
+        x => panic!("{:?}", x),
+    }
+    assert_eq!(env.exceptions, FPE_INVALID);
+
+    // enabled exception is reported and the cause is not kept in FCSR
+    let mut registers = RegisterFile::new(0);
+    registers.write_fcsr(FPE_DIVIDE_BY_ZERO << FCSR_ENABLES_SHIFT);
+    let mut env = FloatEnv::new(registers.read_fcsr());
+    env.raise(FPE_DIVIDE_BY_ZERO);
+    assert_eq!(env.commit(&mut registers), Err(FPE_DIVIDE_BY_ZERO));
+    assert_eq!(registers.read_fcsr(), FPE_DIVIDE_BY_ZERO << FCSR_ENABLES_SHIFT);
+
+    let mut env = FloatEnv::new(registers.read_fcsr());
+    env.raise(FPE_INEXACT);
+    assert_eq!(env.commit(&mut registers), Ok(()));
+    assert_eq!(
+        registers.read_fcsr(),
+        (FPE_DIVIDE_BY_ZERO << FCSR_ENABLES_SHIFT) | (FPE_INEXACT << FCSR_CAUSE_SHIFT) |
+            (FPE_INEXACT << FCSR_FLAGS_SHIFT)
+    );
+}
+
+#[test]
+fn test_raw_bits_round_trip() {
+    for fr in &[false, true] {
+        let mut registers = RegisterFile::new(0);
+        registers.set_fpu_64bit(*fr);
+        registers.write_fpr_word(2, 0x3f80_0000);
+        match FloatFmt::from_raw(FMT_S, 2, &registers) {
+            FloatFmt::S(a) => assert_eq!(a, 1.0),
+            x => panic!("{:?}", x),
+        }
+
+        // signaling NaN payload must survive the trip
+        registers.write_fpr_doubleword(4, 0x7ff0_0000_dead_beef);
+        FloatFmt::from_raw(FMT_D, 4, &registers).save(6, &mut registers);
+        assert_eq!(registers.read_fpr_doubleword(6), 0x7ff0_0000_dead_beef);
+        assert_eq!(registers.read_fpr_high(6), 0x7ff0_0000);
+        assert_eq!(registers.read_fpr_word(6), 0xdead_beef);
+    }
+}
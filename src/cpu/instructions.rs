@@ -1,19 +1,38 @@
-//! Mainly one HUGE `eval_instruction` function.
+//! Mainly one HUGE `eval_instruction` function. Instructions are decoded by `decoder::decode`
+//! first, the evaluation is a match over the decoded `Instruction`.
 
 use cpu::bitutils::*;
+use cpu::branch::BranchCondition;
+use cpu::compressed::ISA_MODE_BIT;
+use cpu::decoder::{decode, DecodeError, Instruction};
+use cpu::doubleword::eval_doubleword_instruction;
+use cpu::dsp::{eval_dsp_instruction, DSP_CONTROL_POS};
 use cpu::event::*;
-use cpu::float::FloatFmt;
-use cpu::instructions_constants::*;
+use cpu::float::*;
+use cpu::isa::Abi;
 use cpu::registers::get_register_name;
 use cpu::registers::RegisterFile;
 use syscalls::System;
-use memory::Memory;
+use memory::{Access, Memory, KERNEL_SEGMENT_START, LINK_LINE_SIZE};
 
 pub fn eval_instruction(
     instruction: u32,
     registers: &mut RegisterFile,
     memory: &mut Memory,
     system: &mut System,
+) -> CPUEvent {
+    let decoded = decode(instruction, registers.get_isa());
+    eval_decoded_instruction(instruction, decoded, registers, memory, system)
+}
+
+/// Executes an instruction decoded in advance, e.g. by the block cache. `instruction` is the raw
+/// encoding `decoded` was obtained from.
+pub fn eval_decoded_instruction(
+    instruction: u32,
+    decoded: Result<Instruction, DecodeError>,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
 ) -> CPUEvent {
     let mut result_cpu_event = CPUEvent::Nothing;
 
@@ -26,591 +45,1023 @@ pub fn eval_instruction(
         );
     }
 
-    let opcode = get_opcode(instruction);
-    let opcode = translate_opcode(opcode);
-    let funct = get_funct(instruction);
-    let rs = get_rs(instruction);
-    let rt = get_rt(instruction);
-    let rd = get_rd(instruction);
+    // Updates FCSR after an FPU operation and performs the write only when no enabled
+    // floating point exception occurred.
+    macro_rules! fpu_commit {
+        ($env:expr, $write:expr) => (
+            match $env.commit(registers) {
+                Ok(()) => $write,
+                Err(cause) => {
+                    result_cpu_event = CPUEvent::Exception(ExceptionKind::FloatingPoint(cause));
+                }
+            }
+        );
+    }
 
-    //print!("0x{:x}:    ", registers.get_pc());
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(DecodeError::Reserved) => {
+            itrace!("reserved instruction 0x{:08x}", instruction);
+            return CPUEvent::Exception(ExceptionKind::ReservedInstruction);
+        }
+        Err(DecodeError::Unsupported) => {
+            warn!("Unimplemented instruction 0x{:08x}, raising reserved instruction", instruction);
+            return CPUEvent::Exception(ExceptionKind::ReservedInstruction);
+        }
+    };
 
-    match opcode {
-        // ALU operation
-        InstructionOpcode::SPECIAL => {
-            //print!("ALUOp ");
-            match funct {
-                // SLL
-                0b000000 => {
-                    if instruction == 0 {
-                        itrace!("nop\t");
-                    } else {
-                        assert_eq!(rs, 0);
-                        itrace!(
-                            "sll\t{},{},{}",
-                            get_register_name(rd),
-                            get_register_name(rt),
-                            get_shift(instruction)
-                        );
-                        let r = registers.read_register(rt) << get_shift(instruction);
-                        registers.write_register(rd, r);
-                    }
-                }
-                // SLLV
-                0b000100 => {
-                    itrace!(
-                        "sllv\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rt),
-                        get_register_name(rs)
-                    );
-                    let r = registers.read_register(rt) << (registers.read_register(rs) & 0x1F);
-                    registers.write_register(rd, r);
-                }
-                // SRLV
-                0b000110 => {
-                    itrace!(
-                        "srlv\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rt),
-                        get_register_name(rs)
-                    );
-                    let r = registers.read_register(rt) >> (registers.read_register(rs) & 0x1F);
-                    registers.write_register(rd, r);
-                }
-                // rotation right / shift right logical
-                0b000010 => {
-                    match rs {
-                        1 => {
-                            itrace!(
-                                "rotr\t{},{},{}",
-                                get_register_name(rd),
-                                get_register_name(rt),
-                                get_shift(instruction)
-                            );
-                            let r = registers.read_register(rt).rotate_right(
-                                get_shift(instruction),
-                            );
-                            registers.write_register(rd, r);
-                        }
-                        0 => {
-                            itrace!(
-                                "srl\t{},{},{}",
-                                get_register_name(rd),
-                                get_register_name(rt),
-                                get_shift(instruction)
-                            );
-                            let r = registers.read_register(rt) >> get_shift(instruction);
-                            registers.write_register(rd, r);
-                        }
-                        _ => panic!("unknown right bitshift variant"),
-                    }
-                }
-                // ADD
-                0b100000 => {
-                    itrace!(
-                        "add\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    let (r, overflow) = registers.read_register(rs).overflowing_add(
-                        registers.read_register(rt),
-                    );
-                    if overflow {
-                        panic!("Overflow occured during addition. Should TRAP. Please FIX");
-                    }
-                    registers.write_register(rd, r);
-                }
-                // ADDU
-                0b100001 => {
-                    itrace!(
-                        "addu\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    let (r, _) = registers.read_register(rs).overflowing_add(
-                        registers.read_register(rt),
-                    );
-                    registers.write_register(rd, r);
-                }
-                // SUBU
-                0b100011 => {
-                    let (r, _) = registers.read_register(rs).overflowing_sub(
-                        registers.read_register(rt),
-                    );
-                    itrace!(
-                        "subu\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    registers.write_register(rd, r);
-                }
-                // OR
-                0b100101 => {
-                    let r = registers.read_register(rs) | registers.read_register(rt);
-                    itrace!(
-                        "or\t{},{},{} - res=0x{:08x}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt),
-                        r
-                    );
-                    registers.write_register(rd, r);
-                }
-                // NOR
-                0b100111 => {
-                    let r = !(registers.read_register(rs) | registers.read_register(rt));
-                    itrace!(
-                        "nor\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    registers.write_register(rd, r);
-                }
-                // SRA
-                0b000011 => {
-                    assert_eq!(rs, 0);
-
-                    itrace!(
-                        "sra\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rt),
-                        get_shift(instruction)
-                    );
-                    let r = ((registers.read_register(rt) as i32) >> get_shift(instruction)) as u32;
-                    registers.write_register(rd, r);
-                }
-                // SRAV
-                0b000111 => {
-                    assert_eq!(get_shift(instruction), 0);
-
-                    itrace!(
-                        "srav\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rt),
-                        get_register_name(rs)
-                    );
-                    let r = ((registers.read_register(rt) as i32) >>
-                                 (registers.read_register(rs) & 0x1F)) as
-                        u32;
-                    registers.write_register(rd, r);
-                }
-                // AND
-                0b100100 => {
-                    let r = registers.read_register(rs) & registers.read_register(rt);
-                    itrace!(
-                        "and\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    registers.write_register(rd, r);
-                }
-                // XOR
-                0b100110 => {
-                    let r = registers.read_register(rs) ^ registers.read_register(rt);
-                    itrace!(
-                        "xor\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    registers.write_register(rd, r);
-                }
-                //JALR
-                0b001001 => {
-                    itrace!(
-                        "jalr\t{},{} - target=0x{:08x}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        registers.read_register(rs)
-                    );
-                    let pc = registers.get_pc();
-                    registers.write_register(rd, pc + 8);
-                    let r = registers.read_register(rs);
-                    assert_eq!(r & 0x00_00_00_03, 0);
-                    result_cpu_event = CPUEvent::FlowChangeDelayed(r);
-                }
-                // JR
-                0b001000 => {
-                    itrace!("jr\t{}", get_register_name(rs));
-                    let t = registers.read_register(rs);
-                    result_cpu_event = CPUEvent::FlowChangeDelayed(t);
-                }
-                // SLT
-                0b101010 => {
-                    itrace!(
-                        "slt\t{},{},{}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt)
-                    );
-                    let r = (registers.read_register(rs) as i32) <
-                        (registers.read_register(rt) as i32);
-                    registers.write_register(rd, r as u32);
-                }
-                // SLTU
-                0b101011 => {
-                    itrace!("sltu\t");
-                    let r = registers.read_register(rs) < registers.read_register(rt);
-                    registers.write_register(rd, r as u32);
-                }
-                // MOVN
-                0b001011 => {
-                    assert_eq!(0, get_shift(instruction));
-                    if registers.read_register(rt) != 0 {
-                        let r = registers.read_register(rs);
-                        registers.write_register(rd, r);
-                    }
-                    itrace!(
-                        "movn\t{},{},{} - value_written={}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt),
-                        registers.read_register(rt) != 0
-                    );
-                }
-                // MOVZ
-                0b001010 => {
-                    assert_eq!(0, get_shift(instruction));
-                    if registers.read_register(rt) == 0 {
-                        let r = registers.read_register(rs);
-                        registers.write_register(rd, r);
-                    }
-                    itrace!(
-                        "movz\t{},{},{} - value_written={}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt),
-                        registers.read_register(rt) != 0
-                    );
-                }
-                // SOP30
-                0b011000 => {
-                    let op = get_shift(instruction);
-                    match op {
-                        0b00010 => {
-                            let v1 = (registers.read_register(rs) as i32) as i64;
-                            let v2 = (registers.read_register(rt) as i32) as i64;
-                            let r = v1 * v2;
-                            let r = (r as u64) as u32;
-                            registers.write_register(rd, r);
-                            itrace!(
-                                "mul\t{},{},{}",
-                                get_register_name(rd),
-                                get_register_name(rs),
-                                get_register_name(rt)
-                            );
-                        }
-                        0b00011 => {
-                            let v1 = (registers.read_register(rs) as i32) as i64;
-                            let v2 = (registers.read_register(rt) as i32) as i64;
-                            let r = v1 * v2;
-                            let r = ((r as u64) >> 32) as u32;
-                            registers.write_register(rd, r);
-                            itrace!(
-                                "muh\t{},{},{}",
-                                get_register_name(rd),
-                                get_register_name(rs),
-                                get_register_name(rt)
-                            );
-                        }
-                        0 => {
-                            assert_eq!(rd, 0);
-                            let v1 = (registers.read_register(rs) as i32) as i64;
-                            let v2 = (registers.read_register(rt) as i32) as i64;
-                            let r = v1 * v2;
-                            let hi = ((r as u64) >> 32) as u32;
-                            let lo = (r as u64) as u32;
-                            registers.write_hi(hi);
-                            registers.write_lo(lo);
-                            itrace!("mult\t{},{}", get_register_name(rs), get_register_name(rt));
-                        }
-                        _ => {
-                            error!("Unknown SOP30 instruction code: {:05b}", op);
-                            panic!("Unknown instruction");
-                        }
-                    }
-                }
-                // SOP31
-                0b011001 => {
-                    let op = get_shift(instruction);
-                    match op {
-                        _ => {
-                            error!("Unknown SOP31 instruction code: {:05b}", op);
-                            panic!("Unknown instruction");
-                        }
-                    }
-                }
-                // SOP32
-                0b011010 => {
-                    let op = get_shift(instruction);
-                    match op {
-                        0b00000 => {
-                            assert_eq!(rd, 0);
-                            // warn!("Deprecated DIV instruction. Removed in release 6 of MIPS32");
-                            itrace!("divu\t{},{}", get_register_name(rs), get_register_name(rt));
-                            let l = (registers.read_register(rs) as i32) /
-                                (registers.read_register(rt) as i32);
-                            let h = (registers.read_register(rs) as i32) %
-                                (registers.read_register(rt) as i32);
-                            registers.write_lo(l as u32);
-                            registers.write_hi(h as u32);
-                        }
-                        _ => {
-                            error!("Unknown SOP32 instruction code: {:05b}", op);
-                            panic!("Unknown instruction");
-                        }
-                    }
-                }
-                // SOP33
-                0b011011 => {
-                    let op = get_shift(instruction);
-                    match op {
-                        0b00000 => {
-                            assert_eq!(rd, 0);
-                            // warn!("Deprecated DIVU instruction. Removed in release 6 of MIPS32");
-                            itrace!("divu\t{},{}", get_register_name(rs), get_register_name(rt));
-                            let l = registers.read_register(rs) / registers.read_register(rt);
-                            let h = registers.read_register(rs) % registers.read_register(rt);
-                            registers.write_lo(l);
-                            registers.write_hi(h);
-                        }
-                        _ => {
-                            error!("Unknown SOP33 instruction code: {:05b}", op);
-                            panic!("Unknown instruction");
-                        }
-                    }
-                }
-                // TEQ
-                0b110100 => {
-                    itrace!("teq\t{},{}", get_register_name(rs), get_register_name(rt));
-                    if (registers.read_register(rs) as i32) ==
-                        (registers.read_register(rt) as i32)
-                    {
-                        error!("TEQ instruction assert did not pass. Trap!");
-                        panic!("Error");
-                    }
-                }
-                // MFHI
-                0b010000 => {
-                    assert_eq!(rt, 0);
-                    assert_eq!(rs, 0);
-                    assert_eq!(get_shift(instruction), 0);
-                    itrace!("mfhi\t{}", get_register_name(rd));
-                    //warn!("Deprecated MFHI instruction. Removed in release 6 of MIPS32");
-                    let r = registers.read_hi();
-                    registers.write_register(rd, r);
-                }
-                0b010010 => {
-                    assert_eq!(rt, 0);
-                    assert_eq!(rs, 0);
-                    assert_eq!(get_shift(instruction), 0);
-                    itrace!("mflo\t{}", get_register_name(rd));
-                    //warn!("Deprecated MFLO instruction. Removed in release 6 of MIPS32");
-                    let r = registers.read_lo();
-                    registers.write_register(rd, r);
-                }
-                // SYSCALL
-                0b001100 => {
-                    result_cpu_event = system.eval_syscall(instruction, registers, memory);
-                }
-                // SYNC
-                0b001111 => {
-                    itrace!("sync - instruction ignored");
-                }
-                // BREAK
-                0b001101 => {
-                    itrace!("break");
-                    error!(
-                        "Breakpoint instruction reached. No idea, how to continue. Terminating!"
-                    );
-                    panic!();
-                }
-                _ => {
-                    error!("Unsupported ALU operation function code 0b{:06b}", funct);
-                    panic!("Unsupported ALU operation function code 0b{:06b}", funct)
-                }
+    // Returns from the function, when the program can't access the memory
+    macro_rules! check_access {
+        ($address:expr, $size:expr, $access:expr) => ({
+            let address = $address;
+            if let Err(fault) = memory.check_access(address, $size, $access) {
+                itrace!("segmentation fault at 0x{:08x}", address);
+                return CPUEvent::Exception(fault);
             }
+        });
+    }
+
+    // user programs can't access the kernel segment nor pages, that are not mapped for them.
+    // Accesses must be naturally aligned, LWL, LWR, SWL and SWR have the size of one byte here.
+    if let Some(operand) = decoded.memory_operand() {
+        let base = registers.read_register(operand.base);
+        let address = add_signed_offset(base, operand.offset as u16);
+        let kernel = address >= KERNEL_SEGMENT_START && registers.get_abi() != Abi::N64;
+        if kernel || address & (operand.size - 1) != 0 {
+            itrace!("address error at 0x{:08x}", address);
+            return CPUEvent::Exception(ExceptionKind::AddressError(address));
         }
-        // ADDIU
-        InstructionOpcode::ADDIU => {
-            let r = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        check_access!(address, operand.size, operand.access);
+    }
+
+    macro_rules! effective_address {
+        ($base:expr, $offset:expr) => (
+            add_signed_offset(registers.read_register($base), $offset as u16)
+        );
+    }
+
+    // LL and SC with the given effective address. SC succeeds only when the LLbit is still set and
+    // there was no store to the linked cache line.
+    macro_rules! load_linked {
+        ($rt:expr, $address:expr) => ({
+            let (rt, address) = ($rt, $address);
+            let r = memory.read_word(address);
             itrace!(
-                "addiu\t{},{},0x{:04x} - res=0x{:x}",
+                "ll\t{},0x{:x} - data=0x{:08x}",
                 get_register_name(rt),
-                get_register_name(rs),
-                get_offset(instruction),
+                address,
                 r
             );
             registers.write_register(rt, r);
+            registers.set_link(address);
+            memory.monitor_line(address);
+            result_cpu_event = CPUEvent::AtomicLoadModifyWriteBegan;
+        });
+    }
+    macro_rules! store_conditional {
+        ($rt:expr, $address:expr) => ({
+            let (rt, address) = ($rt, $address);
+            let linked = registers.get_link().map_or(false, |link| {
+                link & !(LINK_LINE_SIZE - 1) == address & !(LINK_LINE_SIZE - 1)
+            }) && memory.is_line_monitored(address);
+            itrace!(
+                "sc\t{},0x{:x} - data=0x{:08x} success={}",
+                get_register_name(rt),
+                address,
+                registers.read_register(rt),
+                linked
+            );
+            if linked {
+                memory.write_word(address, registers.read_register(rt));
+            }
+            registers.clear_link();
+            memory.clear_monitor();
+            registers.write_register(rt, linked as u32);
+        });
+    }
+
+    // Branch with a delay slot comparing two registers. The return address is written even when
+    // the branch is not taken, branch likely skips the delay slot in that case.
+    macro_rules! branch {
+        ($name:expr, $condition:expr, $rs:expr, $rt:expr, $offset:expr, $link:expr, $likely:expr) => ({
+            let (rs, rt) = ($rs, $rt);
+            let pc = registers.get_pc();
+            let target = (pc as i32 + 4 + $offset) as u32;
+            let jump =
+                $condition.evaluate(registers.read_register64(rs), registers.read_register64(rt));
+            itrace!(
+                "{}\t{},{},0x{:x} - jumped={}",
+                $name,
+                get_register_name(rs),
+                get_register_name(rt),
+                target,
+                jump
+            );
+            if $link {
+                registers.write_address(31, pc + 8);
+            }
+            if jump {
+                result_cpu_event = CPUEvent::FlowChangeDelayed(target);
+            } else if $likely {
+                result_cpu_event = CPUEvent::DelaySlotNullified;
+            }
+        });
+    }
+
+    // Release 6 compact branch. There is no delay slot, when the branch is not taken, the next
+    // instruction is in a forbidden slot. The link register is written in both cases.
+    macro_rules! compact_branch {
+        ($name:expr, $condition:expr, $rs:expr, $rt:expr, $offset:expr, $link:expr) => ({
+            let (rs, rt) = ($rs, $rt);
+            let target = (registers.get_pc() as i32 + 4 + $offset) as u32;
+            let jump =
+                $condition.evaluate(registers.read_register64(rs), registers.read_register64(rt));
+            itrace!(
+                "{}\t{},{},0x{:x} - jumped={}",
+                $name,
+                get_register_name(rs),
+                get_register_name(rt),
+                target,
+                jump
+            );
+            if $link {
+                let pc = registers.get_pc();
+                registers.write_address(31, pc + 4);
+            }
+            result_cpu_event = if jump {
+                CPUEvent::FlowChangeImmediate(target)
+            } else {
+                CPUEvent::ForbiddenSlotAhead
+            };
+        });
+    }
+
+    macro_rules! trap {
+        ($name:expr, $condition:expr, $rs:expr, $b:expr) => ({
+            let (rs, b) = ($rs, $b);
+            let val = registers.read_register64(rs);
+            let trap = $condition.evaluate(val, b);
+            itrace!(
+                "{}\t{},0x{:x} - val=0x{:x} => trap={}",
+                $name,
+                get_register_name(rs),
+                b,
+                val,
+                trap
+            );
+            if trap {
+                result_cpu_event = CPUEvent::Exception(ExceptionKind::Trap);
+            }
+        });
+    }
+
+    macro_rules! fpu_unary {
+        ($name:expr, $op:ident, $fmt:expr, $fd:expr, $fs:expr) => ({
+            let (fmt, fd, fs) = ($fmt, $fd, $fs);
+            itrace!("{}.{}\tf{},f{}", $name, FloatFmt::format_name(fmt), fd, fs);
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let r = FloatFmt::from_raw(fmt, fs, registers).$op(&mut env);
+            fpu_commit!(env, r.save(fd, registers));
+        });
+    }
+    macro_rules! fpu_binary {
+        ($name:expr, $op:ident, $fmt:expr, $fd:expr, $fs:expr, $ft:expr) => ({
+            let (fmt, fd, fs, ft) = ($fmt, $fd, $fs, $ft);
+            itrace!("{}.{}\tf{},f{},f{}", $name, FloatFmt::format_name(fmt), fd, fs, ft);
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let a = FloatFmt::from_raw(fmt, fs, registers);
+            let b = FloatFmt::from_raw(fmt, ft, registers);
+            let r = a.$op(b, &mut env);
+            fpu_commit!(env, r.save(fd, registers));
+        });
+    }
+    macro_rules! fpu_convert {
+        ($name:expr, $target:expr, $rounding:expr, $fmt:expr, $fd:expr, $fs:expr) => ({
+            let (fmt, fd, fs) = ($fmt, $fd, $fs);
+            itrace!(
+                "{}.{}.{}\tf{},f{}",
+                $name,
+                FloatFmt::format_name($target),
+                FloatFmt::format_name(fmt),
+                fd,
+                fs
+            );
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let r = FloatFmt::from_raw(fmt, fs, registers).convert($target, $rounding, &mut env);
+            fpu_commit!(env, r.save(fd, registers));
+        });
+    }
+    macro_rules! fpu_min_max {
+        ($name:expr, $max:expr, $magnitude:expr, $fmt:expr, $fd:expr, $fs:expr, $ft:expr) => ({
+            let (fmt, fd, fs, ft) = ($fmt, $fd, $fs, $ft);
+            itrace!("{}.{}\tf{},f{},f{}", $name, FloatFmt::format_name(fmt), fd, fs, ft);
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let a = FloatFmt::from_raw(fmt, fs, registers);
+            let b = FloatFmt::from_raw(fmt, ft, registers);
+            let r = a.min_max(b, $max, $magnitude, &mut env);
+            fpu_commit!(env, r.save(fd, registers));
+        });
+    }
+    // pre-R6 multiply-add is not fused, the product is rounded first
+    macro_rules! fpu_multiply_add {
+        ($name:expr, $sub:expr, $negate:expr, $fmt:expr, $fd:expr, $fr:expr, $fs:expr, $ft:expr) => ({
+            let (fmt, fd, fr, fs, ft) = ($fmt, $fd, $fr, $fs, $ft);
+            itrace!(
+                "{}.{}\tf{},f{},f{},f{}",
+                $name,
+                FloatFmt::format_name(fmt),
+                fd,
+                fr,
+                fs,
+                ft
+            );
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let product = FloatFmt::from_raw(fmt, fs, registers)
+                .mul(FloatFmt::from_raw(fmt, ft, registers), &mut env);
+            let addend = FloatFmt::from_raw(fmt, fr, registers);
+            let r = if $sub {
+                product.sub(addend, &mut env)
+            } else {
+                product.add(addend, &mut env)
+            };
+            let r = if $negate { r.neg(&mut env) } else { r };
+            fpu_commit!(env, r.save(fd, registers));
+        });
+    }
+
+    //print!("0x{:x}:    ", registers.get_pc());
+
+    match decoded {
+        Instruction::Sll { rd, rt, shift } => {
+            if instruction == 0 {
+                itrace!("nop\t");
+            } else {
+                itrace!(
+                    "sll\t{},{},{}",
+                    get_register_name(rd),
+                    get_register_name(rt),
+                    shift
+                );
+                let r = registers.read_register(rt) << shift;
+                registers.write_register(rd, r);
+            }
         }
-        // ANDI
-        InstructionOpcode::ANDI => {
+        Instruction::Srl { rd, rt, shift } => {
             itrace!(
-                "andi\t{},{},0x{:x}",
+                "srl\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rt),
+                shift
+            );
+            let r = registers.read_register(rt) >> shift;
+            registers.write_register(rd, r);
+        }
+        Instruction::Sra { rd, rt, shift } => {
+            itrace!(
+                "sra\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rt),
+                shift
+            );
+            let r = ((registers.read_register(rt) as i32) >> shift) as u32;
+            registers.write_register(rd, r);
+        }
+        Instruction::Rotr { rd, rt, shift } => {
+            itrace!(
+                "rotr\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rt),
+                shift
+            );
+            let r = registers.read_register(rt).rotate_right(shift);
+            registers.write_register(rd, r);
+        }
+        Instruction::Sllv { rd, rt, rs } => {
+            itrace!(
+                "sllv\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rt),
+                get_register_name(rs)
+            );
+            let r = registers.read_register(rt) << (registers.read_register(rs) & 0x1F);
+            registers.write_register(rd, r);
+        }
+        Instruction::Srlv { rd, rt, rs } => {
+            itrace!(
+                "srlv\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rt),
+                get_register_name(rs)
+            );
+            let r = registers.read_register(rt) >> (registers.read_register(rs) & 0x1F);
+            registers.write_register(rd, r);
+        }
+        Instruction::Srav { rd, rt, rs } => {
+            itrace!(
+                "srav\t{},{},{}",
+                get_register_name(rd),
                 get_register_name(rt),
+                get_register_name(rs)
+            );
+            let r = ((registers.read_register(rt) as i32) >>
+                         (registers.read_register(rs) & 0x1F)) as
+                u32;
+            registers.write_register(rd, r);
+        }
+        Instruction::Rotrv { rd, rt, rs } => {
+            itrace!(
+                "rotrv\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rt),
+                get_register_name(rs)
+            );
+            let r = registers.read_register(rt).rotate_right(registers.read_register(rs) & 0x1F);
+            registers.write_register(rd, r);
+        }
+        Instruction::Add { rd, rs, rt } => {
+            itrace!(
+                "add\t{},{},{}",
+                get_register_name(rd),
                 get_register_name(rs),
-                get_offset(instruction)
+                get_register_name(rt)
             );
-            let r = registers.read_register(rs) & (get_offset(instruction) as u32);
-            registers.write_register(rt, r);
+            let (r, overflow) = (registers.read_register(rs) as i32).overflowing_add(
+                registers.read_register(rt) as i32,
+            );
+            if overflow {
+                return CPUEvent::Exception(ExceptionKind::IntegerOverflow);
+            }
+            registers.write_register(rd, r as u32);
         }
-        // XORI
-        InstructionOpcode::XORI => {
+        Instruction::Addu { rd, rs, rt } => {
             itrace!(
-                "xori\t{},{},0x{:x}",
+                "addu\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            let (r, _) = registers.read_register(rs).overflowing_add(
+                registers.read_register(rt),
+            );
+            registers.write_register(rd, r);
+        }
+        Instruction::Sub { rd, rs, rt } => {
+            itrace!(
+                "sub\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            let (r, overflow) = (registers.read_register(rs) as i32).overflowing_sub(
+                registers.read_register(rt) as i32,
+            );
+            if overflow {
+                return CPUEvent::Exception(ExceptionKind::IntegerOverflow);
+            }
+            registers.write_register(rd, r as u32);
+        }
+        Instruction::Subu { rd, rs, rt } => {
+            let (r, _) = registers.read_register(rs).overflowing_sub(
+                registers.read_register(rt),
+            );
+            itrace!(
+                "subu\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            registers.write_register(rd, r);
+        }
+        Instruction::Or { rd, rs, rt } => {
+            let r = registers.read_register64(rs) | registers.read_register64(rt);
+            itrace!(
+                "or\t{},{},{} - res=0x{:08x}",
+                get_register_name(rd),
+                get_register_name(rs),
                 get_register_name(rt),
+                r
+            );
+            registers.write_register64(rd, r);
+        }
+        Instruction::Nor { rd, rs, rt } => {
+            let r = !(registers.read_register64(rs) | registers.read_register64(rt));
+            itrace!(
+                "nor\t{},{},{}",
+                get_register_name(rd),
                 get_register_name(rs),
-                get_offset(instruction)
+                get_register_name(rt)
             );
-            let r = registers.read_register(rs) ^ (get_offset(instruction) as u32);
-            registers.write_register(rt, r);
+            registers.write_register64(rd, r);
         }
-        // ORI
-        InstructionOpcode::ORI => {
+        Instruction::And { rd, rs, rt } => {
+            let r = registers.read_register64(rs) & registers.read_register64(rt);
             itrace!(
-                "ori\t{},{},0x{:04x}",
+                "and\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            registers.write_register64(rd, r);
+        }
+        Instruction::Xor { rd, rs, rt } => {
+            let r = registers.read_register64(rs) ^ registers.read_register64(rt);
+            itrace!(
+                "xor\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            registers.write_register64(rd, r);
+        }
+        Instruction::Slt { rd, rs, rt } => {
+            itrace!(
+                "slt\t{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            let r = (registers.read_register64(rs) as i64) <
+                (registers.read_register64(rt) as i64);
+            registers.write_register(rd, r as u32);
+        }
+        Instruction::Sltu { rd, rs, rt } => {
+            itrace!("sltu\t");
+            let r = registers.read_register64(rs) < registers.read_register64(rt);
+            registers.write_register(rd, r as u32);
+        }
+        Instruction::Jalr { rd, rs } => {
+            itrace!(
+                "jalr\t{},{} - target=0x{:08x}",
+                get_register_name(rd),
+                get_register_name(rs),
+                registers.read_register(rs)
+            );
+            let pc = registers.get_pc();
+            registers.write_address(rd, pc + 8);
+            let r = registers.read_register(rs);
+            result_cpu_event = CPUEvent::FlowChangeDelayed(r);
+        }
+        Instruction::Jr { rs } => {
+            itrace!("jr\t{}", get_register_name(rs));
+            let t = registers.read_register(rs);
+            result_cpu_event = CPUEvent::FlowChangeDelayed(t);
+        }
+        Instruction::Movf { rd, rs, cc } |
+        Instruction::Movt { rd, rs, cc } => {
+            let on_true = matches!(decoded, Instruction::Movt { .. });
+            itrace!(
+                "mov{}\t{},{},$fcc{}",
+                if on_true { "t" } else { "f" },
+                get_register_name(rd),
+                get_register_name(rs),
+                cc
+            );
+            if registers.read_fcc(cc) == on_true {
+                let r = registers.read_register64(rs);
+                registers.write_register64(rd, r);
+            }
+        }
+        Instruction::Movn { rd, rs, rt } => {
+            if registers.read_register64(rt) != 0 {
+                let r = registers.read_register64(rs);
+                registers.write_register64(rd, r);
+            }
+            itrace!(
+                "movn\t{},{},{} - value_written={}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                registers.read_register(rt) != 0
+            );
+        }
+        Instruction::Movz { rd, rs, rt } => {
+            if registers.read_register64(rt) == 0 {
+                let r = registers.read_register64(rs);
+                registers.write_register64(rd, r);
+            }
+            itrace!(
+                "movz\t{},{},{} - value_written={}",
+                get_register_name(rd),
+                get_register_name(rs),
                 get_register_name(rt),
+                registers.read_register(rt) != 0
+            );
+        }
+        Instruction::Seleqz { rd, rs, rt } |
+        Instruction::Selnez { rd, rs, rt } => {
+            let on_zero = matches!(decoded, Instruction::Seleqz { .. });
+            itrace!(
+                "sel{}\t{},{},{}",
+                if on_zero { "eqz" } else { "nez" },
+                get_register_name(rd),
                 get_register_name(rs),
-                get_offset(instruction)
+                get_register_name(rt)
             );
-            let r = registers.read_register(rs) | (get_offset(instruction) as u32);
-            registers.write_register(rt, r);
+            let r = if (registers.read_register64(rt) == 0) == on_zero {
+                registers.read_register64(rs)
+            } else {
+                0
+            };
+            registers.write_register64(rd, r);
         }
-        // BAL or BGEZAL
-        InstructionOpcode::REGIMM => {
-            let mut lower = false;
-            let mut equal = false;
-            let mut higher = false;
-            let mut link;
-            let mut inst;
-            if rs == 0 && rt == 0b10001 {
-                inst = "bal";
-                equal = true;
-                link = true;
-            } else if rt == 0b10001 {
-                // if necessary, just remove this panic
-                panic!("BGEZAL was removed in release 6");
-            /*
-                inst = "BGEZAL";
-                higher = true;
-                equal = true;
-                link = true;
-                */
-            } else if rt == 0b00000 {
-                inst = "bltz";
-                lower = true;
-                link = false;
-            } else if rt == 0b000001 {
-                inst = "bgez";
-                higher = true;
-                equal = true;
-                link = false;
+        Instruction::Lsa { rd, rs, rt, shift } => {
+            itrace!(
+                "lsa\t{},{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                shift
+            );
+            let r = registers.read_register(rs) << shift;
+            registers.write_register(rd, r.wrapping_add(registers.read_register(rt)));
+        }
+        // MULT, MULTU (pre-R6), the accumulator is selected by the DSP ASE
+        Instruction::Mult { ac, rs, rt } |
+        Instruction::Multu { ac, rs, rt } => {
+            let unsigned = matches!(decoded, Instruction::Multu { .. });
+            itrace!(
+                "{}\tac{},{},{}",
+                if unsigned { "multu" } else { "mult" },
+                ac,
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let r = if unsigned {
+                (a as u64) * (b as u64)
             } else {
-                panic!("Unknown weird conditional jump with rt=0b{:05b}", rt);
+                (a as i32 as i64 * b as i32 as i64) as u64
+            };
+            registers.write_accumulator(ac, r);
+        }
+        // DIV, DIVU (pre-R6)
+        Instruction::Div { rs, rt } |
+        Instruction::Divu { rs, rt } => {
+            let unsigned = matches!(decoded, Instruction::Divu { .. });
+            itrace!(
+                "{}\tac0,{},{}",
+                if unsigned { "divu" } else { "div" },
+                get_register_name(rs),
+                get_register_name(rt)
+            );
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            // division by zero does not trap, the result is UNPREDICTABLE and compilers check the
+            // divisor using TEQ. HI and LO are left untouched.
+            let result = if unsigned {
+                a.checked_div(b).zip(a.checked_rem(b))
+            } else if b != 0 {
+                let (a, b) = (a as i32, b as i32);
+                Some((a.wrapping_div(b) as u32, a.wrapping_rem(b) as u32))
+            } else {
+                None
+            };
+            if let Some((quotient, remainder)) = result {
+                registers.write_hi(0, remainder);
+                registers.write_lo(0, quotient);
             }
-
-            let val = registers.read_register(rs) as i32;
-            let pc = registers.get_pc();
-            let address =
-                (pc as i32 + 4 + sign_extend((get_offset(instruction) as u32) << 2, 18)) as u32;
-            let mut jumped = false;
-
-            if (lower && val < 0) || (equal && val == 0) || (higher && val > 0) {
-                if link {
-                    registers.write_register(31, pc + 8);
-                }
-                result_cpu_event = CPUEvent::FlowChangeDelayed(address);
-                jumped = true;
+        }
+        // MUL, MUH, MULU, MUHU, DIV, MOD, DIVU, MODU (R6), SPECIAL2 MUL (pre-R6)
+        Instruction::Mul { rd, rs, rt } |
+        Instruction::Muh { rd, rs, rt } |
+        Instruction::Mulu { rd, rs, rt } |
+        Instruction::Muhu { rd, rs, rt } |
+        Instruction::DivR6 { rd, rs, rt } |
+        Instruction::Mod { rd, rs, rt } |
+        Instruction::DivuR6 { rd, rs, rt } |
+        Instruction::Modu { rd, rs, rt } => {
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let (sa, sb) = (a as i32 as i64, b as i32 as i64);
+            let (name, r) = match decoded {
+                Instruction::Mul { .. } => ("mul", (sa * sb) as u32),
+                Instruction::Muh { .. } => ("muh", ((sa * sb) >> 32) as u32),
+                Instruction::Mulu { .. } => ("mulu", a.wrapping_mul(b)),
+                Instruction::Muhu { .. } => ("muhu", ((a as u64 * b as u64) >> 32) as u32),
+                // results of division by zero are UNPREDICTABLE, zero is used
+                Instruction::DivR6 { .. } if b == 0 => ("div", 0),
+                Instruction::Mod { .. } if b == 0 => ("mod", 0),
+                Instruction::DivuR6 { .. } if b == 0 => ("divu", 0),
+                Instruction::Modu { .. } if b == 0 => ("modu", 0),
+                Instruction::DivR6 { .. } => ("div", (a as i32).wrapping_div(b as i32) as u32),
+                Instruction::Mod { .. } => ("mod", (a as i32).wrapping_rem(b as i32) as u32),
+                Instruction::DivuR6 { .. } => ("divu", a / b),
+                Instruction::Modu { .. } => ("modu", a % b),
+                _ => unreachable!(),
+            };
+            itrace!(
+                "{}\t{},{},{} - res=0x{:08x}",
+                name,
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            registers.write_register(rd, r);
+        }
+        // MADD, MADDU, MSUB, MSUBU
+        Instruction::Madd { ac, rs, rt } |
+        Instruction::Maddu { ac, rs, rt } |
+        Instruction::Msub { ac, rs, rt } |
+        Instruction::Msubu { ac, rs, rt } => {
+            let (name, unsigned, subtract) = match decoded {
+                Instruction::Madd { .. } => ("madd", false, false),
+                Instruction::Maddu { .. } => ("maddu", true, false),
+                Instruction::Msub { .. } => ("msub", false, true),
+                _ => ("msubu", true, true),
+            };
+            let (a, b) = (registers.read_register(rs), registers.read_register(rt));
+            let product = if unsigned {
+                (a as u64).wrapping_mul(b as u64)
+            } else {
+                ((a as i32 as i64) * (b as i32 as i64)) as u64
+            };
+            let acc = registers.read_accumulator(ac);
+            let r = if subtract {
+                acc.wrapping_sub(product)
+            } else {
+                acc.wrapping_add(product)
+            };
+            itrace!(
+                "{}\tac{},{},{} - acc=0x{:016x}",
+                name,
+                ac,
+                get_register_name(rs),
+                get_register_name(rt),
+                r
+            );
+            registers.write_accumulator(ac, r);
+        }
+        Instruction::Mfhi { rd, ac } => {
+            itrace!("mfhi\t{},ac{}", get_register_name(rd), ac);
+            let r = registers.read_hi64(ac);
+            registers.write_register64(rd, r);
+        }
+        Instruction::Mflo { rd, ac } => {
+            itrace!("mflo\t{},ac{}", get_register_name(rd), ac);
+            let r = registers.read_lo64(ac);
+            registers.write_register64(rd, r);
+        }
+        Instruction::Mthi { rs, ac } => {
+            itrace!("mthi\t{},ac{}", get_register_name(rs), ac);
+            let val = registers.read_register64(rs);
+            registers.write_hi64(ac, val);
+        }
+        Instruction::Mtlo { rs, ac } => {
+            itrace!("mtlo\t{},ac{}", get_register_name(rs), ac);
+            let val = registers.read_register64(rs);
+            registers.write_lo64(ac, val);
+        }
+        Instruction::Clz { rd, rs } |
+        Instruction::Clo { rd, rs } => {
+            let val = registers.read_register(rs);
+            let (name, r) = if let Instruction::Clz { .. } = decoded {
+                ("clz", val.leading_zeros())
+            } else {
+                ("clo", (!val).leading_zeros())
+            };
+            itrace!(
+                "{}\t{},{} - res={}",
+                name,
+                get_register_name(rd),
+                get_register_name(rs),
+                r
+            );
+            registers.write_register(rd, r);
+        }
+        Instruction::Tge { rs, rt } => {
+            trap!("tge", BranchCondition::GreaterEqual, rs, registers.read_register64(rt))
+        }
+        Instruction::Tgeu { rs, rt } => {
+            trap!("tgeu", BranchCondition::GreaterEqualUnsigned, rs, registers.read_register64(rt))
+        }
+        Instruction::Tlt { rs, rt } => {
+            trap!("tlt", BranchCondition::LessThan, rs, registers.read_register64(rt))
+        }
+        Instruction::Tltu { rs, rt } => {
+            trap!("tltu", BranchCondition::LessThanUnsigned, rs, registers.read_register64(rt))
+        }
+        Instruction::Teq { rs, rt } => {
+            trap!("teq", BranchCondition::Equal, rs, registers.read_register64(rt))
+        }
+        Instruction::Tne { rs, rt } => {
+            trap!("tne", BranchCondition::NotEqual, rs, registers.read_register64(rt))
+        }
+        // the immediate is sign extended even for the unsigned comparisons
+        Instruction::Tgei { rs, immediate } => {
+            trap!("tgei", BranchCondition::GreaterEqual, rs, immediate as i64 as u64)
+        }
+        Instruction::Tgeiu { rs, immediate } => {
+            trap!("tgeiu", BranchCondition::GreaterEqualUnsigned, rs, immediate as i64 as u64)
+        }
+        Instruction::Tlti { rs, immediate } => {
+            trap!("tlti", BranchCondition::LessThan, rs, immediate as i64 as u64)
+        }
+        Instruction::Tltiu { rs, immediate } => {
+            trap!("tltiu", BranchCondition::LessThanUnsigned, rs, immediate as i64 as u64)
+        }
+        Instruction::Teqi { rs, immediate } => {
+            trap!("teqi", BranchCondition::Equal, rs, immediate as i64 as u64)
+        }
+        Instruction::Tnei { rs, immediate } => {
+            trap!("tnei", BranchCondition::NotEqual, rs, immediate as i64 as u64)
+        }
+        Instruction::Sdbbp { .. } => {
+            itrace!("sdbbp");
+            result_cpu_event = CPUEvent::Exception(ExceptionKind::Breakpoint);
+        }
+        Instruction::Syscall { .. } => {
+            // returning from the kernel clears the LLbit
+            registers.clear_link();
+            result_cpu_event = system.eval_syscall(instruction, registers, memory);
+        }
+        Instruction::Sync { .. } => {
+            itrace!("sync - instruction ignored");
+        }
+        Instruction::Break { .. } => {
+            itrace!("break");
+            result_cpu_event = CPUEvent::Exception(ExceptionKind::Breakpoint);
+        }
+        // ADDI (pre-R6)
+        Instruction::Addi { rt, rs, immediate } => {
+            itrace!(
+                "addi\t{},{},0x{:04x}",
+                get_register_name(rt),
+                get_register_name(rs),
+                immediate
+            );
+            let (r, overflow) = (registers.read_register(rs) as i32).overflowing_add(
+                immediate as i32,
+            );
+            if overflow {
+                return CPUEvent::Exception(ExceptionKind::IntegerOverflow);
             }
+            registers.write_register(rt, r as u32);
+        }
+        Instruction::Addiu { rt, rs, immediate } => {
+            let r = add_signed_offset(registers.read_register(rs), immediate as u16);
+            itrace!(
+                "addiu\t{},{},0x{:04x} - res=0x{:x}",
+                get_register_name(rt),
+                get_register_name(rs),
+                immediate,
+                r
+            );
+            registers.write_register(rt, r);
+        }
+        Instruction::Andi { rt, rs, immediate } => {
             itrace!(
-                "{}\t{},0x{:x} - val=0x{:x} => {}",
-                inst,
+                "andi\t{},{},0x{:x}",
+                get_register_name(rt),
+                get_register_name(rs),
+                immediate
+            );
+            let r = registers.read_register64(rs) & (immediate as u64);
+            registers.write_register64(rt, r);
+        }
+        Instruction::Xori { rt, rs, immediate } => {
+            itrace!(
+                "xori\t{},{},0x{:x}",
+                get_register_name(rt),
                 get_register_name(rs),
-                address,
-                val,
-                jumped
+                immediate
             );
+            let r = registers.read_register64(rs) ^ (immediate as u64);
+            registers.write_register64(rt, r);
         }
-        // BEQ
-        InstructionOpcode::BEQ => {
-            let target_offset = sign_extend((get_offset(instruction) as u32) << 2, 18);
-            let r = (registers.get_pc() as i32 + 4 + target_offset) as u32;
+        Instruction::Ori { rt, rs, immediate } => {
             itrace!(
-                "beq\t{},{},0x{:x} - jumped={}",
-                get_register_name(rs),
+                "ori\t{},{},0x{:04x}",
                 get_register_name(rt),
-                r,
-                registers.read_register(rs) == registers.read_register(rt)
+                get_register_name(rs),
+                immediate
             );
-            if registers.read_register(rs) == registers.read_register(rt) {
-                result_cpu_event = CPUEvent::FlowChangeDelayed(r);
-            }
+            let r = registers.read_register64(rs) | (immediate as u64);
+            registers.write_register64(rt, r);
         }
-        // BNE
-        InstructionOpcode::BNE => {
-            let target_offset = sign_extend((get_offset(instruction) as u32) << 2, 18);
-            let r = (registers.get_pc() as i32 + 4 + target_offset) as u32;
+        Instruction::Slti { rt, rs, immediate } => {
+            let a = (registers.read_register64(rs) as i64) < immediate as i64;
             itrace!(
-                "bne\t{},{},0x{:x} - jumped={}",
+                "slti\t{},{},0x{:x} - {:08x} < {:08x} = {}",
+                get_register_name(rt),
                 get_register_name(rs),
+                immediate,
+                registers.read_register(rs),
+                immediate as i32,
+                a
+            );
+            registers.write_register(rt, a as u32);
+        }
+        Instruction::Sltiu { rt, rs, immediate } => {
+            let a = registers.read_register64(rs) < immediate as i64 as u64;
+            itrace!(
+                "sltiu\t{},{},0x{:x} - {:08x} < {:08x} = {}",
                 get_register_name(rt),
-                r,
-                registers.read_register(rs) != registers.read_register(rt)
+                get_register_name(rs),
+                immediate,
+                registers.read_register(rs),
+                immediate as i32 as u32,
+                a
             );
-            if registers.read_register(rs) != registers.read_register(rt) {
-                result_cpu_event = CPUEvent::FlowChangeDelayed(r);
-            }
+            registers.write_register(rt, a as u32);
+        }
+        Instruction::Lui { rt, immediate } => {
+            itrace!("lui\t{},0x{:04x}", get_register_name(rt), immediate);
+            registers.write_register(rt, (immediate as u32) << 16);
+        }
+        // AUI (R6)
+        Instruction::Aui { rt, rs, immediate } => {
+            itrace!("aui\t");
+            let r = add_to_upper_bits(registers.read_register(rs), immediate);
+            registers.write_register(rt, r);
+        }
+        Instruction::Beq { rs, rt, offset } => {
+            branch!("beq", BranchCondition::Equal, rs, rt, offset, false, false)
+        }
+        Instruction::Bne { rs, rt, offset } => {
+            branch!("bne", BranchCondition::NotEqual, rs, rt, offset, false, false)
+        }
+        Instruction::Blez { rs, offset } => {
+            branch!("blez", BranchCondition::LessEqual, rs, 0, offset, false, false)
+        }
+        Instruction::Bgtz { rs, offset } => {
+            branch!("bgtz", BranchCondition::GreaterThan, rs, 0, offset, false, false)
+        }
+        // branch likely (pre-R6)
+        Instruction::Beql { rs, rt, offset } => {
+            branch!("beql", BranchCondition::Equal, rs, rt, offset, false, true)
+        }
+        Instruction::Bnel { rs, rt, offset } => {
+            branch!("bnel", BranchCondition::NotEqual, rs, rt, offset, false, true)
+        }
+        Instruction::Blezl { rs, offset } => {
+            branch!("blezl", BranchCondition::LessEqual, rs, 0, offset, false, true)
+        }
+        Instruction::Bgtzl { rs, offset } => {
+            branch!("bgtzl", BranchCondition::GreaterThan, rs, 0, offset, false, true)
+        }
+        Instruction::Bltz { rs, offset } => {
+            branch!("bltz", BranchCondition::LessThan, rs, 0, offset, false, false)
+        }
+        Instruction::Bgez { rs, offset } => {
+            branch!("bgez", BranchCondition::GreaterEqual, rs, 0, offset, false, false)
         }
-        // BGTZ
-        InstructionOpcode::BGTZ => {
-            assert_eq!(rt, 0);
-            let target_offset = sign_extend((get_offset(instruction) as u32) << 2, 18);
-            let target = (registers.get_pc() as i32 + 4 + target_offset) as u32;
-            itrace!("bgtz\t{},0x{:x}", get_register_name(rs), target);
-            if (registers.read_register(rs) as i32) > 0 {
+        Instruction::Bltzl { rs, offset } => {
+            branch!("bltzl", BranchCondition::LessThan, rs, 0, offset, false, true)
+        }
+        Instruction::Bgezl { rs, offset } => {
+            branch!("bgezl", BranchCondition::GreaterEqual, rs, 0, offset, false, true)
+        }
+        Instruction::Bltzal { rs, offset } => {
+            branch!("bltzal", BranchCondition::LessThan, rs, 0, offset, true, false)
+        }
+        Instruction::Bgezal { rs, offset } => {
+            branch!("bgezal", BranchCondition::GreaterEqual, rs, 0, offset, true, false)
+        }
+        Instruction::Bltzall { rs, offset } => {
+            branch!("bltzall", BranchCondition::LessThan, rs, 0, offset, true, true)
+        }
+        Instruction::Bgezall { rs, offset } => {
+            branch!("bgezall", BranchCondition::GreaterEqual, rs, 0, offset, true, true)
+        }
+        Instruction::Nal { offset } => {
+            branch!("nal", BranchCondition::LessThan, 0, 0, offset, true, false)
+        }
+        Instruction::Bal { offset } => {
+            branch!("bal", BranchCondition::GreaterEqual, 0, 0, offset, true, false)
+        }
+        // BPOSGE32 (DSP ASE)
+        Instruction::Bposge32 { offset } => {
+            let pos = registers.read_dsp_control() & DSP_CONTROL_POS;
+            let target = (registers.get_pc() as i32 + 4 + offset) as u32;
+            itrace!("bposge32\t0x{:x} - pos={} => {}", target, pos, pos >= 32);
+            if pos >= 32 {
                 result_cpu_event = CPUEvent::FlowChangeDelayed(target);
             }
         }
-        //BLEZ
-        InstructionOpcode::BLEZ => {
-            assert_eq!(rt, 0);
-            let target_offset = sign_extend((get_offset(instruction) as u32) << 2, 18);
-            let target = (registers.get_pc() as i32 + 4 + target_offset) as u32;
-            itrace!("blez\t{},0x{:x}", get_register_name(rs), target);
-            if (registers.read_register(rs) as i32) <= 0 {
-                result_cpu_event = CPUEvent::FlowChangeDelayed(target);
+        // SYNCI and CACHE drop decoded instructions of the page, other caches are not emulated
+        Instruction::Synci { base, offset } => {
+            let address = effective_address!(base, offset);
+            itrace!("synci\t0x{:x}", address);
+            memory.invalidate_code_page(address);
+        }
+        Instruction::Cache { op, base, offset } => {
+            let address = effective_address!(base, offset);
+            itrace!("cache\t0x{:x},0x{:x}", op, address);
+            memory.invalidate_code_page(address);
+        }
+        // compact branches (R6)
+        Instruction::Blezalc { rt, offset } => {
+            compact_branch!("blezalc", BranchCondition::LessEqual, rt, 0, offset, true)
+        }
+        Instruction::Bgtzalc { rt, offset } => {
+            compact_branch!("bgtzalc", BranchCondition::GreaterThan, rt, 0, offset, true)
+        }
+        Instruction::Bgezalc { rt, offset } => {
+            compact_branch!("bgezalc", BranchCondition::GreaterEqual, rt, 0, offset, true)
+        }
+        Instruction::Bltzalc { rt, offset } => {
+            compact_branch!("bltzalc", BranchCondition::LessThan, rt, 0, offset, true)
+        }
+        Instruction::Beqzalc { rt, offset } => {
+            compact_branch!("beqzalc", BranchCondition::Equal, rt, 0, offset, true)
+        }
+        Instruction::Bnezalc { rt, offset } => {
+            compact_branch!("bnezalc", BranchCondition::NotEqual, rt, 0, offset, true)
+        }
+        Instruction::Blezc { rt, offset } => {
+            compact_branch!("blezc", BranchCondition::LessEqual, rt, 0, offset, false)
+        }
+        Instruction::Bgtzc { rt, offset } => {
+            compact_branch!("bgtzc", BranchCondition::GreaterThan, rt, 0, offset, false)
+        }
+        Instruction::Bgezc { rt, offset } => {
+            compact_branch!("bgezc", BranchCondition::GreaterEqual, rt, 0, offset, false)
+        }
+        Instruction::Bltzc { rt, offset } => {
+            compact_branch!("bltzc", BranchCondition::LessThan, rt, 0, offset, false)
+        }
+        Instruction::Beqzc { rs, offset } => {
+            compact_branch!("beqzc", BranchCondition::Equal, rs, 0, offset, false)
+        }
+        Instruction::Bnezc { rs, offset } => {
+            compact_branch!("bnezc", BranchCondition::NotEqual, rs, 0, offset, false)
+        }
+        Instruction::Beqc { rs, rt, offset } => {
+            compact_branch!("beqc", BranchCondition::Equal, rs, rt, offset, false)
+        }
+        Instruction::Bnec { rs, rt, offset } => {
+            compact_branch!("bnec", BranchCondition::NotEqual, rs, rt, offset, false)
+        }
+        Instruction::Bgec { rs, rt, offset } => {
+            compact_branch!("bgec", BranchCondition::GreaterEqual, rs, rt, offset, false)
+        }
+        Instruction::Bltc { rs, rt, offset } => {
+            compact_branch!("bltc", BranchCondition::LessThan, rs, rt, offset, false)
+        }
+        Instruction::Bgeuc { rs, rt, offset } => {
+            compact_branch!("bgeuc", BranchCondition::GreaterEqualUnsigned, rs, rt, offset, false)
+        }
+        Instruction::Bltuc { rs, rt, offset } => {
+            compact_branch!("bltuc", BranchCondition::LessThanUnsigned, rs, rt, offset, false)
+        }
+        Instruction::Bovc { rs, rt, offset } => {
+            compact_branch!("bovc", BranchCondition::Overflow, rs, rt, offset, false)
+        }
+        Instruction::Bnvc { rs, rt, offset } => {
+            compact_branch!("bnvc", BranchCondition::NoOverflow, rs, rt, offset, false)
+        }
+        // JIC, JIALC (R6)
+        Instruction::Jic { rt, offset } |
+        Instruction::Jialc { rt, offset } => {
+            let link = matches!(decoded, Instruction::Jialc { .. });
+            let target = add_signed_offset(registers.read_register(rt), offset as u16);
+            itrace!(
+                "{}\t{},0x{:x} - target=0x{:x}",
+                if link { "jialc" } else { "jic" },
+                get_register_name(rt),
+                offset,
+                target
+            );
+            if link {
+                let pc = registers.get_pc();
+                registers.write_address(31, pc + 4);
+            }
+            result_cpu_event = CPUEvent::FlowChangeImmediate(target);
+        }
+        // BC, BALC (R6)
+        Instruction::Bc { offset } |
+        Instruction::Balc { offset } => {
+            let link = matches!(decoded, Instruction::Balc { .. });
+            let target = (registers.get_pc() as i32 + 4 + offset) as u32;
+            itrace!("{}\t0x{:x}", if link { "balc" } else { "bc" }, target);
+            if link {
+                let pc = registers.get_pc();
+                registers.write_address(31, pc + 4);
             }
+            result_cpu_event = CPUEvent::FlowChangeImmediate(target);
         }
-        // J
-        InstructionOpcode::J => {
+        Instruction::J { target } => {
             itrace!("j\t");
             let pc = registers.get_pc() + 4;
-            let target = (pc & 0xF0_00_00_00) | ((instruction & 0x03_FF_FF_FF) << 2);
+            let target = (pc & 0xF0_00_00_00) | target;
             result_cpu_event = CPUEvent::FlowChangeDelayed(target);
         }
-        // JAL
-        InstructionOpcode::JAL => {
+        Instruction::Jal { target } => {
             itrace!("jal\t");
             let pc = registers.get_pc();
-            let target = (pc & 0xF0_00_00_00) | ((instruction & 0x03_FF_FF_FF) << 2);
-            registers.write_register(31, pc + 8);
+            let target = (pc & 0xF0_00_00_00) | target;
+            registers.write_address(31, pc + 8);
             result_cpu_event = CPUEvent::FlowChangeDelayed(target);
         }
-        // AUI
-        InstructionOpcode::AUI => {
-            itrace!("aui\t");
-            let r = add_to_upper_bits(registers.read_register(rs), get_offset(instruction));
-            registers.write_register(rt, r);
+        // JALX, switches to the compressed ISA
+        Instruction::Jalx { target } => {
+            itrace!("jalx\t");
+            let pc = registers.get_pc();
+            let target = ((pc + 4) & 0xF0_00_00_00) | target;
+            registers.write_address(31, pc + 8);
+            result_cpu_event = CPUEvent::FlowChangeDelayed(target | ISA_MODE_BIT);
         }
-        // LB
-        InstructionOpcode::LB => {
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Lb { rt, base, offset } => {
+            let addr = effective_address!(base, offset);
             let r = sign_extend(memory.read_byte(addr), 8);
             itrace!(
                 "lb\t{},0x{:x} - data=0x{:08x}",
@@ -620,28 +1071,19 @@ pub fn eval_instruction(
             );
             registers.write_register(rt, r as u32);
         }
-        //LHU
-        InstructionOpcode::LHU => {
-            let r = memory.read_halfword(add_signed_offset(
-                registers.read_register(rs),
-                get_offset(instruction),
-            ));
+        Instruction::Lhu { rt, base, offset } => {
+            let r = memory.read_halfword(effective_address!(base, offset));
             itrace!("lhu\tdata={:08x}", r);
             registers.write_register(rt, r);
         }
-        //LH
-        InstructionOpcode::LH => {
-            let r = memory.read_halfword(add_signed_offset(
-                registers.read_register(rs),
-                get_offset(instruction),
-            ));
+        Instruction::Lh { rt, base, offset } => {
+            let r = memory.read_halfword(effective_address!(base, offset));
             itrace!("lh\tdata={:08x}", r);
             let r = sign_extend(r, 16) as u32;
             registers.write_register(rt, r);
         }
-        // LBU
-        InstructionOpcode::LBU => {
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Lbu { rt, base, offset } => {
+            let addr = effective_address!(base, offset);
             let r = memory.read_byte(addr);
             itrace!(
                 "lbu\t{},0x{:x} - data=0x{:08x}",
@@ -651,9 +1093,8 @@ pub fn eval_instruction(
             );
             registers.write_register(rt, r);
         }
-        // LW
-        InstructionOpcode::LW => {
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Lw { rt, base, offset } => {
+            let addr = effective_address!(base, offset);
             let r = memory.read_word(addr);
             itrace!(
                 "lw\tmem[0x{:x}] -> {}, data=0x{:08x}",
@@ -663,66 +1104,33 @@ pub fn eval_instruction(
             );
             registers.write_register(rt, r);
         }
-        // LWL
-        InstructionOpcode::LWL => {
-            // FIXME this is tested just on BigEndian
-
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
-            let r = memory.read_word(addr);
-            let vaddr = (addr % 4) * 8;
-            let mask = (0xFF_FF_FF_FF >> vaddr) << vaddr;
-            itrace!(
-                "lwl\t{},0x{:x} - data=0x{:08x}, mask=0x{:x} (the data will be overlayed by previous register content)",
-                get_register_name(rt),
-                addr,
-                r,
-                mask
-            );
-            let pv = registers.read_register(rt);
-            registers.write_register(rt, (pv & !mask) | (r & mask));
-        }
-        // LWR
-        InstructionOpcode::LWR => {
-            //FIXME this is tested just on BigEndian
-
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction)) - 3;
-            let r = memory.read_word(addr);
-            let vaddr = ((4 - (addr % 4)) % 4) * 8;
-            let mask = (0xFF_FF_FF_FF << vaddr) >> vaddr;
-            itrace!(
-                "lwr\t{},0x{:x} - data=0x{:08x}, mask=0x{:x} (the data will be overlayed by previous register content)",
-                get_register_name(rt),
-                addr,
-                r,
-                mask
-            );
+        Instruction::Lwl { rt, base, offset } | Instruction::Lwr { rt, base, offset } => {
+            let addr = effective_address!(base, offset);
             let pv = registers.read_register(rt);
-            registers.write_register(rt, (pv & !mask) | (r & mask));
-        }
-        // LL
-        InstructionOpcode::LL => {
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
-            let r = memory.read_word(addr);
+            let (name, r) = if let Instruction::Lwl { .. } = decoded {
+                ("lwl", memory.read_word_unaligned_lwl(addr, pv))
+            } else {
+                ("lwr", memory.read_word_unaligned_lwr(addr, pv))
+            };
             itrace!(
-                "ll\t{},0x{:x} - data=0x{:08x} - synchronized nature of the instruction is ignored, this is preR6 version of the instruction",
+                "{}\t{},0x{:x} - res=0x{:08x}",
+                name,
                 get_register_name(rt),
                 addr,
                 r
             );
             registers.write_register(rt, r);
-            result_cpu_event = CPUEvent::AtomicLoadModifyWriteBegan;
         }
-        // SB
-        InstructionOpcode::SB => {
+        Instruction::Ll { rt, base, offset } => {
+            load_linked!(rt, effective_address!(base, offset));
+        }
+        Instruction::Sb { rt, base, offset } => {
             itrace!("sb\t");
-            memory.write_byte(
-                add_signed_offset(registers.read_register(rs), get_offset(instruction)),
-                registers.read_register(rt),
-            );
+            let address = effective_address!(base, offset);
+            memory.write_byte(address, registers.read_register(rt));
         }
-        // SH
-        InstructionOpcode::SH => {
-            let address = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Sh { rt, base, offset } => {
+            let address = effective_address!(base, offset);
             itrace!(
                 "sh\t{},0x{:x} - data=0x{:04x}",
                 get_register_name(rt),
@@ -731,9 +1139,8 @@ pub fn eval_instruction(
             );
             memory.write_halfword(address, registers.read_register(rt));
         }
-        // SW
-        InstructionOpcode::SW => {
-            let address = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Sw { rt, base, offset } => {
+            let address = effective_address!(base, offset);
             itrace!(
                 "sw\t{} -> mem[0x{:x}], data=0x{:08x}",
                 get_register_name(rt),
@@ -742,9 +1149,8 @@ pub fn eval_instruction(
             );
             memory.write_word(address, registers.read_register(rt));
         }
-        // SWL
-        InstructionOpcode::SWL => {
-            let address = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Swl { rt, base, offset } => {
+            let address = effective_address!(base, offset);
             itrace!(
                 "swl\t{},0x{:x} - data=0x{:08x} (only part of the data will be stored)",
                 get_register_name(rt),
@@ -753,9 +1159,8 @@ pub fn eval_instruction(
             );
             memory.write_word_unaligned_swl(address, registers.read_register(rt));
         }
-        // SWR
-        InstructionOpcode::SWR => {
-            let address = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Swr { rt, base, offset } => {
+            let address = effective_address!(base, offset);
             itrace!(
                 "swr\t{},0x{:x} - data=0x{:08x} (only part of the data will be stored)",
                 get_register_name(rt),
@@ -764,201 +1169,852 @@ pub fn eval_instruction(
             );
             memory.write_word_unaligned_swr(address, registers.read_register(rt));
         }
-        // SC
-        InstructionOpcode::SC => {
-            let address = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Sc { rt, base, offset } => {
+            store_conditional!(rt, effective_address!(base, offset));
+        }
+        Instruction::Pref { hint, base, offset } => {
+            let address = effective_address!(base, offset);
+            itrace!("pref\t{},0x{:x} - instruction ignored", hint, address);
+        }
+        // PC relative (R6)
+        Instruction::Addiupc { rs, offset } => {
+            let r = registers.get_pc().wrapping_add(offset as u32);
+            itrace!("addiupc\t{},0x{:x} - res=0x{:08x}", get_register_name(rs), offset, r);
+            registers.write_address(rs, r);
+        }
+        // LWPC, LWUPC (MIPS64 instruction)
+        Instruction::Lwpc { rs, offset } |
+        Instruction::Lwupc { rs, offset } => {
+            let unsigned = matches!(decoded, Instruction::Lwupc { .. });
+            let address = registers.get_pc().wrapping_add(offset as u32);
+            if address >= KERNEL_SEGMENT_START && registers.get_abi() != Abi::N64 {
+                itrace!("address error at 0x{:08x}", address);
+                return CPUEvent::Exception(ExceptionKind::AddressError(address));
+            }
+            check_access!(address, 4, Access::Read);
+            let r = memory.read_word(address);
             itrace!(
-                "sc\t{},0x{:x} - data=0x{:08x}",
-                get_register_name(rt),
+                "{}\t{},mem[0x{:x}] - data=0x{:08x}",
+                if unsigned { "lwupc" } else { "lwpc" },
+                get_register_name(rs),
                 address,
-                registers.read_register(rt)
+                r
             );
-            memory.write_word(address, registers.read_register(rt));
-            registers.write_register(rt, 1); // to indicate success
+            if unsigned {
+                registers.write_register64(rs, r as u64);
+            } else {
+                registers.write_register(rs, r);
+            }
+        }
+        Instruction::Auipc { rs, immediate } => {
+            let r = registers.get_pc().wrapping_add((immediate as u32) << 16);
+            itrace!("auipc\t{},0x{:04x} - res=0x{:08x}", get_register_name(rs), immediate, r);
+            registers.write_address(rs, r);
         }
-        // SLTI
-        InstructionOpcode::SLTI => {
-            let a = registers.read_register(rs) < (get_offset(instruction) as u32);
+        Instruction::Aluipc { rs, immediate } => {
+            let r = registers.get_pc().wrapping_add((immediate as u32) << 16) & 0xFF_FF_00_00;
+            itrace!("aluipc\t{},0x{:04x} - res=0x{:08x}", get_register_name(rs), immediate, r);
+            registers.write_address(rs, r);
+        }
+        Instruction::Ext { rt, rs, pos, size } => {
             itrace!(
-                "slti\t{},{},0x{:x} - {:08x} < {:08x} = {}",
+                "ext\t{},{},pos={},size={}",
                 get_register_name(rt),
                 get_register_name(rs),
-                get_offset(instruction),
-                registers.read_register(rs),
-                get_offset(instruction),
-                a
+                pos,
+                size
             );
-            if a {
-                registers.write_register(rt, 1);
-            } else {
-                registers.write_register(rt, 0);
-            }
+            let r = (registers.read_register(rs) >> pos) & bit_mask(size);
+            registers.write_register(rt, r);
         }
-        // SLTIU
-        InstructionOpcode::SLTIU => {
-            let a = registers.read_register(rs) <
-                (sign_extend(get_offset(instruction) as u32, 16) as u32);
+        Instruction::Ins { rt, rs, pos, size } => {
             itrace!(
-                "sltiu\t{},{},0x{:x} - {:08x} < {:08x} = {}",
+                "ins\t{},{},pos={},size={}",
                 get_register_name(rt),
                 get_register_name(rs),
-                sign_extend(get_offset(instruction) as u32, 16),
-                registers.read_register(rs),
-                (sign_extend(get_offset(instruction) as u32, 16) as u32),
-                a
+                pos,
+                size
+            );
+            let mask = bit_mask(size) << pos;
+            let r = (registers.read_register(rt) & !mask) |
+                ((registers.read_register(rs) << pos) & mask);
+            registers.write_register(rt, r);
+        }
+        // BITSWAP (R6)
+        Instruction::Bitswap { rd, rt } => {
+            itrace!("bitswap\t{},{}", get_register_name(rd), get_register_name(rt));
+            let val = registers.read_register(rt);
+            let r = (0..4).fold(0, |r, i| {
+                r | ((((val >> (8 * i)) as u8).reverse_bits() as u32) << (8 * i))
+            });
+            registers.write_register(rd, r);
+        }
+        Instruction::Wsbh { rd, rt } => {
+            itrace!("wsbh\t{},{}", get_register_name(rd), get_register_name(rt));
+            let val = registers.read_register(rt);
+            registers.write_register(rd, ((val & 0x00_FF_00_FF) << 8) | ((val & 0xFF_00_FF_00) >> 8));
+        }
+        // ALIGN (R6)
+        Instruction::Align { rd, rs, rt, bp } => {
+            itrace!(
+                "align\t{},{},{},{}",
+                get_register_name(rd),
+                get_register_name(rs),
+                get_register_name(rt),
+                bp
             );
-            if a {
-                registers.write_register(rt, 1);
+            let val = registers.read_register(rt);
+            let r = if bp == 0 {
+                val
             } else {
-                registers.write_register(rt, 0);
-            }
+                (val << (8 * bp)) | (registers.read_register(rs) >> (32 - 8 * bp))
+            };
+            registers.write_register(rd, r);
         }
-        // PCREL
-        InstructionOpcode::PCREL => {
-            itrace!("PCREL ");
-            match rt {
-                0b11111 => {
-                    print!("ALUIPC");
-                    let r = 0xFF_FF_00_00 &
-                        (((registers.get_pc() as i32) +
-                              (((get_offset(instruction) as u32) << 16) as i32)) as
-                             u32);
-                    registers.write_register(rs, r);
-                }
-                _ => panic!("Unknown PCREL operation"),
-            }
+        Instruction::Seb { rd, rt } => {
+            itrace!("seb\t{},{}", get_register_name(rd), get_register_name(rt));
+            let r = sign_extend(registers.read_register(rt) & 0xFF, 8) as u32;
+            registers.write_register(rd, r);
         }
-        // SPECIAL2
-        InstructionOpcode::SPECIAL2 => {
-            match funct {
-                // MUL
-                0b000010 => {
-                    assert_eq!(get_shift(instruction), 0);
-                    let (r, _) = (registers.read_register(rs) as i32).overflowing_mul(
-                        registers.read_register(rt) as
-                            i32,
-                    );
-                    itrace!(
-                        "mul\t{},{},{} - res_value={}",
-                        get_register_name(rd),
-                        get_register_name(rs),
-                        get_register_name(rt),
-                        r
-                    );
-                    registers.write_register(rd, r as u32);
-                }
-                _ => {
-                    error!("Unknown SPECIAL3 funct: {:06b}", funct);
-                    panic!("Error");
-                }
-            }
+        Instruction::Seh { rd, rt } => {
+            itrace!("seh\t{},{}", get_register_name(rd), get_register_name(rt));
+            let r = sign_extend(registers.read_register(rt) & 0xFF_FF, 16) as u32;
+            registers.write_register(rd, r);
         }
-        // SPECIAL3
-        InstructionOpcode::SPECIAL3 => {
-            match funct {
-                // ALIGN
-                0b100000 => {
-                    itrace!("align");
-                    let shift = get_shift(instruction);
-                    assert_eq!(shift & 0xFC, 0b01000);
-                    let bp = shift & 0x03;
-                    let r = (registers.read_register(rt) << (8 * bp)) |
-                        (registers.read_register(rs) >> (32 - 8 * bp));
-                    registers.write_register(rd, r);
-                }
-                0b111011 => {
-                    itrace!("rdhwr");
-                    //let sel = get_offset(instruction) & 0x07;
-                    match rd {
-                        29 => {
-                            // should throw an exception
-                            warn!(
-                                "Attempt to read from UserLocalRegister in coprocessor. Unsupported. Faking it with some constant value."
-                            );
-                            registers.write_register(rt, 0x58e950); // this value was copied from gdb on real HW
-                            // UserLocal Register. This register provides read access to the coprocessor 0
-                            // UserLocal register, if it is implemented.
-                            // In some operating environments, the UserLocal register is a pointer to a
-                            // thread-specific storage block.
-                        }
-                        _ => {
-                            println!();
-
-                            panic!(
-                                "Attempt to read unknown CPU hardware register - rd={} into register rt={}",
-                                rd,
-                                rt
-                            );
-                        }
+        Instruction::Rdhwr { rt, rd } => {
+            itrace!("rdhwr\t{},{}", get_register_name(rt), rd);
+            let r = match rd {
+                // CPUNum
+                0 => 0,
+                // SYNCI_Step, cache line size of 74Kc
+                1 => 32,
+                // CC, host monotonic clock is used as a cycle counter
+                2 => {
+                    let mut time = ::libc::timespec { tv_sec: 0, tv_nsec: 0 };
+                    unsafe {
+                        ::libc::clock_gettime(::libc::CLOCK_MONOTONIC, &mut time);
                     }
+                    (time.tv_sec as u64)
+                        .wrapping_mul(1_000_000_000)
+                        .wrapping_add(time.tv_nsec as u64) as u32
                 }
-                //EXT
-                0b000000 => {
-                    let pos = get_shift(instruction);
-                    let size = rd + 1;
-
-                    itrace!(
-                        "ext\t{},{},pos={},size={}",
-                        get_register_name(rt),
-                        get_register_name(rs),
-                        pos,
-                        size
-                    );
-
-                    assert!(pos < 32);
-                    assert!(size <= 32);
-                    assert!(pos + size <= 32);
-                    let r = ((((1 << (size + 1)) - 1) << pos) & registers.read_register(rs)) >> pos;
-                    registers.write_register(rt, r);
-                }
-                _ => {
-                    error!(
-                        "Tried to execute SPECIAL3 instruction with unknown FUNCT - 0b{:06b}",
-                        funct
-                    );
-                    panic!(
-                        "Tried to execute SPECIAL3 instruction with unknown FUNCT - 0b{:06b}",
-                        funct
-                    );
-                }
+                // CCRes
+                3 => 1,
+                // UserLocal, the thread pointer of the program
+                29 => registers.get_user_local(),
+                _ => return CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            };
+            if rd == 29 {
+                registers.write_address(rt, r);
+            } else {
+                registers.write_register(rt, r);
             }
         }
-        InstructionOpcode::SWC1 => {
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
-            let r = registers.read_fpr(rt) as u32;
-            itrace!("swc1\tfpr[{}] -> mem[0x{:x}], data=0x{:08x}", rt, addr, r);
+        Instruction::Dsp(instruction) => {
+            result_cpu_event = eval_dsp_instruction(instruction, registers, memory);
+        }
+        // MIPS64 instructions, decoded only for 64 bit ABIs
+        Instruction::Doubleword(instruction) => {
+            result_cpu_event = eval_doubleword_instruction(instruction, registers, memory);
+        }
+        Instruction::Swc1 { ft, base, offset } => {
+            let addr = effective_address!(base, offset);
+            let r = registers.read_fpr_word(ft);
+            itrace!("swc1\tfpr[{}] -> mem[0x{:x}], data=0x{:08x}", ft, addr, r);
             memory.write_word(addr, r);
         }
-        InstructionOpcode::LWC1 => {
-            let addr = add_signed_offset(registers.read_register(rs), get_offset(instruction));
+        Instruction::Lwc1 { ft, base, offset } => {
+            let addr = effective_address!(base, offset);
             let r = memory.read_word(addr);
-            itrace!("lwc1\tmem[0x{:x}] -> fpr[{}], data=0x{:08x}", addr, rt, r);
-            registers.write_fpr(rt, r as f32);
-        }
-        InstructionOpcode::COP1 => {
-            let fmt = rs;
-            let ft = rt;
-            let fs = rd;
-            let fd = get_shift(instruction);
-            match funct {
-                0b000000 => {
-                    itrace!("float ADD {} <- {} + {}", fd, fs, ft);
-                    let a = FloatFmt::from_raw(fmt, fs, registers);
-                    let b = FloatFmt::from_raw(fmt, ft, registers);
-                    (a + b).save(fd, registers);
-                    unimplemented!("needs testing");
+            itrace!("lwc1\tmem[0x{:x}] -> fpr[{}], data=0x{:08x}", addr, ft, r);
+            registers.write_fpr_word(ft, r);
+        }
+        Instruction::Ldc1 { ft, base, offset } => {
+            let addr = effective_address!(base, offset);
+            let r = memory.read_doubleword(addr);
+            itrace!("ldc1\tmem[0x{:x}] -> fpr[{}], data=0x{:016x}", addr, ft, r);
+            registers.write_fpr_doubleword(ft, r);
+        }
+        Instruction::Sdc1 { ft, base, offset } => {
+            let addr = effective_address!(base, offset);
+            let r = registers.read_fpr_doubleword(ft);
+            itrace!("sdc1\tfpr[{}] -> mem[0x{:x}], data=0x{:016x}", ft, addr, r);
+            memory.write_doubleword(addr, r);
+        }
+        Instruction::Mfc1 { rt, fs } => {
+            let r = registers.read_fpr_word(fs);
+            itrace!("mfc1\t{},f{} - data=0x{:08x}", get_register_name(rt), fs, r);
+            registers.write_register(rt, r);
+        }
+        Instruction::Cfc1 { rt, fs } => {
+            let r = registers.read_fpu_control(fs);
+            itrace!("cfc1\t{},fcr{} - data=0x{:08x}", get_register_name(rt), fs, r);
+            registers.write_register(rt, r);
+        }
+        Instruction::Mfhc1 { rt, fs } => {
+            let r = registers.read_fpr_high(fs);
+            itrace!("mfhc1\t{},f{} - data=0x{:08x}", get_register_name(rt), fs, r);
+            registers.write_register(rt, r);
+        }
+        Instruction::Mtc1 { rt, fs } => {
+            let r = registers.read_register(rt);
+            itrace!("mtc1\t{},f{} - data=0x{:08x}", get_register_name(rt), fs, r);
+            registers.write_fpr_word(fs, r);
+        }
+        Instruction::Ctc1 { rt, fs } => {
+            let r = registers.read_register(rt);
+            itrace!("ctc1\t{},fcr{} - data=0x{:08x}", get_register_name(rt), fs, r);
+            registers.write_fpu_control(fs, r);
+            // writing a cause bit together with its enable bit traps immediately
+            let fcsr = registers.read_fcsr();
+            let cause = enabled_exceptions(fcsr);
+            if cause != 0 {
+                registers.write_fcsr(fcsr & !FCSR_CAUSE_MASK);
+                result_cpu_event = CPUEvent::Exception(ExceptionKind::FloatingPoint(cause));
+            }
+        }
+        Instruction::Mthc1 { rt, fs } => {
+            let r = registers.read_register(rt);
+            itrace!("mthc1\t{},f{} - data=0x{:08x}", get_register_name(rt), fs, r);
+            registers.write_fpr_high(fs, r);
+        }
+        // BC1F, BC1T, BC1FL, BC1TL (pre-R6)
+        Instruction::Bc1f { cc, offset } |
+        Instruction::Bc1t { cc, offset } |
+        Instruction::Bc1fl { cc, offset } |
+        Instruction::Bc1tl { cc, offset } => {
+            let (name, on_true, likely) = match decoded {
+                Instruction::Bc1f { .. } => ("bc1f", false, false),
+                Instruction::Bc1t { .. } => ("bc1t", true, false),
+                Instruction::Bc1fl { .. } => ("bc1fl", false, true),
+                _ => ("bc1tl", true, true),
+            };
+            let target = (registers.get_pc() as i32 + 4 + offset) as u32;
+            let jump = registers.read_fcc(cc) == on_true;
+            itrace!("{}\t$fcc{},0x{:x} - jumped={}", name, cc, target, jump);
+            if jump {
+                result_cpu_event = CPUEvent::FlowChangeDelayed(target);
+            } else if likely {
+                result_cpu_event = CPUEvent::DelaySlotNullified;
+            }
+        }
+        // BC1EQZ, BC1NEZ (R6)
+        Instruction::Bc1eqz { ft, offset } |
+        Instruction::Bc1nez { ft, offset } => {
+            let on_zero = matches!(decoded, Instruction::Bc1eqz { .. });
+            let target = (registers.get_pc() as i32 + 4 + offset) as u32;
+            let jump = (registers.read_fpr_word(ft) & 1 == 0) == on_zero;
+            itrace!(
+                "bc1{}\tf{},0x{:x} - jumped={}",
+                if on_zero { "eqz" } else { "nez" },
+                ft,
+                target,
+                jump
+            );
+            if jump {
+                result_cpu_event = CPUEvent::FlowChangeDelayed(target);
+            }
+        }
+        // CMP.cond.fmt (R6), the result is a mask in an FPR
+        Instruction::CmpCond { cond, fmt, fd, fs, ft } => {
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let a = FloatFmt::from_raw(fmt, fs, registers);
+            let b = FloatFmt::from_raw(fmt, ft, registers);
+            let r = a.compare(b, cond, &mut env);
+            itrace!(
+                "cmp.{}.{}\tf{},f{},f{} - res={}",
+                cond,
+                FloatFmt::format_name(fmt),
+                fd,
+                fs,
+                ft,
+                r
+            );
+            let r = if fmt == FMT_S {
+                FloatFmt::W(if r { -1 } else { 0 })
+            } else {
+                FloatFmt::L(if r { -1 } else { 0 })
+            };
+            fpu_commit!(env, r.save(fd, registers));
+        }
+        Instruction::AddFmt { fmt, fd, fs, ft } => fpu_binary!("add", add, fmt, fd, fs, ft),
+        Instruction::SubFmt { fmt, fd, fs, ft } => fpu_binary!("sub", sub, fmt, fd, fs, ft),
+        Instruction::MulFmt { fmt, fd, fs, ft } => fpu_binary!("mul", mul, fmt, fd, fs, ft),
+        Instruction::DivFmt { fmt, fd, fs, ft } => fpu_binary!("div", div, fmt, fd, fs, ft),
+        Instruction::SqrtFmt { fmt, fd, fs } => fpu_unary!("sqrt", sqrt, fmt, fd, fs),
+        Instruction::AbsFmt { fmt, fd, fs } => fpu_unary!("abs", abs, fmt, fd, fs),
+        Instruction::NegFmt { fmt, fd, fs } => fpu_unary!("neg", neg, fmt, fd, fs),
+        Instruction::RecipFmt { fmt, fd, fs } => fpu_unary!("recip", recip, fmt, fd, fs),
+        Instruction::RsqrtFmt { fmt, fd, fs } => fpu_unary!("rsqrt", rsqrt, fmt, fd, fs),
+        Instruction::MovFmt { fmt, fd, fs } => {
+            itrace!("mov.{}\tf{},f{}", FloatFmt::format_name(fmt), fd, fs);
+            FloatFmt::from_raw(fmt, fs, registers).save(fd, registers);
+        }
+        Instruction::RoundL { fmt, fd, fs } => {
+            fpu_convert!("round", FMT_L, RoundingMode::Nearest, fmt, fd, fs)
+        }
+        Instruction::TruncL { fmt, fd, fs } => {
+            fpu_convert!("trunc", FMT_L, RoundingMode::Zero, fmt, fd, fs)
+        }
+        Instruction::CeilL { fmt, fd, fs } => {
+            fpu_convert!("ceil", FMT_L, RoundingMode::PlusInfinity, fmt, fd, fs)
+        }
+        Instruction::FloorL { fmt, fd, fs } => {
+            fpu_convert!("floor", FMT_L, RoundingMode::MinusInfinity, fmt, fd, fs)
+        }
+        Instruction::RoundW { fmt, fd, fs } => {
+            fpu_convert!("round", FMT_W, RoundingMode::Nearest, fmt, fd, fs)
+        }
+        Instruction::TruncW { fmt, fd, fs } => {
+            fpu_convert!("trunc", FMT_W, RoundingMode::Zero, fmt, fd, fs)
+        }
+        Instruction::CeilW { fmt, fd, fs } => {
+            fpu_convert!("ceil", FMT_W, RoundingMode::PlusInfinity, fmt, fd, fs)
+        }
+        Instruction::FloorW { fmt, fd, fs } => {
+            fpu_convert!("floor", FMT_W, RoundingMode::MinusInfinity, fmt, fd, fs)
+        }
+        // CVT.S.PU
+        Instruction::CvtS { fmt, fd, fs } if fmt == FMT_PS => {
+            itrace!("cvt.s.pu\tf{},f{}", fd, fs);
+            match FloatFmt::from_raw(fmt, fs, registers) {
+                FloatFmt::PS(_, upper) => FloatFmt::S(upper).save(fd, registers),
+                _ => unreachable!(),
+            }
+        }
+        Instruction::CvtS { fmt, fd, fs } => {
+            let rounding = RoundingMode::from_fcsr(registers.read_fcsr());
+            fpu_convert!("cvt", FMT_S, rounding, fmt, fd, fs)
+        }
+        Instruction::CvtD { fmt, fd, fs } => {
+            let rounding = RoundingMode::from_fcsr(registers.read_fcsr());
+            fpu_convert!("cvt", FMT_D, rounding, fmt, fd, fs)
+        }
+        Instruction::CvtW { fmt, fd, fs } => {
+            let rounding = RoundingMode::from_fcsr(registers.read_fcsr());
+            fpu_convert!("cvt", FMT_W, rounding, fmt, fd, fs)
+        }
+        Instruction::CvtL { fmt, fd, fs } => {
+            let rounding = RoundingMode::from_fcsr(registers.read_fcsr());
+            fpu_convert!("cvt", FMT_L, rounding, fmt, fd, fs)
+        }
+        // SEL (R6)
+        Instruction::SelFmt { fmt, fd, fs, ft } => {
+            itrace!("sel.{}\tf{},f{},f{}", FloatFmt::format_name(fmt), fd, fs, ft);
+            let src = if registers.read_fpr_word(fd) & 1 == 0 {
+                fs
+            } else {
+                ft
+            };
+            FloatFmt::from_raw(fmt, src, registers).save(fd, registers);
+        }
+        // MOVF.fmt, MOVT.fmt (pre-R6)
+        Instruction::MovfFmt { fmt, fd, fs, cc } |
+        Instruction::MovtFmt { fmt, fd, fs, cc } => {
+            let on_true = matches!(decoded, Instruction::MovtFmt { .. });
+            itrace!(
+                "mov{}.{}\tf{},f{},$fcc{}",
+                if on_true { "t" } else { "f" },
+                FloatFmt::format_name(fmt),
+                fd,
+                fs,
+                cc
+            );
+            if fmt == FMT_PS {
+                // each half is moved on its own condition code
+                let src = registers.read_fpr_doubleword(fs);
+                let mut r = registers.read_fpr_doubleword(fd);
+                if registers.read_fcc(cc) == on_true {
+                    r = (r & 0xFFFF_FFFF_0000_0000) | (src & 0xFFFF_FFFF);
+                }
+                if registers.read_fcc(cc + 1) == on_true {
+                    r = (r & 0xFFFF_FFFF) | (src & 0xFFFF_FFFF_0000_0000);
                 }
-                _ => panic!("Unknown COP1 funct, 0b{:06b}", funct),
+                registers.write_fpr_doubleword(fd, r);
+            } else if registers.read_fcc(cc) == on_true {
+                FloatFmt::from_raw(fmt, fs, registers).save(fd, registers);
+            }
+        }
+        // MOVZ.fmt, MOVN.fmt (pre-R6)
+        Instruction::MovzFmt { fmt, fd, fs, rt } |
+        Instruction::MovnFmt { fmt, fd, fs, rt } => {
+            let on_zero = matches!(decoded, Instruction::MovzFmt { .. });
+            itrace!(
+                "mov{}.{}\tf{},f{},{}",
+                if on_zero { "z" } else { "n" },
+                FloatFmt::format_name(fmt),
+                fd,
+                fs,
+                get_register_name(rt)
+            );
+            if (registers.read_register(rt) == 0) == on_zero {
+                FloatFmt::from_raw(fmt, fs, registers).save(fd, registers);
+            }
+        }
+        // SELEQZ, SELNEZ (R6)
+        Instruction::SeleqzFmt { fmt, fd, fs, ft } |
+        Instruction::SelnezFmt { fmt, fd, fs, ft } => {
+            let on_zero = matches!(decoded, Instruction::SeleqzFmt { .. });
+            itrace!(
+                "sel{}.{}\tf{},f{},f{}",
+                if on_zero { "eqz" } else { "nez" },
+                FloatFmt::format_name(fmt),
+                fd,
+                fs,
+                ft
+            );
+            if (registers.read_fpr_word(ft) & 1 == 0) == on_zero {
+                FloatFmt::from_raw(fmt, fs, registers).save(fd, registers);
+            } else if fmt == FMT_S {
+                FloatFmt::W(0).save(fd, registers);
+            } else {
+                FloatFmt::L(0).save(fd, registers);
+            }
+        }
+        // MADDF, MSUBF (R6)
+        Instruction::MaddfFmt { fmt, fd, fs, ft } |
+        Instruction::MsubfFmt { fmt, fd, fs, ft } => {
+            let sub = matches!(decoded, Instruction::MsubfFmt { .. });
+            itrace!(
+                "{}.{}\tf{},f{},f{}",
+                if sub { "msubf" } else { "maddf" },
+                FloatFmt::format_name(fmt),
+                fd,
+                fs,
+                ft
+            );
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let a = FloatFmt::from_raw(fmt, fs, registers);
+            let b = FloatFmt::from_raw(fmt, ft, registers);
+            let c = FloatFmt::from_raw(fmt, fd, registers);
+            let b = if sub { b.neg(&mut env) } else { b };
+            let r = c.fused_mul_add(a, b, &mut env);
+            fpu_commit!(env, r.save(fd, registers));
+        }
+        // RINT (R6)
+        Instruction::RintFmt { fmt, fd, fs } => {
+            itrace!("rint.{}\tf{},f{}", FloatFmt::format_name(fmt), fd, fs);
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let rounding = env.rounding();
+            let r = FloatFmt::from_raw(fmt, fs, registers).round_integral(rounding, &mut env);
+            fpu_commit!(env, r.save(fd, registers));
+        }
+        // CLASS (R6)
+        Instruction::ClassFmt { fmt, fd, fs } => {
+            itrace!("class.{}\tf{},f{}", FloatFmt::format_name(fmt), fd, fs);
+            FloatFmt::from_raw(fmt, fs, registers).class().save(fd, registers);
+        }
+        // MIN, MINA, MAX, MAXA (R6)
+        Instruction::MinFmt { fmt, fd, fs, ft } => {
+            fpu_min_max!("min", false, false, fmt, fd, fs, ft)
+        }
+        Instruction::MinaFmt { fmt, fd, fs, ft } => {
+            fpu_min_max!("mina", false, true, fmt, fd, fs, ft)
+        }
+        Instruction::MaxFmt { fmt, fd, fs, ft } => {
+            fpu_min_max!("max", true, false, fmt, fd, fs, ft)
+        }
+        Instruction::MaxaFmt { fmt, fd, fs, ft } => {
+            fpu_min_max!("maxa", true, true, fmt, fd, fs, ft)
+        }
+        // C.cond.fmt (pre-R6)
+        Instruction::CCond { cond, fmt, cc, fs, ft } => {
+            let mut env = FloatEnv::new(registers.read_fcsr());
+            let a = FloatFmt::from_raw(fmt, fs, registers);
+            let b = FloatFmt::from_raw(fmt, ft, registers);
+            if fmt == FMT_PS {
+                let (lower, upper) = a.compare_paired(b, cond, &mut env);
+                fpu_commit!(env, {
+                    registers.write_fcc(cc, lower);
+                    registers.write_fcc(cc + 1, upper);
+                });
+            } else {
+                let r = a.compare(b, cond, &mut env);
+                fpu_commit!(env, registers.write_fcc(cc, r));
             }
+            itrace!(
+                "c.{}.{}\t$fcc{},f{},f{} - res={}",
+                cond,
+                FloatFmt::format_name(fmt),
+                cc,
+                fs,
+                ft,
+                registers.read_fcc(cc)
+            );
+        }
+        // LWXC1, LDXC1, LUXC1
+        Instruction::Lwxc1 { fd, base, index } => {
+            let addr = registers.read_register(base).wrapping_add(registers.read_register(index));
+            check_access!(addr, 4, Access::Read);
+            let r = memory.read_word(addr);
+            itrace!("lwxc1\tmem[0x{:x}] -> fpr[{}], data=0x{:08x}", addr, fd, r);
+            registers.write_fpr_word(fd, r);
+        }
+        Instruction::Ldxc1 { fd, base, index } |
+        Instruction::Luxc1 { fd, base, index } => {
+            let addr = registers.read_register(base).wrapping_add(registers.read_register(index));
+            let addr = if let Instruction::Luxc1 { .. } = decoded { addr & !0b111 } else { addr };
+            check_access!(addr, 8, Access::Read);
+            let r = memory.read_doubleword(addr);
+            itrace!("ldxc1\tmem[0x{:x}] -> fpr[{}], data=0x{:016x}", addr, fd, r);
+            registers.write_fpr_doubleword(fd, r);
         }
-        _ => {
-            panic!(
-                "Tried to execute unimplemented instruction, OPCODE = {:?}",
-                opcode
-            )
+        // SWXC1, SDXC1, SUXC1
+        Instruction::Swxc1 { fs, base, index } => {
+            let addr = registers.read_register(base).wrapping_add(registers.read_register(index));
+            check_access!(addr, 4, Access::Write);
+            let r = registers.read_fpr_word(fs);
+            itrace!("swxc1\tfpr[{}] -> mem[0x{:x}], data=0x{:08x}", fs, addr, r);
+            memory.write_word(addr, r);
+        }
+        Instruction::Sdxc1 { fs, base, index } |
+        Instruction::Suxc1 { fs, base, index } => {
+            let addr = registers.read_register(base).wrapping_add(registers.read_register(index));
+            let addr = if let Instruction::Suxc1 { .. } = decoded { addr & !0b111 } else { addr };
+            check_access!(addr, 8, Access::Write);
+            let r = registers.read_fpr_doubleword(fs);
+            itrace!("sdxc1\tfpr[{}] -> mem[0x{:x}], data=0x{:016x}", fs, addr, r);
+            memory.write_doubleword(addr, r);
+        }
+        Instruction::Prefx { .. } => {
+            itrace!("prefx - instruction ignored");
+        }
+        // MADD.fmt, MSUB.fmt, NMADD.fmt, NMSUB.fmt
+        Instruction::MaddFmt { fmt, fd, fr, fs, ft } => {
+            fpu_multiply_add!("madd", false, false, fmt, fd, fr, fs, ft)
+        }
+        Instruction::MsubFmt { fmt, fd, fr, fs, ft } => {
+            fpu_multiply_add!("msub", true, false, fmt, fd, fr, fs, ft)
+        }
+        Instruction::NmaddFmt { fmt, fd, fr, fs, ft } => {
+            fpu_multiply_add!("nmadd", false, true, fmt, fd, fr, fs, ft)
+        }
+        Instruction::NmsubFmt { fmt, fd, fr, fs, ft } => {
+            fpu_multiply_add!("nmsub", true, true, fmt, fd, fr, fs, ft)
         }
     };
 
     return result_cpu_event;
     //println!(" instruction=0x{:08x}", instruction);
 }
+
+/// Start of the code page mapped by `test_machine`
+#[cfg(test)]
+pub const TEST_CODE: u32 = 0x0040_0000;
+/// Start of the data page mapped by `test_machine`
+#[cfg(test)]
+pub const TEST_DATA: u32 = 0x1000_0000;
+
+/// Big endian O32 CPU state for instruction tests, with a code page at `TEST_CODE` and a data page
+/// at `TEST_DATA`
+#[cfg(test)]
+pub fn test_machine() -> (RegisterFile<'static>, Memory, System) {
+    use cpu::control::CPUFlags;
+    use memory::{Endianness, PROT_EXEC, PROT_READ, PROT_WRITE};
+
+    let mut memory = Memory::new(Endianness::BigEndian);
+    memory.map(TEST_CODE, 0x1000, PROT_READ | PROT_EXEC);
+    memory.map(TEST_DATA, 0x1000, PROT_READ | PROT_WRITE);
+    let system = System::new(CPUFlags::default().syscalls_conf, Abi::O32);
+    (RegisterFile::new(0x7ff00000), memory, system)
+}
+
+#[test]
+fn test_misaligned_access() {
+    let (mut registers, mut memory, mut system) = test_machine();
+    registers.write_register(4, 0x10000002);
+    // lw v0,0(a0)
+    assert!(
+        eval_instruction(0x8C82_0000, &mut registers, &mut memory, &mut system) ==
+            CPUEvent::Exception(ExceptionKind::AddressError(0x10000002))
+    );
+    // sh v0,1(a0)
+    assert!(
+        eval_instruction(0xA482_0001, &mut registers, &mut memory, &mut system) ==
+            CPUEvent::Exception(ExceptionKind::AddressError(0x10000003))
+    );
+    // lh v0,0(a0) and lwl v0,1(a0)
+    for instruction in &[0x8482_0000, 0x8882_0001] {
+        assert!(
+            eval_instruction(*instruction, &mut registers, &mut memory, &mut system) ==
+                CPUEvent::Nothing
+        );
+    }
+}
+
+#[test]
+fn test_movt_ps() {
+    let (mut registers, mut memory, mut system) = test_machine();
+    registers.write_fpr_doubleword(0, 0x1111_1111_2222_2222);
+    registers.write_fpr_doubleword(2, 0x3333_3333_4444_4444);
+    registers.write_fcc(2, false);
+    registers.write_fcc(3, true);
+    // movt.ps $f0,$f2,$fcc2 moves only the upper half
+    assert!(
+        eval_instruction(0x46C9_1011, &mut registers, &mut memory, &mut system) == CPUEvent::Nothing
+    );
+    assert_eq!(registers.read_fpr_doubleword(0), 0x3333_3333_2222_2222);
+    // movf.ps $f0,$f2,$fcc2 moves only the lower half
+    assert!(
+        eval_instruction(0x46C8_1011, &mut registers, &mut memory, &mut system) == CPUEvent::Nothing
+    );
+    assert_eq!(registers.read_fpr_doubleword(0), 0x3333_3333_4444_4444);
+}
+
+#[test]
+fn test_divide() {
+    use cpu::isa::IsaRevision;
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+    let mut run = |instruction, r: &mut RegisterFile| {
+        assert!(eval_instruction(instruction, r, m, s) == CPUEvent::Nothing);
+    };
+
+    // mthi a0, mtlo a1, mfhi v0 and mflo v1 round trip through ac0
+    r.write_register(4, 0x1234_5678);
+    r.write_register(5, 0x9ABC_DEF0);
+    run(0x0080_0011, r);
+    run(0x00A0_0013, r);
+    assert_eq!(r.read_accumulator(0), 0x1234_5678_9ABC_DEF0);
+    run(0x0000_1010, r);
+    run(0x0000_1812, r);
+    assert_eq!((r.read_register(2), r.read_register(3)), (0x1234_5678, 0x9ABC_DEF0));
+
+    // div a0,a1 and divu a0,a1 by zero leave HI and LO untouched
+    r.write_register(5, 0);
+    run(0x0085_001A, r);
+    run(0x0085_001B, r);
+    assert_eq!(r.read_accumulator(0), 0x1234_5678_9ABC_DEF0);
+    // div a0,a1 of INT_MIN by -1 wraps with no remainder
+    r.write_register(4, 0x8000_0000);
+    r.write_register(5, 0xFFFF_FFFF);
+    run(0x0085_001A, r);
+    assert_eq!((r.read_hi(0), r.read_lo(0)), (0, 0x8000_0000));
+    r.write_register(4, -7i32 as u32);
+    r.write_register(5, 2);
+    run(0x0085_001A, r);
+    assert_eq!((r.read_hi(0), r.read_lo(0)), (-1i32 as u32, -3i32 as u32));
+
+    // div, mod, divu and modu v0,a0,a1 of R6 write zero on division by zero
+    r.set_isa_revision(IsaRevision::R6);
+    r.write_register(4, 0x8000_0000);
+    r.write_register(5, 0);
+    for instruction in &[0x0085_109A, 0x0085_10DA, 0x0085_109B, 0x0085_10DB] {
+        r.write_register(2, 1);
+        run(*instruction, r);
+        assert_eq!(r.read_register(2), 0);
+    }
+    // div and mod of INT_MIN by -1
+    r.write_register(5, 0xFFFF_FFFF);
+    run(0x0085_109A, r);
+    assert_eq!(r.read_register(2), 0x8000_0000);
+    run(0x0085_10DA, r);
+    assert_eq!(r.read_register(2), 0);
+    // divu and modu treat both as unsigned
+    run(0x0085_109B, r);
+    assert_eq!(r.read_register(2), 0);
+    run(0x0085_10DB, r);
+    assert_eq!(r.read_register(2), 0x8000_0000);
+}
+
+#[test]
+fn test_load_linked() {
+    let (mut registers, mut memory, mut system) = test_machine();
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+    let mut run = |instruction, r: &mut RegisterFile, m: &mut Memory| {
+        eval_instruction(instruction, r, m, s)
+    };
+    r.write_register(4, TEST_DATA);
+    m.write_word(TEST_DATA, 1);
+    // ll v0,0(a0) and sc v0,0(a0) with nothing in between
+    assert!(run(0xC082_0000, r, m) == CPUEvent::AtomicLoadModifyWriteBegan);
+    r.write_register(2, 2);
+    run(0xE082_0000, r, m);
+    assert_eq!((r.read_register(2), m.read_word(TEST_DATA)), (1, 2));
+
+    // sw v1,4(a0) to the linked line breaks the link, sc does not store then
+    r.write_register(3, 3);
+    run(0xC082_0000, r, m);
+    run(0xAC83_0004, r, m);
+    r.write_register(2, 4);
+    run(0xE082_0000, r, m);
+    assert_eq!((r.read_register(2), m.read_word(TEST_DATA)), (0, 2));
+    // sc v0,32(a0) to another line fails
+    run(0xC082_0000, r, m);
+    r.write_register(2, 4);
+    run(0xE082_0020, r, m);
+    assert_eq!((r.read_register(2), m.read_word(TEST_DATA + 32)), (0, 0));
+
+    // getpid syscall between ll and sc
+    run(0xC082_0000, r, m);
+    r.write_register(2, 4020);
+    run(0x0000_000C, r, m);
+    r.write_register(2, 4);
+    run(0xE082_0000, r, m);
+    assert_eq!((r.read_register(2), m.read_word(TEST_DATA)), (0, 2));
+
+    // read(fd, a0, 4) from /dev/zero into the linked line
+    let fd = unsafe { ::libc::open("/dev/zero\0".as_ptr() as *const ::libc::c_char, 0) };
+    assert!(fd >= 0);
+    run(0xC082_0000, r, m);
+    m.write_word(TEST_DATA + 8, 0xFFFF_FFFF);
+    let (mut registers, _, mut system) = test_machine();
+    registers.write_register(2, 4003);
+    registers.write_register(4, fd as u32);
+    registers.write_register(5, TEST_DATA + 8);
+    registers.write_register(6, 4);
+    system.eval_syscall(0x0000_000C, &mut registers, m);
+    unsafe { ::libc::close(fd) };
+    assert_eq!((registers.read_register(2), m.read_word(TEST_DATA + 8)), (4, 0));
+    r.write_register(2, 4);
+    run(0xE082_0000, r, m);
+    assert_eq!((r.read_register(2), m.read_word(TEST_DATA)), (0, 2));
+}
+
+#[test]
+fn test_pc_relative() {
+    use cpu::isa::IsaRevision;
+    use memory::{PROT_EXEC, PROT_READ};
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    registers.set_isa_revision(IsaRevision::R6);
+    memory.map(0x0040_F000, 0x2000, PROT_READ | PROT_EXEC);
+    memory.write_word(0x0040_FFFC, 0x8000_0000);
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+    let mut run = |instruction, r: &mut RegisterFile| eval_instruction(instruction, r, m, s);
+    // instructions just past a 64K boundary reaching back below it
+    r.set_pc(0x0041_0004);
+    // addiupc v0,-8
+    run(0xEC47_FFFE, r);
+    assert_eq!(r.read_register(2), 0x0040_FFFC);
+    // lwpc v0,-8
+    assert!(run(0xEC4F_FFFE, r) == CPUEvent::Nothing);
+    assert_eq!(r.read_register64(2), 0xFFFF_FFFF_8000_0000);
+    // lwupc v0,-8 is sign extended as well on a 32 bit CPU
+    run(0xEC57_FFFE, r);
+    assert_eq!(r.read_register64(2), 0xFFFF_FFFF_8000_0000);
+    // auipc v0,0xffff
+    run(0xEC5E_FFFF, r);
+    assert_eq!(r.read_register(2), 0x0040_0004);
+    // aluipc v0,0xffff and aluipc v0,0x1 clear the low halfword
+    run(0xEC5F_FFFF, r);
+    assert_eq!(r.read_register(2), 0x0040_0000);
+    run(0xEC5F_0001, r);
+    assert_eq!(r.read_register(2), 0x0042_0000);
+
+    // lwpc v0,16 reaching into the kernel segment
+    r.set_pc(0x7FFF_FFF0);
+    assert!(
+        run(0xEC48_0004, r) == CPUEvent::Exception(ExceptionKind::AddressError(0x8000_0000))
+    );
+
+    // n64 keeps the upper half of lwupc clear
+    r.set_abi(Abi::N64, 0);
+    r.set_pc(0x0041_0004);
+    run(0xEC57_FFFE, r);
+    assert_eq!(r.read_register64(2), 0x8000_0000);
+}
+
+#[test]
+fn test_bit_field() {
+    use cpu::isa::IsaRevision;
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+    let mut run = |instruction, r: &mut RegisterFile| -> u64 {
+        assert!(eval_instruction(instruction, r, m, s) == CPUEvent::Nothing);
+        r.read_register64(2)
+    };
+    r.write_register(4, 0x8765_4321);
+    // ext v0,a0,0,32 and ext v0,a0,4,8
+    assert_eq!(run(0x7C82_F800, r), 0xFFFF_FFFF_8765_4321);
+    assert_eq!(run(0x7C82_3900, r), 0x32);
+    // ins v0,a0,24,8 and ins v0,a0,0,32
+    r.write_register(2, 0x0AAA_AAAA);
+    assert_eq!(run(0x7C82_FE04, r), 0x21AA_AAAA);
+    assert_eq!(run(0x7C82_F804, r), 0xFFFF_FFFF_8765_4321);
+    // wsbh v0,a0
+    assert_eq!(run(0x7C04_10A0, r), 0x6587_2143);
+    // seb v0,a0 and seh v0,a0
+    assert_eq!(run(0x7C04_1420, r), 0x21);
+    assert_eq!(run(0x7C04_1620, r), 0x4321);
+    r.write_register(4, 0x1234_5680);
+    assert_eq!(run(0x7C04_1420, r), 0xFFFF_FFFF_FFFF_FF80);
+    r.write_register(4, 0x1234_8000);
+    assert_eq!(run(0x7C04_1620, r), 0xFFFF_FFFF_FFFF_8000);
+
+    r.set_isa_revision(IsaRevision::R6);
+    r.write_register(4, 0x1234_5678);
+    r.write_register(5, 0x9ABC_DEF0);
+    // bitswap v0,a0
+    assert_eq!(run(0x7C04_1020, r), 0x482C_6A1E);
+    // align v0,a0,a1 with byte positions 0, 1 and 3
+    assert_eq!(run(0x7C85_1220, r), 0xFFFF_FFFF_9ABC_DEF0);
+    assert_eq!(run(0x7C85_1260, r), 0xFFFF_FFFF_BCDE_F012);
+    assert_eq!(run(0x7C85_12E0, r), 0xFFFF_FFFF_F012_3456);
+}
+
+#[test]
+fn test_float_program() {
+    use cpu::isa::IsaRevision;
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+    let mut run = |instruction, r: &mut RegisterFile| eval_instruction(instruction, r, m, s);
+    r.set_pc(TEST_CODE);
+    r.write_register(4, 2);
+    r.write_register(5, TEST_DATA);
+    // mtc1 a0,f0; cvt.d.w f2,f0; sqrt.d f4,f2; mul.d f6,f4,f4
+    for &instruction in &[0x4484_0000, 0x4680_00A1, 0x4620_1104, 0x4624_2182] {
+        assert!(run(instruction, r) == CPUEvent::Nothing);
+    }
+    assert_eq!(r.read_fpr_doubleword(2), 2.0f64.to_bits());
+    assert_eq!(r.read_fpr_doubleword(6), 0x4000_0000_0000_0001);
+    // cfc1 v0,$31 shows the inexact cause and flag
+    run(0x4442_F800, r);
+    assert_eq!(
+        r.read_register(2),
+        FPE_INEXACT << FCSR_CAUSE_SHIFT | FPE_INEXACT << FCSR_FLAGS_SHIFT
+    );
+
+    // c.lt.d f2,f6 is true, so bc1t jumps and bc1f doesn't
+    run(0x4626_103C, r);
+    assert!(run(0x4501_0004, r) == CPUEvent::FlowChangeDelayed(TEST_CODE + 20));
+    assert!(run(0x4500_0004, r) == CPUEvent::Nothing);
+    // round.w.d f8,f6 and trunc.w.d f8,f4 moved back by mfc1 v0,f8
+    run(0x4620_320C, r);
+    run(0x4402_4000, r);
+    assert_eq!(r.read_register(2), 2);
+    run(0x4620_220D, r);
+    run(0x4402_4000, r);
+    assert_eq!(r.read_register(2), 1);
+    // sdc1 f6,8(a1) and ldc1 f10,8(a1)
+    run(0xF4A6_0008, r);
+    run(0xD4AA_0008, r);
+    assert_eq!(r.read_fpr_doubleword(10), 0x4000_0000_0000_0001);
+
+    // ctc1 a0,$31 enables the division by zero exception, div.d f12,f2,f14 then traps
+    r.write_register(4, FPE_DIVIDE_BY_ZERO << FCSR_ENABLES_SHIFT);
+    run(0x44C4_F800, r);
+    r.write_fpr_doubleword(12, 1.0f64.to_bits());
+    r.write_fpr_doubleword(14, 0);
+    let event = run(0x462E_1303, r);
+    assert!(event == CPUEvent::Exception(ExceptionKind::FloatingPoint(FPE_DIVIDE_BY_ZERO)));
+    assert_eq!(r.read_fpr_doubleword(12), 1.0f64.to_bits());
+
+    // R6 cmp.lt.d f16,f2,f6 writes a mask tested by bc1nez and bc1eqz
+    r.set_isa_revision(IsaRevision::R6);
+    r.set_fpu_64bit(true);
+    r.write_fcsr(0);
+    r.write_fpr_doubleword(2, 2.0f64.to_bits());
+    r.write_fpr_doubleword(6, 0x4000_0000_0000_0001);
+    run(0x46A6_1404, r);
+    assert_eq!(r.read_fpr_doubleword(16), u64::max_value());
+    assert!(run(0x45B0_0004, r) == CPUEvent::FlowChangeDelayed(TEST_CODE + 20));
+    // cmp.lt.d f16,f6,f2
+    run(0x46A2_3404, r);
+    assert_eq!(r.read_fpr_doubleword(16), 0);
+    assert!(run(0x4530_0004, r) == CPUEvent::FlowChangeDelayed(TEST_CODE + 20));
+    assert!(run(0x45B0_0004, r) == CPUEvent::Nothing);
+}
@@ -1,3 +1,5 @@
+use cpu::isa::IsaRevision;
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, Eq, PartialEq)]
 pub enum InstructionOpcode {
@@ -10,6 +12,7 @@ pub enum InstructionOpcode {
     BLEZ,
     BGTZ,
     ADDI,
+    POP10,
     ADDIU,
     SLTI,
     SLTIU,
@@ -25,6 +28,9 @@ pub enum InstructionOpcode {
     BNEL,
     BLEZL,
     BGTZL,
+    POP26,
+    POP27,
+    POP30,
     SPECIAL2,
     JALX,
     SPECIAL3,
@@ -43,20 +49,69 @@ pub enum InstructionOpcode {
     CACHE,
     LL,
     LWC1,
+    LWC2,
     BC,
-    //LWC2, see translation function
     PREF,
     LDC1,
     LDC2,
+    POP66,
     SC,
     SWC1,
+    SWC2,
     BALC,
     PCREL,
     SDC1,
     SDC2,
+    POP76,
+    // MIPS64 only
+    DADDI,
+    DADDIU,
+    DAUI,
+    LDL,
+    LDR,
+    LWU,
+    SDL,
+    SDR,
+    LLD,
+    SCD,
+    LD,
+    SD,
+    /// Encoding not defined in the selected ISA revision
+    Reserved,
 }
 
-pub fn translate_opcode(opcode: u32) -> InstructionOpcode {
+/// Translates the major opcode. Release 6 removed or reused some of the encodings, pre-R6 meaning
+/// is selected for older revisions.
+pub fn translate_opcode(opcode: u32, revision: IsaRevision) -> InstructionOpcode {
+    if !revision.is_r6() {
+        match opcode {
+            0b001000 => return InstructionOpcode::ADDI,
+            0b010100 => return InstructionOpcode::BEQL,
+            0b010101 => return InstructionOpcode::BNEL,
+            0b010110 => return InstructionOpcode::BLEZL,
+            0b010111 => return InstructionOpcode::BGTZL,
+            0b110010 => return InstructionOpcode::LWC2,
+            0b110110 => return InstructionOpcode::LDC2,
+            0b111010 => return InstructionOpcode::SWC2,
+            0b111110 => return InstructionOpcode::SDC2,
+            0b101111 => return InstructionOpcode::CACHE,
+            0b110000 => return InstructionOpcode::LL,
+            0b110011 => return InstructionOpcode::PREF,
+            0b111000 => return InstructionOpcode::SC,
+            0b011100 => return InstructionOpcode::SPECIAL2,
+            0b011101 => return InstructionOpcode::JALX,
+            0b011000 => return InstructionOpcode::DADDI,
+            0b011010 => return InstructionOpcode::LDL,
+            0b011011 => return InstructionOpcode::LDR,
+            0b101100 => return InstructionOpcode::SDL,
+            0b101101 => return InstructionOpcode::SDR,
+            0b110100 => return InstructionOpcode::LLD,
+            0b111100 => return InstructionOpcode::SCD,
+            0b111011 => return InstructionOpcode::Reserved,
+            _ => {}
+        }
+    }
+
     match opcode {
         0b000000 => InstructionOpcode::SPECIAL,
         0b000001 => InstructionOpcode::REGIMM,
@@ -66,7 +121,7 @@ pub fn translate_opcode(opcode: u32) -> InstructionOpcode {
         0b000101 => InstructionOpcode::BNE,
         0b000110 => InstructionOpcode::BLEZ,
         0b000111 => InstructionOpcode::BGTZ,
-        0b001000 => InstructionOpcode::ADDI,
+        0b001000 => InstructionOpcode::POP10,
         0b001001 => InstructionOpcode::ADDIU,
         0b001010 => InstructionOpcode::SLTI,
         0b001011 => InstructionOpcode::SLTIU,
@@ -78,12 +133,11 @@ pub fn translate_opcode(opcode: u32) -> InstructionOpcode {
         0b010001 => InstructionOpcode::COP1,
         0b010010 => InstructionOpcode::COP2,
         0b010011 => InstructionOpcode::COP1X,
-        0b010100 => InstructionOpcode::BEQL,
-        0b010101 => InstructionOpcode::BNEL,
-        0b010110 => InstructionOpcode::BLEZL,
-        0b010111 => InstructionOpcode::BGTZL,
-        0b011100 => InstructionOpcode::SPECIAL2,
-        0b011101 => InstructionOpcode::JALX,
+        0b010110 => InstructionOpcode::POP26,
+        0b010111 => InstructionOpcode::POP27,
+        0b011000 => InstructionOpcode::POP30,
+        0b011001 => InstructionOpcode::DADDIU,
+        0b011101 => InstructionOpcode::DAUI,
         0b011111 => InstructionOpcode::SPECIAL3,
         0b100000 => InstructionOpcode::LB,
         0b100001 => InstructionOpcode::LH,
@@ -92,25 +146,41 @@ pub fn translate_opcode(opcode: u32) -> InstructionOpcode {
         0b100100 => InstructionOpcode::LBU,
         0b100101 => InstructionOpcode::LHU,
         0b100110 => InstructionOpcode::LWR,
+        0b100111 => InstructionOpcode::LWU,
         0b101000 => InstructionOpcode::SB,
         0b101001 => InstructionOpcode::SH,
         0b101010 => InstructionOpcode::SWL,
         0b101011 => InstructionOpcode::SW,
         0b101110 => InstructionOpcode::SWR,
-        0b101111 => InstructionOpcode::CACHE,
-        0b110000 => InstructionOpcode::LL,
         0b110001 => InstructionOpcode::LWC1,
         0b110010 => InstructionOpcode::BC,
-        // 0b110010 => InstructionOpcode::LWC2,   this instruction was moved in R6 under COP2
-        0b110011 => InstructionOpcode::PREF,
         0b110101 => InstructionOpcode::LDC1,
-        0b110110 => InstructionOpcode::LDC2,
-        0b111000 => InstructionOpcode::SC,
+        0b110110 => InstructionOpcode::POP66,
+        0b110111 => InstructionOpcode::LD,
         0b111001 => InstructionOpcode::SWC1,
         0b111010 => InstructionOpcode::BALC,
         0b111011 => InstructionOpcode::PCREL,
         0b111101 => InstructionOpcode::SDC1,
-        0b111110 => InstructionOpcode::SDC2,
-        _ => panic!("Unknown opcode {:06b}."),
+        0b111110 => InstructionOpcode::POP76,
+        0b111111 => InstructionOpcode::SD,
+        _ => InstructionOpcode::Reserved,
+    }
+}
+
+/// Checks whether the instruction is a jump or a branch. Those are not allowed in delay slots
+/// and in forbidden slots of R6 compact branches. JALX is missing, its encoding is DAUI in R6.
+pub fn is_control_transfer(instruction: u32) -> bool {
+    let rs = (instruction >> 21) & 0b11111;
+    let rt = (instruction >> 16) & 0b11111;
+    match instruction >> 26 {
+        // JR, JALR
+        0b000000 => instruction & 0b111110 == 0b001000,
+        // branches in REGIMM have rt 0-3 and 16-19, the rest are traps and SYNCI
+        0b000001 => rt & 0b01100 == 0,
+        // BC1F, BC1T, BC1EQZ, BC1NEZ
+        0b010001 => rs == 0b01000 || rs == 0b01001 || rs == 0b01101,
+        0b000010..=0b001000 | 0b010100..=0b011000 | 0b110010 | 0b110110 | 0b111010 |
+        0b111110 => true,
+        _ => false,
     }
 }
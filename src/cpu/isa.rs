@@ -0,0 +1,131 @@
+//! Revisions of the MIPS32 and MIPS64 instruction sets. Release 6 reassigned several opcodes, so
+//! the decoder needs to know which revision the emulated program was built for. Compressed
+//! instruction sets and the ABI are selected the same way.
+
+use std::str::FromStr;
+
+/// Mask of the architecture field in ELF header `e_flags`
+const EF_MIPS_ARCH: u32 = 0xf000_0000;
+const E_MIPS_ARCH_32R2: u32 = 0x7000_0000;
+const E_MIPS_ARCH_64R2: u32 = 0x8000_0000;
+const E_MIPS_ARCH_32R6: u32 = 0x9000_0000;
+const E_MIPS_ARCH_64R6: u32 = 0xa000_0000;
+/// Flag of binaries containing microMIPS code
+const EF_MIPS_MICROMIPS: u32 = 0x0200_0000;
+/// Flag of 64 bit binaries using 32 bit pointers
+const EF_MIPS_ABI2: u32 = 0x0000_0020;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+pub enum IsaRevision {
+    R1,
+    R2,
+    R5,
+    R6,
+}
+
+impl IsaRevision {
+    /// Detects revision from ELF header flags. Release 5 has no flag of its own and MIPS I-V
+    /// binaries are treated as Release 1, because they run unmodified on MIPS32 CPUs.
+    pub fn from_elf_flags(e_flags: u32) -> IsaRevision {
+        match e_flags & EF_MIPS_ARCH {
+            E_MIPS_ARCH_32R2 | E_MIPS_ARCH_64R2 => IsaRevision::R2,
+            E_MIPS_ARCH_32R6 | E_MIPS_ARCH_64R6 => IsaRevision::R6,
+            _ => IsaRevision::R1,
+        }
+    }
+
+    pub fn is_r6(self) -> bool {
+        self == IsaRevision::R6
+    }
+}
+
+/// Compressed instruction set entered by jumps to odd addresses. CPUs implement at most one of
+/// them, which one is decided by the binary.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CompressedIsa {
+    Mips16e,
+    MicroMips,
+}
+
+impl CompressedIsa {
+    /// MIPS16e is the default, because that is what the emulated 74Kc implements. Neither is
+    /// available in Release 6.
+    pub fn from_elf_flags(e_flags: u32) -> Option<CompressedIsa> {
+        if IsaRevision::from_elf_flags(e_flags).is_r6() {
+            None
+        } else if e_flags & EF_MIPS_MICROMIPS != 0 {
+            Some(CompressedIsa::MicroMips)
+        } else {
+            Some(CompressedIsa::Mips16e)
+        }
+    }
+}
+
+/// Calling convention of the binary. It decides the width of registers and pointers, the syscall
+/// table and the layout of structs passed to the kernel.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Abi {
+    O32,
+    N32,
+    N64,
+}
+
+impl Abi {
+    /// Detects ABI from ELF class and header flags. N32 binaries are ELFCLASS32 with the ABI2 flag.
+    pub fn from_elf_header(is_64: bool, e_flags: u32) -> Abi {
+        if is_64 {
+            Abi::N64
+        } else if e_flags & EF_MIPS_ABI2 != 0 {
+            Abi::N32
+        } else {
+            Abi::O32
+        }
+    }
+
+    /// 64 bit ABIs run on a CPU with 64 bit registers and instructions
+    pub fn is_64bit(self) -> bool {
+        self != Abi::O32
+    }
+
+    /// Size of pointers and `long` in bytes
+    pub fn pointer_size(self) -> u32 {
+        if self == Abi::N64 { 8 } else { 4 }
+    }
+}
+
+/// Everything the decoder needs to know about the emulated CPU
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Isa {
+    pub revision: IsaRevision,
+    pub compressed: Option<CompressedIsa>,
+    pub abi: Abi,
+}
+
+impl FromStr for IsaRevision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IsaRevision, String> {
+        match s.to_lowercase().trim_start_matches("mips32") {
+            "r1" | "" => Ok(IsaRevision::R1),
+            "r2" => Ok(IsaRevision::R2),
+            "r5" => Ok(IsaRevision::R5),
+            "r6" => Ok(IsaRevision::R6),
+            _ => Err(format!("Unknown ISA revision {}", s)),
+        }
+    }
+}
+
+#[test]
+fn test_revision_detection() {
+    assert_eq!(IsaRevision::from_elf_flags(0x0000_1007), IsaRevision::R1);
+    assert_eq!(IsaRevision::from_elf_flags(0x7000_1007), IsaRevision::R2);
+    assert_eq!(IsaRevision::from_elf_flags(0x9000_1007), IsaRevision::R6);
+    assert_eq!("mips32r6".parse(), Ok(IsaRevision::R6));
+    assert_eq!("R5".parse(), Ok(IsaRevision::R5));
+    assert_eq!(CompressedIsa::from_elf_flags(0x7000_1007), Some(CompressedIsa::Mips16e));
+    assert_eq!(CompressedIsa::from_elf_flags(0x7200_1007), Some(CompressedIsa::MicroMips));
+    assert_eq!(CompressedIsa::from_elf_flags(0x9000_1007), None);
+    assert_eq!(Abi::from_elf_header(false, 0x7000_1007), Abi::O32);
+    assert_eq!(Abi::from_elf_header(false, 0x8000_0027), Abi::N32);
+    assert_eq!(Abi::from_elf_header(true, 0x8000_0007), Abi::N64);
+}
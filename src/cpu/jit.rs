@@ -0,0 +1,724 @@
+//! Dynamic binary translation of hot basic blocks to x86-64 code. Blocks from the block cache are
+//! counted when the CPU loop enters them and translated once they get hot. Only the common integer
+//! instructions are translated, translation of a block stops at the first instruction that is not
+//! and the interpreter takes over from there. Syscalls, traps and everything touching the FPU or
+//! the accumulators therefore always run in `eval_instruction`.
+//!
+//! Translated code works directly with the register file and the emulated memory. RBX points to
+//! the general purpose registers, R12 to the memory and R13 to the `Frame` of the call. R14 holds
+//! the branch target while the delay slot runs. Stores are performed by `store`, so that they
+//! break LL/SC links and invalidate code the same way stores of the interpreter do. Loads check
+//! the page table inline, accesses that would fault are left to the interpreter.
+
+use cpu::bitutils::bit_mask;
+use cpu::block_cache::{BasicBlock, DecodedInstruction};
+use cpu::decoder::Instruction;
+use cpu::emitter::{Alu, Cond, Emitter, Load, Reg, Shift};
+use cpu::instructions_constants::is_control_transfer;
+use cpu::registers::RegisterFile;
+use libc;
+use memory::{Access, Memory, PAGE_SIZE, PROT_READ, PROT_WRITE};
+use std::mem;
+use std::ptr;
+
+/// Blocks are translated, when they are entered this many times
+const HOT_THRESHOLD: u32 = 32;
+
+/// JIT state of a basic block
+pub enum Translation {
+    /// Number of times the block was entered so far
+    Cold(u32),
+    Compiled(CompiledBlock),
+    /// The first instruction of the block can't be translated
+    Untranslatable,
+}
+
+/// Where the translated code left
+#[derive(Debug, Eq, PartialEq)]
+pub enum JitExit {
+    /// Control transfer or end of the block, execution continues at the address
+    Continue(u32),
+    /// Instruction at the address has to be executed by the interpreter
+    Interpret(u32),
+    /// Like `Interpret`, but the instruction is in the delay slot of a jump to the second address
+    DelaySlot(u32, u32),
+}
+
+const EXIT_CONTINUE: u32 = 0;
+const EXIT_INTERPRET: u32 = 1;
+const EXIT_DELAY_SLOT: u32 = 2;
+
+/// State shared by the translated code and `store`
+#[repr(C)]
+struct Frame {
+    memory: *mut Memory,
+    exit: u32,
+    target: u32,
+    pages: *const u8,
+}
+
+const FRAME_EXIT: i32 = 8;
+const FRAME_TARGET: i32 = 12;
+const FRAME_PAGES: i32 = 16;
+
+// results of `store`
+const STORE_DONE: u32 = 0;
+const STORE_MODIFIED_CODE: u32 = 1;
+const STORE_FAULT: u32 = 2;
+
+type BlockFunction = unsafe extern "C" fn(gpr: *mut u64, data: *mut u8, frame: *mut Frame) -> u32;
+
+/// Translated code of a block in its own executable mapping
+pub struct CompiledBlock {
+    code: *mut libc::c_void,
+    len: usize,
+}
+
+impl CompiledBlock {
+    fn new(code: &[u8]) -> CompiledBlock {
+        unsafe {
+            let mapping = libc::mmap(
+                ptr::null_mut(),
+                code.len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if mapping == libc::MAP_FAILED {
+                panic!("Could not allocate memory for translated code");
+            }
+            ptr::copy_nonoverlapping(code.as_ptr(), mapping as *mut u8, code.len());
+            if libc::mprotect(mapping, code.len(), libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                panic!("Could not make translated code executable");
+            }
+            CompiledBlock {
+                code: mapping,
+                len: code.len(),
+            }
+        }
+    }
+
+    fn function(&self) -> BlockFunction {
+        unsafe { mem::transmute(self.code) }
+    }
+}
+
+impl Drop for CompiledBlock {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.code, self.len);
+        }
+    }
+}
+
+/// Performs stores of the translated code. When the store modified cached code, the translated
+/// block has to be left. Misaligned stores and stores, that the page protection doesn't allow,
+/// are not performed, the interpreter raises the exception instead.
+extern "C" fn store(frame: *mut Frame, address: u32, value: u32, size: u32) -> u32 {
+    let memory = unsafe { &mut *(*frame).memory };
+    if address & (size - 1) != 0 || memory.check_access(address, size, Access::Write).is_err() {
+        return STORE_FAULT;
+    }
+    match size {
+        1 => memory.write_byte(address, value),
+        2 => memory.write_halfword(address, value),
+        _ => memory.write_word(address, value),
+    }
+    if memory.is_code_modified() {
+        STORE_MODIFIED_CODE
+    } else {
+        STORE_DONE
+    }
+}
+
+pub struct Jit {
+    big_endian: bool,
+    /// Translate single instructions without control transfers, so that the tracefile can be
+    /// checked after every one of them
+    lockstep: bool,
+    threshold: u32,
+}
+
+impl Jit {
+    pub fn new(big_endian: bool, lockstep: bool) -> Jit {
+        Jit {
+            big_endian,
+            lockstep,
+            threshold: if lockstep { 1 } else { HOT_THRESHOLD },
+        }
+    }
+
+    /// Counts entries of the block and translates it, when it gets hot. Returns whether the block
+    /// can be executed by `run`.
+    pub fn prepare(&self, block: &BasicBlock) -> bool {
+        {
+            let mut translation = block.translation.borrow_mut();
+            match *translation {
+                Translation::Compiled(_) => return true,
+                Translation::Untranslatable => return false,
+                Translation::Cold(count) if count + 1 < self.threshold => {
+                    *translation = Translation::Cold(count + 1);
+                    return false;
+                }
+                Translation::Cold(_) => {}
+            }
+        }
+
+        let translation = self.translate(block);
+        let compiled = matches!(translation, Translation::Compiled(_));
+        *block.translation.borrow_mut() = translation;
+        compiled
+    }
+
+    /// Runs translated code of the block, `prepare` must have returned true for it.
+    pub fn run(
+        &self,
+        block: &BasicBlock,
+        registers: &mut RegisterFile,
+        memory: &mut Memory,
+    ) -> JitExit {
+        let function = match *block.translation.borrow() {
+            Translation::Compiled(ref compiled) => compiled.function(),
+            _ => panic!("Block at 0x{:08x} is not translated", block.get_start()),
+        };
+
+        let mut frame = Frame {
+            memory: memory as *mut Memory,
+            exit: EXIT_CONTINUE,
+            target: 0,
+            pages: memory.get_page_table_ptr(),
+        };
+        let pc = unsafe { function(registers.get_gpr_ptr(), memory.get_data_ptr(), &mut frame) };
+        match frame.exit {
+            EXIT_CONTINUE => JitExit::Continue(pc),
+            EXIT_INTERPRET => JitExit::Interpret(pc),
+            _ => JitExit::DelaySlot(pc, frame.target),
+        }
+    }
+
+    fn translate(&self, block: &BasicBlock) -> Translation {
+        let start = block.get_start();
+        let instructions = block.get_instructions();
+
+        let mut e = Emitter::new();
+        for &reg in &[Reg::Rbp, Reg::Rbx, Reg::R12, Reg::R13, Reg::R14] {
+            e.push(reg);
+        }
+        e.mov(true, Reg::Rbx, Reg::Rdi);
+        e.mov(true, Reg::R12, Reg::Rsi);
+        e.mov(true, Reg::R13, Reg::Rdx);
+
+        let mut translated = 0;
+        let mut index = 0;
+        loop {
+            let pc = start + index as u32 * 4;
+            if index == instructions.len() || (self.lockstep && translated == 1) {
+                exit(&mut e, EXIT_CONTINUE, pc);
+                break;
+            }
+
+            let instruction = &instructions[index];
+            if is_control_transfer(instruction.raw) {
+                // the branch and its delay slot are translated together or not at all
+                let mut branch = Emitter::new();
+                let delay_slot = instructions.get(index + 1).filter(
+                    |d| !is_control_transfer(d.raw),
+                );
+                let ok = match delay_slot {
+                    Some(delay_slot) if !self.lockstep => {
+                        self.translate_branch(&mut branch, instruction, pc) &&
+                            self.translate_instruction(&mut branch, delay_slot, pc + 4, true)
+                    }
+                    _ => false,
+                };
+                if ok {
+                    e.append(branch);
+                    exit_to_target(&mut e);
+                    translated += 2;
+                } else {
+                    exit(&mut e, EXIT_INTERPRET, pc);
+                }
+                break;
+            }
+
+            let mut code = Emitter::new();
+            if self.translate_instruction(&mut code, instruction, pc, false) {
+                e.append(code);
+                translated += 1;
+                index += 1;
+            } else {
+                exit(&mut e, EXIT_INTERPRET, pc);
+                break;
+            }
+        }
+
+        if translated == 0 {
+            debug!("Block at 0x{:08x} can't be translated", start);
+            Translation::Untranslatable
+        } else {
+            debug!(
+                "Translated block at 0x{:08x}, {} instructions into {} bytes",
+                start,
+                translated,
+                e.len()
+            );
+            Translation::Compiled(CompiledBlock::new(e.code()))
+        }
+    }
+
+    /// Emits the branch part of a control transfer. The target, or the address after the delay
+    /// slot when the branch is not taken, is left in R14.
+    fn translate_branch(&self, e: &mut Emitter, instruction: &DecodedInstruction, pc: u32) -> bool {
+        let decoded = match instruction.decoded {
+            Ok(decoded) => decoded,
+            Err(_) => return false,
+        };
+
+        let (cond, offset) = match decoded {
+            Instruction::Beq { rs, rt, offset } |
+            Instruction::Bne { rs, rt, offset } => {
+                read(e, Reg::Rax, rs, true);
+                read(e, Reg::Rcx, rt, true);
+                e.alu(Alu::Cmp, true, Reg::Rax, Reg::Rcx);
+                let cond = if let Instruction::Beq { .. } = decoded {
+                    Cond::Equal
+                } else {
+                    Cond::NotEqual
+                };
+                (cond, offset)
+            }
+            Instruction::Blez { rs, offset } |
+            Instruction::Bgtz { rs, offset } |
+            Instruction::Bltz { rs, offset } |
+            Instruction::Bgez { rs, offset } => {
+                read(e, Reg::Rax, rs, true);
+                e.alu_imm(Alu::Cmp, true, Reg::Rax, 0);
+                let cond = match decoded {
+                    Instruction::Blez { .. } => Cond::LessEqual,
+                    Instruction::Bgtz { .. } => Cond::Greater,
+                    Instruction::Bltz { .. } => Cond::Less,
+                    _ => Cond::GreaterEqual,
+                };
+                (cond, offset)
+            }
+            Instruction::J { target } => {
+                e.mov_imm32(Reg::R14, ((pc + 4) & 0xF0_00_00_00) | target);
+                return true;
+            }
+            Instruction::Jal { target } => {
+                e.store_imm(true, Reg::Rbx, gpr(31), pc + 8);
+                e.mov_imm32(Reg::R14, (pc & 0xF0_00_00_00) | target);
+                return true;
+            }
+            Instruction::Jr { rs } => {
+                read(e, Reg::R14, rs, false);
+                return true;
+            }
+            Instruction::Jalr { rd, rs } => {
+                // the interpreter reads the target after writing the link
+                if rd == rs && rd != 0 {
+                    return false;
+                }
+                read(e, Reg::Rcx, rs, false);
+                // misaligned targets are left to the interpreter
+                e.test_imm(Reg::Rcx, 0b11);
+                let mut misaligned = Emitter::new();
+                exit(&mut misaligned, EXIT_INTERPRET, pc);
+                unless(e, Cond::Equal, misaligned);
+                if rd != 0 {
+                    e.store_imm(true, Reg::Rbx, gpr(rd), pc + 8);
+                }
+                e.mov(false, Reg::R14, Reg::Rcx);
+                return true;
+            }
+            _ => return false,
+        };
+
+        let target = (pc as i32 + 4 + offset) as u32;
+        e.mov_imm32(Reg::R14, pc + 8);
+        e.mov_imm32(Reg::Rdx, target);
+        e.cmov(cond, false, Reg::R14, Reg::Rdx);
+        true
+    }
+
+    /// Emits a single instruction, nothing is emitted when it can't be translated.
+    fn translate_instruction(
+        &self,
+        e: &mut Emitter,
+        instruction: &DecodedInstruction,
+        pc: u32,
+        delay_slot: bool,
+    ) -> bool {
+        let decoded = match instruction.decoded {
+            Ok(decoded) => decoded,
+            Err(_) => return false,
+        };
+        if instruction.raw == 0 {
+            // nop
+            return true;
+        }
+
+        // exit to the interpreter before the instruction has any effect
+        let fallback = || {
+            let mut e = Emitter::new();
+            if delay_slot {
+                e.store_imm(false, Reg::R13, FRAME_EXIT, EXIT_DELAY_SLOT);
+                e.store(false, Reg::R13, FRAME_TARGET, Reg::R14);
+                e.mov_imm32(Reg::Rax, pc);
+                epilogue(&mut e);
+            } else {
+                exit(&mut e, EXIT_INTERPRET, pc);
+            }
+            e
+        };
+
+        // effective address in EAX, the kernel segment is left to the interpreter
+        let address = |e: &mut Emitter, base: u32, offset: i16| {
+            read(e, Reg::Rax, base, false);
+            if offset != 0 {
+                e.alu_imm(Alu::Add, false, Reg::Rax, offset as i32);
+            }
+            e.test(false, Reg::Rax, Reg::Rax);
+            unless(e, Cond::NotSign, fallback());
+        };
+
+        // aligned loads from readable pages only, the address in EAX is kept
+        let readable = |e: &mut Emitter, size: u32| {
+            if size > 1 {
+                e.test_imm(Reg::Rax, size - 1);
+                unless(e, Cond::Equal, fallback());
+            }
+            e.mov(false, Reg::Rcx, Reg::Rax);
+            e.shift_imm(Shift::Shr, false, Reg::Rcx, PAGE_SIZE.trailing_zeros() as u8);
+            e.load(true, Reg::Rdx, Reg::R13, FRAME_PAGES);
+            e.load_indexed(Load::ByteUnsigned, Reg::Rcx, Reg::Rdx, Reg::Rcx);
+            e.test_imm(Reg::Rcx, (PROT_READ | PROT_WRITE) as u32);
+            unless(e, Cond::NotEqual, fallback());
+        };
+
+        match decoded {
+            Instruction::Sll { rd, rt, shift } |
+            Instruction::Srl { rd, rt, shift } |
+            Instruction::Sra { rd, rt, shift } |
+            Instruction::Rotr { rd, rt, shift } => {
+                let op = match decoded {
+                    Instruction::Sll { .. } => Shift::Shl,
+                    Instruction::Srl { .. } => Shift::Shr,
+                    Instruction::Sra { .. } => Shift::Sar,
+                    _ => Shift::Ror,
+                };
+                read(e, Reg::Rax, rt, false);
+                e.shift_imm(op, false, Reg::Rax, shift as u8);
+                write32(e, rd, Reg::Rax);
+            }
+            Instruction::Sllv { rd, rt, rs } |
+            Instruction::Srlv { rd, rt, rs } |
+            Instruction::Srav { rd, rt, rs } |
+            Instruction::Rotrv { rd, rt, rs } => {
+                let op = match decoded {
+                    Instruction::Sllv { .. } => Shift::Shl,
+                    Instruction::Srlv { .. } => Shift::Shr,
+                    Instruction::Srav { .. } => Shift::Sar,
+                    _ => Shift::Ror,
+                };
+                read(e, Reg::Rcx, rs, false);
+                read(e, Reg::Rax, rt, false);
+                e.shift_cl(op, false, Reg::Rax);
+                write32(e, rd, Reg::Rax);
+            }
+            Instruction::Addu { rd, rs, rt } |
+            Instruction::Subu { rd, rs, rt } => {
+                let op = if let Instruction::Addu { .. } = decoded {
+                    Alu::Add
+                } else {
+                    Alu::Sub
+                };
+                read(e, Reg::Rax, rs, false);
+                read(e, Reg::Rcx, rt, false);
+                e.alu(op, false, Reg::Rax, Reg::Rcx);
+                write32(e, rd, Reg::Rax);
+            }
+            // logical operations work with whole registers
+            Instruction::And { rd, rs, rt } |
+            Instruction::Or { rd, rs, rt } |
+            Instruction::Xor { rd, rs, rt } |
+            Instruction::Nor { rd, rs, rt } => {
+                let op = match decoded {
+                    Instruction::And { .. } => Alu::And,
+                    Instruction::Xor { .. } => Alu::Xor,
+                    _ => Alu::Or,
+                };
+                read(e, Reg::Rax, rs, true);
+                read(e, Reg::Rcx, rt, true);
+                e.alu(op, true, Reg::Rax, Reg::Rcx);
+                if let Instruction::Nor { .. } = decoded {
+                    e.not(true, Reg::Rax);
+                }
+                write(e, rd, Reg::Rax);
+            }
+            Instruction::Slt { rd, rs, rt } |
+            Instruction::Sltu { rd, rs, rt } => {
+                read(e, Reg::Rax, rs, true);
+                read(e, Reg::Rcx, rt, true);
+                e.alu(Alu::Cmp, true, Reg::Rax, Reg::Rcx);
+                let cond = if let Instruction::Slt { .. } = decoded {
+                    Cond::Less
+                } else {
+                    Cond::Below
+                };
+                set(e, rd, cond);
+            }
+            Instruction::Movz { rd, rs, rt } |
+            Instruction::Movn { rd, rs, rt } => {
+                read(e, Reg::Rax, rt, true);
+                e.test(true, Reg::Rax, Reg::Rax);
+                let mut mov = Emitter::new();
+                read(&mut mov, Reg::Rcx, rs, true);
+                write(&mut mov, rd, Reg::Rcx);
+                let skip = if let Instruction::Movz { .. } = decoded {
+                    Cond::NotEqual
+                } else {
+                    Cond::Equal
+                };
+                unless(e, skip, mov);
+            }
+            Instruction::Mul { rd, rs, rt } => {
+                read(e, Reg::Rax, rs, false);
+                read(e, Reg::Rcx, rt, false);
+                e.imul(Reg::Rax, Reg::Rcx);
+                write32(e, rd, Reg::Rax);
+            }
+            Instruction::Addiu { rt, rs, immediate } => {
+                read(e, Reg::Rax, rs, false);
+                e.alu_imm(Alu::Add, false, Reg::Rax, immediate as i32);
+                write32(e, rt, Reg::Rax);
+            }
+            Instruction::Andi { rt, rs, immediate } |
+            Instruction::Ori { rt, rs, immediate } |
+            Instruction::Xori { rt, rs, immediate } => {
+                let op = match decoded {
+                    Instruction::Andi { .. } => Alu::And,
+                    Instruction::Ori { .. } => Alu::Or,
+                    _ => Alu::Xor,
+                };
+                read(e, Reg::Rax, rs, true);
+                e.alu_imm(op, true, Reg::Rax, immediate as i32);
+                write(e, rt, Reg::Rax);
+            }
+            Instruction::Slti { rt, rs, immediate } |
+            Instruction::Sltiu { rt, rs, immediate } => {
+                read(e, Reg::Rax, rs, true);
+                e.alu_imm(Alu::Cmp, true, Reg::Rax, immediate as i32);
+                let cond = if let Instruction::Slti { .. } = decoded {
+                    Cond::Less
+                } else {
+                    Cond::Below
+                };
+                set(e, rt, cond);
+            }
+            Instruction::Lui { rt, immediate } => {
+                if rt != 0 {
+                    e.store_imm(true, Reg::Rbx, gpr(rt), (immediate as u32) << 16);
+                }
+            }
+            Instruction::Seb { rd, rt } |
+            Instruction::Seh { rd, rt } => {
+                let bits = if let Instruction::Seb { .. } = decoded {
+                    8
+                } else {
+                    16
+                };
+                read(e, Reg::Rax, rt, false);
+                e.sign_extend(bits, Reg::Rax, Reg::Rax);
+                write32(e, rd, Reg::Rax);
+            }
+            Instruction::Ext { rt, rs, pos, size } => {
+                read(e, Reg::Rax, rs, false);
+                e.shift_imm(Shift::Shr, false, Reg::Rax, pos as u8);
+                e.alu_imm(Alu::And, false, Reg::Rax, bit_mask(size) as i32);
+                write32(e, rt, Reg::Rax);
+            }
+            Instruction::Ins { rt, rs, pos, size } => {
+                let mask = bit_mask(size) << pos;
+                read(e, Reg::Rax, rt, false);
+                e.alu_imm(Alu::And, false, Reg::Rax, !mask as i32);
+                read(e, Reg::Rcx, rs, false);
+                e.shift_imm(Shift::Shl, false, Reg::Rcx, pos as u8);
+                e.alu_imm(Alu::And, false, Reg::Rcx, mask as i32);
+                e.alu(Alu::Or, false, Reg::Rax, Reg::Rcx);
+                write32(e, rt, Reg::Rax);
+            }
+            Instruction::Lb { rt, base, offset } |
+            Instruction::Lbu { rt, base, offset } |
+            Instruction::Lh { rt, base, offset } |
+            Instruction::Lhu { rt, base, offset } |
+            Instruction::Lw { rt, base, offset } => {
+                let size = match decoded {
+                    Instruction::Lb { .. } | Instruction::Lbu { .. } => 1,
+                    Instruction::Lh { .. } | Instruction::Lhu { .. } => 2,
+                    _ => 4,
+                };
+                address(e, base, offset);
+                readable(e, size);
+                match decoded {
+                    Instruction::Lb { .. } => {
+                        e.load_indexed(Load::ByteSigned, Reg::Rax, Reg::R12, Reg::Rax);
+                    }
+                    Instruction::Lbu { .. } => {
+                        e.load_indexed(Load::ByteUnsigned, Reg::Rax, Reg::R12, Reg::Rax);
+                    }
+                    Instruction::Lh { .. } |
+                    Instruction::Lhu { .. } => {
+                        e.load_indexed(Load::Halfword, Reg::Rax, Reg::R12, Reg::Rax);
+                        if self.big_endian {
+                            e.swap16(Reg::Rax);
+                        }
+                        if let Instruction::Lh { .. } = decoded {
+                            e.sign_extend(16, Reg::Rax, Reg::Rax);
+                        } else {
+                            e.zero_extend(16, Reg::Rax, Reg::Rax);
+                        }
+                    }
+                    _ => {
+                        e.load_indexed(Load::Word, Reg::Rax, Reg::R12, Reg::Rax);
+                        if self.big_endian {
+                            e.bswap32(Reg::Rax);
+                        }
+                    }
+                }
+                write32(e, rt, Reg::Rax);
+            }
+            Instruction::Sb { rt, base, offset } |
+            Instruction::Sh { rt, base, offset } |
+            Instruction::Sw { rt, base, offset } => {
+                let size = match decoded {
+                    Instruction::Sb { .. } => 1,
+                    Instruction::Sh { .. } => 2,
+                    _ => 4,
+                };
+                address(e, base, offset);
+                read(e, Reg::Rdx, rt, false);
+                e.mov(false, Reg::Rsi, Reg::Rax);
+                e.mov(true, Reg::Rdi, Reg::R13);
+                e.mov_imm32(Reg::Rcx, size);
+                e.mov_imm64(Reg::Rax, store as *const () as usize as u64);
+                e.call(Reg::Rax);
+                e.alu_imm(Alu::Cmp, false, Reg::Rax, STORE_FAULT as i32);
+                unless(e, Cond::NotEqual, fallback());
+
+                // stale code may follow, the block cache has to be synchronized first
+                e.test(false, Reg::Rax, Reg::Rax);
+                let mut modified = Emitter::new();
+                if delay_slot {
+                    exit_to_target(&mut modified);
+                } else {
+                    exit(&mut modified, EXIT_CONTINUE, pc + 4);
+                }
+                unless(e, Cond::Equal, modified);
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Offset of a general purpose register in the register file, $zero is not stored
+fn gpr(reg: u32) -> i32 {
+    (reg as i32 - 1) * 8
+}
+
+/// Reads a register, 32 bit reads clear the upper half of `dst`
+fn read(e: &mut Emitter, dst: Reg, reg: u32, wide: bool) {
+    if reg == 0 {
+        e.alu(Alu::Xor, false, dst, dst);
+    } else {
+        e.load(wide, dst, Reg::Rbx, gpr(reg));
+    }
+}
+
+fn write(e: &mut Emitter, reg: u32, src: Reg) {
+    if reg != 0 {
+        e.store(true, Reg::Rbx, gpr(reg), src);
+    }
+}
+
+/// Writes a 32 bit result sign extended, the same as `RegisterFile::write_register`
+fn write32(e: &mut Emitter, reg: u32, src: Reg) {
+    if reg != 0 {
+        e.sign_extend32(src, src);
+        write(e, reg, src);
+    }
+}
+
+/// Writes 1 into the register when the condition holds, 0 otherwise
+fn set(e: &mut Emitter, reg: u32, cond: Cond) {
+    e.set(cond, Reg::Rax);
+    e.zero_extend(8, Reg::Rax, Reg::Rax);
+    write(e, reg, Reg::Rax);
+}
+
+/// Emits `code`, that is skipped when the condition holds
+fn unless(e: &mut Emitter, cond: Cond, code: Emitter) {
+    e.jump_over(cond, code.len());
+    e.append(code);
+}
+
+fn epilogue(e: &mut Emitter) {
+    for &reg in &[Reg::R14, Reg::R13, Reg::R12, Reg::Rbx, Reg::Rbp] {
+        e.pop(reg);
+    }
+    e.ret();
+}
+
+fn exit(e: &mut Emitter, kind: u32, pc: u32) {
+    e.store_imm(false, Reg::R13, FRAME_EXIT, kind);
+    e.mov_imm32(Reg::Rax, pc);
+    epilogue(e);
+}
+
+/// Continues at the address in R14
+fn exit_to_target(e: &mut Emitter) {
+    e.store_imm(false, Reg::R13, FRAME_EXIT, EXIT_CONTINUE);
+    e.mov(false, Reg::Rax, Reg::R14);
+    epilogue(e);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_translated_block() {
+    use cpu::block_cache::BlockCache;
+    use cpu::isa::{Abi, Isa, IsaRevision};
+    use memory::Endianness;
+
+    let isa = Isa {
+        revision: IsaRevision::R2,
+        compressed: None,
+        abi: Abi::O32,
+    };
+    let mut memory = Memory::new(Endianness::BigEndian);
+    memory.map(0x10000000, 0x1000, PROT_READ | PROT_WRITE);
+    let code = [
+        0x3C091000, // lui t1,0x1000
+        0x35290010, // ori t1,t1,0x10
+        0x24080005, // li t0,5
+        0xAD280000, // sw t0,0(t1)
+        0x812A0003, // lb t2,3(t1)
+        0x110A0002, // beq t0,t2,0x400020
+        0x240BFFFF, // li t3,-1
+    ];
+    for (i, word) in code.iter().enumerate() {
+        memory.write_word(0x400000 + i as u32 * 4, *word);
+    }
+
+    let mut cache = BlockCache::new();
+    let block = cache.get_block(0x400000, &mut memory, isa);
+    let jit = Jit::new(true, false);
+    while !jit.prepare(&block) {}
+
+    let mut registers = RegisterFile::new(0x7ff00000);
+    assert_eq!(jit.run(&block, &mut registers, &mut memory), JitExit::Continue(0x400020));
+    assert_eq!(memory.read_word(0x10000010), 5);
+    assert_eq!(registers.read_register(10), 5);
+    assert_eq!(registers.read_register64(11), u64::MAX);
+}
@@ -0,0 +1,727 @@
+//! microMIPS instruction set. It is a re-encoding of MIPS32 with 16 and 32 bit instructions, the
+//! size is given by the major opcode. 32 bit instructions are fetched as two halfwords, the first
+//! one holds the opcode. Branch offsets are in halfwords and delay slots are either 16 or 32 bits
+//! wide.
+
+use cpu::bitutils::sign_extend;
+use cpu::branch::BranchCondition;
+use cpu::compressed::*;
+use cpu::event::{CPUEvent, ExceptionKind};
+use cpu::instructions::eval_instruction;
+use cpu::registers::{get_register_name, RegisterFile, RETURN_ADDRESS, STACK_POINTER};
+use memory::{Access, Memory};
+use syscalls::System;
+
+/// GPRs accessible by 3 bit register fields
+const REGISTER_MAP: [u32; 8] = [16, 17, 2, 3, 4, 5, 6, 7];
+/// Source registers of SB16, SH16 and SW16
+const STORE_REGISTER_MAP: [u32; 8] = [0, 17, 2, 3, 4, 5, 6, 7];
+/// Source registers of MOVEP
+const MOVEP_REGISTER_MAP: [u32; 8] = [0, 17, 2, 3, 16, 18, 19, 20];
+/// Destination register pairs of MOVEP
+const MOVEP_DESTINATIONS: [(u32, u32); 8] = [
+    (5, 6),
+    (5, 7),
+    (6, 7),
+    (4, 21),
+    (4, 22),
+    (4, 5),
+    (4, 6),
+    (4, 7),
+];
+/// Immediates of ANDI16
+const ANDI16_IMMEDIATES: [u32; 16] = [
+    128, 1, 2, 3, 4, 7, 8, 15, 16, 31, 32, 63, 64, 255, 32768, 65535,
+];
+const GLOBAL_POINTER: u32 = 28;
+
+pub fn eval_micromips_instruction(
+    address: u32,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> (CPUEvent, u32) {
+    let first = memory.fetch_halfword(address);
+    match (first >> 10) & 0b111 {
+        0b001..=0b011 => (eval_16bit(first, address, registers, memory, system), 2),
+        _ => {
+            let instruction = (first << 16) | memory.fetch_halfword(address + 2);
+            (eval_32bit(instruction, address, registers, memory, system), 4)
+        }
+    }
+}
+
+/// Branch to the given target after the delay slot, when `jump` is set
+fn branch(name: &str, address: u32, rs: u32, target: u32, jump: bool) -> CPUEvent {
+    trace!(
+        "0x{:x}:\t{}\t{},0x{:x} - jumped={}",
+        address,
+        name,
+        get_register_name(rs),
+        target,
+        jump
+    );
+    if jump {
+        CPUEvent::FlowChangeDelayed(target)
+    } else {
+        CPUEvent::Nothing
+    }
+}
+
+fn eval_16bit(
+    instruction: u32,
+    address: u32,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> CPUEvent {
+    macro_rules! itrace {
+        ($fmt:expr, $($arg:tt)*) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address, $($arg)*);
+        );
+        ($fmt:expr) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address);
+        );
+    }
+
+    // evaluates equivalent MIPS32 instruction
+    macro_rules! mips32 {
+        ($instruction:expr) => (
+            eval_instruction($instruction, registers, memory, system)
+        );
+    }
+
+    let opcode = instruction >> 10;
+    let rd = REGISTER_MAP[((instruction >> 7) & 0b111) as usize];
+    let rs = REGISTER_MAP[((instruction >> 4) & 0b111) as usize];
+    let rt = REGISTER_MAP[((instruction >> 1) & 0b111) as usize];
+    let rd5 = (instruction >> 5) & 0x1F;
+    let rs5 = instruction & 0x1F;
+    // offset of the 16 bit loads and stores
+    let offset4 = |shift: u32| (instruction & 0xF) << shift;
+    let branch_target = |offset: u32| (address + 2).wrapping_add(offset) | ISA_MODE_BIT;
+
+    match opcode {
+        // ADDU16, SUBU16, the first source operand is in bits 3:1
+        0b000001 => {
+            let funct = if instruction & 1 == 0 {
+                FUNCT_ADDU
+            } else {
+                FUNCT_SUBU
+            };
+            mips32!(encode_register(OP_SPECIAL, rt, rs, rd, 0, funct))
+        }
+        // SLL16, SRL16
+        0b001001 => {
+            let shift = match (instruction >> 1) & 0b111 {
+                0 => 8,
+                s => s,
+            };
+            let funct = if instruction & 1 == 0 {
+                FUNCT_SLL
+            } else {
+                FUNCT_SRL
+            };
+            mips32!(encode_register(OP_SPECIAL, 0, rs, rd, shift, funct))
+        }
+        // LBU16, LHU16, LW16, the offset 0xF of LBU16 stands for -1
+        0b000010 => {
+            let offset = match offset4(0) {
+                0xF => -1i32 as u32,
+                o => o,
+            };
+            mips32!(encode_immediate(OP_LBU, rs, rd, offset))
+        }
+        0b001010 => mips32!(encode_immediate(OP_LHU, rs, rd, offset4(1))),
+        0b011010 => mips32!(encode_immediate(OP_LW, rs, rd, offset4(2))),
+        // SB16, SH16, SW16
+        0b100010 | 0b101010 | 0b111010 => {
+            let rt = STORE_REGISTER_MAP[((instruction >> 7) & 0b111) as usize];
+            match opcode {
+                0b100010 => mips32!(encode_immediate(OP_SB, rs, rt, offset4(0))),
+                0b101010 => mips32!(encode_immediate(OP_SH, rs, rt, offset4(1))),
+                _ => mips32!(encode_immediate(OP_SW, rs, rt, offset4(2))),
+            }
+        }
+        // LWSP, SWSP
+        0b010010 => mips32!(encode_immediate(OP_LW, STACK_POINTER, rd5, rs5 << 2)),
+        0b110010 => mips32!(encode_immediate(OP_SW, STACK_POINTER, rd5, rs5 << 2)),
+        // LWGP
+        0b011001 => {
+            let offset = (sign_extend(instruction & 0x7F, 7) << 2) as u32;
+            mips32!(encode_immediate(OP_LW, GLOBAL_POINTER, rd, offset))
+        }
+        // MOVE16
+        0b000011 => mips32!(encode_register(OP_SPECIAL, rs5, 0, rd5, 0, FUNCT_ADDU)),
+        // ANDI16
+        0b001011 => {
+            let immediate = ANDI16_IMMEDIATES[(instruction & 0xF) as usize];
+            mips32!(encode_immediate(OP_ANDI, rs, rd, immediate))
+        }
+        // LI16
+        0b111011 => {
+            let immediate = match instruction & 0x7F {
+                0x7F => -1i32 as u32,
+                i => i,
+            };
+            mips32!(encode_immediate(OP_ADDIU, 0, rd, immediate))
+        }
+        // POOL16C
+        0b010001 => {
+            let rd = REGISTER_MAP[((instruction >> 3) & 0b111) as usize];
+            let rs = REGISTER_MAP[(instruction & 0b111) as usize];
+            match (instruction >> 4) & 0x3F {
+                // NOT16, XOR16, AND16, OR16
+                0x00..=0x03 => mips32!(encode_register(OP_SPECIAL, rs, 0, rd, 0, FUNCT_NOR)),
+                0x04..=0x07 => mips32!(encode_register(OP_SPECIAL, rd, rs, rd, 0, FUNCT_XOR)),
+                0x08..=0x0B => mips32!(encode_register(OP_SPECIAL, rd, rs, rd, 0, FUNCT_AND)),
+                0x0C..=0x0F => mips32!(encode_register(OP_SPECIAL, rd, rs, rd, 0, FUNCT_OR)),
+                // LWM16, SWM16
+                0x10..=0x17 => {
+                    let load = instruction & 0x40 == 0;
+                    let count = (instruction >> 4) & 0b11;
+                    let mut list: Vec<u32> = (16..17 + count).collect();
+                    list.push(RETURN_ADDRESS);
+                    let base = registers
+                        .read_register(STACK_POINTER)
+                        .wrapping_add((instruction & 0xF) << 2);
+                    itrace!(
+                        "{}\t{:?},0x{:x}",
+                        if load { "lwm16" } else { "swm16" },
+                        list,
+                        base
+                    );
+                    let access = if load { Access::Read } else { Access::Write };
+                    if let Err(fault) = memory.check_access(base, list.len() as u32 * 4, access) {
+                        return CPUEvent::Exception(fault);
+                    }
+                    for (i, r) in list.into_iter().enumerate() {
+                        let addr = base.wrapping_add(i as u32 * 4);
+                        if load {
+                            let value = memory.read_word(addr);
+                            registers.write_register(r, value);
+                        } else {
+                            memory.write_word(addr, registers.read_register(r));
+                        }
+                    }
+                    CPUEvent::Nothing
+                }
+                // JR16, JRC, JALR16, JALRS16
+                0x18..=0x1F => {
+                    let compact = instruction & 0x3E0 == 0x1A0;
+                    let target = registers.read_register(rs5);
+                    itrace!(
+                        "{}\t{} - target=0x{:x}",
+                        ["jr16", "jrc", "jalr16", "jalrs16"][((instruction >> 5) & 0b11) as usize],
+                        get_register_name(rs5),
+                        target
+                    );
+                    match (instruction >> 5) & 0b11 {
+                        // the delay slot of JALR16 is 32 bits wide, of JALRS16 16 bits wide
+                        0b10 => registers.write_address(RETURN_ADDRESS, (address + 6) | 1),
+                        0b11 => registers.write_address(RETURN_ADDRESS, (address + 4) | 1),
+                        _ => {}
+                    }
+                    if compact {
+                        CPUEvent::FlowChangeImmediate(target)
+                    } else {
+                        CPUEvent::FlowChangeDelayed(target)
+                    }
+                }
+                // MFHI16, MFLO16
+                0x20 | 0x21 => mips32!(encode_register(OP_SPECIAL, 0, 0, rs5, 0, FUNCT_MFHI)),
+                0x24 | 0x25 => mips32!(encode_register(OP_SPECIAL, 0, 0, rs5, 0, FUNCT_MFLO)),
+                // BREAK16, SDBBP16
+                0x28 => mips32!(encode_register(OP_SPECIAL, 0, 0, 0, 0, FUNCT_BREAK)),
+                0x2C => {
+                    itrace!("sdbbp16\t{}", instruction & 0xF);
+                    CPUEvent::Exception(ExceptionKind::Breakpoint)
+                }
+                // JRADDIUSP
+                0x30 | 0x31 => {
+                    let target = registers.read_register(RETURN_ADDRESS);
+                    let sp = registers.read_register(STACK_POINTER).wrapping_add(rs5 << 2);
+                    itrace!("jraddiusp\t{} - target=0x{:x}", rs5 << 2, target);
+                    registers.write_register(STACK_POINTER, sp);
+                    CPUEvent::FlowChangeImmediate(target)
+                }
+                _ => {
+                    itrace!("reserved POOL16C instruction 0x{:04x}", instruction);
+                    CPUEvent::Exception(ExceptionKind::ReservedInstruction)
+                }
+            }
+        }
+        // ADDIUS5, ADDIUSP
+        0b010011 => {
+            if instruction & 1 == 0 {
+                let immediate = sign_extend((instruction >> 1) & 0xF, 4) as u32;
+                mips32!(encode_immediate(OP_ADDIU, rd5, rd5, immediate))
+            } else {
+                let encoded = (instruction >> 1) & 0x1FF;
+                let immediate = match encoded {
+                    0 | 1 => 256 + encoded,
+                    2..=255 => encoded,
+                    256..=509 => encoded.wrapping_sub(512),
+                    _ => encoded.wrapping_sub(768),
+                };
+                mips32!(encode_immediate(
+                    OP_ADDIU,
+                    STACK_POINTER,
+                    STACK_POINTER,
+                    immediate << 2,
+                ))
+            }
+        }
+        // ADDIUR2, ADDIUR1SP
+        0b011011 => {
+            if instruction & 1 == 0 {
+                let immediate = [1, 4, 8, 12, 16, 20, 24, -1i32 as u32]
+                    [((instruction >> 1) & 0b111) as usize];
+                mips32!(encode_immediate(OP_ADDIU, rs, rd, immediate))
+            } else {
+                let immediate = ((instruction >> 1) & 0x3F) << 2;
+                mips32!(encode_immediate(OP_ADDIU, STACK_POINTER, rd, immediate))
+            }
+        }
+        // MOVEP
+        0b100001 if instruction & 1 == 0 => {
+            let (d, e) = MOVEP_DESTINATIONS[((instruction >> 7) & 0b111) as usize];
+            let s = MOVEP_REGISTER_MAP[((instruction >> 1) & 0b111) as usize];
+            let t = MOVEP_REGISTER_MAP[((instruction >> 4) & 0b111) as usize];
+            let (s, t) = (registers.read_register(s), registers.read_register(t));
+            itrace!(
+                "movep\t{},{} - values=0x{:x},0x{:x}",
+                get_register_name(d),
+                get_register_name(e),
+                s,
+                t
+            );
+            registers.write_register(d, s);
+            registers.write_register(e, t);
+            CPUEvent::Nothing
+        }
+        // BEQZ16, BNEZ16
+        0b100011 | 0b101011 => {
+            let target = branch_target((sign_extend(instruction & 0x7F, 7) << 1) as u32);
+            let zero = registers.read_register(rd) == 0;
+            if opcode == 0b100011 {
+                branch("beqz16", address, rd, target, zero)
+            } else {
+                branch("bnez16", address, rd, target, !zero)
+            }
+        }
+        // B16
+        0b110011 => {
+            let target = branch_target((sign_extend(instruction & 0x3FF, 10) << 1) as u32);
+            branch("b16", address, 0, target, true)
+        }
+        _ => {
+            itrace!("reserved instruction 0x{:04x}", instruction);
+            CPUEvent::Exception(ExceptionKind::ReservedInstruction)
+        }
+    }
+}
+
+fn eval_32bit(
+    instruction: u32,
+    address: u32,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> CPUEvent {
+    macro_rules! itrace {
+        ($fmt:expr, $($arg:tt)*) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address, $($arg)*);
+        );
+        ($fmt:expr) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address);
+        );
+    }
+
+    // evaluates equivalent MIPS32 instruction
+    macro_rules! mips32 {
+        ($instruction:expr) => (
+            eval_instruction($instruction, registers, memory, system)
+        );
+    }
+
+    let opcode = instruction >> 26;
+    let rt = (instruction >> 21) & 0x1F;
+    let rs = (instruction >> 16) & 0x1F;
+    let rd = (instruction >> 11) & 0x1F;
+    let immediate = instruction & 0xFFFF;
+    let branch_target = (address + 4).wrapping_add((sign_extend(immediate, 16) << 1) as u32) |
+        ISA_MODE_BIT;
+    let special = |rs, rt, rd, funct| encode_register(OP_SPECIAL, rs, rt, rd, 0, funct);
+
+    // instructions with the same operands as in MIPS32
+    let translated = match opcode {
+        0b000100 => Some(OP_ADDI),
+        0b001100 => Some(OP_ADDIU),
+        0b100100 => Some(OP_SLTI),
+        0b101100 => Some(OP_SLTIU),
+        0b110100 => Some(OP_ANDI),
+        0b010100 => Some(OP_ORI),
+        0b011100 => Some(OP_XORI),
+        0b000111 => Some(OP_LB),
+        0b000101 => Some(OP_LBU),
+        0b001111 => Some(OP_LH),
+        0b001101 => Some(OP_LHU),
+        0b111111 => Some(OP_LW),
+        0b000110 => Some(OP_SB),
+        0b001110 => Some(OP_SH),
+        0b111110 => Some(OP_SW),
+        0b100111 => Some(OP_LWC1),
+        0b100110 => Some(OP_SWC1),
+        0b101111 => Some(OP_LDC1),
+        0b101110 => Some(OP_SDC1),
+        _ => None,
+    };
+    if let Some(op) = translated {
+        return mips32!(encode_immediate(op, rs, rt, immediate));
+    }
+
+    match opcode {
+        // POOL32A
+        0b000000 => {
+            let minor = (instruction >> 6) & 0xF;
+            match instruction & 0x3F {
+                // SLL, SRL, SRA, ROTR
+                0x00 => {
+                    let (rotate, funct) = match minor {
+                        0 => (0, FUNCT_SLL),
+                        1 => (0, FUNCT_SRL),
+                        2 => (0, FUNCT_SRA),
+                        3 => (1, FUNCT_SRL),
+                        _ => return CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+                    };
+                    mips32!(encode_register(OP_SPECIAL, rotate, rs, rt, rd, funct))
+                }
+                // register operations
+                0x10 => {
+                    let (rotate, funct) = match minor {
+                        0x0 => (0, FUNCT_SLLV),
+                        0x1 => (0, FUNCT_SRLV),
+                        0x2 => (0, FUNCT_SRAV),
+                        0x3 => (1, FUNCT_SRLV),
+                        0x4 => (0, FUNCT_ADD),
+                        0x5 => (0, FUNCT_ADDU),
+                        0x6 => (0, FUNCT_SUB),
+                        0x7 => (0, FUNCT_SUBU),
+                        0x8 => {
+                            return mips32!(encode_register(OP_SPECIAL2, rs, rt, rd, 0, FUNCT_MUL))
+                        }
+                        0x9 => (0, FUNCT_AND),
+                        0xA => (0, FUNCT_OR),
+                        0xB => (0, FUNCT_NOR),
+                        0xC => (0, FUNCT_XOR),
+                        0xD => (0, FUNCT_SLT),
+                        0xE => (0, FUNCT_SLTU),
+                        _ => return CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+                    };
+                    mips32!(encode_register(OP_SPECIAL, rs, rt, rd, rotate, funct))
+                }
+                // MOVN, MOVZ
+                0x18 if minor == 0 => mips32!(special(rs, rt, rd, FUNCT_MOVN)),
+                0x18 if minor == 1 => mips32!(special(rs, rt, rd, FUNCT_MOVZ)),
+                // INS, EXT
+                0x0C => {
+                    let lsb = (instruction >> 6) & 0x1F;
+                    mips32!(encode_register(OP_SPECIAL3, rs, rt, rd, lsb, FUNCT_INS))
+                }
+                0x2C => {
+                    let lsb = (instruction >> 6) & 0x1F;
+                    mips32!(encode_register(OP_SPECIAL3, rs, rt, rd, lsb, FUNCT_EXT))
+                }
+                // BREAK
+                0x07 => mips32!(special(0, 0, 0, FUNCT_BREAK)),
+                // POOL32AXF
+                0x3C => eval_pool32axf(instruction, address, registers, memory, system),
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        // POOL32I
+        0b010000 => {
+            let value = registers.read_register64(rs);
+            let regimm = |rt| encode_immediate(OP_REGIMM, rs, rt, immediate);
+            match rt {
+                // BLTZ, BGEZ, BLEZ, BGTZ
+                0b00000 | 0b00010 | 0b00100 | 0b00110 => {
+                    let (name, condition) = match rt {
+                        0b00000 => ("bltz", BranchCondition::LessThan),
+                        0b00010 => ("bgez", BranchCondition::GreaterEqual),
+                        0b00100 => ("blez", BranchCondition::LessEqual),
+                        _ => ("bgtz", BranchCondition::GreaterThan),
+                    };
+                    branch(name, address, rs, branch_target, condition.evaluate(value, 0))
+                }
+                // BLTZAL, BGEZAL, BLTZALS, BGEZALS
+                0b00001 | 0b00011 | 0b10001 | 0b10011 => {
+                    let condition = if rt & 0b10 == 0 {
+                        BranchCondition::LessThan
+                    } else {
+                        BranchCondition::GreaterEqual
+                    };
+                    // the short delay slot variants have a 16 bit delay slot
+                    let delay_slot = if rt & 0b10000 == 0 { 4 } else { 2 };
+                    registers.write_address(RETURN_ADDRESS, (address + 4 + delay_slot) | 1);
+                    branch("bal", address, rs, branch_target, condition.evaluate(value, 0))
+                }
+                // BEQZC, BNEZC
+                0b00111 | 0b00101 => {
+                    let jump = (value == 0) == (rt == 0b00111);
+                    itrace!(
+                        "{}\t{},0x{:x} - jumped={}",
+                        if rt == 0b00111 { "beqzc" } else { "bnezc" },
+                        get_register_name(rs),
+                        branch_target,
+                        jump
+                    );
+                    if jump {
+                        CPUEvent::FlowChangeImmediate(branch_target)
+                    } else {
+                        CPUEvent::Nothing
+                    }
+                }
+                // TLTI, TGEI, TLTIU, TGEIU, TNEI, TEQI
+                0b01000 => mips32!(regimm(0b01010)),
+                0b01001 => mips32!(regimm(0b01000)),
+                0b01010 => mips32!(regimm(0b01011)),
+                0b01011 => mips32!(regimm(0b01001)),
+                0b01100 => mips32!(regimm(0b01110)),
+                0b01110 => mips32!(regimm(0b01100)),
+                // LUI
+                0b01101 => mips32!(encode_immediate(OP_LUI, 0, rs, immediate)),
+                // SYNCI
+                0b10000 => {
+                    itrace!("synci - instruction ignored");
+                    CPUEvent::Nothing
+                }
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        // POOL32C
+        0b011000 => {
+            let offset = sign_extend(instruction & 0xFFF, 12) as u32;
+            let op = match (instruction >> 12) & 0xF {
+                0x0 => OP_LWL,
+                0x1 => OP_LWR,
+                0x3 => OP_LL,
+                0x8 => OP_SWL,
+                0x9 => OP_SWR,
+                0xB => OP_SC,
+                // PREF
+                0x2 => {
+                    itrace!("pref - instruction ignored");
+                    return CPUEvent::Nothing;
+                }
+                _ => return CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            };
+            mips32!(encode_immediate(op, rs, rt, offset))
+        }
+        // BEQ, BNE
+        0b100101 | 0b101101 => {
+            let equal = registers.read_register(rs) == registers.read_register(rt);
+            if opcode == 0b100101 {
+                branch("beq", address, rs, branch_target, equal)
+            } else {
+                branch("bne", address, rs, branch_target, !equal)
+            }
+        }
+        // J, JAL, JALS
+        0b110101 | 0b111101 | 0b011101 => {
+            let target = ((address + 4) & 0xF8_00_00_00) | ((instruction & 0x03_FF_FF_FF) << 1);
+            itrace!("j\t0x{:x}", target);
+            match opcode {
+                0b111101 => registers.write_address(RETURN_ADDRESS, (address + 8) | 1),
+                0b011101 => registers.write_address(RETURN_ADDRESS, (address + 6) | 1),
+                _ => {}
+            }
+            CPUEvent::FlowChangeDelayed(target | ISA_MODE_BIT)
+        }
+        // JALX
+        0b111100 => {
+            let target = ((address + 4) & 0xF0_00_00_00) | ((instruction & 0x03_FF_FF_FF) << 2);
+            itrace!("jalx\t0x{:x}", target);
+            registers.write_address(RETURN_ADDRESS, (address + 8) | 1);
+            CPUEvent::FlowChangeDelayed(target)
+        }
+        // ADDIUPC
+        0b011110 => {
+            let rs = REGISTER_MAP[((instruction >> 23) & 0b111) as usize];
+            let offset = (sign_extend(instruction & 0x7F_FF_FF, 23) << 2) as u32;
+            let r = (address & !0b11).wrapping_add(offset);
+            itrace!("addiupc\t{},0x{:x}", get_register_name(rs), r);
+            registers.write_register(rs, r);
+            CPUEvent::Nothing
+        }
+        _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+    }
+}
+
+/// POOL32AXF, the minor opcode is in bits 11:6 and its extension in bits 15:12
+fn eval_pool32axf(
+    instruction: u32,
+    address: u32,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> CPUEvent {
+    macro_rules! itrace {
+        ($fmt:expr, $($arg:tt)*) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address, $($arg)*);
+        );
+        ($fmt:expr) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address);
+        );
+    }
+
+    // evaluates equivalent MIPS32 instruction
+    macro_rules! mips32 {
+        ($instruction:expr) => (
+            eval_instruction($instruction, registers, memory, system)
+        );
+    }
+
+    let rt = (instruction >> 21) & 0x1F;
+    let rs = (instruction >> 16) & 0x1F;
+    let extension = (instruction >> 12) & 0xF;
+    let special = |rs, rt, rd, funct| encode_register(OP_SPECIAL, rs, rt, rd, 0, funct);
+
+    match (instruction >> 6) & 0x3F {
+        // TEQ, TGE, TGEU, TLT, TLTU, TNE
+        0x00 => mips32!(special(rs, rt, 0, FUNCT_TEQ)),
+        0x08 => mips32!(special(rs, rt, 0, FUNCT_TGE)),
+        0x10 => mips32!(special(rs, rt, 0, FUNCT_TGEU)),
+        0x20 => mips32!(special(rs, rt, 0, FUNCT_TLT)),
+        0x28 => mips32!(special(rs, rt, 0, FUNCT_TLTU)),
+        0x30 => mips32!(special(rs, rt, 0, FUNCT_TNE)),
+        0x2C => {
+            match extension {
+                // SEB, SEH, WSBH
+                0x2 => mips32!(encode_register(OP_SPECIAL3, 0, rs, rt, BSHFL_SEB, FUNCT_BSHFL)),
+                0x3 => mips32!(encode_register(OP_SPECIAL3, 0, rs, rt, BSHFL_SEH, FUNCT_BSHFL)),
+                0x7 => mips32!(encode_register(OP_SPECIAL3, 0, rs, rt, BSHFL_WSBH, FUNCT_BSHFL)),
+                // CLO, CLZ
+                0x4 => mips32!(encode_register(OP_SPECIAL2, rs, rt, rt, 0, FUNCT_CLO)),
+                0x5 => mips32!(encode_register(OP_SPECIAL2, rs, rt, rt, 0, FUNCT_CLZ)),
+                // RDHWR
+                0x6 => mips32!(encode_register(OP_SPECIAL3, 0, rt, rs, 0, FUNCT_RDHWR)),
+                // MULT, MULTU, DIV, DIVU
+                0x8 => mips32!(special(rs, rt, 0, FUNCT_MULT)),
+                0x9 => mips32!(special(rs, rt, 0, FUNCT_MULTU)),
+                0xA => mips32!(special(rs, rt, 0, FUNCT_DIV)),
+                0xB => mips32!(special(rs, rt, 0, FUNCT_DIVU)),
+                // MADD, MADDU, MSUB, MSUBU
+                0xC => mips32!(encode_register(OP_SPECIAL2, rs, rt, 0, 0, FUNCT_MADD)),
+                0xD => mips32!(encode_register(OP_SPECIAL2, rs, rt, 0, 0, FUNCT_MADDU)),
+                0xE => mips32!(encode_register(OP_SPECIAL2, rs, rt, 0, 0, FUNCT_MSUB)),
+                0xF => mips32!(encode_register(OP_SPECIAL2, rs, rt, 0, 0, FUNCT_MSUBU)),
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        // JALR, JALR.HB, JALRS, JALRS.HB
+        0x3C => {
+            let target = registers.read_register(rs);
+            itrace!(
+                "jalr\t{},{} - target=0x{:x}",
+                get_register_name(rt),
+                get_register_name(rs),
+                target
+            );
+            let delay_slot = if extension & 0b100 == 0 { 4 } else { 2 };
+            registers.write_address(rt, (address + 4 + delay_slot) | 1);
+            CPUEvent::FlowChangeDelayed(target)
+        }
+        0x2D => {
+            match extension {
+                0x6 => mips32!(special(0, 0, 0, FUNCT_SYNC)),
+                0x8 => mips32!(special(0, 0, 0, FUNCT_SYSCALL)),
+                0xD => {
+                    itrace!("sdbbp\t{}", (instruction >> 16) & 0x3FF);
+                    CPUEvent::Exception(ExceptionKind::Breakpoint)
+                }
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        // MFHI, MFLO, MTHI, MTLO
+        0x35 => {
+            match extension {
+                0x0 => mips32!(special(0, 0, rs, FUNCT_MFHI)),
+                0x1 => mips32!(special(0, 0, rs, FUNCT_MFLO)),
+                0x2 => mips32!(special(rs, 0, 0, FUNCT_MTHI)),
+                0x3 => mips32!(special(rs, 0, 0, FUNCT_MTLO)),
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+    }
+}
+
+#[test]
+fn test_micromips() {
+    use cpu::instructions::{test_machine, TEST_CODE as CODE, TEST_DATA as DATA};
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    let run = |halfwords: &[u32], r: &mut RegisterFile, m: &mut Memory, s: &mut System| {
+        eval_halfwords(eval_micromips_instruction, halfwords, r, m, s)
+    };
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+
+    // addiu v0,a0,5 is fetched as two halfwords
+    r.write_register(4, 10);
+    assert!(run(&[0x3044, 0x0005], r, m, s) == (CPUEvent::Nothing, 4));
+    assert_eq!(r.read_register(2), 15);
+
+    // lbu16 v0,-1(a0), the offset 0xF is -1 only for LBU16
+    m.write_byte(DATA + 0xF, 0xAB);
+    r.write_register(4, DATA + 0x10);
+    assert!(run(&[0x094F], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!(r.read_register(2), 0xAB);
+    // sb16 v0,15(a0)
+    r.write_register(2, 0xCD);
+    assert!(run(&[0x894F], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!(m.read_byte(DATA + 0x1F), 0xCD);
+    assert_eq!(m.read_byte(DATA + 0xF), 0xAB);
+
+    // addiusp -16 and addiusp 1024, which is encoded as 0
+    r.write_register(STACK_POINTER, 0x7ff00000);
+    assert!(run(&[0x4FF9], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!(r.read_register(STACK_POINTER), 0x7FEF_FFF0);
+    assert!(run(&[0x4C01], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!(r.read_register(STACK_POINTER), 0x7FF0_03F0);
+
+    // movep a1,a2,s0,s4
+    r.write_register(16, 0x11);
+    r.write_register(20, 0x22);
+    assert!(run(&[0x8478], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!((r.read_register(5), r.read_register(6)), (0x11, 0x22));
+
+    // lwm16 {s0,s1,ra},8(sp)
+    r.write_register(STACK_POINTER, DATA + 0x100);
+    for (i, value) in [1, 2, 3].iter().enumerate() {
+        m.write_word(DATA + 0x108 + i as u32 * 4, *value);
+    }
+    assert!(run(&[0x4512], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!(r.read_register(16), 1);
+    assert_eq!(r.read_register(17), 2);
+    assert_eq!(r.read_register(RETURN_ADDRESS), 3);
+    // the address wraps around to the unmapped page 0
+    r.write_register(STACK_POINTER, 0xFFFF_FFF8);
+    assert!(
+        run(&[0x4512], r, m, s) ==
+            (CPUEvent::Exception(ExceptionKind::SegmentationFault(0)), 2)
+    );
+
+    // jalr16 a0 has a 32 bit delay slot, jalrs16 a0 a 16 bit one
+    r.write_register(4, 0x0040_1001);
+    assert!(run(&[0x45C4], r, m, s) == (CPUEvent::FlowChangeDelayed(0x0040_1001), 2));
+    assert_eq!(r.read_register(RETURN_ADDRESS), CODE + 7);
+    assert!(run(&[0x45E4], r, m, s) == (CPUEvent::FlowChangeDelayed(0x0040_1001), 2));
+    assert_eq!(r.read_register(RETURN_ADDRESS), CODE + 5);
+
+    // jraddiusp 8 with the stack pointer wrapping around
+    r.write_register(STACK_POINTER, 0xFFFF_FFFC);
+    assert!(run(&[0x4702], r, m, s) == (CPUEvent::FlowChangeImmediate(CODE + 5), 2));
+    assert_eq!(r.read_register(STACK_POINTER), 4);
+}
@@ -0,0 +1,479 @@
+//! MIPS16e instruction set. Instructions are 16 bits wide and only 8 GPRs are directly accessible.
+//! The EXTEND prefix widens immediate of the following instruction to 16 bits, JAL and JALX are
+//! the only other 32 bit instructions. Branches have no delay slot, jumps have a 16 bit one.
+
+use cpu::bitutils::{bit_mask, sign_extend};
+use cpu::compressed::*;
+use cpu::event::{CPUEvent, ExceptionKind};
+use cpu::instructions::eval_instruction;
+use cpu::registers::{get_register_name, RegisterFile, RETURN_ADDRESS, STACK_POINTER};
+use memory::{Access, Memory};
+use syscalls::System;
+
+/// GPRs accessible by 3 bit register fields
+const REGISTER_MAP: [u32; 8] = [16, 17, 2, 3, 4, 5, 6, 7];
+/// Implicit destination of comparisons and condition of BTEQZ and BTNEZ
+const T8: u32 = 24;
+
+const EXTEND: u32 = 0b11110;
+
+pub fn eval_mips16_instruction(
+    address: u32,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+    system: &mut System,
+) -> (CPUEvent, u32) {
+    macro_rules! itrace {
+        ($fmt:expr, $($arg:tt)*) => (
+            trace!(concat!("0x{:x}:\t", $fmt), address, $($arg)*);
+        );
+    }
+
+    // evaluates equivalent MIPS32 instruction
+    macro_rules! mips32 {
+        ($instruction:expr) => (
+            eval_instruction($instruction, registers, memory, system)
+        );
+    }
+
+    let first = memory.fetch_halfword(address);
+    let (extend, instruction, size) = if first >> 11 == EXTEND {
+        (Some(first & 0x7FF), memory.fetch_halfword(address + 2), 4)
+    } else {
+        (None, first, 2)
+    };
+
+    let opcode = instruction >> 11;
+    let rx = REGISTER_MAP[((instruction >> 8) & 0b111) as usize];
+    let ry = REGISTER_MAP[((instruction >> 5) & 0b111) as usize];
+    let rz = REGISTER_MAP[((instruction >> 2) & 0b111) as usize];
+
+    // Immediate field `bits` wide and scaled by `shift`. Extended instructions have a signed 16 bit
+    // immediate instead, which is never scaled.
+    let immediate = |bits: u8, shift: u32, signed: bool| match extend {
+        Some(e) => {
+            let i = ((e & 0x1F) << 11) | (e & 0x7E0) | (instruction & 0x1F);
+            sign_extend(i, 16) as u32
+        }
+        None => {
+            let i = instruction & bit_mask(bits as u32);
+            let i = if signed { sign_extend(i, bits) as u32 } else { i };
+            i << shift
+        }
+    };
+    // branch targets, the extended offset is scaled as well
+    let branch_target = |bits: u8| {
+        let offset = if extend.is_some() {
+            immediate(bits, 1, true) << 1
+        } else {
+            immediate(bits, 1, true)
+        };
+        (address + size).wrapping_add(offset) | ISA_MODE_BIT
+    };
+    // PC relative instructions use the word aligned address of the instruction or the EXTEND
+    let pc_relative = |offset: u32| (address & !0b11).wrapping_add(offset);
+
+    // only immediate instructions can be extended
+    let extendable = match opcode {
+        0b00011 | 0b11100 | 0b11101 => false,
+        0b01100 => (instruction >> 8) & 0b111 <= 0b100,
+        _ => true,
+    };
+    if extend.is_some() && !extendable {
+        itrace!("extended instruction 0x{:04x} can't be extended", instruction);
+        return (CPUEvent::Exception(ExceptionKind::ReservedInstruction), size);
+    }
+
+    let event = match opcode {
+        // ADDIUSP
+        0b00000 => mips32!(encode_immediate(OP_ADDIU, STACK_POINTER, rx, immediate(8, 2, false))),
+        // ADDIUPC
+        0b00001 => {
+            let r = pc_relative(immediate(8, 2, false));
+            itrace!("addiupc\t{},0x{:x}", get_register_name(rx), r);
+            registers.write_register(rx, r);
+            CPUEvent::Nothing
+        }
+        // B
+        0b00010 => {
+            let target = branch_target(11);
+            itrace!("b\t0x{:x}", target);
+            CPUEvent::FlowChangeImmediate(target)
+        }
+        // JAL, JALX
+        0b00011 => {
+            let exchange = instruction & 0x400 != 0;
+            let index = ((instruction & 0x1F) << 21) | (((instruction >> 5) & 0x1F) << 16) |
+                memory.fetch_halfword(address + 2);
+            let target = ((address + 4) & 0xF0_00_00_00) | (index << 2);
+            let target = if exchange { target } else { target | ISA_MODE_BIT };
+            itrace!("{}\t0x{:x}", if exchange { "jalx" } else { "jal" }, target);
+            // the delay slot is 16 bits wide
+            registers.write_address(RETURN_ADDRESS, (address + 6) | ISA_MODE_BIT);
+            return (CPUEvent::FlowChangeDelayed(target), 4);
+        }
+        // BEQZ, BNEZ
+        0b00100 | 0b00101 => {
+            let target = branch_target(8);
+            let zero = registers.read_register(rx) == 0;
+            let jump = zero == (opcode == 0b00100);
+            itrace!(
+                "{}\t{},0x{:x} - jumped={}",
+                if opcode == 0b00100 { "beqz" } else { "bnez" },
+                get_register_name(rx),
+                target,
+                jump
+            );
+            if jump {
+                CPUEvent::FlowChangeImmediate(target)
+            } else {
+                CPUEvent::Nothing
+            }
+        }
+        // SLL, SRL, SRA
+        0b00110 => {
+            let shift = match extend {
+                Some(e) => (e >> 6) & 0x1F,
+                None => {
+                    match (instruction >> 2) & 0b111 {
+                        0 => 8,
+                        s => s,
+                    }
+                }
+            };
+            let funct = match instruction & 0b11 {
+                0b00 => FUNCT_SLL,
+                0b10 => FUNCT_SRL,
+                0b11 => FUNCT_SRA,
+                _ => return (CPUEvent::Exception(ExceptionKind::ReservedInstruction), size),
+            };
+            mips32!(encode_register(OP_SPECIAL, 0, ry, rx, shift, funct))
+        }
+        // ADDIU ry, rx, immediate
+        0b01000 if instruction & 0x10 == 0 => {
+            let i = match extend {
+                Some(e) => {
+                    sign_extend(((e & 0xF) << 11) | (e & 0x7F0) | (instruction & 0xF), 15)
+                }
+                None => sign_extend(instruction & 0xF, 4),
+            };
+            mips32!(encode_immediate(OP_ADDIU, rx, ry, i as u32))
+        }
+        // ADDIU rx, immediate
+        0b01001 => mips32!(encode_immediate(OP_ADDIU, rx, rx, immediate(8, 0, true))),
+        // SLTI, SLTIU
+        0b01010 => mips32!(encode_immediate(OP_SLTI, rx, T8, immediate(8, 0, false))),
+        0b01011 => mips32!(encode_immediate(OP_SLTIU, rx, T8, immediate(8, 0, false))),
+        // I8 group
+        0b01100 => {
+            match (instruction >> 8) & 0b111 {
+                // BTEQZ, BTNEZ
+                0b000 | 0b001 => {
+                    let target = branch_target(8);
+                    let equal = instruction & 0x100 == 0;
+                    let jump = (registers.read_register(T8) == 0) == equal;
+                    itrace!(
+                        "{}\t0x{:x} - jumped={}",
+                        if equal { "bteqz" } else { "btnez" },
+                        target,
+                        jump
+                    );
+                    if jump {
+                        CPUEvent::FlowChangeImmediate(target)
+                    } else {
+                        CPUEvent::Nothing
+                    }
+                }
+                // SW ra, offset(sp)
+                0b010 => {
+                    mips32!(encode_immediate(
+                        OP_SW,
+                        STACK_POINTER,
+                        RETURN_ADDRESS,
+                        immediate(8, 2, false),
+                    ))
+                }
+                // ADJSP
+                0b011 => {
+                    mips32!(encode_immediate(
+                        OP_ADDIU,
+                        STACK_POINTER,
+                        STACK_POINTER,
+                        immediate(8, 3, true),
+                    ))
+                }
+                // SAVE, RESTORE
+                0b100 => save_restore(instruction, extend, registers, memory),
+                // MOVE r32, rz
+                0b101 => {
+                    let r32 = ((instruction >> 5) & 0b111) | (instruction & 0b11000);
+                    let rz = REGISTER_MAP[(instruction & 0b111) as usize];
+                    mips32!(encode_register(OP_SPECIAL, rz, 0, r32, 0, FUNCT_ADDU))
+                }
+                // MOVE ry, r32
+                0b111 => {
+                    mips32!(encode_register(OP_SPECIAL, instruction & 0x1F, 0, ry, 0, FUNCT_ADDU))
+                }
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        // LI, CMPI
+        0b01101 => mips32!(encode_immediate(OP_ORI, 0, rx, immediate(8, 0, false))),
+        0b01110 => mips32!(encode_immediate(OP_XORI, rx, T8, immediate(8, 0, false))),
+        // loads and stores with base register rx
+        0b10000 => mips32!(encode_immediate(OP_LB, rx, ry, immediate(5, 0, false))),
+        0b10001 => mips32!(encode_immediate(OP_LH, rx, ry, immediate(5, 1, false))),
+        0b10011 => mips32!(encode_immediate(OP_LW, rx, ry, immediate(5, 2, false))),
+        0b10100 => mips32!(encode_immediate(OP_LBU, rx, ry, immediate(5, 0, false))),
+        0b10101 => mips32!(encode_immediate(OP_LHU, rx, ry, immediate(5, 1, false))),
+        0b11000 => mips32!(encode_immediate(OP_SB, rx, ry, immediate(5, 0, false))),
+        0b11001 => mips32!(encode_immediate(OP_SH, rx, ry, immediate(5, 1, false))),
+        0b11011 => mips32!(encode_immediate(OP_SW, rx, ry, immediate(5, 2, false))),
+        // LW rx, offset(sp), SW rx, offset(sp)
+        0b10010 => mips32!(encode_immediate(OP_LW, STACK_POINTER, rx, immediate(8, 2, false))),
+        0b11010 => mips32!(encode_immediate(OP_SW, STACK_POINTER, rx, immediate(8, 2, false))),
+        // LW rx, offset(pc)
+        0b10110 => {
+            let addr = pc_relative(immediate(8, 2, false));
+            if let Err(fault) = memory.check_access(addr, 4, Access::Read) {
+                return (CPUEvent::Exception(fault), size);
+            }
+            let r = memory.read_word(addr);
+            itrace!("lw\t{},0x{:x} - data=0x{:08x}", get_register_name(rx), addr, r);
+            registers.write_register(rx, r);
+            CPUEvent::Nothing
+        }
+        // ADDU, SUBU
+        0b11100 => {
+            match instruction & 0b11 {
+                0b01 => mips32!(encode_register(OP_SPECIAL, rx, ry, rz, 0, FUNCT_ADDU)),
+                0b11 => mips32!(encode_register(OP_SPECIAL, rx, ry, rz, 0, FUNCT_SUBU)),
+                _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+            }
+        }
+        // RR group
+        0b11101 => {
+            let funct = instruction & 0x1F;
+            let special = |rs, rt, rd, funct| encode_register(OP_SPECIAL, rs, rt, rd, 0, funct);
+            match funct {
+                // JR, JRC, JALR, JALRC
+                0b00000 => {
+                    let compact = instruction & 0x80 != 0;
+                    let link = instruction & 0x40 != 0;
+                    let source = if instruction & 0x20 != 0 {
+                        RETURN_ADDRESS
+                    } else {
+                        rx
+                    };
+                    if link && source == RETURN_ADDRESS {
+                        return (CPUEvent::Exception(ExceptionKind::ReservedInstruction), size);
+                    }
+                    let target = registers.read_register(source);
+                    itrace!(
+                        "j{}r{}\t{} - target=0x{:x}",
+                        if link { "al" } else { "" },
+                        if compact { "c" } else { "" },
+                        get_register_name(source),
+                        target
+                    );
+                    if compact {
+                        if link {
+                            registers.write_address(RETURN_ADDRESS, (address + 2) | ISA_MODE_BIT);
+                        }
+                        CPUEvent::FlowChangeImmediate(target)
+                    } else {
+                        if link {
+                            registers.write_address(RETURN_ADDRESS, (address + 4) | ISA_MODE_BIT);
+                        }
+                        CPUEvent::FlowChangeDelayed(target)
+                    }
+                }
+                // SDBBP
+                0b00001 => {
+                    itrace!("sdbbp\t{}", (instruction >> 5) & 0x3F);
+                    CPUEvent::Exception(ExceptionKind::Breakpoint)
+                }
+                // SLT, SLTU
+                0b00010 => mips32!(special(rx, ry, T8, FUNCT_SLT)),
+                0b00011 => mips32!(special(rx, ry, T8, FUNCT_SLTU)),
+                // SLLV, SRLV, SRAV
+                0b00100 => mips32!(special(rx, ry, ry, FUNCT_SLLV)),
+                0b00110 => mips32!(special(rx, ry, ry, FUNCT_SRLV)),
+                0b00111 => mips32!(special(rx, ry, ry, FUNCT_SRAV)),
+                // BREAK
+                0b00101 => mips32!(special(0, 0, 0, FUNCT_BREAK)),
+                // CMP, NEG
+                0b01010 => mips32!(special(rx, ry, T8, FUNCT_XOR)),
+                0b01011 => mips32!(special(0, ry, rx, FUNCT_SUBU)),
+                // AND, OR, XOR, NOT
+                0b01100 => mips32!(special(rx, ry, rx, FUNCT_AND)),
+                0b01101 => mips32!(special(rx, ry, rx, FUNCT_OR)),
+                0b01110 => mips32!(special(rx, ry, rx, FUNCT_XOR)),
+                0b01111 => mips32!(special(ry, 0, rx, FUNCT_NOR)),
+                // MFHI, MFLO
+                0b10000 => mips32!(special(0, 0, rx, FUNCT_MFHI)),
+                0b10010 => mips32!(special(0, 0, rx, FUNCT_MFLO)),
+                // ZEB, ZEH, SEB, SEH
+                0b10001 => {
+                    match (instruction >> 5) & 0b111 {
+                        0b000 => mips32!(encode_immediate(OP_ANDI, rx, rx, 0xFF)),
+                        0b001 => mips32!(encode_immediate(OP_ANDI, rx, rx, 0xFFFF)),
+                        0b100 => {
+                            mips32!(encode_register(OP_SPECIAL3, 0, rx, rx, BSHFL_SEB, FUNCT_BSHFL))
+                        }
+                        0b101 => {
+                            mips32!(encode_register(OP_SPECIAL3, 0, rx, rx, BSHFL_SEH, FUNCT_BSHFL))
+                        }
+                        _ => CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+                    }
+                }
+                // MULT, MULTU, DIV, DIVU
+                0b11000 => mips32!(special(rx, ry, 0, FUNCT_MULT)),
+                0b11001 => mips32!(special(rx, ry, 0, FUNCT_MULTU)),
+                0b11010 => mips32!(special(rx, ry, 0, FUNCT_DIV)),
+                0b11011 => mips32!(special(rx, ry, 0, FUNCT_DIVU)),
+                _ => {
+                    itrace!("reserved RR instruction 0x{:04x}", instruction);
+                    CPUEvent::Exception(ExceptionKind::ReservedInstruction)
+                }
+            }
+        }
+        // 64 bit instructions and EXTEND without an instruction to extend
+        _ => {
+            itrace!("reserved instruction 0x{:04x}", instruction);
+            CPUEvent::Exception(ExceptionKind::ReservedInstruction)
+        }
+    };
+
+    (event, size)
+}
+
+/// SAVE and RESTORE. SAVE stores arguments to the caller's frame, pushes return address and the
+/// selected static registers and allocates the stack frame. RESTORE does the opposite, except
+/// for the arguments.
+fn save_restore(
+    instruction: u32,
+    extend: Option<u32>,
+    registers: &mut RegisterFile,
+    memory: &mut Memory,
+) -> CPUEvent {
+    let save = instruction & 0x80 != 0;
+    let (xsregs, aregs, frame_size) = match extend {
+        Some(e) => {
+            let frame = (((e >> 4) & 0xF) << 4) | (instruction & 0xF);
+            ((e >> 8) & 0b111, e & 0xF, frame << 3)
+        }
+        None => {
+            match instruction & 0xF {
+                0 => (0, 0, 128),
+                f => (0, 0, f << 3),
+            }
+        }
+    };
+    // number of arguments stored to the caller's frame and of static arguments saved in this one
+    let (arguments, static_arguments) = match aregs {
+        0..=3 => (0, aregs),
+        4..=7 => (1, aregs - 4),
+        8..=10 => (2, aregs - 8),
+        11 => (0, 4),
+        12 | 13 => (3, aregs - 12),
+        14 => (4, 0),
+        _ => return CPUEvent::Exception(ExceptionKind::ReservedInstruction),
+    };
+
+    // registers in order from the top of the frame
+    let mut saved = Vec::new();
+    if instruction & 0x40 != 0 {
+        saved.push(RETURN_ADDRESS);
+    }
+    if xsregs == 7 {
+        saved.push(30);
+    }
+    saved.extend((18..18 + xsregs.min(6)).rev());
+    if instruction & 0x10 != 0 {
+        saved.push(17);
+    }
+    if instruction & 0x20 != 0 {
+        saved.push(16);
+    }
+    saved.extend((8 - static_arguments..8).rev());
+
+    let sp = registers.read_register(STACK_POINTER);
+    trace!(
+        "0x{:x}:\t{}\t{:?},{} - sp=0x{:x}",
+        registers.get_pc(),
+        if save { "save" } else { "restore" },
+        saved,
+        frame_size,
+        sp
+    );
+    // the whole frame is checked upfront, so that a fault doesn't leave it half written
+    let (start, end, access) = if save {
+        (sp.wrapping_sub(saved.len() as u32 * 4), sp.wrapping_add(arguments * 4), Access::Write)
+    } else {
+        let frame = sp.wrapping_add(frame_size);
+        (frame.wrapping_sub(saved.len() as u32 * 4), frame, Access::Read)
+    };
+    if end > start {
+        if let Err(fault) = memory.check_access(start, end - start, access) {
+            return CPUEvent::Exception(fault);
+        }
+    }
+    if save {
+        for i in 0..arguments {
+            memory.write_word(sp.wrapping_add(i * 4), registers.read_register(4 + i));
+        }
+        let mut top = sp;
+        for r in saved {
+            top = top.wrapping_sub(4);
+            memory.write_word(top, registers.read_register(r));
+        }
+        registers.write_register(STACK_POINTER, sp.wrapping_sub(frame_size));
+    } else {
+        let frame = sp.wrapping_add(frame_size);
+        let mut top = frame;
+        for r in saved {
+            top = top.wrapping_sub(4);
+            let value = memory.read_word(top);
+            registers.write_register(r, value);
+        }
+        registers.write_register(STACK_POINTER, frame);
+    }
+
+    CPUEvent::Nothing
+}
+
+#[test]
+fn test_mips16() {
+    use cpu::instructions::{test_machine, TEST_CODE as CODE};
+
+    let (mut registers, mut memory, mut system) = test_machine();
+    let run = |halfwords: &[u32], r: &mut RegisterFile, m: &mut Memory, s: &mut System| {
+        eval_halfwords(eval_mips16_instruction, halfwords, r, m, s)
+    };
+    let (r, m, s) = (&mut registers, &mut memory, &mut system);
+
+    // addiu v0,sp,16 scales the immediate, the extended addiu v0,sp,-4 doesn't
+    assert!(run(&[0x0204], r, m, s) == (CPUEvent::Nothing, 2));
+    assert_eq!(r.read_register(2), 0x7ff00010);
+    assert!(run(&[0xF7FF, 0x021C], r, m, s) == (CPUEvent::Nothing, 4));
+    assert_eq!(r.read_register(2), 0x7FEF_FFFC);
+
+    // li v0,0x1234 and addiu v0,-0x100 with EXTEND
+    assert!(run(&[0xF222, 0x6A14], r, m, s) == (CPUEvent::Nothing, 4));
+    assert_eq!(r.read_register(2), 0x1234);
+    assert!(run(&[0xF71F, 0x4A00], r, m, s) == (CPUEvent::Nothing, 4));
+    assert_eq!(r.read_register(2), 0x1134);
+    // addu can't be extended
+    assert!(
+        run(&[0xF000, 0xE049], r, m, s) ==
+            (CPUEvent::Exception(ExceptionKind::ReservedInstruction), 4)
+    );
+
+    // jalr a0 returns after its 16 bit delay slot, jalrc a0 right after itself
+    r.write_register(4, 0x0040_1001);
+    assert!(run(&[0xEC40], r, m, s) == (CPUEvent::FlowChangeDelayed(0x0040_1001), 2));
+    assert_eq!(r.read_register(RETURN_ADDRESS), CODE + 5);
+    assert!(run(&[0xECC0], r, m, s) == (CPUEvent::FlowChangeImmediate(0x0040_1001), 2));
+    assert_eq!(r.read_register(RETURN_ADDRESS), CODE + 3);
+}
@@ -2,10 +2,21 @@
 //! the system running on it.
 
 pub mod bitutils;
+pub mod block_cache;
+pub mod branch;
+pub mod compressed;
 pub mod control;
+pub mod decoder;
+pub mod doubleword;
+pub mod dsp;
+pub mod emitter;
 pub mod event;
 pub mod instructions;
 pub mod registers;
 pub mod watchdog;
 pub mod instructions_constants;
 pub mod float;
+pub mod isa;
+pub mod jit;
+pub mod micromips;
+pub mod mips16;
@@ -1,5 +1,7 @@
 //! All register operations are performed here.
 
+use cpu::float::{FCSR_ABS2008, FCSR_NAN2008};
+use cpu::isa::{Abi, CompressedIsa, Isa, IsaRevision};
 use cpu::watchdog::Watchdog;
 
 pub const V0: u32 = 2;
@@ -9,24 +11,64 @@ pub const A3: u32 = 7;
 pub const STACK_POINTER: u32 = 29;
 pub const RETURN_ADDRESS: u32 = 31;
 
+// FPU control registers accessible by CFC1 and CTC1
+pub const FCR_FIR: u32 = 0;
+pub const FCR_FCCR: u32 = 25;
+pub const FCR_FEXR: u32 = 26;
+pub const FCR_FENR: u32 = 28;
+pub const FCR_FCSR: u32 = 31;
+
+/// FCSR bits writable by software. NAN2008 and ABS2008 are fixed by the emulated ISA revision.
+const FCSR_WRITABLE_MASK: u32 = 0xFF83_FFFF;
+/// Flag (6:2) and Cause (17:12) fields, as seen through FEXR.
+const FEXR_MASK: u32 = 0x0003_F07C;
+/// RM (1:0) and Enables (11:7) fields, as seen through FENR.
+const FENR_MASK: u32 = 0x0000_0F83;
+
 
 pub struct RegisterFile<'a> {
-    gpr: [u32; 31],
-    fpr: [f32; 32],
+    /// General purpose registers are 64 bits wide. Results of 32 bit operations are stored sign
+    /// extended, so O32 programs never see the upper half.
+    gpr: [u64; 31],
+    fpr: [u64; 32],
+    fpu_64bit: bool,
+    fcsr: u32,
+    isa_revision: IsaRevision,
+    compressed_isa: Option<CompressedIsa>,
+    abi: Abi,
+    /// Upper 32 bits of all n64 addresses, see `write_address`
+    address_base: u64,
     pc: u32,
-    hi: u32,
-    lo: u32,
+    /// HI and LO registers as ac0 and DSP accumulators ac1-ac3
+    hi: [u64; 4],
+    lo: [u64; 4],
+    dsp_control: u32,
+    /// LLbit and the address loaded by the last LL
+    ll_bit: bool,
+    ll_address: u32,
+    /// thread pointer set by `set_thread_area`, read by RDHWR
+    user_local: u32,
     watchdog: Option<&'a Watchdog>,
 }
 
 impl<'a> RegisterFile<'a> {
     pub fn new(stack_pointer: u32) -> RegisterFile<'a> {
         let mut r = RegisterFile {
-            gpr: [0u32; 31],
-            fpr: [0f32; 32],
+            gpr: [0u64; 31],
+            fpr: [0u64; 32],
+            fpu_64bit: false,
+            fcsr: 0u32,
+            isa_revision: IsaRevision::R2,
+            compressed_isa: None,
+            abi: Abi::O32,
+            address_base: 0u64,
             pc: 0u32,
-            hi: 0u32,
-            lo: 0u32,
+            hi: [0u64; 4],
+            lo: [0u64; 4],
+            dsp_control: 0u32,
+            ll_bit: false,
+            ll_address: 0u32,
+            user_local: 0u32,
             watchdog: None,
         };
         r.write_register(29, stack_pointer);
@@ -37,7 +79,64 @@ impl<'a> RegisterFile<'a> {
         self.watchdog = Some(watchdog);
     }
 
+    pub fn get_isa_revision(&self) -> IsaRevision {
+        self.isa_revision
+    }
+
+    /// Selects the emulated ISA revision. Release 6 FPU always has 64 bit registers and uses
+    /// IEEE 754-2008 NaN encoding and sign operations.
+    pub fn set_isa_revision(&mut self, revision: IsaRevision) {
+        self.isa_revision = revision;
+        if revision.is_r6() {
+            self.fpu_64bit = true;
+            self.fcsr |= FCSR_NAN2008 | FCSR_ABS2008;
+        }
+    }
+
+    pub fn get_compressed_isa(&self) -> Option<CompressedIsa> {
+        self.compressed_isa
+    }
+
+    /// Selects the compressed ISA, release 6 has none.
+    pub fn set_compressed_isa(&mut self, isa: Option<CompressedIsa>) {
+        self.compressed_isa = if self.isa_revision.is_r6() { None } else { isa };
+    }
+
+    pub fn get_abi(&self) -> Abi {
+        self.abi
+    }
+
+    /// Selects the ABI. n64 programs live in a 4GB window of the address space, `address_base`
+    /// holds its upper 32 bits.
+    pub fn set_abi(&mut self, abi: Abi, address_base: u64) {
+        self.abi = abi;
+        self.address_base = if abi == Abi::N64 { address_base } else { 0 };
+    }
+
+    pub fn get_isa(&self) -> Isa {
+        Isa {
+            revision: self.isa_revision,
+            compressed: self.compressed_isa,
+            abi: self.abi,
+        }
+    }
+
+    /// Raw pointer to the general purpose registers for translated code, `$1` is the first one.
+    pub fn get_gpr_ptr(&mut self) -> *mut u64 {
+        self.gpr.as_mut_ptr()
+    }
+
+    /// Reads the lower 32 bits of a register, as used by all 32 bit instructions.
     pub fn read_register(&self, id: u32) -> u32 {
+        self.read_register64(id) as u32
+    }
+
+    /// Writes a 32 bit result, which is sign extended to the full register width.
+    pub fn write_register(&mut self, id: u32, value: u32) {
+        self.write_register64(id, value as i32 as i64 as u64);
+    }
+
+    pub fn read_register64(&self, id: u32) -> u64 {
         let res = if id == 0 {
             0
         } else {
@@ -46,29 +145,157 @@ impl<'a> RegisterFile<'a> {
 
         // runtime check
         if let Some(watchdog) = self.watchdog {
-            watchdog.check_read(id, res);
+            watchdog.check_read(id, res as u32);
         }
 
         res
     }
 
-    pub fn write_register(&mut self, id: u32, value: u32) {
+    pub fn write_register64(&mut self, id: u32, value: u64) {
         // runtime check
         if let Some(watchdog) = self.watchdog {
-            watchdog.check_write(id, value);
+            watchdog.check_write(id, value as u32);
         }
 
+        // O32 runs on a 32 bit CPU, the upper half is always just the sign extension
+        let value = if self.abi.is_64bit() {
+            value
+        } else {
+            value as u32 as i32 as i64 as u64
+        };
+
         if id != 0 {
             self.gpr[id as usize - 1] = value;
         }
     }
 
-    pub fn read_fpr(&self, id: u32) -> f32 {
-        self.fpr[id as usize]
+    /// Writes an address in the emulated memory to a register. Memory is indexed by 32 bit
+    /// offsets, n64 pointers get the upper half of the address window back.
+    pub fn write_address(&mut self, id: u32, address: u32) {
+        if self.abi == Abi::N64 {
+            let value = self.address_base | address as u64;
+            self.write_register64(id, value);
+        } else {
+            self.write_register(id, address);
+        }
     }
 
-    pub fn write_fpr(&mut self, id: u32, val: f32) {
-        self.fpr[id as usize] = val;
+    /// FPU registers are stored as raw bits. With FR=0 (the default for O32), there are 32
+    /// registers 32 bits wide and 64 bit values are stored in even-odd pairs. With FR=1, every
+    /// register is 64 bits wide.
+    pub fn set_fpu_64bit(&mut self, fr: bool) {
+        self.fpu_64bit = fr;
+    }
+
+    pub fn is_fpu_64bit(&self) -> bool {
+        self.fpu_64bit
+    }
+
+    pub fn read_fpr_word(&self, id: u32) -> u32 {
+        self.fpr[id as usize] as u32
+    }
+
+    pub fn write_fpr_word(&mut self, id: u32, value: u32) {
+        if self.fpu_64bit {
+            // upper half is UNPREDICTABLE after 32 bit writes, we leave it as it was
+            let upper = self.fpr[id as usize] & 0xFF_FF_FF_FF_00_00_00_00;
+            self.fpr[id as usize] = upper | value as u64;
+        } else {
+            self.fpr[id as usize] = value as u64;
+        }
+    }
+
+    /// Upper 32 bits of a 64 bit value, as accessed by MFHC1 and MTHC1.
+    pub fn read_fpr_high(&self, id: u32) -> u32 {
+        if self.fpu_64bit {
+            (self.fpr[id as usize] >> 32) as u32
+        } else {
+            self.fpr[(id | 1) as usize] as u32
+        }
+    }
+
+    pub fn write_fpr_high(&mut self, id: u32, value: u32) {
+        if self.fpu_64bit {
+            let lower = self.fpr[id as usize] & 0xFF_FF_FF_FF;
+            self.fpr[id as usize] = ((value as u64) << 32) | lower;
+        } else {
+            self.fpr[(id | 1) as usize] = value as u64;
+        }
+    }
+
+    pub fn read_fpr_doubleword(&self, id: u32) -> u64 {
+        if self.fpu_64bit {
+            self.fpr[id as usize]
+        } else {
+            ((self.read_fpr_high(id) as u64) << 32) | (self.read_fpr_word(id & !1) as u64)
+        }
+    }
+
+    pub fn write_fpr_doubleword(&mut self, id: u32, value: u64) {
+        if self.fpu_64bit {
+            self.fpr[id as usize] = value;
+        } else {
+            self.fpr[(id & !1) as usize] = value & 0xFF_FF_FF_FF;
+            self.fpr[(id | 1) as usize] = value >> 32;
+        }
+    }
+
+    pub fn read_fcsr(&self) -> u32 {
+        self.fcsr
+    }
+
+    pub fn write_fcsr(&mut self, value: u32) {
+        self.fcsr = value;
+    }
+
+    /// Reads FPU control register as CFC1 does. FCCR, FEXR and FENR are just different views of
+    /// fields stored in FCSR. Unimplemented control registers read as zero.
+    pub fn read_fpu_control(&self, id: u32) -> u32 {
+        match id {
+            FCR_FIR => {
+                // S, D, PS, W and L formats are implemented
+                let mut fir = 0x0037_0000;
+                if self.fpu_64bit {
+                    fir |= 1 << 22;
+                }
+                if self.fcsr & FCSR_NAN2008 != 0 {
+                    fir |= 1 << 23;
+                }
+                fir
+            }
+            FCR_FCCR => ((self.fcsr >> 24) & 0xFE) | ((self.fcsr >> 23) & 1),
+            FCR_FEXR => self.fcsr & FEXR_MASK,
+            FCR_FENR => (self.fcsr & FENR_MASK) | ((self.fcsr >> 22) & 0b100),
+            FCR_FCSR => self.fcsr,
+            _ => 0,
+        }
+    }
+
+    /// Writes FPU control register as CTC1 does. Writes to FIR and to unimplemented control
+    /// registers are ignored.
+    pub fn write_fpu_control(&mut self, id: u32, value: u32) {
+        let (mask, value) = match id {
+            FCR_FCCR => (0xFE80_0000, ((value & 0xFE) << 24) | ((value & 1) << 23)),
+            FCR_FEXR => (FEXR_MASK, value),
+            FCR_FENR => (FENR_MASK | (1 << 24), value | ((value & 0b100) << 22)),
+            FCR_FCSR => (FCSR_WRITABLE_MASK, value),
+            _ => return,
+        };
+        self.fcsr = (self.fcsr & !mask) | (value & mask);
+    }
+
+    /// Reads one of the eight floating point condition codes. CC0 is bit 23 of FCSR, the others
+    /// are stored in bits 25-31.
+    pub fn read_fcc(&self, cc: u32) -> bool {
+        self.fcsr & fcc_mask(cc) != 0
+    }
+
+    pub fn write_fcc(&mut self, cc: u32, value: bool) {
+        if value {
+            self.fcsr |= fcc_mask(cc);
+        } else {
+            self.fcsr &= !fcc_mask(cc);
+        }
     }
 
     pub fn get_pc(&self) -> u32 {
@@ -79,32 +306,101 @@ impl<'a> RegisterFile<'a> {
         self.pc = value;
     }
 
-    pub fn read_hi(&self) -> u32 {
-        self.hi
+    pub fn read_hi(&self, ac: u32) -> u32 {
+        self.hi[ac as usize] as u32
+    }
+
+    pub fn read_lo(&self, ac: u32) -> u32 {
+        self.lo[ac as usize] as u32
+    }
+
+    pub fn write_hi(&mut self, ac: u32, value: u32) {
+        self.hi[ac as usize] = value as i32 as i64 as u64;
     }
 
-    pub fn read_lo(&self) -> u32 {
-        self.lo
+    pub fn write_lo(&mut self, ac: u32, value: u32) {
+        self.lo[ac as usize] = value as i32 as i64 as u64;
     }
 
-    pub fn write_hi(&mut self, value: u32) {
-        self.hi = value;
+    /// Full 64 bit HI, as written by doubleword multiply and divide
+    pub fn read_hi64(&self, ac: u32) -> u64 {
+        self.hi[ac as usize]
     }
 
-    pub fn write_lo(&mut self, value: u32) {
-        self.lo = value;
+    pub fn read_lo64(&self, ac: u32) -> u64 {
+        self.lo[ac as usize]
+    }
+
+    pub fn write_hi64(&mut self, ac: u32, value: u64) {
+        self.hi[ac as usize] = value;
+    }
+
+    pub fn write_lo64(&mut self, ac: u32, value: u64) {
+        self.lo[ac as usize] = value;
+    }
+
+    /// Lower halves of HI and LO as one 64 bit value used by multiply-accumulate instructions
+    pub fn read_accumulator(&self, ac: u32) -> u64 {
+        ((self.read_hi(ac) as u64) << 32) | self.read_lo(ac) as u64
+    }
+
+    pub fn write_accumulator(&mut self, ac: u32, value: u64) {
+        self.write_hi(ac, (value >> 32) as u32);
+        self.write_lo(ac, value as u32);
+    }
+
+    pub fn read_dsp_control(&self) -> u32 {
+        self.dsp_control
+    }
+
+    pub fn write_dsp_control(&mut self, value: u32) {
+        self.dsp_control = value;
+    }
+
+    /// Sets the LLbit after LL from the address.
+    pub fn set_link(&mut self, address: u32) {
+        self.ll_bit = true;
+        self.ll_address = address;
+    }
+
+    pub fn get_user_local(&self) -> u32 {
+        self.user_local
+    }
+
+    pub fn set_user_local(&mut self, user_local: u32) {
+        self.user_local = user_local;
+    }
+
+    /// Clears the LLbit. This happens on SC and whenever the kernel takes over, i.e. on syscalls
+    /// and signal delivery.
+    pub fn clear_link(&mut self) {
+        self.ll_bit = false;
+    }
+
+    /// Address of the last LL, when the LLbit is still set.
+    pub fn get_link(&self) -> Option<u32> {
+        if self.ll_bit { Some(self.ll_address) } else { None }
     }
 
     pub fn print_registers(&self) {
         println!("\nREGISTERS:");
         for i in 0..32 {
-            println!("{}:\t0x{:08x}", get_register_name(i), self.read_register(i));
+            if self.abi.is_64bit() {
+                println!("{}:\t0x{:016x}", get_register_name(i), self.read_register64(i));
+            } else {
+                println!("{}:\t0x{:08x}", get_register_name(i), self.read_register(i));
+            }
         }
         println!("------");
         println!();
     }
 }
 
+fn fcc_mask(cc: u32) -> u32 {
+    assert!(cc < 8);
+    if cc == 0 { 1 << 23 } else { 1 << (24 + cc) }
+}
+
 pub fn get_register_name(id: u32) -> &'static str {
     match id {
         0 => "zero",
@@ -137,7 +433,7 @@ pub fn get_register_name(id: u32) -> &'static str {
         27 => "k1",
         28 => "gp",
         29 => "sp",
-        30 => "fp",
+        30 => "s8",
         31 => "ra",
         _ => unreachable!(),
     }
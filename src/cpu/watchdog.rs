@@ -6,7 +6,6 @@ use cpu::control::CPUFlagsWatchdog;
 use cpu::registers::get_register_name;
 use cpu::registers::RegisterFile;
 use flate2::read::GzDecoder;
-use memory::Memory;
 use serde_json;
 use std::collections::HashMap;
 use std::fs::File;
@@ -103,10 +102,11 @@ impl Watchdog {
         let _ = self.real_trace.take();
     }
 
+    /// Runs checks before the instruction is executed, `instruction` is the one at PC.
     pub fn run_cpu_watchdogs(
         &mut self,
         register_file: &mut RegisterFile,
-        memory: &Memory,
+        instruction: u32,
         check_trace: bool,
     ) {
         // null pointer
@@ -115,7 +115,7 @@ impl Watchdog {
         }
 
         // too many nops
-        if memory.fetch_instruction(register_file.get_pc()) == 0 {
+        if instruction == 0 {
             self.nop_count += 1
         } else {
             self.nop_count = 0;
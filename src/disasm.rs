@@ -0,0 +1,1128 @@
+//! Disassembler printing the same text as `objdump -d` from GNU binutils. Only the standard 32 bit
+//! encodings and the DSP ASE are understood, less common MIPS64 instructions are printed as
+//! `.word`, the same way objdump prints data.
+
+use std::fs::File;
+use std::io::Read;
+
+use goblin::elf::program_header::{PF_X, PT_LOAD};
+use goblin::elf::section_header::SHT_NOBITS;
+use goblin::elf::sym::{STB_GLOBAL, STT_FILE, STT_FUNC, STT_SECTION};
+use goblin::error;
+
+use cpu::decoder::{decode, Instruction};
+use cpu::doubleword::DoublewordInstruction;
+use cpu::dsp::DspInstruction;
+use cpu::float::{FloatFmt, FMT_PS, FMT_S};
+use cpu::isa::{Abi, Isa, IsaRevision};
+use cpu::registers::get_register_name;
+use elf::load_elf;
+use memory::Memory;
+
+/// Symbols used to label addresses, sorted by their address
+pub struct SymbolTable {
+    symbols: Vec<(u64, String)>,
+}
+
+impl SymbolTable {
+    pub fn new(symbols: Vec<(u64, String)>) -> SymbolTable {
+        let mut symbols = symbols;
+        symbols.sort_by_key(|&(address, _)| address);
+        symbols.dedup_by_key(|&mut (address, _)| address);
+        SymbolTable { symbols }
+    }
+
+    /// Symbol starting exactly at the address
+    pub fn label(&self, address: u64) -> Option<&str> {
+        self.symbols
+            .binary_search_by_key(&address, |&(a, _)| a)
+            .ok()
+            .map(|i| self.symbols[i].1.as_str())
+    }
+
+    /// `symbol+offset` of the nearest symbol below the address, if there is any
+    pub fn symbolize(&self, address: u64) -> Option<String> {
+        let index = match self.symbols.binary_search_by_key(&address, |&(a, _)| a) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (symbol_address, ref name) = self.symbols[index];
+        if symbol_address == address {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+0x{:x}", name, address - symbol_address))
+        }
+    }
+
+    /// Address followed by `<symbol+offset>` of the nearest symbol below it, if there is any
+    pub fn describe(&self, address: u64) -> String {
+        match self.symbolize(address) {
+            Some(symbol) => format!("{:x} <{}>", address, symbol),
+            None => format!("{:x}", address),
+        }
+    }
+}
+
+fn fpr(id: u32) -> String {
+    format!("$f{}", id)
+}
+
+/// Condition names of pre-R6 `C.cond.fmt`
+const C_CONDITIONS: [&str; 16] = [
+    "f", "un", "eq", "ueq", "olt", "ult", "ole", "ule", "sf", "ngle", "seq", "ngl", "lt", "nge",
+    "le", "ngt",
+];
+
+/// Condition names of R6 `CMP.cond.fmt`, the ones with bit 4 set are negated
+fn cmp_condition(cond: u32) -> &'static str {
+    const NAMES: [&str; 16] = [
+        "af", "un", "eq", "ueq", "lt", "ult", "le", "ule", "saf", "sun", "seq", "sueq", "slt",
+        "sult", "sle", "sule",
+    ];
+    match cond {
+        0b10001 => "or",
+        0b10010 => "une",
+        0b10011 => "ne",
+        0b11001 => "sor",
+        0b11010 => "sune",
+        0b11011 => "sne",
+        _ => NAMES[(cond & 0xF) as usize],
+    }
+}
+
+/// Prints one instruction located at `address` as `mnemonic\toperands`.
+pub fn format_instruction(
+    instruction: u32,
+    address: u64,
+    isa: Isa,
+    symbols: &SymbolTable,
+) -> String {
+    let decoded = match decode(instruction, isa) {
+        Ok(decoded) => decoded,
+        Err(_) => return format!(".word\t0x{:x}", instruction),
+    };
+
+    let reg = get_register_name;
+    let branch = |offset: i32| symbols.describe((address as i64 + 4 + offset as i64) as u64);
+    let jump = |target: u32| symbols.describe(((address + 4) & !0x0FFF_FFFF) | target as u64);
+    let mem = |rt: &str, base: u32, offset: i16| format!("{},{}({})", rt, offset, reg(base));
+    let ac = |ac: u32| if ac == 0 { String::new() } else { format!("$ac{},", ac) };
+    let fcc = |cc: u32| if cc == 0 { String::new() } else { format!("$fcc{},", cc) };
+    let code = |code: u32| if code == 0 { String::new() } else { format!("0x{:x}", code) };
+
+    let (mnemonic, operands): (String, String) = match decoded {
+        Instruction::Sll { rd: 0, rt: 0, shift: 0 } => ("nop".into(), String::new()),
+        Instruction::Sll { rd: 0, rt: 0, shift: 1 } => ("ssnop".into(), String::new()),
+        Instruction::Sll { rd: 0, rt: 0, shift: 3 } => ("ehb".into(), String::new()),
+        Instruction::Sll { rd, rt, shift } |
+        Instruction::Srl { rd, rt, shift } |
+        Instruction::Sra { rd, rt, shift } |
+        Instruction::Rotr { rd, rt, shift } => {
+            let name = match decoded {
+                Instruction::Sll { .. } => "sll",
+                Instruction::Srl { .. } => "srl",
+                Instruction::Sra { .. } => "sra",
+                _ => "rotr",
+            };
+            (name.into(), format!("{},{},0x{:x}", reg(rd), reg(rt), shift))
+        }
+        Instruction::Sllv { rd, rt, rs } |
+        Instruction::Srlv { rd, rt, rs } |
+        Instruction::Srav { rd, rt, rs } |
+        Instruction::Rotrv { rd, rt, rs } => {
+            let name = match decoded {
+                Instruction::Sllv { .. } => "sllv",
+                Instruction::Srlv { .. } => "srlv",
+                Instruction::Srav { .. } => "srav",
+                _ => "rotrv",
+            };
+            (name.into(), format!("{},{},{}", reg(rd), reg(rt), reg(rs)))
+        }
+        Instruction::Addu { rd, rs, rt: 0 } |
+        Instruction::Or { rd, rs, rt: 0 } => ("move".into(), format!("{},{}", reg(rd), reg(rs))),
+        Instruction::Sub { rd, rs: 0, rt } => ("neg".into(), format!("{},{}", reg(rd), reg(rt))),
+        Instruction::Subu { rd, rs: 0, rt } => ("negu".into(), format!("{},{}", reg(rd), reg(rt))),
+        Instruction::Nor { rd, rs, rt: 0 } => ("not".into(), format!("{},{}", reg(rd), reg(rs))),
+        Instruction::Add { rd, rs, rt } |
+        Instruction::Addu { rd, rs, rt } |
+        Instruction::Sub { rd, rs, rt } |
+        Instruction::Subu { rd, rs, rt } |
+        Instruction::And { rd, rs, rt } |
+        Instruction::Or { rd, rs, rt } |
+        Instruction::Xor { rd, rs, rt } |
+        Instruction::Nor { rd, rs, rt } |
+        Instruction::Slt { rd, rs, rt } |
+        Instruction::Sltu { rd, rs, rt } |
+        Instruction::Movz { rd, rs, rt } |
+        Instruction::Movn { rd, rs, rt } |
+        Instruction::Seleqz { rd, rs, rt } |
+        Instruction::Selnez { rd, rs, rt } |
+        Instruction::Mul { rd, rs, rt } |
+        Instruction::Muh { rd, rs, rt } |
+        Instruction::Mulu { rd, rs, rt } |
+        Instruction::Muhu { rd, rs, rt } |
+        Instruction::DivR6 { rd, rs, rt } |
+        Instruction::Mod { rd, rs, rt } |
+        Instruction::DivuR6 { rd, rs, rt } |
+        Instruction::Modu { rd, rs, rt } => {
+            let name = match decoded {
+                Instruction::Add { .. } => "add",
+                Instruction::Addu { .. } => "addu",
+                Instruction::Sub { .. } => "sub",
+                Instruction::Subu { .. } => "subu",
+                Instruction::And { .. } => "and",
+                Instruction::Or { .. } => "or",
+                Instruction::Xor { .. } => "xor",
+                Instruction::Nor { .. } => "nor",
+                Instruction::Slt { .. } => "slt",
+                Instruction::Sltu { .. } => "sltu",
+                Instruction::Movz { .. } => "movz",
+                Instruction::Movn { .. } => "movn",
+                Instruction::Seleqz { .. } => "seleqz",
+                Instruction::Selnez { .. } => "selnez",
+                Instruction::Mul { .. } => "mul",
+                Instruction::Muh { .. } => "muh",
+                Instruction::Mulu { .. } => "mulu",
+                Instruction::Muhu { .. } => "muhu",
+                Instruction::DivR6 { .. } => "div",
+                Instruction::Mod { .. } => "mod",
+                Instruction::DivuR6 { .. } => "divu",
+                _ => "modu",
+            };
+            (name.into(), format!("{},{},{}", reg(rd), reg(rs), reg(rt)))
+        }
+        Instruction::Movf { rd, rs, cc } => {
+            ("movf".into(), format!("{},{},$fcc{}", reg(rd), reg(rs), cc))
+        }
+        Instruction::Movt { rd, rs, cc } => {
+            ("movt".into(), format!("{},{},$fcc{}", reg(rd), reg(rs), cc))
+        }
+        Instruction::Lsa { rd, rs, rt, shift } => {
+            ("lsa".into(), format!("{},{},{},{}", reg(rd), reg(rs), reg(rt), shift))
+        }
+        Instruction::Clz { rd, rs } => ("clz".into(), format!("{},{}", reg(rd), reg(rs))),
+        Instruction::Clo { rd, rs } => ("clo".into(), format!("{},{}", reg(rd), reg(rs))),
+        Instruction::Mult { ac: a, rs, rt } |
+        Instruction::Multu { ac: a, rs, rt } |
+        Instruction::Madd { ac: a, rs, rt } |
+        Instruction::Maddu { ac: a, rs, rt } |
+        Instruction::Msub { ac: a, rs, rt } |
+        Instruction::Msubu { ac: a, rs, rt } => {
+            let name = match decoded {
+                Instruction::Mult { .. } => "mult",
+                Instruction::Multu { .. } => "multu",
+                Instruction::Madd { .. } => "madd",
+                Instruction::Maddu { .. } => "maddu",
+                Instruction::Msub { .. } => "msub",
+                _ => "msubu",
+            };
+            (name.into(), format!("{}{},{}", ac(a), reg(rs), reg(rt)))
+        }
+        Instruction::Div { rs, rt } => ("div".into(), format!("zero,{},{}", reg(rs), reg(rt))),
+        Instruction::Divu { rs, rt } => ("divu".into(), format!("zero,{},{}", reg(rs), reg(rt))),
+        Instruction::Mfhi { rd, ac: 0 } => ("mfhi".into(), reg(rd).into()),
+        Instruction::Mflo { rd, ac: 0 } => ("mflo".into(), reg(rd).into()),
+        Instruction::Mthi { rs, ac: 0 } => ("mthi".into(), reg(rs).into()),
+        Instruction::Mtlo { rs, ac: 0 } => ("mtlo".into(), reg(rs).into()),
+        Instruction::Mfhi { rd, ac } => ("mfhi".into(), format!("{},$ac{}", reg(rd), ac)),
+        Instruction::Mflo { rd, ac } => ("mflo".into(), format!("{},$ac{}", reg(rd), ac)),
+        Instruction::Mthi { rs, ac } => ("mthi".into(), format!("{},$ac{}", reg(rs), ac)),
+        Instruction::Mtlo { rs, ac } => ("mtlo".into(), format!("{},$ac{}", reg(rs), ac)),
+
+        Instruction::Addiu { rt, rs: 0, immediate } => {
+            ("li".into(), format!("{},{}", reg(rt), immediate))
+        }
+        Instruction::Ori { rt, rs: 0, immediate } => {
+            ("li".into(), format!("{},0x{:x}", reg(rt), immediate))
+        }
+        Instruction::Addi { rt, rs, immediate } |
+        Instruction::Addiu { rt, rs, immediate } |
+        Instruction::Slti { rt, rs, immediate } |
+        Instruction::Sltiu { rt, rs, immediate } => {
+            let name = match decoded {
+                Instruction::Addi { .. } => "addi",
+                Instruction::Addiu { .. } => "addiu",
+                Instruction::Slti { .. } => "slti",
+                _ => "sltiu",
+            };
+            (name.into(), format!("{},{},{}", reg(rt), reg(rs), immediate))
+        }
+        Instruction::Andi { rt, rs, immediate } |
+        Instruction::Ori { rt, rs, immediate } |
+        Instruction::Xori { rt, rs, immediate } |
+        Instruction::Aui { rt, rs, immediate } => {
+            let name = match decoded {
+                Instruction::Andi { .. } => "andi",
+                Instruction::Ori { .. } => "ori",
+                Instruction::Xori { .. } => "xori",
+                _ => "aui",
+            };
+            (name.into(), format!("{},{},0x{:x}", reg(rt), reg(rs), immediate))
+        }
+        Instruction::Lui { rt, immediate } => {
+            ("lui".into(), format!("{},0x{:x}", reg(rt), immediate))
+        }
+        Instruction::Ext { rt, rs, pos, size } => {
+            ("ext".into(), format!("{},{},0x{:x},0x{:x}", reg(rt), reg(rs), pos, size))
+        }
+        Instruction::Ins { rt, rs, pos, size } => {
+            ("ins".into(), format!("{},{},0x{:x},0x{:x}", reg(rt), reg(rs), pos, size))
+        }
+        Instruction::Wsbh { rd, rt } => ("wsbh".into(), format!("{},{}", reg(rd), reg(rt))),
+        Instruction::Seb { rd, rt } => ("seb".into(), format!("{},{}", reg(rd), reg(rt))),
+        Instruction::Seh { rd, rt } => ("seh".into(), format!("{},{}", reg(rd), reg(rt))),
+        Instruction::Bitswap { rd, rt } => ("bitswap".into(), format!("{},{}", reg(rd), reg(rt))),
+        Instruction::Align { rd, rs, rt, bp } => {
+            ("align".into(), format!("{},{},{},{}", reg(rd), reg(rs), reg(rt), bp))
+        }
+
+        Instruction::J { target } => ("j".into(), jump(target)),
+        Instruction::Jal { target } => ("jal".into(), jump(target)),
+        Instruction::Jalx { target } => ("jalx".into(), jump(target)),
+        Instruction::Jr { rs } |
+        Instruction::Jalr { rd: 0, rs } => ("jr".into(), reg(rs).into()),
+        Instruction::Jalr { rd: 31, rs } => ("jalr".into(), reg(rs).into()),
+        Instruction::Jalr { rd, rs } => ("jalr".into(), format!("{},{}", reg(rd), reg(rs))),
+        Instruction::Beq { rs: 0, rt: 0, offset } => ("b".into(), branch(offset)),
+        Instruction::Beq { rs, rt: 0, offset } => {
+            ("beqz".into(), format!("{},{}", reg(rs), branch(offset)))
+        }
+        Instruction::Bne { rs, rt: 0, offset } => {
+            ("bnez".into(), format!("{},{}", reg(rs), branch(offset)))
+        }
+        Instruction::Beql { rs, rt: 0, offset } => {
+            ("beqzl".into(), format!("{},{}", reg(rs), branch(offset)))
+        }
+        Instruction::Bnel { rs, rt: 0, offset } => {
+            ("bnezl".into(), format!("{},{}", reg(rs), branch(offset)))
+        }
+        Instruction::Beq { rs, rt, offset } |
+        Instruction::Bne { rs, rt, offset } |
+        Instruction::Beql { rs, rt, offset } |
+        Instruction::Bnel { rs, rt, offset } |
+        Instruction::Beqc { rs, rt, offset } |
+        Instruction::Bnec { rs, rt, offset } |
+        Instruction::Bgec { rs, rt, offset } |
+        Instruction::Bltc { rs, rt, offset } |
+        Instruction::Bgeuc { rs, rt, offset } |
+        Instruction::Bltuc { rs, rt, offset } |
+        Instruction::Bovc { rs, rt, offset } |
+        Instruction::Bnvc { rs, rt, offset } => {
+            let name = match decoded {
+                Instruction::Beq { .. } => "beq",
+                Instruction::Bne { .. } => "bne",
+                Instruction::Beql { .. } => "beql",
+                Instruction::Bnel { .. } => "bnel",
+                Instruction::Beqc { .. } => "beqc",
+                Instruction::Bnec { .. } => "bnec",
+                Instruction::Bgec { .. } => "bgec",
+                Instruction::Bltc { .. } => "bltc",
+                Instruction::Bgeuc { .. } => "bgeuc",
+                Instruction::Bltuc { .. } => "bltuc",
+                Instruction::Bovc { .. } => "bovc",
+                _ => "bnvc",
+            };
+            (name.into(), format!("{},{},{}", reg(rs), reg(rt), branch(offset)))
+        }
+        Instruction::Blez { rs, offset } |
+        Instruction::Bgtz { rs, offset } |
+        Instruction::Blezl { rs, offset } |
+        Instruction::Bgtzl { rs, offset } |
+        Instruction::Bltz { rs, offset } |
+        Instruction::Bgez { rs, offset } |
+        Instruction::Bltzl { rs, offset } |
+        Instruction::Bgezl { rs, offset } |
+        Instruction::Bltzal { rs, offset } |
+        Instruction::Bgezal { rs, offset } |
+        Instruction::Bltzall { rs, offset } |
+        Instruction::Bgezall { rs, offset } |
+        Instruction::Beqzc { rs, offset } |
+        Instruction::Bnezc { rs, offset } |
+        Instruction::Blezc { rt: rs, offset } |
+        Instruction::Bgezc { rt: rs, offset } |
+        Instruction::Bgtzc { rt: rs, offset } |
+        Instruction::Bltzc { rt: rs, offset } |
+        Instruction::Blezalc { rt: rs, offset } |
+        Instruction::Bgezalc { rt: rs, offset } |
+        Instruction::Bgtzalc { rt: rs, offset } |
+        Instruction::Bltzalc { rt: rs, offset } |
+        Instruction::Beqzalc { rt: rs, offset } |
+        Instruction::Bnezalc { rt: rs, offset } => {
+            let name = match decoded {
+                Instruction::Blez { .. } => "blez",
+                Instruction::Bgtz { .. } => "bgtz",
+                Instruction::Blezl { .. } => "blezl",
+                Instruction::Bgtzl { .. } => "bgtzl",
+                Instruction::Bltz { .. } => "bltz",
+                Instruction::Bgez { .. } => "bgez",
+                Instruction::Bltzl { .. } => "bltzl",
+                Instruction::Bgezl { .. } => "bgezl",
+                Instruction::Bltzal { .. } => "bltzal",
+                Instruction::Bgezal { .. } => "bgezal",
+                Instruction::Bltzall { .. } => "bltzall",
+                Instruction::Bgezall { .. } => "bgezall",
+                Instruction::Beqzc { .. } => "beqzc",
+                Instruction::Bnezc { .. } => "bnezc",
+                Instruction::Blezc { .. } => "blezc",
+                Instruction::Bgezc { .. } => "bgezc",
+                Instruction::Bgtzc { .. } => "bgtzc",
+                Instruction::Bltzc { .. } => "bltzc",
+                Instruction::Blezalc { .. } => "blezalc",
+                Instruction::Bgezalc { .. } => "bgezalc",
+                Instruction::Bgtzalc { .. } => "bgtzalc",
+                Instruction::Bltzalc { .. } => "bltzalc",
+                Instruction::Beqzalc { .. } => "beqzalc",
+                _ => "bnezalc",
+            };
+            (name.into(), format!("{},{}", reg(rs), branch(offset)))
+        }
+        Instruction::Nal { offset: 4 } => ("nal".into(), String::new()),
+        Instruction::Nal { offset } => ("bltzal".into(), format!("zero,{}", branch(offset))),
+        Instruction::Bal { offset } => ("bal".into(), branch(offset)),
+        Instruction::Bposge32 { offset } => ("bposge32".into(), branch(offset)),
+        Instruction::Bc { offset } => ("bc".into(), branch(offset)),
+        Instruction::Balc { offset } => ("balc".into(), branch(offset)),
+        Instruction::Jic { rt, offset: 0 } => ("jrc".into(), reg(rt).into()),
+        Instruction::Jialc { rt, offset: 0 } => ("jalrc".into(), reg(rt).into()),
+        Instruction::Jic { rt, offset } => ("jic".into(), format!("{},{}", reg(rt), offset)),
+        Instruction::Jialc { rt, offset } => ("jialc".into(), format!("{},{}", reg(rt), offset)),
+
+        Instruction::Addiupc { rs, offset } => {
+            ("addiupc".into(), format!("{},{}", reg(rs), offset))
+        }
+        Instruction::Lwpc { rs, offset } => ("lwpc".into(), format!("{},{}", reg(rs), offset)),
+        Instruction::Lwupc { rs, offset } => ("lwupc".into(), format!("{},{}", reg(rs), offset)),
+        Instruction::Auipc { rs, immediate } => {
+            ("auipc".into(), format!("{},0x{:x}", reg(rs), immediate))
+        }
+        Instruction::Aluipc { rs, immediate } => {
+            ("aluipc".into(), format!("{},0x{:x}", reg(rs), immediate))
+        }
+
+        Instruction::Lb { rt, base, offset } |
+        Instruction::Lbu { rt, base, offset } |
+        Instruction::Lh { rt, base, offset } |
+        Instruction::Lhu { rt, base, offset } |
+        Instruction::Lw { rt, base, offset } |
+        Instruction::Lwl { rt, base, offset } |
+        Instruction::Lwr { rt, base, offset } |
+        Instruction::Ll { rt, base, offset } |
+        Instruction::Sb { rt, base, offset } |
+        Instruction::Sh { rt, base, offset } |
+        Instruction::Sw { rt, base, offset } |
+        Instruction::Swl { rt, base, offset } |
+        Instruction::Swr { rt, base, offset } |
+        Instruction::Sc { rt, base, offset } => {
+            let name = match decoded {
+                Instruction::Lb { .. } => "lb",
+                Instruction::Lbu { .. } => "lbu",
+                Instruction::Lh { .. } => "lh",
+                Instruction::Lhu { .. } => "lhu",
+                Instruction::Lw { .. } => "lw",
+                Instruction::Lwl { .. } => "lwl",
+                Instruction::Lwr { .. } => "lwr",
+                Instruction::Ll { .. } => "ll",
+                Instruction::Sb { .. } => "sb",
+                Instruction::Sh { .. } => "sh",
+                Instruction::Sw { .. } => "sw",
+                Instruction::Swl { .. } => "swl",
+                Instruction::Swr { .. } => "swr",
+                _ => "sc",
+            };
+            (name.into(), mem(reg(rt), base, offset))
+        }
+        Instruction::Pref { hint, base, offset } => {
+            ("pref".into(), mem(&format!("0x{:x}", hint), base, offset))
+        }
+        Instruction::Cache { op, base, offset } => {
+            ("cache".into(), mem(&format!("0x{:x}", op), base, offset))
+        }
+        Instruction::Synci { base, offset } => {
+            ("synci".into(), format!("{}({})", offset, reg(base)))
+        }
+
+        Instruction::Tge { rs, rt } |
+        Instruction::Tgeu { rs, rt } |
+        Instruction::Tlt { rs, rt } |
+        Instruction::Tltu { rs, rt } |
+        Instruction::Teq { rs, rt } |
+        Instruction::Tne { rs, rt } => {
+            let name = match decoded {
+                Instruction::Tge { .. } => "tge",
+                Instruction::Tgeu { .. } => "tgeu",
+                Instruction::Tlt { .. } => "tlt",
+                Instruction::Tltu { .. } => "tltu",
+                Instruction::Teq { .. } => "teq",
+                _ => "tne",
+            };
+            let trap_code = (instruction >> 6) & 0x3FF;
+            let operands = if trap_code == 0 {
+                format!("{},{}", reg(rs), reg(rt))
+            } else {
+                format!("{},{},0x{:x}", reg(rs), reg(rt), trap_code)
+            };
+            (name.into(), operands)
+        }
+        Instruction::Tgei { rs, immediate } |
+        Instruction::Tgeiu { rs, immediate } |
+        Instruction::Tlti { rs, immediate } |
+        Instruction::Tltiu { rs, immediate } |
+        Instruction::Teqi { rs, immediate } |
+        Instruction::Tnei { rs, immediate } => {
+            let name = match decoded {
+                Instruction::Tgei { .. } => "tgei",
+                Instruction::Tgeiu { .. } => "tgeiu",
+                Instruction::Tlti { .. } => "tlti",
+                Instruction::Tltiu { .. } => "tltiu",
+                Instruction::Teqi { .. } => "teqi",
+                _ => "tnei",
+            };
+            (name.into(), format!("{},{}", reg(rs), immediate))
+        }
+        Instruction::Syscall { code: c } => ("syscall".into(), code(c)),
+        Instruction::Sdbbp { code: c } => ("sdbbp".into(), code(c)),
+        Instruction::Break { code } => {
+            let (upper, lower) = (code >> 10, code & 0x3FF);
+            let operands = match (upper, lower) {
+                (0, 0) => String::new(),
+                (upper, 0) => format!("0x{:x}", upper),
+                (upper, lower) => format!("0x{:x},0x{:x}", upper, lower),
+            };
+            ("break".into(), operands)
+        }
+        Instruction::Sync { stype: 0 } => ("sync".into(), String::new()),
+        Instruction::Sync { stype } => ("sync".into(), format!("0x{:x}", stype)),
+        Instruction::Rdhwr { rt, rd } => ("rdhwr".into(), format!("{},${}", reg(rt), rd)),
+
+        Instruction::Mfc1 { rt, fs } |
+        Instruction::Mfhc1 { rt, fs } |
+        Instruction::Mtc1 { rt, fs } |
+        Instruction::Mthc1 { rt, fs } => {
+            let name = match decoded {
+                Instruction::Mfc1 { .. } => "mfc1",
+                Instruction::Mfhc1 { .. } => "mfhc1",
+                Instruction::Mtc1 { .. } => "mtc1",
+                _ => "mthc1",
+            };
+            (name.into(), format!("{},{}", reg(rt), fpr(fs)))
+        }
+        Instruction::Cfc1 { rt, fs } => ("cfc1".into(), format!("{},${}", reg(rt), fs)),
+        Instruction::Ctc1 { rt, fs } => ("ctc1".into(), format!("{},${}", reg(rt), fs)),
+        Instruction::Lwc1 { ft, base, offset } => ("lwc1".into(), mem(&fpr(ft), base, offset)),
+        Instruction::Ldc1 { ft, base, offset } => ("ldc1".into(), mem(&fpr(ft), base, offset)),
+        Instruction::Swc1 { ft, base, offset } => ("swc1".into(), mem(&fpr(ft), base, offset)),
+        Instruction::Sdc1 { ft, base, offset } => ("sdc1".into(), mem(&fpr(ft), base, offset)),
+        Instruction::Lwxc1 { fd: f, base, index } |
+        Instruction::Ldxc1 { fd: f, base, index } |
+        Instruction::Luxc1 { fd: f, base, index } |
+        Instruction::Swxc1 { fs: f, base, index } |
+        Instruction::Sdxc1 { fs: f, base, index } |
+        Instruction::Suxc1 { fs: f, base, index } => {
+            let name = match decoded {
+                Instruction::Lwxc1 { .. } => "lwxc1",
+                Instruction::Ldxc1 { .. } => "ldxc1",
+                Instruction::Luxc1 { .. } => "luxc1",
+                Instruction::Swxc1 { .. } => "swxc1",
+                Instruction::Sdxc1 { .. } => "sdxc1",
+                _ => "suxc1",
+            };
+            (name.into(), format!("{},{}({})", fpr(f), reg(index), reg(base)))
+        }
+        Instruction::Prefx { hint, base, index } => {
+            ("prefx".into(), format!("0x{:x},{}({})", hint, reg(index), reg(base)))
+        }
+
+        Instruction::Bc1f { cc, offset } => ("bc1f".into(), fcc(cc) + &branch(offset)),
+        Instruction::Bc1t { cc, offset } => ("bc1t".into(), fcc(cc) + &branch(offset)),
+        Instruction::Bc1fl { cc, offset } => ("bc1fl".into(), fcc(cc) + &branch(offset)),
+        Instruction::Bc1tl { cc, offset } => ("bc1tl".into(), fcc(cc) + &branch(offset)),
+        Instruction::Bc1eqz { ft, offset } => {
+            ("bc1eqz".into(), format!("{},{}", fpr(ft), branch(offset)))
+        }
+        Instruction::Bc1nez { ft, offset } => {
+            ("bc1nez".into(), format!("{},{}", fpr(ft), branch(offset)))
+        }
+
+        Instruction::AddFmt { fmt, fd, fs, ft } |
+        Instruction::SubFmt { fmt, fd, fs, ft } |
+        Instruction::MulFmt { fmt, fd, fs, ft } |
+        Instruction::DivFmt { fmt, fd, fs, ft } |
+        Instruction::MaddfFmt { fmt, fd, fs, ft } |
+        Instruction::MsubfFmt { fmt, fd, fs, ft } |
+        Instruction::MinFmt { fmt, fd, fs, ft } |
+        Instruction::MinaFmt { fmt, fd, fs, ft } |
+        Instruction::MaxFmt { fmt, fd, fs, ft } |
+        Instruction::MaxaFmt { fmt, fd, fs, ft } |
+        Instruction::SelFmt { fmt, fd, fs, ft } |
+        Instruction::SeleqzFmt { fmt, fd, fs, ft } |
+        Instruction::SelnezFmt { fmt, fd, fs, ft } => {
+            let name = match decoded {
+                Instruction::AddFmt { .. } => "add",
+                Instruction::SubFmt { .. } => "sub",
+                Instruction::MulFmt { .. } => "mul",
+                Instruction::DivFmt { .. } => "div",
+                Instruction::MaddfFmt { .. } => "maddf",
+                Instruction::MsubfFmt { .. } => "msubf",
+                Instruction::MinFmt { .. } => "min",
+                Instruction::MinaFmt { .. } => "mina",
+                Instruction::MaxFmt { .. } => "max",
+                Instruction::MaxaFmt { .. } => "maxa",
+                Instruction::SelFmt { .. } => "sel",
+                Instruction::SeleqzFmt { .. } => "seleqz",
+                _ => "selnez",
+            };
+            (
+                format!("{}.{}", name, FloatFmt::format_name(fmt)),
+                format!("{},{},{}", fpr(fd), fpr(fs), fpr(ft)),
+            )
+        }
+        Instruction::SqrtFmt { fmt, fd, fs } |
+        Instruction::AbsFmt { fmt, fd, fs } |
+        Instruction::MovFmt { fmt, fd, fs } |
+        Instruction::NegFmt { fmt, fd, fs } |
+        Instruction::RecipFmt { fmt, fd, fs } |
+        Instruction::RsqrtFmt { fmt, fd, fs } |
+        Instruction::RintFmt { fmt, fd, fs } |
+        Instruction::ClassFmt { fmt, fd, fs } => {
+            let name = match decoded {
+                Instruction::SqrtFmt { .. } => "sqrt",
+                Instruction::AbsFmt { .. } => "abs",
+                Instruction::MovFmt { .. } => "mov",
+                Instruction::NegFmt { .. } => "neg",
+                Instruction::RecipFmt { .. } => "recip",
+                Instruction::RsqrtFmt { .. } => "rsqrt",
+                Instruction::RintFmt { .. } => "rint",
+                _ => "class",
+            };
+            (format!("{}.{}", name, FloatFmt::format_name(fmt)), format!("{},{}", fpr(fd), fpr(fs)))
+        }
+        // CVT.S.PU has its own name
+        Instruction::CvtS { fmt: FMT_PS, fd, fs } => {
+            ("cvt.s.pu".into(), format!("{},{}", fpr(fd), fpr(fs)))
+        }
+        Instruction::RoundL { fmt, fd, fs } |
+        Instruction::TruncL { fmt, fd, fs } |
+        Instruction::CeilL { fmt, fd, fs } |
+        Instruction::FloorL { fmt, fd, fs } |
+        Instruction::RoundW { fmt, fd, fs } |
+        Instruction::TruncW { fmt, fd, fs } |
+        Instruction::CeilW { fmt, fd, fs } |
+        Instruction::FloorW { fmt, fd, fs } |
+        Instruction::CvtS { fmt, fd, fs } |
+        Instruction::CvtD { fmt, fd, fs } |
+        Instruction::CvtW { fmt, fd, fs } |
+        Instruction::CvtL { fmt, fd, fs } => {
+            let name = match decoded {
+                Instruction::RoundL { .. } => "round.l",
+                Instruction::TruncL { .. } => "trunc.l",
+                Instruction::CeilL { .. } => "ceil.l",
+                Instruction::FloorL { .. } => "floor.l",
+                Instruction::RoundW { .. } => "round.w",
+                Instruction::TruncW { .. } => "trunc.w",
+                Instruction::CeilW { .. } => "ceil.w",
+                Instruction::FloorW { .. } => "floor.w",
+                Instruction::CvtS { .. } => "cvt.s",
+                Instruction::CvtD { .. } => "cvt.d",
+                Instruction::CvtW { .. } => "cvt.w",
+                _ => "cvt.l",
+            };
+            (format!("{}.{}", name, FloatFmt::format_name(fmt)), format!("{},{}", fpr(fd), fpr(fs)))
+        }
+        Instruction::MaddFmt { fmt, fd, fr, fs, ft } |
+        Instruction::MsubFmt { fmt, fd, fr, fs, ft } |
+        Instruction::NmaddFmt { fmt, fd, fr, fs, ft } |
+        Instruction::NmsubFmt { fmt, fd, fr, fs, ft } => {
+            let name = match decoded {
+                Instruction::MaddFmt { .. } => "madd",
+                Instruction::MsubFmt { .. } => "msub",
+                Instruction::NmaddFmt { .. } => "nmadd",
+                _ => "nmsub",
+            };
+            (
+                format!("{}.{}", name, FloatFmt::format_name(fmt)),
+                format!("{},{},{},{}", fpr(fd), fpr(fr), fpr(fs), fpr(ft)),
+            )
+        }
+        Instruction::CCond { cond, fmt, cc, fs, ft } => {
+            (
+                format!("c.{}.{}", C_CONDITIONS[cond as usize], FloatFmt::format_name(fmt)),
+                format!("{}{},{}", fcc(cc), fpr(fs), fpr(ft)),
+            )
+        }
+        Instruction::CmpCond { cond, fmt, fd, fs, ft } => {
+            // the decoder has already turned the W and L format fields into S and D
+            (
+                format!("cmp.{}.{}", cmp_condition(cond), if fmt == FMT_S { "s" } else { "d" }),
+                format!("{},{},{}", fpr(fd), fpr(fs), fpr(ft)),
+            )
+        }
+        Instruction::MovfFmt { fmt, fd, fs, cc } => {
+            (format!("movf.{}", FloatFmt::format_name(fmt)), format!("{},{},$fcc{}", fpr(fd), fpr(fs), cc))
+        }
+        Instruction::MovtFmt { fmt, fd, fs, cc } => {
+            (format!("movt.{}", FloatFmt::format_name(fmt)), format!("{},{},$fcc{}", fpr(fd), fpr(fs), cc))
+        }
+        Instruction::MovzFmt { fmt, fd, fs, rt } => {
+            (format!("movz.{}", FloatFmt::format_name(fmt)), format!("{},{},{}", fpr(fd), fpr(fs), reg(rt)))
+        }
+        Instruction::MovnFmt { fmt, fd, fs, rt } => {
+            (format!("movn.{}", FloatFmt::format_name(fmt)), format!("{},{},{}", fpr(fd), fpr(fs), reg(rt)))
+        }
+
+        Instruction::Doubleword(doubleword) => {
+            match format_doubleword_instruction(doubleword) {
+                Some(text) => return text,
+                None => (".word".into(), format!("0x{:x}", instruction)),
+            }
+        }
+        Instruction::Dsp(dsp) => return format_dsp_instruction(dsp),
+    };
+
+    if operands.is_empty() {
+        mnemonic
+    } else {
+        format!("{}\t{}", mnemonic, operands)
+    }
+}
+
+/// The most common MIPS64 instructions, the rest is printed as `.word`
+fn format_doubleword_instruction(decoded: DoublewordInstruction) -> Option<String> {
+    let reg = get_register_name;
+    let mem = |name: &str, rt: u32, base: u32, offset: i16| {
+        format!("{}\t{},{}({})", name, reg(rt), offset, reg(base))
+    };
+
+    Some(match decoded {
+        DoublewordInstruction::Daddiu { rt, rs: 0, immediate } => {
+            format!("li\t{},{}", reg(rt), immediate)
+        }
+        DoublewordInstruction::Daddi { rt, rs, immediate } => {
+            format!("daddi\t{},{},{}", reg(rt), reg(rs), immediate)
+        }
+        DoublewordInstruction::Daddiu { rt, rs, immediate } => {
+            format!("daddiu\t{},{},{}", reg(rt), reg(rs), immediate)
+        }
+        DoublewordInstruction::Ld { rt, base, offset } => mem("ld", rt, base, offset),
+        DoublewordInstruction::Lwu { rt, base, offset } => mem("lwu", rt, base, offset),
+        DoublewordInstruction::Ldl { rt, base, offset } => mem("ldl", rt, base, offset),
+        DoublewordInstruction::Ldr { rt, base, offset } => mem("ldr", rt, base, offset),
+        DoublewordInstruction::Lld { rt, base, offset } => mem("lld", rt, base, offset),
+        DoublewordInstruction::Sd { rt, base, offset } => mem("sd", rt, base, offset),
+        DoublewordInstruction::Sdl { rt, base, offset } => mem("sdl", rt, base, offset),
+        DoublewordInstruction::Sdr { rt, base, offset } => mem("sdr", rt, base, offset),
+        DoublewordInstruction::Scd { rt, base, offset } => mem("scd", rt, base, offset),
+        DoublewordInstruction::Dmfc1 { rt, fs } => format!("dmfc1\t{},$f{}", reg(rt), fs),
+        DoublewordInstruction::Dmtc1 { rt, fs } => format!("dmtc1\t{},$f{}", reg(rt), fs),
+        DoublewordInstruction::Daddu { rd, rs, rt: 0 } => format!("move\t{},{}", reg(rd), reg(rs)),
+        DoublewordInstruction::Dsubu { rd, rs: 0, rt } => format!("dnegu\t{},{}", reg(rd), reg(rt)),
+        DoublewordInstruction::Dadd { rd, rs, rt } |
+        DoublewordInstruction::Daddu { rd, rs, rt } |
+        DoublewordInstruction::Dsub { rd, rs, rt } |
+        DoublewordInstruction::Dsubu { rd, rs, rt } |
+        DoublewordInstruction::Dmul { rd, rs, rt } |
+        DoublewordInstruction::Dmuh { rd, rs, rt } |
+        DoublewordInstruction::Dmulu { rd, rs, rt } |
+        DoublewordInstruction::Dmuhu { rd, rs, rt } |
+        DoublewordInstruction::DdivR6 { rd, rs, rt } |
+        DoublewordInstruction::Dmod { rd, rs, rt } |
+        DoublewordInstruction::DdivuR6 { rd, rs, rt } |
+        DoublewordInstruction::Dmodu { rd, rs, rt } => {
+            let name = match decoded {
+                DoublewordInstruction::Dadd { .. } => "dadd",
+                DoublewordInstruction::Daddu { .. } => "daddu",
+                DoublewordInstruction::Dsub { .. } => "dsub",
+                DoublewordInstruction::Dsubu { .. } => "dsubu",
+                DoublewordInstruction::Dmul { .. } => "dmul",
+                DoublewordInstruction::Dmuh { .. } => "dmuh",
+                DoublewordInstruction::Dmulu { .. } => "dmulu",
+                DoublewordInstruction::Dmuhu { .. } => "dmuhu",
+                DoublewordInstruction::DdivR6 { .. } => "ddiv",
+                DoublewordInstruction::Dmod { .. } => "dmod",
+                DoublewordInstruction::DdivuR6 { .. } => "ddivu",
+                _ => "dmodu",
+            };
+            format!("{}\t{},{},{}", name, reg(rd), reg(rs), reg(rt))
+        }
+        DoublewordInstruction::Dsllv { rd, rt, rs } |
+        DoublewordInstruction::Dsrlv { rd, rt, rs } |
+        DoublewordInstruction::Dsrav { rd, rt, rs } |
+        DoublewordInstruction::Drotrv { rd, rt, rs } => {
+            let name = match decoded {
+                DoublewordInstruction::Dsllv { .. } => "dsllv",
+                DoublewordInstruction::Dsrlv { .. } => "dsrlv",
+                DoublewordInstruction::Dsrav { .. } => "dsrav",
+                _ => "drotrv",
+            };
+            format!("{}\t{},{},{}", name, reg(rd), reg(rt), reg(rs))
+        }
+        // shifts by 32 or more have their own mnemonics
+        DoublewordInstruction::Dsll { rd, rt, shift } |
+        DoublewordInstruction::Dsrl { rd, rt, shift } |
+        DoublewordInstruction::Dsra { rd, rt, shift } |
+        DoublewordInstruction::Drotr { rd, rt, shift } => {
+            let name = match decoded {
+                DoublewordInstruction::Dsll { .. } => "dsll",
+                DoublewordInstruction::Dsrl { .. } => "dsrl",
+                DoublewordInstruction::Dsra { .. } => "dsra",
+                _ => "drotr",
+            };
+            let suffix = if shift >= 32 { "32" } else { "" };
+            format!("{}{}\t{},{},0x{:x}", name, suffix, reg(rd), reg(rt), shift & 0x1F)
+        }
+        DoublewordInstruction::Dmult { rs, rt } => format!("dmult\t{},{}", reg(rs), reg(rt)),
+        DoublewordInstruction::Dmultu { rs, rt } => format!("dmultu\t{},{}", reg(rs), reg(rt)),
+        DoublewordInstruction::Ddiv { rs, rt } => format!("ddiv\tzero,{},{}", reg(rs), reg(rt)),
+        DoublewordInstruction::Ddivu { rs, rt } => format!("ddivu\tzero,{},{}", reg(rs), reg(rt)),
+        _ => return None,
+    })
+}
+
+/// DSP ASE instructions, immediates are printed in decimal except for the DSPControl masks
+fn format_dsp_instruction(decoded: DspInstruction) -> String {
+    let reg = get_register_name;
+    let operands = match decoded {
+        DspInstruction::Lwx { rd, base, index } |
+        DspInstruction::Lhx { rd, base, index } |
+        DspInstruction::Lbux { rd, base, index } => {
+            format!("{},{}({})", reg(rd), reg(index), reg(base))
+        }
+        DspInstruction::Insv { rt, rs } => format!("{},{}", reg(rt), reg(rs)),
+        DspInstruction::AdduQb { rd, rs, rt } |
+        DspInstruction::SubuQb { rd, rs, rt } |
+        DspInstruction::AdduSQb { rd, rs, rt } |
+        DspInstruction::SubuSQb { rd, rs, rt } |
+        DspInstruction::MuleuSPhQbl { rd, rs, rt } |
+        DspInstruction::MuleuSPhQbr { rd, rs, rt } |
+        DspInstruction::AddqPh { rd, rs, rt } |
+        DspInstruction::SubqPh { rd, rs, rt } |
+        DspInstruction::AddqSPh { rd, rs, rt } |
+        DspInstruction::SubqSPh { rd, rs, rt } |
+        DspInstruction::Addsc { rd, rs, rt } |
+        DspInstruction::Addwc { rd, rs, rt } |
+        DspInstruction::Modsub { rd, rs, rt } |
+        DspInstruction::AddqSW { rd, rs, rt } |
+        DspInstruction::SubqSW { rd, rs, rt } |
+        DspInstruction::MuleqSWPhl { rd, rs, rt } |
+        DspInstruction::MuleqSWPhr { rd, rs, rt } |
+        DspInstruction::MulqRsPh { rd, rs, rt } |
+        DspInstruction::CmpguEqQb { rd, rs, rt } |
+        DspInstruction::CmpguLtQb { rd, rs, rt } |
+        DspInstruction::CmpguLeQb { rd, rs, rt } |
+        DspInstruction::PickQb { rd, rs, rt } |
+        DspInstruction::PickPh { rd, rs, rt } |
+        DspInstruction::PrecrqQbPh { rd, rs, rt } |
+        DspInstruction::PackrlPh { rd, rs, rt } |
+        DspInstruction::PrecrquSQbPh { rd, rs, rt } |
+        DspInstruction::PrecrqPhW { rd, rs, rt } |
+        DspInstruction::PrecrqRsPhW { rd, rs, rt } |
+        DspInstruction::AdduPh { rd, rs, rt } |
+        DspInstruction::SubuPh { rd, rs, rt } |
+        DspInstruction::AdduSPh { rd, rs, rt } |
+        DspInstruction::SubuSPh { rd, rs, rt } |
+        DspInstruction::MulqSPh { rd, rs, rt } |
+        DspInstruction::AdduhQb { rd, rs, rt } |
+        DspInstruction::AdduhRQb { rd, rs, rt } |
+        DspInstruction::SubuhQb { rd, rs, rt } |
+        DspInstruction::SubuhRQb { rd, rs, rt } |
+        DspInstruction::AddqhPh { rd, rs, rt } |
+        DspInstruction::AddqhRPh { rd, rs, rt } |
+        DspInstruction::SubqhPh { rd, rs, rt } |
+        DspInstruction::SubqhRPh { rd, rs, rt } |
+        DspInstruction::AddqhW { rd, rs, rt } |
+        DspInstruction::AddqhRW { rd, rs, rt } |
+        DspInstruction::SubqhW { rd, rs, rt } |
+        DspInstruction::SubqhRW { rd, rs, rt } |
+        DspInstruction::MulPh { rd, rs, rt } |
+        DspInstruction::MulSPh { rd, rs, rt } |
+        DspInstruction::MulqSW { rd, rs, rt } |
+        DspInstruction::MulqRsW { rd, rs, rt } |
+        DspInstruction::CmpgduEqQb { rd, rs, rt } |
+        DspInstruction::CmpgduLtQb { rd, rs, rt } |
+        DspInstruction::CmpgduLeQb { rd, rs, rt } |
+        DspInstruction::PrecrQbPh { rd, rs, rt } => {
+            format!("{},{},{}", reg(rd), reg(rs), reg(rt))
+        }
+        DspInstruction::PrecrSraPhW { rt, rs, shift } |
+        DspInstruction::PrecrSraRPhW { rt, rs, shift } |
+        DspInstruction::Append { rt, rs, shift } |
+        DspInstruction::Prepend { rt, rs, shift } |
+        DspInstruction::Balign { rt, rs, bp: shift } => {
+            format!("{},{},{}", reg(rt), reg(rs), shift)
+        }
+        DspInstruction::RadduWQb { rd, rs } => format!("{},{}", reg(rd), reg(rs)),
+        DspInstruction::CmpuEqQb { rs, rt } |
+        DspInstruction::CmpuLtQb { rs, rt } |
+        DspInstruction::CmpuLeQb { rs, rt } |
+        DspInstruction::CmpEqPh { rs, rt } |
+        DspInstruction::CmpLtPh { rs, rt } |
+        DspInstruction::CmpLePh { rs, rt } => format!("{},{}", reg(rs), reg(rt)),
+        DspInstruction::ReplQb { rd, immediate } => format!("{},{}", reg(rd), immediate),
+        DspInstruction::ReplPh { rd, immediate } => format!("{},{}", reg(rd), immediate),
+        DspInstruction::ReplvQb { rd, rt } |
+        DspInstruction::ReplvPh { rd, rt } |
+        DspInstruction::PrecequPhQbl { rd, rt } |
+        DspInstruction::PrecequPhQbr { rd, rt } |
+        DspInstruction::PrecequPhQbla { rd, rt } |
+        DspInstruction::PrecequPhQbra { rd, rt } |
+        DspInstruction::PreceuPhQbl { rd, rt } |
+        DspInstruction::PreceuPhQbr { rd, rt } |
+        DspInstruction::PreceuPhQbla { rd, rt } |
+        DspInstruction::PreceuPhQbra { rd, rt } |
+        DspInstruction::PreceqWPhl { rd, rt } |
+        DspInstruction::PreceqWPhr { rd, rt } |
+        DspInstruction::AbsqSPh { rd, rt } |
+        DspInstruction::AbsqSW { rd, rt } |
+        DspInstruction::Bitrev { rd, rt } |
+        DspInstruction::AbsqSQb { rd, rt } => format!("{},{}", reg(rd), reg(rt)),
+        DspInstruction::ShllQb { rd, rt, shift } |
+        DspInstruction::ShrlQb { rd, rt, shift } |
+        DspInstruction::ShllPh { rd, rt, shift } |
+        DspInstruction::ShraPh { rd, rt, shift } |
+        DspInstruction::ShllSPh { rd, rt, shift } |
+        DspInstruction::ShraRPh { rd, rt, shift } |
+        DspInstruction::ShllSW { rd, rt, shift } |
+        DspInstruction::ShraRW { rd, rt, shift } |
+        DspInstruction::ShraQb { rd, rt, shift } |
+        DspInstruction::ShraRQb { rd, rt, shift } |
+        DspInstruction::ShrlPh { rd, rt, shift } => format!("{},{},{}", reg(rd), reg(rt), shift),
+        DspInstruction::ShllvQb { rd, rt, rs } |
+        DspInstruction::ShrlvQb { rd, rt, rs } |
+        DspInstruction::ShllvPh { rd, rt, rs } |
+        DspInstruction::ShravPh { rd, rt, rs } |
+        DspInstruction::ShllvSPh { rd, rt, rs } |
+        DspInstruction::ShravRPh { rd, rt, rs } |
+        DspInstruction::ShllvSW { rd, rt, rs } |
+        DspInstruction::ShravRW { rd, rt, rs } |
+        DspInstruction::ShravQb { rd, rt, rs } |
+        DspInstruction::ShravRQb { rd, rt, rs } |
+        DspInstruction::ShrlvPh { rd, rt, rs } => {
+            format!("{},{},{}", reg(rd), reg(rt), reg(rs))
+        }
+        DspInstruction::DpauHQbl { ac, rs, rt } |
+        DspInstruction::DpauHQbr { ac, rs, rt } |
+        DspInstruction::DpsuHQbl { ac, rs, rt } |
+        DspInstruction::DpsuHQbr { ac, rs, rt } |
+        DspInstruction::DpaqSWPh { ac, rs, rt } |
+        DspInstruction::DpsqSWPh { ac, rs, rt } |
+        DspInstruction::MulsaqSWPh { ac, rs, rt } |
+        DspInstruction::DpaqSaLW { ac, rs, rt } |
+        DspInstruction::DpsqSaLW { ac, rs, rt } |
+        DspInstruction::MaqSaWPhl { ac, rs, rt } |
+        DspInstruction::MaqSaWPhr { ac, rs, rt } |
+        DspInstruction::MaqSWPhl { ac, rs, rt } |
+        DspInstruction::MaqSWPhr { ac, rs, rt } |
+        DspInstruction::DpaWPh { ac, rs, rt } |
+        DspInstruction::DpsWPh { ac, rs, rt } |
+        DspInstruction::DpaxWPh { ac, rs, rt } |
+        DspInstruction::DpsxWPh { ac, rs, rt } |
+        DspInstruction::DpaqxSWPh { ac, rs, rt } |
+        DspInstruction::DpaqxSaWPh { ac, rs, rt } |
+        DspInstruction::DpsqxSWPh { ac, rs, rt } |
+        DspInstruction::DpsqxSaWPh { ac, rs, rt } |
+        DspInstruction::MulsaWPh { ac, rs, rt } => {
+            format!("$ac{},{},{}", ac, reg(rs), reg(rt))
+        }
+        DspInstruction::ExtrW { rt, ac, shift } |
+        DspInstruction::ExtrRW { rt, ac, shift } |
+        DspInstruction::ExtrRsW { rt, ac, shift } |
+        DspInstruction::ExtrSH { rt, ac, shift } |
+        DspInstruction::Extp { rt, ac, size: shift } |
+        DspInstruction::Extpdp { rt, ac, size: shift } => {
+            format!("{},$ac{},{}", reg(rt), ac, shift)
+        }
+        DspInstruction::ExtrvW { rt, ac, rs } |
+        DspInstruction::ExtrvRW { rt, ac, rs } |
+        DspInstruction::ExtrvRsW { rt, ac, rs } |
+        DspInstruction::ExtrvSH { rt, ac, rs } |
+        DspInstruction::Extpv { rt, ac, rs } |
+        DspInstruction::Extpdpv { rt, ac, rs } => format!("{},$ac{},{}", reg(rt), ac, reg(rs)),
+        DspInstruction::Shilo { ac, shift } => format!("$ac{},{}", ac, shift),
+        DspInstruction::Shilov { ac, rs } => format!("$ac{},{}", ac, reg(rs)),
+        DspInstruction::Mthlip { rs, ac } => format!("{},$ac{}", reg(rs), ac),
+        DspInstruction::Rddsp { rd, mask } => format!("{},0x{:x}", reg(rd), mask),
+        DspInstruction::Wrdsp { rs, mask } => format!("{},0x{:x}", reg(rs), mask),
+    };
+    format!("{}\t{}", decoded.mnemonic(), operands)
+}
+
+/// Disassembles words in the range `start..stop` in the objdump format, each symbol starts a new
+/// block with a label. Like objdump, the block at the start is labeled even when it does not start
+/// at a symbol.
+pub fn disassemble(
+    memory: &Memory,
+    start: u64,
+    stop: u64,
+    isa: Isa,
+    symbols: &SymbolTable,
+) -> String {
+    let mut output = String::new();
+    let mut address = start & !0b11;
+    let width = if isa.abi == Abi::N64 { 16 } else { 8 };
+    if symbols.label(address).is_none() {
+        if let Some(symbol) = symbols.symbolize(address) {
+            output.push_str(&format!("\n{:0width$x} <{}>:\n", address, symbol, width = width));
+        }
+    }
+    while address < stop {
+        if let Some(label) = symbols.label(address) {
+            output.push_str(&format!("\n{:0width$x} <{}>:\n", address, label, width = width));
+        }
+        let instruction = memory.fetch_instruction(address as u32);
+        output.push_str(&format!(
+            "{:8x}:\t{:08x} \t{}\n",
+            address,
+            instruction,
+            format_instruction(instruction, address, isa, symbols)
+        ));
+        address += 4;
+    }
+    output
+}
+
+/// Disassembles executable sections of an ELF binary, optionally limited to an address range.
+/// Binaries without section headers are disassembled by their executable segments.
+pub fn disassemble_elf(
+    path: &str,
+    isa_revision: Option<IsaRevision>,
+    start: Option<u64>,
+    stop: Option<u64>,
+) -> error::Result<String> {
+    let image = load_elf(path)?;
+    let isa = Isa {
+        revision: isa_revision.unwrap_or(image.isa_revision),
+        compressed: image.compressed_isa,
+        abi: image.abi,
+    };
+
+    let mut buffer = Vec::new();
+    File::open(path)?.read_to_end(&mut buffer)?;
+    let elf = ::goblin::elf::Elf::parse(&buffer)?;
+
+    let mut symbols = Vec::new();
+    for sym in elf.syms.iter() {
+        let name = elf.strtab.get_unsafe(sym.st_name).unwrap_or("");
+        if !name.is_empty() && sym.st_type() != STT_SECTION && sym.st_type() != STT_FILE &&
+            sym.st_shndx != 0
+        {
+            // the lowest bit marks compressed code
+            symbols.push((sym.st_value & !1, name.to_string(), sym.st_type() == STT_FUNC,
+                          sym.st_bind() == STB_GLOBAL));
+        }
+    }
+    // when there are more symbols at one address, functions and global ones win
+    symbols.sort_by_key(|&(address, _, function, global)| (address, !function, !global));
+    let mut symbols: Vec<_> = symbols.into_iter().map(|(a, name, _, _)| (a, name)).collect();
+    // objdump labels stripped binaries by their sections
+    if symbols.is_empty() {
+        for sh in elf.section_headers.iter() {
+            if sh.is_alloc() && sh.sh_size != 0 {
+                let name = elf.shdr_strtab.get_unsafe(sh.sh_name).unwrap_or("");
+                symbols.push((sh.sh_addr, name.to_string()));
+            }
+        }
+    }
+    let symbols = SymbolTable::new(symbols);
+
+    let mut regions = Vec::new();
+    for sh in elf.section_headers.iter() {
+        if sh.is_executable() && sh.sh_type != SHT_NOBITS && sh.sh_size != 0 {
+            let name = elf.shdr_strtab.get_unsafe(sh.sh_name).unwrap_or("");
+            regions.push((format!("section {}", name), sh.sh_addr, sh.sh_addr + sh.sh_size));
+        }
+    }
+    if regions.is_empty() {
+        for (i, ph) in elf.program_headers.iter().enumerate() {
+            if ph.p_type == PT_LOAD && ph.p_flags & PF_X != 0 && ph.p_filesz != 0 {
+                regions.push((format!("segment {}", i), ph.p_vaddr, ph.p_vaddr + ph.p_filesz));
+            }
+        }
+    }
+
+    let format = match (elf.is_64, image.abi, image.memory.is_big_endian()) {
+        (true, _, true) => "elf64-tradbigmips",
+        (true, _, false) => "elf64-tradlittlemips",
+        (false, Abi::N32, true) => "elf32-ntradbigmips",
+        (false, Abi::N32, false) => "elf32-ntradlittlemips",
+        (false, _, true) => "elf32-tradbigmips",
+        (false, _, false) => "elf32-tradlittlemips",
+    };
+    let mut output = format!("\n{}:     file format {}\n\n", path, format);
+    for (name, region_start, region_stop) in regions {
+        let region_start = start.map_or(region_start, |start| start.max(region_start));
+        let region_stop = stop.map_or(region_stop, |stop| stop.min(region_stop));
+        if region_start >= region_stop {
+            continue;
+        }
+        output.push_str(&format!("\nDisassembly of {}:\n", name));
+        output.push_str(&disassemble(&image.memory, region_start, region_stop, isa, &symbols));
+    }
+    Ok(output)
+}
+
+#[test]
+fn test_objdump_output() {
+    let isa = Isa {
+        revision: IsaRevision::R2,
+        compressed: None,
+        abi: Abi::O32,
+    };
+    let symbols = SymbolTable::new(vec![(0x400100, "main".to_string())]);
+    // expected lines are hand-written in the GNU objdump syntax, not copied from its output
+    let golden = [
+        (0x27BDFFE0, "addiu\tsp,sp,-32"),
+        (0x8FBF001C, "lw\tra,28(sp)"),
+        (0x00000000, "nop"),
+        (0x03E00008, "jr\tra"),
+        (0x0320F809, "jalr\tt9"),
+        (0x3C1C0002, "lui\tgp,0x2"),
+        (0x00801025, "move\tv0,a0"),
+        (0x24020FA1, "li\tv0,4001"),
+        (0x00021080, "sll\tv0,v0,0x2"),
+        (0x1080FFFE, "beqz\ta0,400104 <main+0x4>"),
+        (0x0000000C, "syscall"),
+        (0x0007000D, "break\t0x7"),
+        (0x0085001A, "div\tzero,a0,a1"),
+        (0x7C8220C0, "ext\tv0,a0,0x3,0x5"),
+        (0x46241000, "add.d\t$f0,$f2,$f4"),
+        (0xC7A00010, "lwc1\t$f0,16(sp)"),
+        (0x7C03E83B, "rdhwr\tv1,$29"),
+        (0x7C85100A, "lwx\tv0,a1(a0)"),
+        (0x7FFD1292, "repl.ph\tv0,-3"),
+        (0x7C850B30, "dpaq_sa.l.w\t$ac1,a0,a1"),
+        (0x7E2211B8, "extr_rs.w\tv0,$ac2,17"),
+        (0x7FB00EB8, "shilo\t$ac1,-5"),
+        (0x7C808CF8, "wrdsp\ta0,0x11"),
+        (0x7C851210, "addu.ph\tv0,a0,a1"),
+        (0x7C823F91, "precr_sra.ph.w\tv0,a0,7"),
+        (0x7C851E30, "dpaqx_s.w.ph\t$ac3,a0,a1"),
+        (0x7DA51653, "shrl.ph\tv0,a1,13"),
+        (0x7C821C31, "balign\tv0,a0,3"),
+    ];
+    for &(instruction, text) in golden.iter() {
+        assert_eq!(format_instruction(instruction, 0x400108, isa, &symbols), text);
+    }
+
+    let isa = Isa { revision: IsaRevision::R6, ..isa };
+    let golden = [
+        (0x46861403, "cmp.ueq.s	$f16,$f2,$f6"),
+        (0x46A61404, "cmp.lt.d	$f16,$f2,$f6"),
+        (0x7C041020, "bitswap	v0,a0"),
+    ];
+    for &(instruction, text) in golden.iter() {
+        assert_eq!(format_instruction(instruction, 0x400108, isa, &symbols), text);
+    }
+}
+
+#[test]
+fn test_objdump_fixture() {
+    // hand-written until regenerated by tools/objdump_fixture.sh, see test_data/README.md
+    let expected = include_str!("../test_data/busybox-mipsel.objdump");
+    let output = disassemble_elf(
+        "mips_binaries/busybox-mipsel",
+        None,
+        Some(0x4000d0),
+        Some(0x400200),
+    ).unwrap();
+    assert_eq!(output, expected);
+}
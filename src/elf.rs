@@ -1,43 +1,358 @@
-
 use std::path::Path;
+use cpu::isa::{Abi, CompressedIsa, IsaRevision};
+use goblin::elf::Elf;
+use goblin::elf::header::{EM_MIPS, ET_DYN};
+use goblin::elf::program_header::{ProgramHeader, PF_R, PF_W, PF_X, PT_LOAD, PT_PHDR};
 use memory::Endianness;
-use memory::Memory;
+use memory::{Memory, KERNEL_SEGMENT_START, MMAP_BASE, PAGE_SIZE, PROT_EXEC, PROT_READ, PROT_WRITE};
 use std::fs::File;
 use goblin::error;
 use std::io::Read;
 
-/// Takes a file as an argument and returns memory image and an entry point
-pub fn load_elf(path: &str) -> error::Result<(Memory, u32)> {
+/// Position independent executables are loaded here, where ordinary ones usually start
+const PIE_BASE: u64 = 0x0040_0000;
+
+// Keys of the auxiliary vector describing the loaded program
+const AT_PHDR: u32 = 3;
+const AT_PHENT: u32 = 4;
+const AT_PHNUM: u32 = 5;
+const AT_BASE: u32 = 7;
+const AT_ENTRY: u32 = 9;
+
+/// Loaded program together with everything the CPU needs to know about it
+pub struct ElfImage {
+    pub memory: Memory,
+    /// Entry point as an offset in the address window of the memory, the interpreter's one once
+    /// it is loaded
+    pub entry_point: u32,
+    pub isa_revision: IsaRevision,
+    pub compressed_isa: Option<CompressedIsa>,
+    pub abi: Abi,
+    /// Dynamic linker requested by PT_INTERP
+    pub interpreter: Option<String>,
+    /// Entries of the auxiliary vector, which tell the dynamic linker where the program is
+    pub auxiliary_vector: Vec<(u32, u64)>,
+}
+
+/// Takes a file as an argument and returns memory image, an entry point, ISA revision the
+/// binary was compiled for, the compressed ISA it can switch to and its ABI. The interpreter of
+/// dynamically linked programs is not loaded, see `load_interpreter`.
+pub fn load_elf(path: &str) -> error::Result<ElfImage> {
     info!("Parsing ELF file and loading program image into memory");
     let path = Path::new(path);
+    let buffer = read_file(path)?;
+    let elf = parse_mips_elf(path, &buffer)?;
+
+    let mut memory = if elf.header.endianness()?.is_little() {
+        Memory::new(Endianness::LittleEndian)
+    } else {
+        Memory::new(Endianness::BigEndian)
+    };
+
+    // 64 bit programs are loaded into the 4GB window containing the entry point
+    let address_base = elf.header.e_entry & !0xFFFF_FFFF;
+    memory.set_address_base(address_base);
+
+    let bias = if elf.header.e_type == ET_DYN {
+        PIE_BASE.wrapping_sub(lowest_page(&elf)?)
+    } else {
+        0
+    };
+    let program_end = load_segments(&mut memory, &buffer, &elf, bias)?;
+    memory.set_heap_start(program_end);
+    let entry_point = elf.header.e_entry.wrapping_add(bias);
+    debug!("\tEntry point is at 0x{:x}", entry_point);
+
+    let revision = IsaRevision::from_elf_flags(elf.header.e_flags);
+    info!("\tDetected ISA revision {:?} (e_flags=0x{:x})", revision, elf.header.e_flags);
+    let compressed_isa = CompressedIsa::from_elf_flags(elf.header.e_flags);
+    let abi = Abi::from_elf_header(elf.is_64, elf.header.e_flags);
+    info!("\tDetected ABI {:?}", abi);
+
+    let mut auxiliary_vector = Vec::new();
+    if let Some(address) = program_headers_address(&elf, bias) {
+        auxiliary_vector.push((AT_PHDR, address));
+    }
+    auxiliary_vector.push((AT_PHENT, elf.header.e_phentsize as u64));
+    auxiliary_vector.push((AT_PHNUM, elf.header.e_phnum as u64));
+    auxiliary_vector.push((AT_BASE, 0));
+    auxiliary_vector.push((AT_ENTRY, entry_point));
+
+    Ok(ElfImage {
+        memory,
+        entry_point: entry_point as u32,
+        isa_revision: revision,
+        compressed_isa,
+        abi,
+        interpreter: elf.interpreter.map(|interpreter| interpreter.to_string()),
+        auxiliary_vector,
+    })
+}
+
+/// Loads the dynamic linker of the program from the path. Like a shared library, it is placed
+/// into free memory. The program then starts in it and the dynamic linker finds the program
+/// through the auxiliary vector.
+pub fn load_interpreter(image: &mut ElfImage, path: &str) -> error::Result<()> {
+    info!("Loading interpreter {}", path);
+    let path = Path::new(path);
+    let buffer = read_file(path)?;
+    let elf = parse_mips_elf(path, &buffer)?;
+    let little_endian = elf.header.endianness()?.is_little();
+    if Abi::from_elf_header(elf.is_64, elf.header.e_flags) != image.abi ||
+        little_endian == image.memory.is_big_endian()
+    {
+        return Err(malformed(
+            format!("{} does not match the ABI of the program", path.display()),
+        ));
+    }
+
+    let low = lowest_page(&elf)?;
+    let base = if elf.header.e_type == ET_DYN {
+        let high = elf.program_headers
+            .iter()
+            .filter(|ph| ph.p_type == PT_LOAD)
+            .map(|ph| ph.p_vaddr.saturating_add(ph.p_memsz))
+            .max()
+            .unwrap_or(low);
+        let size = high - low;
+        let address = if size <= KERNEL_SEGMENT_START as u64 {
+            image.memory.find_free_area(0, size as u32)
+        } else {
+            None
+        };
+        match address {
+            Some(address) => image.memory.get_address_base() + address as u64,
+            None => {
+                return Err(malformed(format!("No free memory for {}", path.display())));
+            }
+        }
+    } else {
+        low
+    };
+    let bias = base.wrapping_sub(low);
+    load_segments(&mut image.memory, &buffer, &elf, bias)?;
+
+    image.entry_point = elf.header.e_entry.wrapping_add(bias) as u32;
+    for entry in &mut image.auxiliary_vector {
+        if entry.0 == AT_BASE {
+            entry.1 = base;
+        }
+    }
+    debug!("\tInterpreter loaded at 0x{:x}, entry point is at 0x{:x}", base, image.entry_point);
+    Ok(())
+}
+
+fn read_file(path: &Path) -> error::Result<Vec<u8>> {
     let mut fd = File::open(path)?;
     let mut buffer = Vec::new();
     fd.read_to_end(&mut buffer)?;
-    if let ::goblin::Object::Elf(elf) = ::goblin::Object::parse(&buffer)? {
-        let mut memory = if elf.header.endianness().unwrap().is_little() {
-            Memory::new(Endianness::LittleEndian)
-        } else {
-            Memory::new(Endianness::BigEndian)
-        };
+    Ok(buffer)
+}
 
+/// Parses the file, anything else than a MIPS ELF binary is an error
+fn parse_mips_elf<'a>(path: &Path, buffer: &'a [u8]) -> error::Result<Elf<'a>> {
+    let elf = match ::goblin::Object::parse(buffer)? {
+        ::goblin::Object::Elf(elf) => elf,
+        _ => return Err(malformed(format!("{} is not an ELF binary", path.display()))),
+    };
+    if elf.header.e_machine != EM_MIPS {
+        return Err(malformed(format!(
+            "{} is not a MIPS binary (e_machine={})",
+            path.display(),
+            elf.header.e_machine
+        )));
+    }
+    Ok(elf)
+}
+
+/// Start of the first page of the lowest PT_LOAD segment
+fn lowest_page(elf: &Elf) -> error::Result<u64> {
+    elf.program_headers
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .map(|ph| ph.p_vaddr & !(PAGE_SIZE as u64 - 1))
+        .min()
+        .ok_or_else(|| malformed("No loadable segments".to_string()))
+}
 
-        for ph in elf.program_headers.into_iter() {
-            debug!("\t{:?}", ph);
-            debug!(
-                "\t   -> Need to copy from ELF file {} bytes from offset {} to address {}",
-                ph.p_filesz,
-                ph.p_offset,
-                ph.p_vaddr
-            );
+/// Loads all PT_LOAD segments moved by `bias` and returns the end of the program, where the heap
+/// can start
+fn load_segments(memory: &mut Memory, buffer: &[u8], elf: &Elf, bias: u64) -> error::Result<u32> {
+    let mut program_end = 0;
+    for ph in elf.program_headers.iter().filter(|ph| ph.p_type == PT_LOAD) {
+        debug!("\t{:?}", ph);
+        let address = ph.p_vaddr.wrapping_add(bias);
+        load_segment(memory, buffer, ph, address)?;
 
-            let data = &buffer.as_slice()[ph.p_offset as usize..
-                                              (ph.p_offset + ph.p_filesz) as usize];
-            memory.write_block_and_update_program_break(ph.p_vaddr as u32, data);
+        // coredumps contain the libraries and the stack too, the heap follows the segments of the
+        // program below the mmap area
+        let end = (address & 0xFFFF_FFFF) + ph.p_memsz;
+        if end <= MMAP_BASE as u64 {
+            program_end = program_end.max(end as u32);
         }
-        debug!("\tEntry point is at {:?}", elf.header.e_entry);
+    }
+    Ok(program_end)
+}
 
-        Ok((memory, elf.header.e_entry as u32))
+/// Address of the program headers in memory, `None` when no segment contains them
+fn program_headers_address(elf: &Elf, bias: u64) -> Option<u64> {
+    if let Some(ph) = elf.program_headers.iter().find(|ph| ph.p_type == PT_PHDR) {
+        return Some(ph.p_vaddr.wrapping_add(bias));
+    }
+    let offset = elf.header.e_phoff;
+    elf.program_headers
+        .iter()
+        .find(|ph| {
+            ph.p_type == PT_LOAD && ph.p_offset <= offset && offset - ph.p_offset < ph.p_filesz
+        })
+        .map(|ph| (ph.p_vaddr + (offset - ph.p_offset)).wrapping_add(bias))
+}
+
+/// Copies a PT_LOAD segment to the address and maps its pages with the segment's permissions.
+/// Like Linux, whole pages are loaded, so the file content preceding the segment in its first
+/// page is there too. The part of the segment past the file content (.bss) is zero filled.
+fn load_segment(
+    memory: &mut Memory,
+    buffer: &[u8],
+    ph: &ProgramHeader,
+    address: u64,
+) -> error::Result<()> {
+    if ph.p_memsz == 0 {
+        return Ok(());
+    }
+    let file_end = ph.p_offset.checked_add(ph.p_filesz);
+    if ph.p_filesz > ph.p_memsz || file_end.is_none_or(|end| end > buffer.len() as u64) {
+        return Err(malformed(format!("Segment at 0x{:x} does not fit the file", address)));
+    }
+    let memory_end = (address & 0xFFFF_FFFF).checked_add(ph.p_memsz);
+    if address & !0xFFFF_FFFF != memory.get_address_base() ||
+        memory_end.is_none_or(|end| end > KERNEL_SEGMENT_START as u64)
+    {
+        return Err(malformed(
+            format!("Segment at 0x{:x} is outside of the address window", address),
+        ));
+    }
+    if ph.p_align > 1 &&
+        (!ph.p_align.is_power_of_two() || ph.p_vaddr % ph.p_align != ph.p_offset % ph.p_align)
+    {
+        return Err(malformed(
+            format!("Segment at 0x{:x} has invalid alignment 0x{:x}", address, ph.p_align),
+        ));
+    }
+
+    let address = address as u32;
+    // the segment is mapped from the start of its page, the file offset must stay congruent
+    let page_offset = address & (PAGE_SIZE - 1);
+    let start = if ph.p_offset % PAGE_SIZE as u64 == page_offset as u64 {
+        page_offset
     } else {
-        panic!("File is not an ELF binary");
+        0
+    };
+    let data = &buffer[(ph.p_offset - start as u64) as usize..
+                           (ph.p_offset + ph.p_filesz) as usize];
+    debug!(
+        "\t   -> Loading {} bytes from offset {} to address 0x{:x}",
+        data.len(),
+        ph.p_offset - start as u64,
+        address - start
+    );
+    memory.write_block(address - start, data);
+    memory.zero_block(address + ph.p_filesz as u32, (ph.p_memsz - ph.p_filesz) as u32);
+    memory.map(address - page_offset, page_offset + ph.p_memsz as u32, page_protection(ph.p_flags));
+    Ok(())
+}
+
+fn malformed(message: String) -> error::Error {
+    error::Error::Malformed(message)
+}
+
+/// Page protection of a segment with the ELF flags
+fn page_protection(p_flags: u32) -> u8 {
+    let mut protection = 0;
+    if p_flags & PF_R != 0 {
+        protection |= PROT_READ;
+    }
+    if p_flags & PF_W != 0 {
+        protection |= PROT_WRITE;
     }
+    if p_flags & PF_X != 0 {
+        protection |= PROT_EXEC;
+    }
+    protection
+}
+
+#[test]
+fn test_load_segments() {
+    let image = load_elf("mips_binaries/busybox-mipsel").unwrap();
+    assert!(image.interpreter.is_none());
+    assert!(image.auxiliary_vector.contains(&(AT_PHDR, 0x400034)));
+    assert!(image.auxiliary_vector.contains(&(AT_ENTRY, 0x4001b0)));
+    let memory = image.memory;
+    // the first page of the text segment starts with the ELF header
+    assert_eq!(memory.read_word(0x400000), 0x464C457F);
+    assert_eq!(memory.get_protection(0x400000), Some(PROT_READ | PROT_EXEC));
+    assert_eq!(memory.get_protection(0x587000), Some(PROT_READ | PROT_WRITE));
+    assert_eq!(memory.get_protection(0x588000), None);
+    assert_eq!(memory.read_word(0x587000), 0);
+    assert_eq!(memory.program_break(), 0x588000);
+    assert!(load_elf("README.md").is_err());
+}
+
+/// Writes a position independent little endian ELF with one segment, 0x1000 bytes of memory of
+/// which the first 0x100 come from the file. The entry point 0x80 holds 0x12345678.
+#[cfg(test)]
+fn write_test_interpreter(path: &Path, e_flags: u32) {
+    let mut buffer = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let push16 = |buffer: &mut Vec<u8>, value: u16| buffer.extend(&value.to_le_bytes());
+    // e_type, e_machine
+    push16(&mut buffer, ET_DYN);
+    push16(&mut buffer, EM_MIPS);
+    // e_version, e_entry, e_phoff, e_shoff, e_flags
+    for &value in &[1, 0x80, 52, 0, e_flags] {
+        buffer.extend(&(value as u32).to_le_bytes());
+    }
+    // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    for &value in &[52, 32, 1, 40, 0, 0] {
+        push16(&mut buffer, value);
+    }
+    // PT_LOAD at offset 0 and address 0, readable and executable
+    for &value in &[PT_LOAD, 0, 0, 0, 0x100, 0x1000, PF_R | PF_X, 0x1000] {
+        buffer.extend(&value.to_le_bytes());
+    }
+    buffer.resize(0x100, 0);
+    buffer[0x80..0x84].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+    ::std::fs::write(path, buffer).unwrap();
+}
+
+#[test]
+fn test_load_interpreter() {
+    let path = ::std::env::temp_dir().join(format!("rumipsem-ld-{}.so", ::std::process::id()));
+    let path_str = path.to_str().unwrap();
+    write_test_interpreter(&path, 0);
+    let mut image = load_elf("mips_binaries/busybox-mipsel").unwrap();
+    load_interpreter(&mut image, path_str).unwrap();
+    let base = image
+        .auxiliary_vector
+        .iter()
+        .find(|entry| entry.0 == AT_BASE)
+        .unwrap()
+        .1 as u32;
+    // the interpreter is placed into free memory above the program
+    assert!(base >= MMAP_BASE);
+    assert_eq!(image.entry_point, base + 0x80);
+    assert_eq!(image.memory.read_word(image.entry_point), 0x1234_5678);
+    assert_eq!(image.memory.get_protection(base), Some(PROT_READ | PROT_EXEC));
+    // the program itself is still there
+    assert!(image.auxiliary_vector.contains(&(AT_ENTRY, 0x4001b0)));
+    assert_eq!(image.memory.read_word(0x400000), 0x464C457F);
+
+    // an n32 interpreter (EF_MIPS_ABI2) for an O32 program and a little endian one for a big
+    // endian program
+    write_test_interpreter(&path, 0x20);
+    let mut image = load_elf("mips_binaries/busybox-mipsel").unwrap();
+    assert!(load_interpreter(&mut image, path_str).is_err());
+    write_test_interpreter(&path, 0);
+    let mut image = load_elf("mips_binaries/busybox-mips").unwrap();
+    assert!(load_interpreter(&mut image, path_str).is_err());
+    ::std::fs::remove_file(path).unwrap();
 }
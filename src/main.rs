@@ -16,22 +16,34 @@ extern crate simplelog;
 
 mod args;
 mod cpu;
+mod disasm;
 mod elf;
 mod memory;
+mod mmap;
 mod mylog;
 mod syscall_numbers;
 mod syscalls;
 
 use args::parse_arguments;
 use cpu::control::CPUFlags;
-use elf::load_elf;
+use elf::{load_elf, load_interpreter, ElfImage};
 use mylog::configure_logging;
+use std::path::Path;
+use syscalls::guest_path;
 
 fn main() {
     let args = parse_arguments();
     configure_logging(args.verbosity_level);
 
-    if args.is_coredump {
+    if args.disassemble {
+        let output = disasm::disassemble_elf(
+            args.executable.as_str(),
+            args.flags.isa_revision,
+            args.start_address,
+            args.stop_address,
+        ).unwrap_or_else(|error| exit_with_load_error(&args.executable, error));
+        print!("{}", output);
+    } else if args.is_coredump {
         let entry_point = args.entry_point.expect(
             "Coredumps do not contain entry point. Must be specified manually.",
         );
@@ -49,27 +61,47 @@ fn main() {
 /// by other means.
 pub fn run_coredump(path: String, entry_point: u32, stack_pointer: u32, flags: CPUFlags) {
     // initialize memory
-    let (memory, _) = load_elf(path.as_str()).expect("Failed to process ELF file");
+    let image = load_elf(path.as_str()).unwrap_or_else(|error| exit_with_load_error(&path, error));
+    let flags = configure_for_image(flags, &image);
 
     // run
     info!("Starting CPU loop:");
-    cpu::control::EmulatorContext::start(memory, stack_pointer, entry_point, flags);
+    cpu::control::EmulatorContext::start(image.memory, stack_pointer, entry_point, flags);
 
     info!("Program terminated gracefully");
 }
 
 
-/// Loads and runs ordinary statically compiled ELF binaries.
+/// Loads and runs ordinary ELF binaries. Dynamically linked ones start in their interpreter, which
+/// is looked up in the sysroot like all absolute paths of the program.
 pub fn run_binary(path: String, arguments: Vec<String>, flags: CPUFlags) {
     //initialize memory and stack
-    let (mut memory, entry_point) = load_elf(path.as_str()).expect("Failed to process ELF file");
+    let mut image =
+        load_elf(path.as_str()).unwrap_or_else(|error| exit_with_load_error(&path, error));
+    if let Some(interpreter) = image.interpreter.clone() {
+        let sysroot = flags.syscalls_conf.sysroot.as_deref();
+        let interpreter = guest_path(sysroot, Path::new(&interpreter));
+        let interpreter = interpreter.to_string_lossy();
+        load_interpreter(&mut image, &interpreter)
+            .unwrap_or_else(|error| exit_with_load_error(&interpreter, error));
+    }
+    let flags = configure_for_image(flags, &image);
+    let (mut memory, entry_point) = (image.memory, image.entry_point);
 
     let mut arguments = arguments;
     arguments.insert(0, path);
 
     let environment_vars = std::env::vars().into_iter().collect();
-    let stack_pointer = 0x7ffffe50;
-    memory.initialize_stack_at(stack_pointer, environment_vars, arguments);
+    // arguments and environment are stored above the stack pointer and everything must stay below
+    // the kernel segment at 0x80000000
+    let stack_pointer = 0x7ff00000;
+    memory.initialize_stack_at(
+        stack_pointer,
+        environment_vars,
+        arguments,
+        &image.auxiliary_vector,
+        flags.abi,
+    );
 
     // run
     info!("Starting CPU loop:");
@@ -77,3 +109,20 @@ pub fn run_binary(path: String, arguments: Vec<String>, flags: CPUFlags) {
 
     info!("Program terminated gracefully");
 }
+
+/// Loading errors are reported to the user, they are not bugs of the emulator
+fn exit_with_load_error(path: &str, error: ::goblin::error::Error) -> ! {
+    eprintln!("Failed to load ELF file {}: {}", path, error);
+    ::std::process::exit(1);
+}
+
+/// Fills in CPU configuration, which is decided by the binary itself. 64 bit ABIs always use
+/// 64 bit FPU registers.
+fn configure_for_image(flags: CPUFlags, image: &ElfImage) -> CPUFlags {
+    let mut flags = flags;
+    flags.isa_revision = flags.isa_revision.or(Some(image.isa_revision));
+    flags.compressed_isa = image.compressed_isa;
+    flags.abi = image.abi;
+    flags.fpu_64bit |= image.abi.is_64bit();
+    flags
+}
@@ -1,24 +1,83 @@
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, NativeEndian};
+use cpu::event::ExceptionKind;
+use cpu::isa::Abi;
 use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
 
 pub const MEMORY_SIZE: usize = 0xFF_FF_FF_FF + 1;
+/// Addresses from here up are reserved for the kernel, user programs can't access them.
+pub const KERNEL_SEGMENT_START: u32 = 0x8000_0000;
+/// Granularity of the LL/SC monitor, cache line size of 74Kc
+pub const LINK_LINE_SIZE: u32 = 32;
+/// Granularity of memory protection
+pub const PAGE_SIZE: u32 = 4096;
+/// Granularity of code invalidation for the decoded block cache
+pub const CODE_PAGE_SIZE: u32 = PAGE_SIZE;
+/// Stack of programs started by the emulator, it ends at the kernel segment
+pub const STACK_SIZE: u32 = 8 * 1024 * 1024;
+
+// Page protection bits, the same values as PROT_* flags of mmap
+pub const PROT_READ: u8 = 0b001;
+pub const PROT_WRITE: u8 = 0b010;
+pub const PROT_EXEC: u8 = 0b100;
+/// Set in the page table for mapped pages, so that PROT_NONE pages differ from unmapped ones
+const PAGE_MAPPED: u8 = 0b1000;
+
+/// Mappings without a fixed address are placed above this address, as on Linux
+pub const MMAP_BASE: u32 = 0x2AAA_B000;
 
 pub enum Endianness {
     LittleEndian,
     BigEndian,
 }
 
+/// Memory access checked by `Memory::check_access`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    /// Protection bits allowing the access, writable pages are readable as on the real CPU
+    fn protection(self) -> u8 {
+        match self {
+            Access::Read => PROT_READ | PROT_WRITE,
+            Access::Write => PROT_WRITE,
+            Access::Execute => PROT_EXEC,
+        }
+    }
+}
+
 /// This simple data structure represents the 4GB RAM of the emulated machine. But we don't want
 /// to hold onto 4GB of real RAM, when we don't actually need it. The trick here is, that when
 /// we create the Vector filled with zeros, Rust runtime will trust the OS to provide zeroed
 /// memory. And because the Linux kernel uses copy-on-write, we can actually allocate all memory
 /// we need and the request will be fullfilled lazily. So the initial allocation does not take
 /// a lot of space and time.
+///
+/// n64 programs get the same 4GB, they are a window of the 64 bit address space. Its upper 32
+/// bits are stored in `address_base` and ignored by all memory accesses.
+///
+/// Which pages are mapped and their protection is tracked in a page table. Instructions of the
+/// program check their accesses with `check_access`, the `read_*` and `write_*` methods themselves
+/// don't, because the emulator uses them to load the program and to implement syscalls.
 pub struct Memory {
     endianness: Endianness,
+    address_base: u64,
+    /// The heap spans from here to the program break
+    heap_start: u32,
     program_break: u32,
+    /// Cache line watched for LL/SC, any store to it breaks the link
+    link_monitor: Option<u32>,
+    /// Bitmap of pages with instructions in the decoded block cache
+    code_pages: Vec<u64>,
+    /// Code pages written to since the block cache looked last, `None` means flush everything
+    modified_code: Option<Vec<u32>>,
+    /// Protection bits of every page, zero for unmapped pages
+    pages: Vec<u8>,
     data: Vec<u8>,
 }
 
@@ -27,7 +86,167 @@ impl Memory {
         Memory {
             endianness,
             data: vec![0; MEMORY_SIZE],
+            address_base: 0,
+            heap_start: 0,
             program_break: 0,
+            link_monitor: None,
+            code_pages: vec![0; MEMORY_SIZE / CODE_PAGE_SIZE as usize / 64],
+            modified_code: Some(Vec::new()),
+            pages: vec![0; MEMORY_SIZE / PAGE_SIZE as usize],
+        }
+    }
+
+    /// Maps pages containing the range with the given protection. Their content is kept.
+    pub fn map(&mut self, address: u32, len: u32, protection: u8) {
+        self.set_protection(address, len, PAGE_MAPPED | protection);
+    }
+
+    pub fn unmap(&mut self, address: u32, len: u32) {
+        self.set_protection(address, len, 0);
+    }
+
+    /// Changes protection of mapped pages in the range, unmapped pages stay unmapped
+    pub fn protect(&mut self, address: u32, len: u32, protection: u8) {
+        for page in Memory::page_range(address, len) {
+            if self.pages[page] & PAGE_MAPPED != 0 {
+                self.pages[page] = PAGE_MAPPED | protection;
+                self.drop_code_page(page as u32 * PAGE_SIZE);
+            }
+        }
+    }
+
+    fn set_protection(&mut self, address: u32, len: u32, protection: u8) {
+        for page in Memory::page_range(address, len) {
+            self.pages[page] = protection;
+            self.drop_code_page(page as u32 * PAGE_SIZE);
+        }
+    }
+
+    /// Cached instructions are dropped, when the page becomes non-executable
+    fn drop_code_page(&mut self, address: u32) {
+        if self.is_code_page(address) {
+            self.invalidate_code_page(address);
+        }
+    }
+
+    fn page_range(address: u32, len: u32) -> ::std::ops::Range<usize> {
+        if len == 0 {
+            return 0..0;
+        }
+        let last = address as u64 + len as u64 - 1;
+        let last = last.min(MEMORY_SIZE as u64 - 1);
+        (address / PAGE_SIZE) as usize..(last / PAGE_SIZE as u64) as usize + 1
+    }
+
+    /// Protection of the page with the address, `None` when the page is not mapped
+    pub fn get_protection(&self, address: u32) -> Option<u8> {
+        let protection = self.pages[(address / PAGE_SIZE) as usize];
+        if protection & PAGE_MAPPED != 0 {
+            Some(protection & !PAGE_MAPPED)
+        } else {
+            None
+        }
+    }
+
+    /// Checks, that all pages of the range are mapped
+    pub fn is_mapped_range(&self, address: u32, len: u32) -> bool {
+        Memory::page_range(address, len).all(|page| self.pages[page] & PAGE_MAPPED != 0)
+    }
+
+    /// Checks, that no page of the range is mapped
+    pub fn is_free_range(&self, address: u32, len: u32) -> bool {
+        Memory::page_range(address, len).all(|page| self.pages[page] & PAGE_MAPPED == 0)
+    }
+
+    /// Finds unmapped pages for a new mapping of `len` bytes. The hint is used, when the area
+    /// there is free, otherwise the lowest free area above `MMAP_BASE` is returned.
+    pub fn find_free_area(&self, hint: u32, len: u32) -> Option<u32> {
+        if len == 0 || len > KERNEL_SEGMENT_START {
+            return None;
+        }
+        let fits = |address: u32| address as u64 + len as u64 <= KERNEL_SEGMENT_START as u64;
+        let aligned = hint & (PAGE_SIZE - 1) == 0;
+        if hint != 0 && aligned && fits(hint) && self.is_free_range(hint, len) {
+            return Some(hint);
+        }
+
+        let needed = Memory::page_range(0, len).len();
+        let mut free = 0;
+        for page in (MMAP_BASE / PAGE_SIZE) as usize..(KERNEL_SEGMENT_START / PAGE_SIZE) as usize {
+            if self.pages[page] & PAGE_MAPPED != 0 {
+                free = 0;
+            } else {
+                free += 1;
+                if free == needed {
+                    return Some((page + 1 - needed) as u32 * PAGE_SIZE);
+                }
+            }
+        }
+        None
+    }
+
+    /// Checks, that the program can access `len` bytes at the address. Accesses of unmapped pages
+    /// and accesses, that the protection does not allow, raise a segmentation fault.
+    pub fn check_access(
+        &self,
+        address: u32,
+        len: u32,
+        access: Access,
+    ) -> Result<(), ExceptionKind> {
+        for page in Memory::page_range(address, len) {
+            let protection = self.pages[page];
+            if protection & PAGE_MAPPED == 0 {
+                let fault = address.max(page as u32 * PAGE_SIZE);
+                return Err(ExceptionKind::SegmentationFault(fault));
+            }
+            if protection & access.protection() == 0 {
+                let fault = address.max(page as u32 * PAGE_SIZE);
+                return Err(ExceptionKind::ProtectionFault(fault));
+            }
+        }
+        Ok(())
+    }
+
+    /// Raw pointer to the page table for translated code, see `check_access`
+    pub fn get_page_table_ptr(&self) -> *const u8 {
+        self.pages.as_ptr()
+    }
+
+    pub fn get_address_base(&self) -> u64 {
+        self.address_base
+    }
+
+    pub fn set_address_base(&mut self, address_base: u64) {
+        self.address_base = address_base;
+    }
+
+    /// Raw pointer to the memory for translated code. Stores must still go through `write_*`.
+    pub fn get_data_ptr(&mut self) -> *mut u8 {
+        self.data.as_mut_ptr()
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        match self.endianness {
+            Endianness::BigEndian => true,
+            Endianness::LittleEndian => false,
+        }
+    }
+
+    /// Reads a pointer of the ABI and returns its offset in the address window
+    pub fn read_pointer(&self, address: u32, abi: Abi) -> u32 {
+        if abi == Abi::N64 {
+            self.read_doubleword(address) as u32
+        } else {
+            self.read_word(address)
+        }
+    }
+
+    pub fn write_pointer(&mut self, address: u32, value: u32, abi: Abi) {
+        if abi == Abi::N64 {
+            let value = if value == 0 { 0 } else { self.address_base | value as u64 };
+            self.write_doubleword(address, value);
+        } else {
+            self.write_word(address, value);
         }
     }
 
@@ -36,9 +255,69 @@ impl Memory {
     }
 
     pub fn write_byte(&mut self, address: u32, value: u32) {
+        if self.link_monitor == Some(address & !(LINK_LINE_SIZE - 1)) {
+            self.link_monitor = None;
+        }
+        if self.is_code_page(address) {
+            self.invalidate_code_page(address);
+        }
         self.data[address as usize] = value as u8;
     }
 
+    fn is_code_page(&self, address: u32) -> bool {
+        let page = address / CODE_PAGE_SIZE;
+        self.code_pages[page as usize / 64] & (1 << (page % 64)) != 0
+    }
+
+    /// Marks the page with the address as containing cached instructions.
+    pub fn mark_code_page(&mut self, address: u32) {
+        let page = address / CODE_PAGE_SIZE;
+        self.code_pages[page as usize / 64] |= 1 << (page % 64);
+    }
+
+    /// Drops cached instructions of the page with the address, called on stores and SYNCI.
+    pub fn invalidate_code_page(&mut self, address: u32) {
+        let page = address / CODE_PAGE_SIZE;
+        self.code_pages[page as usize / 64] &= !(1 << (page % 64));
+        if let Some(pages) = self.modified_code.as_mut() {
+            pages.push(page * CODE_PAGE_SIZE);
+        }
+    }
+
+    /// Drops all cached instructions, used by the `cacheflush` syscall.
+    pub fn flush_code(&mut self) {
+        for bits in self.code_pages.iter_mut().filter(|bits| **bits != 0) {
+            *bits = 0;
+        }
+        self.modified_code = None;
+    }
+
+    /// Returns code pages modified since the last call, `None` when the whole cache was flushed.
+    pub fn take_modified_code(&mut self) -> Option<Vec<u32>> {
+        self.modified_code.replace(Vec::new())
+    }
+
+    pub fn is_code_modified(&self) -> bool {
+        match self.modified_code {
+            Some(ref pages) => !pages.is_empty(),
+            None => true,
+        }
+    }
+
+    /// Starts watching the cache line with the address for LL/SC. Only one line is watched.
+    pub fn monitor_line(&mut self, address: u32) {
+        self.link_monitor = Some(address & !(LINK_LINE_SIZE - 1));
+    }
+
+    /// Checks, that there was no store to the line since `monitor_line`.
+    pub fn is_line_monitored(&self, address: u32) -> bool {
+        self.link_monitor == Some(address & !(LINK_LINE_SIZE - 1))
+    }
+
+    pub fn clear_monitor(&mut self) {
+        self.link_monitor = None;
+    }
+
     pub fn read_halfword(&self, address: u32) -> u32 {
         match self.endianness {
             Endianness::LittleEndian => LittleEndian::read_u16(self.read_slice(address, 2)) as u32,
@@ -53,6 +332,13 @@ impl Memory {
         }
     }
 
+    pub fn read_doubleword(&self, address: u32) -> u64 {
+        match self.endianness {
+            Endianness::LittleEndian => LittleEndian::read_u64(self.read_slice(address, 8)),
+            Endianness::BigEndian => BigEndian::read_u64(self.read_slice(address, 8)),
+        }
+    }
+
     pub fn read_slice(&self, address: u32, len: u32) -> &[u8] {
         &self.data[address as usize..(address + len) as usize]
     }
@@ -61,6 +347,12 @@ impl Memory {
         self.read_word(address)
     }
 
+    /// Compressed instructions are fetched by halfwords, 32 bit ones have the most significant
+    /// halfword first regardless of endianness.
+    pub fn fetch_halfword(&self, address: u32) -> u32 {
+        self.read_halfword(address)
+    }
+
     pub fn write_halfword(&mut self, address: u32, value: u32) {
         match self.endianness {
             Endianness::BigEndian => {
@@ -91,138 +383,155 @@ impl Memory {
         }
     }
 
-    /// SWL instruction support
-    pub fn write_word_unaligned_swl(&mut self, eff_address: u32, value: u32) {
-        let vaddr = eff_address % 4;
-        let addr = eff_address - vaddr;
-        // Spagetti code, but hopefully more understandable
+    pub fn write_doubleword(&mut self, address: u32, value: u64) {
         match self.endianness {
             Endianness::BigEndian => {
-                match vaddr {
-                    0 => {
-                        self.write_byte(addr + 3, value >> 0);
-                        self.write_byte(addr + 2, value >> 8);
-                        self.write_byte(addr + 1, value >> 16);
-                        self.write_byte(addr + 0, value >> 24);
-                    }
-                    1 => {
-                        self.write_byte(addr + 3, value >> 8);
-                        self.write_byte(addr + 2, value >> 16);
-                        self.write_byte(addr + 1, value >> 24);
-                    }
-                    2 => {
-                        self.write_byte(addr + 3, value >> 16);
-                        self.write_byte(addr + 2, value >> 24);
-                    }
-                    3 => {
-                        self.write_byte(addr + 3, value >> 24);
-                    }
-                    _ => unreachable!(),
-                }
+                self.write_word(address, (value >> 32) as u32);
+                self.write_word(address + 4, value as u32);
             }
             Endianness::LittleEndian => {
-                match vaddr {
-                    0 => {
-                        self.write_byte(addr + 3, value >> 24);
-                    }
-                    1 => {
-                        self.write_byte(addr + 3, value >> 16);
-                        self.write_byte(addr + 2, value >> 24);
-                    }
-                    2 => {
-                        self.write_byte(addr + 3, value >> 8);
-                        self.write_byte(addr + 2, value >> 16);
-                        self.write_byte(addr + 1, value >> 24);
-                    }
-                    3 => {
-                        self.write_byte(addr + 3, value >> 0);
-                        self.write_byte(addr + 2, value >> 8);
-                        self.write_byte(addr + 1, value >> 16);
-                        self.write_byte(addr + 0, value >> 24);
-                    }
-                    _ => unreachable!(),
-                }
+                self.write_word(address, value as u32);
+                self.write_word(address + 4, (value >> 32) as u32);
             }
         }
     }
 
+    /// LDL instruction support, the most significant bytes of the register are replaced
+    pub fn read_doubleword_unaligned_ldl(&self, eff_address: u32, register: u64) -> u64 {
+        let shift = self.unaligned_doubleword_shift(eff_address, true);
+        let word = self.read_doubleword(eff_address & !0b111);
+        let keep = if shift == 0 { 0 } else { u64::MAX >> (64 - shift) };
+        (word << shift) | (register & keep)
+    }
+
+    /// LDR instruction support, the least significant bytes of the register are replaced
+    pub fn read_doubleword_unaligned_ldr(&self, eff_address: u32, register: u64) -> u64 {
+        let shift = self.unaligned_doubleword_shift(eff_address, false);
+        let word = self.read_doubleword(eff_address & !0b111);
+        let keep = if shift == 0 { 0 } else { !(u64::MAX >> shift) };
+        (word >> shift) | (register & keep)
+    }
+
+    /// SDL instruction support
+    pub fn write_doubleword_unaligned_sdl(&mut self, eff_address: u32, value: u64) {
+        let shift = self.unaligned_doubleword_shift(eff_address, true);
+        let address = eff_address & !0b111;
+        let word = self.read_doubleword(address);
+        let keep = if shift == 0 { 0 } else { !(u64::MAX >> shift) };
+        self.write_doubleword(address, (word & keep) | (value >> shift));
+    }
+
+    /// SDR instruction support
+    pub fn write_doubleword_unaligned_sdr(&mut self, eff_address: u32, value: u64) {
+        let shift = self.unaligned_doubleword_shift(eff_address, false);
+        let address = eff_address & !0b111;
+        let word = self.read_doubleword(address);
+        let keep = if shift == 0 { 0 } else { u64::MAX >> (64 - shift) };
+        self.write_doubleword(address, (word & keep) | (value << shift));
+    }
+
+    /// Number of bits the aligned doubleword is shifted by in the unaligned instructions. The
+    /// left variants address the most significant byte, so the shift goes the other way on
+    /// little endian.
+    fn unaligned_doubleword_shift(&self, eff_address: u32, left: bool) -> u32 {
+        let byte = eff_address & 0b111;
+        let byte = if self.is_big_endian() == left { byte } else { 7 - byte };
+        byte * 8
+    }
+
+    /// LWL instruction support, the most significant bytes of the register are replaced
+    pub fn read_word_unaligned_lwl(&self, eff_address: u32, register: u32) -> u32 {
+        let shift = self.unaligned_word_shift(eff_address, true);
+        let word = self.read_word(eff_address & !0b11);
+        let keep = if shift == 0 { 0 } else { u32::MAX >> (32 - shift) };
+        (word << shift) | (register & keep)
+    }
+
+    /// LWR instruction support, the least significant bytes of the register are replaced
+    pub fn read_word_unaligned_lwr(&self, eff_address: u32, register: u32) -> u32 {
+        let shift = self.unaligned_word_shift(eff_address, false);
+        let word = self.read_word(eff_address & !0b11);
+        let keep = if shift == 0 { 0 } else { !(u32::MAX >> shift) };
+        (word >> shift) | (register & keep)
+    }
+
+    /// Word variant of `unaligned_doubleword_shift`
+    fn unaligned_word_shift(&self, eff_address: u32, left: bool) -> u32 {
+        let byte = eff_address & 0b11;
+        let byte = if self.is_big_endian() == left { byte } else { 3 - byte };
+        byte * 8
+    }
+
+    /// SWL instruction support
+    pub fn write_word_unaligned_swl(&mut self, eff_address: u32, value: u32) {
+        let shift = self.unaligned_word_shift(eff_address, true);
+        let address = eff_address & !0b11;
+        let word = self.read_word(address);
+        let keep = if shift == 0 { 0 } else { !(u32::MAX >> shift) };
+        self.write_word(address, (word & keep) | (value >> shift));
+    }
+
     /// SWR instruction support
     pub fn write_word_unaligned_swr(&mut self, eff_address: u32, value: u32) {
-        let vaddr = eff_address % 4;
-        let address = eff_address - vaddr;
-        // spagetti again, yay
-        match self.endianness {
-            Endianness::BigEndian => {
-                match vaddr {
-                    0 => {
-                        self.write_byte(address + 0, value >> 0);
-                    }
-                    1 => {
-                        self.write_byte(address + 0, value >> 8);
-                        self.write_byte(address + 1, value >> 0);
-                    }
-                    2 => {
-                        self.write_byte(address + 0, value >> 16);
-                        self.write_byte(address + 1, value >> 8);
-                        self.write_byte(address + 2, value >> 0);
-                    }
-                    3 => {
-                        self.write_byte(address + 0, value >> 24);
-                        self.write_byte(address + 1, value >> 16);
-                        self.write_byte(address + 2, value >> 8);
-                        self.write_byte(address + 3, value >> 0);
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            Endianness::LittleEndian => {
-                match vaddr {
-                    0 => {
-                        self.write_byte(address + 0, value >> 24);
-                        self.write_byte(address + 1, value >> 16);
-                        self.write_byte(address + 2, value >> 8);
-                        self.write_byte(address + 3, value >> 0);
-                    }
-                    1 => {
-                        self.write_byte(address + 0, value >> 16);
-                        self.write_byte(address + 1, value >> 8);
-                        self.write_byte(address + 2, value >> 0);
-                    }
-                    2 => {
-                        self.write_byte(address + 0, value >> 8);
-                        self.write_byte(address + 1, value >> 0);
-                    }
-                    3 => {
-                        self.write_byte(address + 0, value >> 0);
-                    }
-                    _ => unreachable!(),
-                }
-            }
-        }
+        let shift = self.unaligned_word_shift(eff_address, false);
+        let address = eff_address & !0b11;
+        let word = self.read_word(address);
+        let keep = if shift == 0 { 0 } else { u32::MAX >> (32 - shift) };
+        self.write_word(address, (word & keep) | (value << shift));
     }
 
-    pub fn write_block_and_update_program_break(&mut self, address: u32, data: &[u8]) {
+    pub fn write_block(&mut self, address: u32, data: &[u8]) {
         if data.len() == 0 {
             return;
         }
 
-        // FIXME Coredumps contain stack, but we want program break address to be lower than that
-        // so we just expect the program break to be lower than 0x70000000
-        if address + (data.len() as u32) < 0x70000000 {
-            self.program_break = self.program_break.max(address + (data.len() as u32));
+        self.block_written(address, data.len() as u32);
+        let data_slice = &mut (self.data.as_mut_slice()[address as usize..
+                                                            (address as usize + data.len())]);
+        data_slice.copy_from_slice(data);
+    }
+
+    pub fn read_block(&self, address: u32, len: u32) -> &[u8] {
+        &self.data[address as usize..address as usize + len as usize]
+    }
+
+    /// Fills the range with zeros, e.g. pages of a new anonymous mapping
+    pub fn zero_block(&mut self, address: u32, len: u32) {
+        if len == 0 {
+            return;
         }
 
-        self.write_block(address, data);
+        self.block_written(address, len);
+        for byte in &mut self.data[address as usize..address as usize + len as usize] {
+            *byte = 0;
+        }
     }
 
-    pub fn write_block(&mut self, address: u32, data: &[u8]) {
-        if data.len() == 0 {
+    /// Copies `len` bytes, the ranges may overlap
+    pub fn copy_block(&mut self, from: u32, to: u32, len: u32) {
+        if len == 0 {
             return;
         }
 
-        let data_slice = &mut (self.data.as_mut_slice()[address as usize..
-                                                            (address as usize + data.len())]);
-        data_slice.copy_from_slice(data);
+        self.block_written(to, len);
+        self.data.copy_within(from as usize..from as usize + len as usize, to as usize);
+    }
+
+    /// Breaks the LL/SC link and drops cached code, that the block write overwrote
+    fn block_written(&mut self, address: u32, len: u32) {
+        // computed in 64 bits, the block may end at the top of the address space
+        let end = address as u64 + len as u64;
+        if let Some(line) = self.link_monitor {
+            if (line as u64) < end && address as u64 <= line as u64 + (LINK_LINE_SIZE as u64 - 1) {
+                self.link_monitor = None;
+            }
+        }
+        let last = (end - 1).min(MEMORY_SIZE as u64 - 1) as u32;
+        for page in (address / CODE_PAGE_SIZE)..=(last / CODE_PAGE_SIZE) {
+            if self.is_code_page(page * CODE_PAGE_SIZE) {
+                self.invalidate_code_page(page * CODE_PAGE_SIZE);
+            }
+        }
     }
 
     pub fn translate_address(&self, address: u32) -> *const u8 {
@@ -233,6 +542,7 @@ impl Memory {
         self.data[address as usize..].as_ptr()
     }
 
+    /// Writes through the returned pointer are not seen by the LL/SC monitor nor the block cache.
     pub fn translate_address_mut(&mut self, address: u32) -> *mut u8 {
         if address == 0 {
             return 0 as *mut u8;
@@ -241,12 +551,39 @@ impl Memory {
         self.data[address as usize..].as_mut_ptr()
     }
 
-    pub fn get_program_break(&self) -> u32 {
+    #[cfg(test)]
+    pub fn program_break(&self) -> u32 {
         self.program_break
     }
 
-    pub fn update_program_break(&mut self, new_value: u32) {
+    /// Places an empty heap at the first page boundary from the address, the end of the program
+    pub fn set_heap_start(&mut self, address: u32) {
+        let start = Memory::page_range(0, address).len() as u32 * PAGE_SIZE;
+        self.heap_start = start;
+        self.program_break = start;
+    }
+
+    /// Moves the program break like `brk` does and returns the resulting break. The heap can't
+    /// shrink below its start nor grow over other mappings or the stack, the old break is returned
+    /// then. New heap pages are zero filled, pages above the break are unmapped.
+    pub fn set_program_break(&mut self, new_value: u32) -> u32 {
+        if new_value < self.heap_start || new_value > KERNEL_SEGMENT_START {
+            return self.program_break;
+        }
+
+        let old_end = Memory::page_range(0, self.program_break).len() as u32 * PAGE_SIZE;
+        let new_end = Memory::page_range(0, new_value).len() as u32 * PAGE_SIZE;
+        if new_end > old_end {
+            if !self.is_free_range(old_end, new_end - old_end) {
+                return self.program_break;
+            }
+            self.map(old_end, new_end - old_end, PROT_READ | PROT_WRITE);
+            self.zero_block(old_end, new_end - old_end);
+        } else if new_end < old_end {
+            self.unmap(new_end, old_end - new_end);
+        }
         self.program_break = new_value;
+        new_value
     }
 
     pub fn initialize_stack_at(
@@ -254,20 +591,31 @@ impl Memory {
         address: u32,
         environment_variables: Vec<(String, String)>,
         arguments: Vec<String>,
+        auxiliary_vector: &[(u32, u64)],
+        abi: Abi,
     ) {
-        assert_eq!(address % 8, 0);
+        assert_eq!(address % 16, 0);
         info!("Generating new stack");
-        let mut pointer_address = address + 4;
+        // the stack grows down from the address, arguments and environment are above it
+        let bottom = address - STACK_SIZE;
+        self.map(bottom, KERNEL_SEGMENT_START - bottom, PROT_READ | PROT_WRITE);
+        // argc and all pointers are `long` sized
+        let size = abi.pointer_size();
+        let mut pointer_address = address + size;
         let mut data_address = pointer_address +
-            (1 + arguments.len() as u32 + 1 + environment_variables.len() as u32 + 1 + 40) * 4;
+            (1 + arguments.len() as u32 + 1 + environment_variables.len() as u32 + 1 + 40) * size;
 
         debug!("\tArguments: {}", arguments.len());
-        self.write_word(address, arguments.len() as u32);
+        if size == 8 {
+            self.write_doubleword(address, arguments.len() as u64);
+        } else {
+            self.write_word(address, arguments.len() as u32);
+        }
         // arguments
         for argument in arguments {
             debug!("\t\tArg: \"{}\" at 0x{:x}", argument, data_address);
-            self.write_word(pointer_address, data_address);
-            pointer_address += 4;
+            self.write_pointer(pointer_address, data_address, abi);
+            pointer_address += size;
 
             for c in argument.bytes() {
                 self.write_byte(data_address, c as u32);
@@ -278,15 +626,15 @@ impl Memory {
         }
 
         // zero after argument pointers
-        self.write_word(pointer_address, 0);
-        pointer_address += 4;
+        self.write_pointer(pointer_address, 0, abi);
+        pointer_address += size;
 
         // environment variables
         debug!("\tEnvironment variables:");
         for (name, value) in environment_variables {
             debug!("\t\t Env: {}=\"{}\"", name, value);
-            self.write_word(pointer_address, data_address);
-            pointer_address += 4;
+            self.write_pointer(pointer_address, data_address, abi);
+            pointer_address += size;
 
             for c in name.bytes() {
                 self.write_byte(data_address, c as u32);
@@ -303,8 +651,8 @@ impl Memory {
         }
 
         // zero after environment variables pointers
-        self.write_word(pointer_address, 0);
-        pointer_address += 4;
+        self.write_pointer(pointer_address, 0, abi);
+        pointer_address += size;
 
 
         // auxiliary vector
@@ -316,13 +664,23 @@ impl Memory {
             .expect("Could not read auxv file in /proc/self");
 
 
-        let mut write_vector = |key: u32, val: u32| {
+        let mut write_vector = |key: u32, val: u64| {
             debug!("\t\tauxv key={} value=0x{:x}", key, val);
-            self.write_word(pointer_address, key);
-            self.write_word(pointer_address + 4, val);
-            pointer_address += 8;
+            if size == 8 {
+                self.write_doubleword(pointer_address, key as u64);
+                self.write_doubleword(pointer_address + 8, val);
+            } else {
+                self.write_word(pointer_address, key);
+                self.write_word(pointer_address + 4, val as u32);
+            }
+            pointer_address += 2 * size;
         };
 
+        // entries describing the program come from the loader, the rest from the host
+        for &(key, val) in auxiliary_vector {
+            write_vector(key, val);
+        }
+
         let mut rdr = Cursor::new(auxv);
 
         loop {
@@ -332,7 +690,7 @@ impl Memory {
             {
                 match v {
                     0 | 1 | 2 | 6 | 8 | 11 | 12 | 13 | 14 | 17 => {
-                        write_vector(v as u32, val as u32);
+                        write_vector(v as u32, val);
                     }
                     _ => {}
                 }
@@ -344,3 +702,43 @@ impl Memory {
         }
     }
 }
+
+#[test]
+fn test_page_protection() {
+    let mut memory = Memory::new(Endianness::LittleEndian);
+    memory.map(0x400000, 0x1800, PROT_READ | PROT_EXEC);
+    memory.map(0x402000, 0x1000, PROT_READ | PROT_WRITE);
+    assert!(memory.check_access(0x401ffc, 4, Access::Execute).is_ok());
+    assert!(memory.check_access(0x402000, 4, Access::Execute).is_err());
+    assert_eq!(
+        memory.check_access(0x401ffe, 4, Access::Write),
+        Err(ExceptionKind::ProtectionFault(0x401ffe))
+    );
+    assert_eq!(
+        memory.check_access(0x402ffe, 4, Access::Read),
+        Err(ExceptionKind::SegmentationFault(0x403000))
+    );
+}
+
+#[test]
+fn test_program_break() {
+    let mut memory = Memory::new(Endianness::LittleEndian);
+    memory.set_heap_start(0x410010);
+    memory.map(0x500000, 0x1000, PROT_READ);
+    assert_eq!(memory.program_break(), 0x411000);
+    assert_eq!(memory.set_program_break(0x412800), 0x412800);
+    assert!(memory.check_access(0x4127fc, 4, Access::Write).is_ok());
+    assert_eq!(memory.set_program_break(0x400000), 0x412800);
+    assert_eq!(memory.set_program_break(0x501000), 0x412800);
+    assert_eq!(memory.set_program_break(0x411000), 0x411000);
+    assert!(memory.check_access(0x411000, 4, Access::Read).is_err());
+}
+
+#[test]
+fn test_block_written_at_top() {
+    let mut memory = Memory::new(Endianness::LittleEndian);
+    memory.map(0xFFFF_F000, 0x1000, PROT_READ | PROT_WRITE);
+    memory.monitor_line(0xFFFF_FFE0);
+    memory.zero_block(0xFFFF_FFF0, 0x10);
+    assert!(!memory.is_line_monitored(0xFFFF_FFE0));
+}
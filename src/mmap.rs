@@ -0,0 +1,535 @@
+//! Emulation of `mmap` and the related syscalls. Mappings live in the emulated memory, the
+//! address space allocator and the page protection are part of `memory::Memory`. This module adds
+//! file backed mappings, which are copied in when mapped. MAP_SHARED ones remember their file and
+//! write their content back on `msync` and `munmap`.
+
+use memory::{Memory, KERNEL_SEGMENT_START, PAGE_SIZE, PROT_EXEC, PROT_READ, PROT_WRITE};
+use std::io::Error;
+
+// MIPS values of the mmap flags, MAP_ANONYMOUS and the ones above it differ from x86
+const MAP_SHARED: u32 = 0x001;
+const MAP_PRIVATE: u32 = 0x002;
+const MAP_FIXED: u32 = 0x010;
+const MAP_NORESERVE: u32 = 0x0400;
+const MAP_ANONYMOUS: u32 = 0x0800;
+const MAP_GROWSDOWN: u32 = 0x1000;
+const MAP_DENYWRITE: u32 = 0x2000;
+const MAP_EXECUTABLE: u32 = 0x4000;
+const MAP_LOCKED: u32 = 0x8000;
+const MAP_POPULATE: u32 = 0x10000;
+const MAP_NONBLOCK: u32 = 0x20000;
+const MAP_STACK: u32 = 0x40000;
+const MAP_FIXED_NOREPLACE: u32 = 0x100000;
+/// Missing in the libc crate, the value is the same on all architectures
+const HOST_MAP_FIXED_NOREPLACE: i32 = 0x100000;
+fn translate_mmap_flags(flags: u32) -> i32 {
+    let mut res = 0i32;
+    let translation = [
+        (MAP_SHARED, ::libc::MAP_SHARED),
+        (MAP_PRIVATE, ::libc::MAP_PRIVATE),
+        (MAP_FIXED, ::libc::MAP_FIXED),
+        (MAP_NORESERVE, ::libc::MAP_NORESERVE),
+        (MAP_ANONYMOUS, ::libc::MAP_ANONYMOUS),
+        (MAP_GROWSDOWN, ::libc::MAP_GROWSDOWN),
+        (MAP_DENYWRITE, ::libc::MAP_DENYWRITE),
+        (MAP_EXECUTABLE, ::libc::MAP_EXECUTABLE),
+        (MAP_LOCKED, ::libc::MAP_LOCKED),
+        (MAP_POPULATE, ::libc::MAP_POPULATE),
+        (MAP_NONBLOCK, ::libc::MAP_NONBLOCK),
+        (MAP_STACK, ::libc::MAP_STACK),
+        (MAP_FIXED_NOREPLACE, HOST_MAP_FIXED_NOREPLACE),
+    ];
+    for &(mips, host) in &translation {
+        if flags & mips == mips {
+            res |= host;
+        }
+    }
+    res
+}
+
+const MREMAP_MAYMOVE: u32 = 1;
+const MREMAP_FIXED: u32 = 2;
+
+const MADV_DONTNEED: u32 = 4;
+
+/// Translates PROT_* bits, they are the same on MIPS and x86
+fn translate_protection(prot: u32) -> u8 {
+    (prot & (PROT_READ | PROT_WRITE | PROT_EXEC) as u32) as u8
+}
+
+fn page_align(len: u32) -> Option<u32> {
+    let aligned = (len as u64 + PAGE_SIZE as u64 - 1) & !(PAGE_SIZE as u64 - 1);
+    if aligned > KERNEL_SEGMENT_START as u64 {
+        None
+    } else {
+        Some(aligned as u32)
+    }
+}
+
+fn error(errno: i32) -> Error {
+    Error::from_raw_os_error(errno)
+}
+
+/// Part of a MAP_SHARED file mapping
+struct SharedMapping {
+    address: u32,
+    len: u32,
+    /// Duplicate of the mapped file descriptor, the program may close its own
+    fd: i32,
+    offset: i64,
+    writable: bool,
+}
+
+impl SharedMapping {
+    fn end(&self) -> u64 {
+        self.address as u64 + self.len as u64
+    }
+
+    fn overlaps(&self, address: u32, len: u32) -> bool {
+        (self.address as u64) < address as u64 + len as u64 && self.end() > address as u64
+    }
+
+    /// Piece of the mapping starting at the address, with its own file descriptor
+    fn piece(&self, address: u32, len: u32) -> SharedMapping {
+        SharedMapping {
+            address,
+            len,
+            fd: unsafe { ::libc::dup(self.fd) },
+            offset: self.offset + (address - self.address) as i64,
+            writable: self.writable,
+        }
+    }
+}
+
+/// File mappings of the program, anonymous mappings are just pages in the memory
+pub struct Mappings {
+    shared: Vec<SharedMapping>,
+}
+
+impl Mappings {
+    pub fn new() -> Mappings {
+        Mappings { shared: Vec::new() }
+    }
+
+    /// `file` is the descriptor and offset, ignored for anonymous mappings
+    pub fn mmap(
+        &mut self,
+        memory: &mut Memory,
+        address: u32,
+        len: u32,
+        prot: u32,
+        flags: u32,
+        file: (i32, i64),
+    ) -> Result<u32, Error> {
+        let (fd, offset) = file;
+        let flags = translate_mmap_flags(flags);
+        let size = page_align(len).ok_or_else(|| error(::libc::ENOMEM))?;
+        let mapping_type = flags & (::libc::MAP_SHARED | ::libc::MAP_PRIVATE);
+        if len == 0 || offset < 0 || offset % PAGE_SIZE as i64 != 0 ||
+            (mapping_type != ::libc::MAP_SHARED && mapping_type != ::libc::MAP_PRIVATE)
+        {
+            return Err(error(::libc::EINVAL));
+        }
+        let fixed = flags & (::libc::MAP_FIXED | HOST_MAP_FIXED_NOREPLACE) != 0;
+        if fixed && address & (PAGE_SIZE - 1) != 0 {
+            return Err(error(::libc::EINVAL));
+        }
+
+        // the file is read first, a failure must leave the memory untouched
+        let anonymous = flags & ::libc::MAP_ANONYMOUS != 0;
+        let content = if anonymous {
+            Vec::new()
+        } else {
+            read_file(fd, size, offset)?
+        };
+
+        let address = if fixed {
+            if address as u64 + size as u64 > KERNEL_SEGMENT_START as u64 {
+                return Err(error(::libc::ENOMEM));
+            }
+            if flags & HOST_MAP_FIXED_NOREPLACE != 0 && !memory.is_free_range(address, size) {
+                return Err(error(::libc::EEXIST));
+            }
+            self.munmap(memory, address, size)?;
+            address
+        } else {
+            memory
+                .find_free_area(address & !(PAGE_SIZE - 1), size)
+                .ok_or_else(|| error(::libc::ENOMEM))?
+        };
+
+        memory.map(address, size, translate_protection(prot));
+        memory.zero_block(address, size);
+        memory.write_block(address, &content);
+        if !anonymous && mapping_type == ::libc::MAP_SHARED && !content.is_empty() {
+            // only the part backed by the file is written back, the file never grows
+            self.shared.push(SharedMapping {
+                address,
+                len: content.len() as u32,
+                fd: unsafe { ::libc::dup(fd) },
+                offset,
+                writable: prot & PROT_WRITE as u32 != 0,
+            });
+        }
+        Ok(address)
+    }
+
+    pub fn munmap(&mut self, memory: &mut Memory, address: u32, len: u32) -> Result<u32, Error> {
+        let size = page_align(len).ok_or_else(|| error(::libc::EINVAL))?;
+        if address & (PAGE_SIZE - 1) != 0 || len == 0 ||
+            address as u64 + size as u64 > KERNEL_SEGMENT_START as u64
+        {
+            return Err(error(::libc::EINVAL));
+        }
+
+        if let Err(e) = self.write_back(memory, address, size) {
+            warn!("Shared mapping could not be written back: {:?}", e);
+        }
+        self.forget(address, size);
+        memory.unmap(address, size);
+        Ok(0)
+    }
+
+    pub fn mprotect(
+        &mut self,
+        memory: &mut Memory,
+        address: u32,
+        len: u32,
+        prot: u32,
+    ) -> Result<u32, Error> {
+        let size = page_align(len).ok_or_else(|| error(::libc::ENOMEM))?;
+        if address & (PAGE_SIZE - 1) != 0 {
+            return Err(error(::libc::EINVAL));
+        }
+        if !memory.is_mapped_range(address, size) {
+            return Err(error(::libc::ENOMEM));
+        }
+
+        memory.protect(address, size, translate_protection(prot));
+        if prot & PROT_WRITE as u32 != 0 {
+            for mapping in self.shared.iter_mut().filter(|m| m.overlaps(address, size)) {
+                mapping.writable = true;
+            }
+        }
+        Ok(0)
+    }
+
+    pub fn mremap(
+        &mut self,
+        memory: &mut Memory,
+        address: u32,
+        old_len: u32,
+        new_len: u32,
+        flags: u32,
+        new_address: u32,
+    ) -> Result<u32, Error> {
+        let old_size = page_align(old_len).ok_or_else(|| error(::libc::EINVAL))?;
+        let new_size = page_align(new_len).ok_or_else(|| error(::libc::ENOMEM))?;
+        let fixed = flags & MREMAP_FIXED != 0;
+        let may_move = flags & MREMAP_MAYMOVE != 0;
+        if address & (PAGE_SIZE - 1) != 0 || old_size == 0 || new_size == 0 ||
+            (fixed && !may_move)
+        {
+            return Err(error(::libc::EINVAL));
+        }
+        if !memory.is_mapped_range(address, old_size) {
+            return Err(error(::libc::EFAULT));
+        }
+
+        if fixed {
+            let overlapping = (new_address as u64) < address as u64 + old_size as u64 &&
+                (address as u64) < new_address as u64 + new_size as u64;
+            if new_address & (PAGE_SIZE - 1) != 0 || overlapping ||
+                new_address as u64 + new_size as u64 > KERNEL_SEGMENT_START as u64
+            {
+                return Err(error(::libc::EINVAL));
+            }
+            self.munmap(memory, new_address, new_size)?;
+            return Ok(self.move_mapping(memory, address, old_size, new_address, new_size));
+        }
+
+        if new_size <= old_size {
+            if new_size < old_size {
+                self.munmap(memory, address + new_size, old_size - new_size)?;
+            }
+            return Ok(address);
+        }
+
+        let grown = address as u64 + new_size as u64 <= KERNEL_SEGMENT_START as u64 &&
+            memory.is_free_range(address + old_size, new_size - old_size);
+        if grown {
+            let protection = memory.get_protection(address + old_size - 1).unwrap();
+            memory.map(address + old_size, new_size - old_size, protection);
+            memory.zero_block(address + old_size, new_size - old_size);
+            Ok(address)
+        } else if may_move {
+            let target = memory
+                .find_free_area(0, new_size)
+                .ok_or_else(|| error(::libc::ENOMEM))?;
+            Ok(self.move_mapping(memory, address, old_size, target, new_size))
+        } else {
+            Err(error(::libc::ENOMEM))
+        }
+    }
+
+    pub fn msync(&mut self, memory: &Memory, address: u32, len: u32) -> Result<u32, Error> {
+        let size = page_align(len).ok_or_else(|| error(::libc::ENOMEM))?;
+        if address & (PAGE_SIZE - 1) != 0 {
+            return Err(error(::libc::EINVAL));
+        }
+        if !memory.is_mapped_range(address, size) {
+            return Err(error(::libc::ENOMEM));
+        }
+        self.write_back(memory, address, size)?;
+        Ok(0)
+    }
+
+    pub fn madvise(
+        &mut self,
+        memory: &mut Memory,
+        address: u32,
+        len: u32,
+        advice: u32,
+    ) -> Result<u32, Error> {
+        let size = page_align(len).ok_or_else(|| error(::libc::EINVAL))?;
+        if address & (PAGE_SIZE - 1) != 0 {
+            return Err(error(::libc::EINVAL));
+        }
+        if !memory.is_mapped_range(address, size) {
+            return Err(error(::libc::ENOMEM));
+        }
+
+        // dropped private pages read back as zeros, private file mappings included. Other advice
+        // is only a hint.
+        if advice == MADV_DONTNEED && !self.shared.iter().any(|m| m.overlaps(address, size)) {
+            memory.zero_block(address, size);
+        }
+        Ok(0)
+    }
+
+    /// Moves pages of a mapping to the target, the content of shared mappings moves with them
+    fn move_mapping(
+        &mut self,
+        memory: &mut Memory,
+        address: u32,
+        old_size: u32,
+        target: u32,
+        new_size: u32,
+    ) -> u32 {
+        if new_size < old_size {
+            if let Err(e) = self.munmap(memory, address + new_size, old_size - new_size) {
+                warn!("Could not shrink mapping: {:?}", e);
+            }
+        }
+        let moved = old_size.min(new_size);
+        let protection = memory.get_protection(address).unwrap();
+        memory.map(target, new_size, protection);
+        memory.copy_block(address, target, moved);
+        memory.zero_block(target + moved, new_size - moved);
+        memory.unmap(address, moved);
+
+        let end = address as u64 + moved as u64;
+        let mut moved_mappings = Vec::new();
+        for mapping in self.shared.iter().filter(|m| m.overlaps(address, moved)) {
+            let start = mapping.address.max(address);
+            let mut piece = mapping.piece(start, (mapping.end().min(end) - start as u64) as u32);
+            piece.address = start - address + target;
+            moved_mappings.push(piece);
+        }
+        self.forget(address, moved);
+        self.shared.extend(moved_mappings);
+        target
+    }
+
+    /// Writes content of writable shared mappings in the range back to their files
+    fn write_back(&self, memory: &Memory, address: u32, len: u32) -> Result<(), Error> {
+        let end = address as u64 + len as u64;
+        for mapping in self.shared.iter().filter(|m| m.writable && m.overlaps(address, len)) {
+            let start = mapping.address.max(address);
+            let stop = mapping.end().min(end) as u32;
+            let data = memory.read_block(start, stop - start);
+            let offset = mapping.offset + (start - mapping.address) as i64;
+            let written = unsafe {
+                ::libc::pwrite(
+                    mapping.fd,
+                    data.as_ptr() as *const ::libc::c_void,
+                    data.len(),
+                    offset as ::libc::off_t,
+                )
+            };
+            if written < 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops shared mappings in the range, parts outside of it are kept
+    fn forget(&mut self, address: u32, len: u32) {
+        let end = address as u64 + len as u64;
+        let mut kept = Vec::new();
+        for mapping in self.shared.drain(..) {
+            if !mapping.overlaps(address, len) {
+                kept.push(mapping);
+                continue;
+            }
+            if mapping.address < address {
+                kept.push(mapping.piece(mapping.address, address - mapping.address));
+            }
+            if mapping.end() > end {
+                kept.push(mapping.piece(end as u32, (mapping.end() - end) as u32));
+            }
+            unsafe {
+                ::libc::close(mapping.fd);
+            }
+        }
+        self.shared = kept;
+    }
+}
+
+/// Reads up to `len` bytes of the file at the offset, less when the file is shorter
+fn read_file(fd: i32, len: u32, offset: i64) -> Result<Vec<u8>, Error> {
+    let mut content = vec![0u8; len as usize];
+    let mut read = 0;
+    while read < content.len() {
+        let n = unsafe {
+            ::libc::pread(
+                fd,
+                content[read..].as_mut_ptr() as *mut ::libc::c_void,
+                content.len() - read,
+                (offset + read as i64) as ::libc::off_t,
+            )
+        };
+        if n < 0 {
+            return Err(Error::last_os_error());
+        } else if n == 0 {
+            break;
+        }
+        read += n as usize;
+    }
+    content.truncate(read);
+    Ok(content)
+}
+
+#[test]
+fn test_anonymous_mappings() {
+    use memory::Endianness;
+
+    let mut memory = Memory::new(Endianness::LittleEndian);
+    let mut mappings = Mappings::new();
+    let rw = (PROT_READ | PROT_WRITE) as u32;
+    let address = mappings.mmap(&mut memory, 0, 5000, rw, 0x802, (-1, 0)).unwrap();
+    assert_eq!(address, ::memory::MMAP_BASE);
+    assert!(memory.is_mapped_range(address, 8192));
+
+    memory.write_word(address + 4096, 0x1234);
+    let next = mappings.mmap(&mut memory, 0, 4096, rw, 0x802, (-1, 0)).unwrap();
+    assert_eq!(next, address + 8192);
+
+    // the mapping can't grow in place, so it moves
+    let moved = mappings.mremap(&mut memory, address, 8192, 12288, 1, 0).unwrap();
+    assert_eq!(moved, next + 4096);
+    assert_eq!(memory.read_word(moved + 4096), 0x1234);
+    assert!(memory.is_free_range(address, 8192));
+
+    assert!(mappings.munmap(&mut memory, moved + 1, 4096).is_err());
+    mappings.munmap(&mut memory, moved, 4096).unwrap();
+    assert_eq!(memory.get_protection(moved + 4096), Some(PROT_READ | PROT_WRITE));
+    assert_eq!(memory.get_protection(moved), None);
+}
+
+/// Unlinked temporary file with the content, for file mapping tests
+#[cfg(test)]
+fn temporary_file(content: &[u8]) -> i32 {
+    let mut template = *b"/tmp/rumipsem-mmap-XXXXXX\0";
+    unsafe {
+        let fd = ::libc::mkstemp(template.as_mut_ptr() as *mut ::libc::c_char);
+        assert!(fd >= 0);
+        ::libc::unlink(template.as_ptr() as *const ::libc::c_char);
+        let written = ::libc::write(fd, content.as_ptr() as *const ::libc::c_void, content.len());
+        assert_eq!(written, content.len() as isize);
+        fd
+    }
+}
+
+#[cfg(test)]
+fn read_file_word(fd: i32, offset: i64) -> u32 {
+    let content = read_file(fd, 4, offset).unwrap();
+    u32::from_le_bytes([content[0], content[1], content[2], content[3]])
+}
+
+#[test]
+fn test_file_mappings() {
+    use memory::Endianness;
+
+    let mut memory = Memory::new(Endianness::LittleEndian);
+    let mut mappings = Mappings::new();
+    let rw = (PROT_READ | PROT_WRITE) as u32;
+
+    // the part of the pages behind a short file reads as zeros, private writes stay in memory
+    let fd = temporary_file(&[0xAB; 100]);
+    let private = mappings.mmap(&mut memory, 0, 8192, rw, MAP_PRIVATE, (fd, 0)).unwrap();
+    assert_eq!(memory.read_block(private, 100), vec![0xAB; 100]);
+    assert!(memory.read_block(private + 100, 8092).iter().all(|&b| b == 0));
+    memory.write_word(private, 0x1234_5678);
+    mappings.munmap(&mut memory, private, 8192).unwrap();
+    assert_eq!(read_file_word(fd, 0), 0xABAB_ABAB);
+    unsafe { ::libc::close(fd) };
+
+    let fd = temporary_file(&[0; 3 * 4096]);
+    let shared = mappings.mmap(&mut memory, 0, 3 * 4096, rw, MAP_SHARED, (fd, 0)).unwrap();
+    memory.write_word(shared + 4, 1);
+    memory.write_word(shared + 4096, 2);
+    memory.write_word(shared + 8192, 3);
+    assert_eq!(read_file_word(fd, 4), 0);
+    mappings.msync(&memory, shared, 4096).unwrap();
+    assert_eq!(read_file_word(fd, 4), 1);
+    assert_eq!(read_file_word(fd, 4096), 0);
+
+    // unmapping the middle page writes it back and splits the mapping in two
+    mappings.munmap(&mut memory, shared + 4096, 4096).unwrap();
+    assert_eq!(read_file_word(fd, 4096), 2);
+    assert_eq!(read_file_word(fd, 8192), 0);
+    memory.write_word(shared + 8, 4);
+    mappings.munmap(&mut memory, shared, 3 * 4096).unwrap();
+    assert_eq!(read_file_word(fd, 8), 4);
+    assert_eq!(read_file_word(fd, 8192), 3);
+    assert!(mappings.shared.is_empty());
+    unsafe { ::libc::close(fd) };
+}
+
+#[test]
+fn test_fixed_mappings() {
+    use memory::Endianness;
+
+    let mut memory = Memory::new(Endianness::LittleEndian);
+    let mut mappings = Mappings::new();
+    let rw = (PROT_READ | PROT_WRITE) as u32;
+    let anonymous = MAP_PRIVATE | MAP_ANONYMOUS;
+    let address = mappings.mmap(&mut memory, 0, 8192, rw, anonymous, (-1, 0)).unwrap();
+    memory.write_word(address + 4096, 0x1234);
+
+    let flags = anonymous | MAP_FIXED_NOREPLACE;
+    let e = mappings.mmap(&mut memory, address + 4096, 4096, rw, flags, (-1, 0)).unwrap_err();
+    assert_eq!(e.raw_os_error(), Some(::libc::EEXIST));
+    assert_eq!(memory.read_word(address + 4096), 0x1234);
+
+    let flags = anonymous | MAP_FIXED;
+    let fixed = mappings.mmap(&mut memory, address + 4096, 4096, PROT_READ as u32, flags, (-1, 0));
+    assert_eq!(fixed.unwrap(), address + 4096);
+    assert_eq!(memory.read_word(address + 4096), 0);
+    assert_eq!(memory.get_protection(address + 4096), Some(PROT_READ));
+    assert_eq!(memory.get_protection(address), Some(PROT_READ | PROT_WRITE));
+
+    // MREMAP_MAYMOVE | MREMAP_FIXED
+    memory.write_word(address, 0x5678);
+    let target = address + 0x10000;
+    let moved = mappings.mremap(&mut memory, address, 4096, 8192, 3, target).unwrap();
+    assert_eq!(moved, target);
+    assert_eq!(memory.read_word(target), 0x5678);
+    assert_eq!(memory.read_word(target + 4096), 0);
+    assert!(memory.is_free_range(address, 4096));
+
+    let e = mappings.mprotect(&mut memory, address, 8192, rw).unwrap_err();
+    assert_eq!(e.raw_os_error(), Some(::libc::ENOMEM));
+}
@@ -366,6 +366,19 @@ pub enum SyscallO32 {
     NRPkey_alloc,
     NRPkey_free,
     NRStatx,
+    NRShmget,
+    NRShmat,
+    NRShmctl,
+    NRSemget,
+    NRSemop,
+    NRSemctl,
+    NRShmdt,
+    NRMsgget,
+    NRMsgsnd,
+    NRMsgrcv,
+    NRMsgctl,
+    NRSemtimedop,
+    NRNewfstatat,
     NRUnknown,
 }
 
@@ -739,3 +752,667 @@ pub fn translate_syscall_number(sn: u32) -> SyscallO32 {
         _ => SyscallO32::NRUnknown,
     }
 }
+
+/// Translates syscall numbers of the n64 ABI. Syscalls with the same name share the variant.
+pub fn translate_syscall_number_n64(sn: u32) -> SyscallO32 {
+    match sn {
+        5000 => SyscallO32::NRRead,
+        5001 => SyscallO32::NRWrite,
+        5002 => SyscallO32::NROpen,
+        5003 => SyscallO32::NRClose,
+        5004 => SyscallO32::NRStat,
+        5005 => SyscallO32::NRFstat,
+        5006 => SyscallO32::NRLstat,
+        5007 => SyscallO32::NRPoll,
+        5008 => SyscallO32::NRLseek,
+        5009 => SyscallO32::NRMmap,
+        5010 => SyscallO32::NRMprotect,
+        5011 => SyscallO32::NRMunmap,
+        5012 => SyscallO32::NRBrk,
+        5013 => SyscallO32::NRRt_sigaction,
+        5014 => SyscallO32::NRRt_sigprocmask,
+        5015 => SyscallO32::NRIoctl,
+        5016 => SyscallO32::NRPread64,
+        5017 => SyscallO32::NRPwrite64,
+        5018 => SyscallO32::NRReadv,
+        5019 => SyscallO32::NRWritev,
+        5020 => SyscallO32::NRAccess,
+        5021 => SyscallO32::NRPipe,
+        5022 => SyscallO32::NR_newselect,
+        5023 => SyscallO32::NRSched_yield,
+        5024 => SyscallO32::NRMremap,
+        5025 => SyscallO32::NRMsync,
+        5026 => SyscallO32::NRMincore,
+        5027 => SyscallO32::NRMadvise,
+        5028 => SyscallO32::NRShmget,
+        5029 => SyscallO32::NRShmat,
+        5030 => SyscallO32::NRShmctl,
+        5031 => SyscallO32::NRDup,
+        5032 => SyscallO32::NRDup2,
+        5033 => SyscallO32::NRPause,
+        5034 => SyscallO32::NRNanosleep,
+        5035 => SyscallO32::NRGetitimer,
+        5036 => SyscallO32::NRSetitimer,
+        5037 => SyscallO32::NRAlarm,
+        5038 => SyscallO32::NRGetpid,
+        5039 => SyscallO32::NRSendfile,
+        5040 => SyscallO32::NRSocket,
+        5041 => SyscallO32::NRConnect,
+        5042 => SyscallO32::NRAccept,
+        5043 => SyscallO32::NRSendto,
+        5044 => SyscallO32::NRRecvfrom,
+        5045 => SyscallO32::NRSendmsg,
+        5046 => SyscallO32::NRRecvmsg,
+        5047 => SyscallO32::NRShutdown,
+        5048 => SyscallO32::NRBind,
+        5049 => SyscallO32::NRListen,
+        5050 => SyscallO32::NRGetsockname,
+        5051 => SyscallO32::NRGetpeername,
+        5052 => SyscallO32::NRSocketpair,
+        5053 => SyscallO32::NRSetsockopt,
+        5054 => SyscallO32::NRGetsockopt,
+        5055 => SyscallO32::NRClone,
+        5056 => SyscallO32::NRFork,
+        5057 => SyscallO32::NRExecve,
+        5058 => SyscallO32::NRExit,
+        5059 => SyscallO32::NRWait4,
+        5060 => SyscallO32::NRKill,
+        5061 => SyscallO32::NRUname,
+        5062 => SyscallO32::NRSemget,
+        5063 => SyscallO32::NRSemop,
+        5064 => SyscallO32::NRSemctl,
+        5065 => SyscallO32::NRShmdt,
+        5066 => SyscallO32::NRMsgget,
+        5067 => SyscallO32::NRMsgsnd,
+        5068 => SyscallO32::NRMsgrcv,
+        5069 => SyscallO32::NRMsgctl,
+        5070 => SyscallO32::NRFcntl,
+        5071 => SyscallO32::NRFlock,
+        5072 => SyscallO32::NRFsync,
+        5073 => SyscallO32::NRFdatasync,
+        5074 => SyscallO32::NRTruncate,
+        5075 => SyscallO32::NRFtruncate,
+        5076 => SyscallO32::NRGetdents,
+        5077 => SyscallO32::NRGetcwd,
+        5078 => SyscallO32::NRChdir,
+        5079 => SyscallO32::NRFchdir,
+        5080 => SyscallO32::NRRename,
+        5081 => SyscallO32::NRMkdir,
+        5082 => SyscallO32::NRRmdir,
+        5083 => SyscallO32::NRCreat,
+        5084 => SyscallO32::NRLink,
+        5085 => SyscallO32::NRUnlink,
+        5086 => SyscallO32::NRSymlink,
+        5087 => SyscallO32::NRReadlink,
+        5088 => SyscallO32::NRChmod,
+        5089 => SyscallO32::NRFchmod,
+        5090 => SyscallO32::NRChown,
+        5091 => SyscallO32::NRFchown,
+        5092 => SyscallO32::NRLchown,
+        5093 => SyscallO32::NRUmask,
+        5094 => SyscallO32::NRGettimeofday,
+        5095 => SyscallO32::NRGetrlimit,
+        5096 => SyscallO32::NRGetrusage,
+        5097 => SyscallO32::NRSysinfo,
+        5098 => SyscallO32::NRTimes,
+        5099 => SyscallO32::NRPtrace,
+        5100 => SyscallO32::NRGetuid,
+        5101 => SyscallO32::NRSyslog,
+        5102 => SyscallO32::NRGetgid,
+        5103 => SyscallO32::NRSetuid,
+        5104 => SyscallO32::NRSetgid,
+        5105 => SyscallO32::NRGeteuid,
+        5106 => SyscallO32::NRGetegid,
+        5107 => SyscallO32::NRSetpgid,
+        5108 => SyscallO32::NRGetppid,
+        5109 => SyscallO32::NRGetpgrp,
+        5110 => SyscallO32::NRSetsid,
+        5111 => SyscallO32::NRSetreuid,
+        5112 => SyscallO32::NRSetregid,
+        5113 => SyscallO32::NRGetgroups,
+        5114 => SyscallO32::NRSetgroups,
+        5115 => SyscallO32::NRSetresuid,
+        5116 => SyscallO32::NRGetresuid,
+        5117 => SyscallO32::NRSetresgid,
+        5118 => SyscallO32::NRGetresgid,
+        5119 => SyscallO32::NRGetpgid,
+        5120 => SyscallO32::NRSetfsuid,
+        5121 => SyscallO32::NRSetfsgid,
+        5122 => SyscallO32::NRGetsid,
+        5123 => SyscallO32::NRCapget,
+        5124 => SyscallO32::NRCapset,
+        5125 => SyscallO32::NRRt_sigpending,
+        5126 => SyscallO32::NRRt_sigtimedwait,
+        5127 => SyscallO32::NRRt_sigqueueinfo,
+        5128 => SyscallO32::NRRt_sigsuspend,
+        5129 => SyscallO32::NRSigaltstack,
+        5130 => SyscallO32::NRUtime,
+        5131 => SyscallO32::NRMknod,
+        5132 => SyscallO32::NRPersonality,
+        5133 => SyscallO32::NRUstat,
+        5134 => SyscallO32::NRStatfs,
+        5135 => SyscallO32::NRFstatfs,
+        5136 => SyscallO32::NRSysfs,
+        5137 => SyscallO32::NRGetpriority,
+        5138 => SyscallO32::NRSetpriority,
+        5139 => SyscallO32::NRSched_setparam,
+        5140 => SyscallO32::NRSched_getparam,
+        5141 => SyscallO32::NRSched_setscheduler,
+        5142 => SyscallO32::NRSched_getscheduler,
+        5143 => SyscallO32::NRSched_get_priority_max,
+        5144 => SyscallO32::NRSched_get_priority_min,
+        5145 => SyscallO32::NRSched_rr_get_interval,
+        5146 => SyscallO32::NRMlock,
+        5147 => SyscallO32::NRMunlock,
+        5148 => SyscallO32::NRMlockall,
+        5149 => SyscallO32::NRMunlockall,
+        5150 => SyscallO32::NRVhangup,
+        5151 => SyscallO32::NRPivot_root,
+        5152 => SyscallO32::NR_sysctl,
+        5153 => SyscallO32::NRPrctl,
+        5154 => SyscallO32::NRAdjtimex,
+        5155 => SyscallO32::NRSetrlimit,
+        5156 => SyscallO32::NRChroot,
+        5157 => SyscallO32::NRSync,
+        5158 => SyscallO32::NRAcct,
+        5159 => SyscallO32::NRSettimeofday,
+        5160 => SyscallO32::NRMount,
+        5161 => SyscallO32::NRUmount2,
+        5162 => SyscallO32::NRSwapon,
+        5163 => SyscallO32::NRSwapoff,
+        5164 => SyscallO32::NRReboot,
+        5165 => SyscallO32::NRSethostname,
+        5166 => SyscallO32::NRSetdomainname,
+        5167 => SyscallO32::NRCreate_module,
+        5168 => SyscallO32::NRInit_module,
+        5169 => SyscallO32::NRDelete_module,
+        5170 => SyscallO32::NRGet_kernel_syms,
+        5171 => SyscallO32::NRQuery_module,
+        5172 => SyscallO32::NRQuotactl,
+        5173 => SyscallO32::NRNfsservctl,
+        5174 => SyscallO32::NRGetpmsg,
+        5175 => SyscallO32::NRPutpmsg,
+        5178 => SyscallO32::NRGettid,
+        5179 => SyscallO32::NRReadahead,
+        5180 => SyscallO32::NRSetxattr,
+        5181 => SyscallO32::NRLsetxattr,
+        5182 => SyscallO32::NRFsetxattr,
+        5183 => SyscallO32::NRGetxattr,
+        5184 => SyscallO32::NRLgetxattr,
+        5185 => SyscallO32::NRFgetxattr,
+        5186 => SyscallO32::NRListxattr,
+        5187 => SyscallO32::NRLlistxattr,
+        5188 => SyscallO32::NRFlistxattr,
+        5189 => SyscallO32::NRRemovexattr,
+        5190 => SyscallO32::NRLremovexattr,
+        5191 => SyscallO32::NRFremovexattr,
+        5192 => SyscallO32::NRTkill,
+        5194 => SyscallO32::NRFutex,
+        5195 => SyscallO32::NRSched_setaffinity,
+        5196 => SyscallO32::NRSched_getaffinity,
+        5197 => SyscallO32::NRCacheflush,
+        5198 => SyscallO32::NRCachectl,
+        5199 => SyscallO32::NRSysmips,
+        5200 => SyscallO32::NRIo_setup,
+        5201 => SyscallO32::NRIo_destroy,
+        5202 => SyscallO32::NRIo_getevents,
+        5203 => SyscallO32::NRIo_submit,
+        5204 => SyscallO32::NRIo_cancel,
+        5205 => SyscallO32::NRExit_group,
+        5206 => SyscallO32::NRLookup_dcookie,
+        5207 => SyscallO32::NREpoll_create,
+        5208 => SyscallO32::NREpoll_ctl,
+        5209 => SyscallO32::NREpoll_wait,
+        5210 => SyscallO32::NRRemap_file_pages,
+        5211 => SyscallO32::NRRt_sigreturn,
+        5212 => SyscallO32::NRSet_tid_address,
+        5213 => SyscallO32::NRRestart_syscall,
+        5214 => SyscallO32::NRSemtimedop,
+        5215 => SyscallO32::NRFadvise64,
+        5216 => SyscallO32::NRTimer_create,
+        5217 => SyscallO32::NRTimer_settime,
+        5218 => SyscallO32::NRTimer_gettime,
+        5219 => SyscallO32::NRTimer_getoverrun,
+        5220 => SyscallO32::NRTimer_delete,
+        5221 => SyscallO32::NRClock_settime,
+        5222 => SyscallO32::NRClock_gettime,
+        5223 => SyscallO32::NRClock_getres,
+        5224 => SyscallO32::NRClock_nanosleep,
+        5225 => SyscallO32::NRTgkill,
+        5226 => SyscallO32::NRUtimes,
+        5227 => SyscallO32::NRMbind,
+        5228 => SyscallO32::NRGet_mempolicy,
+        5229 => SyscallO32::NRSet_mempolicy,
+        5230 => SyscallO32::NRMq_open,
+        5231 => SyscallO32::NRMq_unlink,
+        5232 => SyscallO32::NRMq_timedsend,
+        5233 => SyscallO32::NRMq_timedreceive,
+        5234 => SyscallO32::NRMq_notify,
+        5235 => SyscallO32::NRMq_getsetattr,
+        5236 => SyscallO32::NRVserver,
+        5237 => SyscallO32::NRWaitid,
+        5239 => SyscallO32::NRAdd_key,
+        5240 => SyscallO32::NRRequest_key,
+        5241 => SyscallO32::NRKeyctl,
+        5242 => SyscallO32::NRSet_thread_area,
+        5243 => SyscallO32::NRInotify_init,
+        5244 => SyscallO32::NRInotify_add_watch,
+        5245 => SyscallO32::NRInotify_rm_watch,
+        5246 => SyscallO32::NRMigrate_pages,
+        5247 => SyscallO32::NROpenat,
+        5248 => SyscallO32::NRMkdirat,
+        5249 => SyscallO32::NRMknodat,
+        5250 => SyscallO32::NRFchownat,
+        5251 => SyscallO32::NRFutimesat,
+        5252 => SyscallO32::NRNewfstatat,
+        5253 => SyscallO32::NRUnlinkat,
+        5254 => SyscallO32::NRRenameat,
+        5255 => SyscallO32::NRLinkat,
+        5256 => SyscallO32::NRSymlinkat,
+        5257 => SyscallO32::NRReadlinkat,
+        5258 => SyscallO32::NRFchmodat,
+        5259 => SyscallO32::NRFaccessat,
+        5260 => SyscallO32::NRPselect6,
+        5261 => SyscallO32::NRPpoll,
+        5262 => SyscallO32::NRUnshare,
+        5263 => SyscallO32::NRSplice,
+        5264 => SyscallO32::NRSync_file_range,
+        5265 => SyscallO32::NRTee,
+        5266 => SyscallO32::NRVmsplice,
+        5267 => SyscallO32::NRMove_pages,
+        5268 => SyscallO32::NRSet_robust_list,
+        5269 => SyscallO32::NRGet_robust_list,
+        5270 => SyscallO32::NRKexec_load,
+        5271 => SyscallO32::NRGetcpu,
+        5272 => SyscallO32::NREpoll_pwait,
+        5273 => SyscallO32::NRIoprio_set,
+        5274 => SyscallO32::NRIoprio_get,
+        5275 => SyscallO32::NRUtimensat,
+        5276 => SyscallO32::NRSignalfd,
+        5277 => SyscallO32::NRTimerfd,
+        5278 => SyscallO32::NREventfd,
+        5279 => SyscallO32::NRFallocate,
+        5280 => SyscallO32::NRTimerfd_create,
+        5281 => SyscallO32::NRTimerfd_gettime,
+        5282 => SyscallO32::NRTimerfd_settime,
+        5283 => SyscallO32::NRSignalfd4,
+        5284 => SyscallO32::NREventfd2,
+        5285 => SyscallO32::NREpoll_create1,
+        5286 => SyscallO32::NRDup3,
+        5287 => SyscallO32::NRPipe2,
+        5288 => SyscallO32::NRInotify_init1,
+        5289 => SyscallO32::NRPreadv,
+        5290 => SyscallO32::NRPwritev,
+        5291 => SyscallO32::NRRt_tgsigqueueinfo,
+        5292 => SyscallO32::NRPerf_event_open,
+        5293 => SyscallO32::NRAccept4,
+        5294 => SyscallO32::NRRecvmmsg,
+        5295 => SyscallO32::NRFanotify_init,
+        5296 => SyscallO32::NRFanotify_mark,
+        5297 => SyscallO32::NRPrlimit64,
+        5298 => SyscallO32::NRName_to_handle_at,
+        5299 => SyscallO32::NROpen_by_handle_at,
+        5300 => SyscallO32::NRClock_adjtime,
+        5301 => SyscallO32::NRSyncfs,
+        5302 => SyscallO32::NRSendmmsg,
+        5303 => SyscallO32::NRSetns,
+        5304 => SyscallO32::NRProcess_vm_readv,
+        5305 => SyscallO32::NRProcess_vm_writev,
+        5306 => SyscallO32::NRKcmp,
+        5307 => SyscallO32::NRFinit_module,
+        5308 => SyscallO32::NRGetdents64,
+        5309 => SyscallO32::NRSched_setattr,
+        5310 => SyscallO32::NRSched_getattr,
+        5311 => SyscallO32::NRRenameat2,
+        5312 => SyscallO32::NRSeccomp,
+        5313 => SyscallO32::NRGetrandom,
+        5314 => SyscallO32::NRMemfd_create,
+        5315 => SyscallO32::NRBpf,
+        5316 => SyscallO32::NRExecveat,
+        5317 => SyscallO32::NRUserfaultfd,
+        5318 => SyscallO32::NRMembarrier,
+        5319 => SyscallO32::NRMlock2,
+        5320 => SyscallO32::NRCopy_file_range,
+        5321 => SyscallO32::NRPreadv2,
+        5322 => SyscallO32::NRPwritev2,
+        5323 => SyscallO32::NRPkey_mprotect,
+        5324 => SyscallO32::NRPkey_alloc,
+        5325 => SyscallO32::NRPkey_free,
+        5326 => SyscallO32::NRStatx,
+        _ => SyscallO32::NRUnknown,
+    }
+}
+
+/// Translates syscall numbers of the n32 ABI. Syscalls with the same name share the variant.
+pub fn translate_syscall_number_n32(sn: u32) -> SyscallO32 {
+    match sn {
+        6000 => SyscallO32::NRRead,
+        6001 => SyscallO32::NRWrite,
+        6002 => SyscallO32::NROpen,
+        6003 => SyscallO32::NRClose,
+        6004 => SyscallO32::NRStat,
+        6005 => SyscallO32::NRFstat,
+        6006 => SyscallO32::NRLstat,
+        6007 => SyscallO32::NRPoll,
+        6008 => SyscallO32::NRLseek,
+        6009 => SyscallO32::NRMmap,
+        6010 => SyscallO32::NRMprotect,
+        6011 => SyscallO32::NRMunmap,
+        6012 => SyscallO32::NRBrk,
+        6013 => SyscallO32::NRRt_sigaction,
+        6014 => SyscallO32::NRRt_sigprocmask,
+        6015 => SyscallO32::NRIoctl,
+        6016 => SyscallO32::NRPread64,
+        6017 => SyscallO32::NRPwrite64,
+        6018 => SyscallO32::NRReadv,
+        6019 => SyscallO32::NRWritev,
+        6020 => SyscallO32::NRAccess,
+        6021 => SyscallO32::NRPipe,
+        6022 => SyscallO32::NR_newselect,
+        6023 => SyscallO32::NRSched_yield,
+        6024 => SyscallO32::NRMremap,
+        6025 => SyscallO32::NRMsync,
+        6026 => SyscallO32::NRMincore,
+        6027 => SyscallO32::NRMadvise,
+        6028 => SyscallO32::NRShmget,
+        6029 => SyscallO32::NRShmat,
+        6030 => SyscallO32::NRShmctl,
+        6031 => SyscallO32::NRDup,
+        6032 => SyscallO32::NRDup2,
+        6033 => SyscallO32::NRPause,
+        6034 => SyscallO32::NRNanosleep,
+        6035 => SyscallO32::NRGetitimer,
+        6036 => SyscallO32::NRSetitimer,
+        6037 => SyscallO32::NRAlarm,
+        6038 => SyscallO32::NRGetpid,
+        6039 => SyscallO32::NRSendfile,
+        6040 => SyscallO32::NRSocket,
+        6041 => SyscallO32::NRConnect,
+        6042 => SyscallO32::NRAccept,
+        6043 => SyscallO32::NRSendto,
+        6044 => SyscallO32::NRRecvfrom,
+        6045 => SyscallO32::NRSendmsg,
+        6046 => SyscallO32::NRRecvmsg,
+        6047 => SyscallO32::NRShutdown,
+        6048 => SyscallO32::NRBind,
+        6049 => SyscallO32::NRListen,
+        6050 => SyscallO32::NRGetsockname,
+        6051 => SyscallO32::NRGetpeername,
+        6052 => SyscallO32::NRSocketpair,
+        6053 => SyscallO32::NRSetsockopt,
+        6054 => SyscallO32::NRGetsockopt,
+        6055 => SyscallO32::NRClone,
+        6056 => SyscallO32::NRFork,
+        6057 => SyscallO32::NRExecve,
+        6058 => SyscallO32::NRExit,
+        6059 => SyscallO32::NRWait4,
+        6060 => SyscallO32::NRKill,
+        6061 => SyscallO32::NRUname,
+        6062 => SyscallO32::NRSemget,
+        6063 => SyscallO32::NRSemop,
+        6064 => SyscallO32::NRSemctl,
+        6065 => SyscallO32::NRShmdt,
+        6066 => SyscallO32::NRMsgget,
+        6067 => SyscallO32::NRMsgsnd,
+        6068 => SyscallO32::NRMsgrcv,
+        6069 => SyscallO32::NRMsgctl,
+        6070 => SyscallO32::NRFcntl,
+        6071 => SyscallO32::NRFlock,
+        6072 => SyscallO32::NRFsync,
+        6073 => SyscallO32::NRFdatasync,
+        6074 => SyscallO32::NRTruncate,
+        6075 => SyscallO32::NRFtruncate,
+        6076 => SyscallO32::NRGetdents,
+        6077 => SyscallO32::NRGetcwd,
+        6078 => SyscallO32::NRChdir,
+        6079 => SyscallO32::NRFchdir,
+        6080 => SyscallO32::NRRename,
+        6081 => SyscallO32::NRMkdir,
+        6082 => SyscallO32::NRRmdir,
+        6083 => SyscallO32::NRCreat,
+        6084 => SyscallO32::NRLink,
+        6085 => SyscallO32::NRUnlink,
+        6086 => SyscallO32::NRSymlink,
+        6087 => SyscallO32::NRReadlink,
+        6088 => SyscallO32::NRChmod,
+        6089 => SyscallO32::NRFchmod,
+        6090 => SyscallO32::NRChown,
+        6091 => SyscallO32::NRFchown,
+        6092 => SyscallO32::NRLchown,
+        6093 => SyscallO32::NRUmask,
+        6094 => SyscallO32::NRGettimeofday,
+        6095 => SyscallO32::NRGetrlimit,
+        6096 => SyscallO32::NRGetrusage,
+        6097 => SyscallO32::NRSysinfo,
+        6098 => SyscallO32::NRTimes,
+        6099 => SyscallO32::NRPtrace,
+        6100 => SyscallO32::NRGetuid,
+        6101 => SyscallO32::NRSyslog,
+        6102 => SyscallO32::NRGetgid,
+        6103 => SyscallO32::NRSetuid,
+        6104 => SyscallO32::NRSetgid,
+        6105 => SyscallO32::NRGeteuid,
+        6106 => SyscallO32::NRGetegid,
+        6107 => SyscallO32::NRSetpgid,
+        6108 => SyscallO32::NRGetppid,
+        6109 => SyscallO32::NRGetpgrp,
+        6110 => SyscallO32::NRSetsid,
+        6111 => SyscallO32::NRSetreuid,
+        6112 => SyscallO32::NRSetregid,
+        6113 => SyscallO32::NRGetgroups,
+        6114 => SyscallO32::NRSetgroups,
+        6115 => SyscallO32::NRSetresuid,
+        6116 => SyscallO32::NRGetresuid,
+        6117 => SyscallO32::NRSetresgid,
+        6118 => SyscallO32::NRGetresgid,
+        6119 => SyscallO32::NRGetpgid,
+        6120 => SyscallO32::NRSetfsuid,
+        6121 => SyscallO32::NRSetfsgid,
+        6122 => SyscallO32::NRGetsid,
+        6123 => SyscallO32::NRCapget,
+        6124 => SyscallO32::NRCapset,
+        6125 => SyscallO32::NRRt_sigpending,
+        6126 => SyscallO32::NRRt_sigtimedwait,
+        6127 => SyscallO32::NRRt_sigqueueinfo,
+        6128 => SyscallO32::NRRt_sigsuspend,
+        6129 => SyscallO32::NRSigaltstack,
+        6130 => SyscallO32::NRUtime,
+        6131 => SyscallO32::NRMknod,
+        6132 => SyscallO32::NRPersonality,
+        6133 => SyscallO32::NRUstat,
+        6134 => SyscallO32::NRStatfs,
+        6135 => SyscallO32::NRFstatfs,
+        6136 => SyscallO32::NRSysfs,
+        6137 => SyscallO32::NRGetpriority,
+        6138 => SyscallO32::NRSetpriority,
+        6139 => SyscallO32::NRSched_setparam,
+        6140 => SyscallO32::NRSched_getparam,
+        6141 => SyscallO32::NRSched_setscheduler,
+        6142 => SyscallO32::NRSched_getscheduler,
+        6143 => SyscallO32::NRSched_get_priority_max,
+        6144 => SyscallO32::NRSched_get_priority_min,
+        6145 => SyscallO32::NRSched_rr_get_interval,
+        6146 => SyscallO32::NRMlock,
+        6147 => SyscallO32::NRMunlock,
+        6148 => SyscallO32::NRMlockall,
+        6149 => SyscallO32::NRMunlockall,
+        6150 => SyscallO32::NRVhangup,
+        6151 => SyscallO32::NRPivot_root,
+        6152 => SyscallO32::NR_sysctl,
+        6153 => SyscallO32::NRPrctl,
+        6154 => SyscallO32::NRAdjtimex,
+        6155 => SyscallO32::NRSetrlimit,
+        6156 => SyscallO32::NRChroot,
+        6157 => SyscallO32::NRSync,
+        6158 => SyscallO32::NRAcct,
+        6159 => SyscallO32::NRSettimeofday,
+        6160 => SyscallO32::NRMount,
+        6161 => SyscallO32::NRUmount2,
+        6162 => SyscallO32::NRSwapon,
+        6163 => SyscallO32::NRSwapoff,
+        6164 => SyscallO32::NRReboot,
+        6165 => SyscallO32::NRSethostname,
+        6166 => SyscallO32::NRSetdomainname,
+        6167 => SyscallO32::NRCreate_module,
+        6168 => SyscallO32::NRInit_module,
+        6169 => SyscallO32::NRDelete_module,
+        6170 => SyscallO32::NRGet_kernel_syms,
+        6171 => SyscallO32::NRQuery_module,
+        6172 => SyscallO32::NRQuotactl,
+        6173 => SyscallO32::NRNfsservctl,
+        6174 => SyscallO32::NRGetpmsg,
+        6175 => SyscallO32::NRPutpmsg,
+        6178 => SyscallO32::NRGettid,
+        6179 => SyscallO32::NRReadahead,
+        6180 => SyscallO32::NRSetxattr,
+        6181 => SyscallO32::NRLsetxattr,
+        6182 => SyscallO32::NRFsetxattr,
+        6183 => SyscallO32::NRGetxattr,
+        6184 => SyscallO32::NRLgetxattr,
+        6185 => SyscallO32::NRFgetxattr,
+        6186 => SyscallO32::NRListxattr,
+        6187 => SyscallO32::NRLlistxattr,
+        6188 => SyscallO32::NRFlistxattr,
+        6189 => SyscallO32::NRRemovexattr,
+        6190 => SyscallO32::NRLremovexattr,
+        6191 => SyscallO32::NRFremovexattr,
+        6192 => SyscallO32::NRTkill,
+        6194 => SyscallO32::NRFutex,
+        6195 => SyscallO32::NRSched_setaffinity,
+        6196 => SyscallO32::NRSched_getaffinity,
+        6197 => SyscallO32::NRCacheflush,
+        6198 => SyscallO32::NRCachectl,
+        6199 => SyscallO32::NRSysmips,
+        6200 => SyscallO32::NRIo_setup,
+        6201 => SyscallO32::NRIo_destroy,
+        6202 => SyscallO32::NRIo_getevents,
+        6203 => SyscallO32::NRIo_submit,
+        6204 => SyscallO32::NRIo_cancel,
+        6205 => SyscallO32::NRExit_group,
+        6206 => SyscallO32::NRLookup_dcookie,
+        6207 => SyscallO32::NREpoll_create,
+        6208 => SyscallO32::NREpoll_ctl,
+        6209 => SyscallO32::NREpoll_wait,
+        6210 => SyscallO32::NRRemap_file_pages,
+        6211 => SyscallO32::NRRt_sigreturn,
+        6212 => SyscallO32::NRFcntl64,
+        6213 => SyscallO32::NRSet_tid_address,
+        6214 => SyscallO32::NRRestart_syscall,
+        6215 => SyscallO32::NRSemtimedop,
+        6216 => SyscallO32::NRFadvise64,
+        6217 => SyscallO32::NRStatfs64,
+        6218 => SyscallO32::NRFstatfs64,
+        6219 => SyscallO32::NRSendfile64,
+        6220 => SyscallO32::NRTimer_create,
+        6221 => SyscallO32::NRTimer_settime,
+        6222 => SyscallO32::NRTimer_gettime,
+        6223 => SyscallO32::NRTimer_getoverrun,
+        6224 => SyscallO32::NRTimer_delete,
+        6225 => SyscallO32::NRClock_settime,
+        6226 => SyscallO32::NRClock_gettime,
+        6227 => SyscallO32::NRClock_getres,
+        6228 => SyscallO32::NRClock_nanosleep,
+        6229 => SyscallO32::NRTgkill,
+        6230 => SyscallO32::NRUtimes,
+        6231 => SyscallO32::NRMbind,
+        6232 => SyscallO32::NRGet_mempolicy,
+        6233 => SyscallO32::NRSet_mempolicy,
+        6234 => SyscallO32::NRMq_open,
+        6235 => SyscallO32::NRMq_unlink,
+        6236 => SyscallO32::NRMq_timedsend,
+        6237 => SyscallO32::NRMq_timedreceive,
+        6238 => SyscallO32::NRMq_notify,
+        6239 => SyscallO32::NRMq_getsetattr,
+        6240 => SyscallO32::NRVserver,
+        6241 => SyscallO32::NRWaitid,
+        6243 => SyscallO32::NRAdd_key,
+        6244 => SyscallO32::NRRequest_key,
+        6245 => SyscallO32::NRKeyctl,
+        6246 => SyscallO32::NRSet_thread_area,
+        6247 => SyscallO32::NRInotify_init,
+        6248 => SyscallO32::NRInotify_add_watch,
+        6249 => SyscallO32::NRInotify_rm_watch,
+        6250 => SyscallO32::NRMigrate_pages,
+        6251 => SyscallO32::NROpenat,
+        6252 => SyscallO32::NRMkdirat,
+        6253 => SyscallO32::NRMknodat,
+        6254 => SyscallO32::NRFchownat,
+        6255 => SyscallO32::NRFutimesat,
+        6256 => SyscallO32::NRNewfstatat,
+        6257 => SyscallO32::NRUnlinkat,
+        6258 => SyscallO32::NRRenameat,
+        6259 => SyscallO32::NRLinkat,
+        6260 => SyscallO32::NRSymlinkat,
+        6261 => SyscallO32::NRReadlinkat,
+        6262 => SyscallO32::NRFchmodat,
+        6263 => SyscallO32::NRFaccessat,
+        6264 => SyscallO32::NRPselect6,
+        6265 => SyscallO32::NRPpoll,
+        6266 => SyscallO32::NRUnshare,
+        6267 => SyscallO32::NRSplice,
+        6268 => SyscallO32::NRSync_file_range,
+        6269 => SyscallO32::NRTee,
+        6270 => SyscallO32::NRVmsplice,
+        6271 => SyscallO32::NRMove_pages,
+        6272 => SyscallO32::NRSet_robust_list,
+        6273 => SyscallO32::NRGet_robust_list,
+        6274 => SyscallO32::NRKexec_load,
+        6275 => SyscallO32::NRGetcpu,
+        6276 => SyscallO32::NREpoll_pwait,
+        6277 => SyscallO32::NRIoprio_set,
+        6278 => SyscallO32::NRIoprio_get,
+        6279 => SyscallO32::NRUtimensat,
+        6280 => SyscallO32::NRSignalfd,
+        6281 => SyscallO32::NRTimerfd,
+        6282 => SyscallO32::NREventfd,
+        6283 => SyscallO32::NRFallocate,
+        6284 => SyscallO32::NRTimerfd_create,
+        6285 => SyscallO32::NRTimerfd_gettime,
+        6286 => SyscallO32::NRTimerfd_settime,
+        6287 => SyscallO32::NRSignalfd4,
+        6288 => SyscallO32::NREventfd2,
+        6289 => SyscallO32::NREpoll_create1,
+        6290 => SyscallO32::NRDup3,
+        6291 => SyscallO32::NRPipe2,
+        6292 => SyscallO32::NRInotify_init1,
+        6293 => SyscallO32::NRPreadv,
+        6294 => SyscallO32::NRPwritev,
+        6295 => SyscallO32::NRRt_tgsigqueueinfo,
+        6296 => SyscallO32::NRPerf_event_open,
+        6297 => SyscallO32::NRAccept4,
+        6298 => SyscallO32::NRRecvmmsg,
+        6299 => SyscallO32::NRFanotify_init,
+        6300 => SyscallO32::NRFanotify_mark,
+        6301 => SyscallO32::NRPrlimit64,
+        6302 => SyscallO32::NRName_to_handle_at,
+        6303 => SyscallO32::NROpen_by_handle_at,
+        6304 => SyscallO32::NRClock_adjtime,
+        6305 => SyscallO32::NRSyncfs,
+        6306 => SyscallO32::NRSendmmsg,
+        6307 => SyscallO32::NRSetns,
+        6308 => SyscallO32::NRProcess_vm_readv,
+        6309 => SyscallO32::NRProcess_vm_writev,
+        6310 => SyscallO32::NRKcmp,
+        6311 => SyscallO32::NRFinit_module,
+        6312 => SyscallO32::NRGetdents64,
+        6313 => SyscallO32::NRSched_setattr,
+        6314 => SyscallO32::NRSched_getattr,
+        6315 => SyscallO32::NRRenameat2,
+        6316 => SyscallO32::NRSeccomp,
+        6317 => SyscallO32::NRGetrandom,
+        6318 => SyscallO32::NRMemfd_create,
+        6319 => SyscallO32::NRBpf,
+        6320 => SyscallO32::NRExecveat,
+        6321 => SyscallO32::NRUserfaultfd,
+        6322 => SyscallO32::NRMembarrier,
+        6323 => SyscallO32::NRMlock2,
+        6324 => SyscallO32::NRCopy_file_range,
+        6325 => SyscallO32::NRPreadv2,
+        6326 => SyscallO32::NRPwritev2,
+        6327 => SyscallO32::NRPkey_mprotect,
+        6328 => SyscallO32::NRPkey_alloc,
+        6329 => SyscallO32::NRPkey_free,
+        6330 => SyscallO32::NRStatx,
+        _ => SyscallO32::NRUnknown,
+    }
+}
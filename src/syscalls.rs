@@ -7,15 +7,20 @@
 
 use cpu::control::CPUFlagsSyscalls;
 use cpu::event::CPUEvent;
+use cpu::isa::Abi;
 use cpu::registers::A3;
 use cpu::registers::RegisterFile;
 use cpu::registers::V0;
 use cpu::registers::STACK_POINTER;
-use memory::Memory;
+use memory::{Access, Memory};
+use mmap::Mappings;
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use num_traits::cast::ToPrimitive;
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString, OsStr};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 use std::io::Error;
 use std::mem::size_of;
 use std::time::SystemTime;
@@ -50,17 +55,18 @@ struct MipsStat {
     _st_padding4: [u32; 14],
 }
 
+/// Kernel `struct sigaction` on MIPS. Unlike other architectures, flags come first and there is
+/// no restorer.
 #[derive(Clone)]
 #[repr(C)]
 struct MipsSigaction {
+    sa_flags: u32,
     __sa_handler: u32,
     sa_mask: [u32; 4],
-    sa_flags: u32,
-    sa_restorer: u32,
 }
 
-impl From<[u32; 7]> for MipsSigaction {
-    fn from(struc: [u32; 7]) -> MipsSigaction {
+impl From<[u32; 6]> for MipsSigaction {
+    fn from(struc: [u32; 6]) -> MipsSigaction {
         unsafe { ::std::mem::transmute(struc) }
     }
 }
@@ -69,17 +75,21 @@ impl From<SigAction> for MipsSigaction {
     fn from(struc: SigAction) -> MipsSigaction {
         // glibc sigset_t has 1024bits, kernel sigset_t has 128bits
         let x: [u32; 32] = unsafe { ::std::mem::transmute(struc.mask()) };
+        let x = guest_sigset(&x);
+        let handler = match struc.handler() {
+            SigHandler::SigIgn => SIG_IGN,
+            _ => SIG_DFL,
+        };
         MipsSigaction {
-            __sa_handler: ::libc::SIG_DFL as u32,
-            sa_restorer: 0,
-            sa_mask: [x[0], x[1], x[2], x[3]],
             sa_flags: 0,
+            __sa_handler: handler,
+            sa_mask: [x[0], x[1], x[2], x[3]],
         }
     }
 }
 
-impl Into<[u32; 7]> for MipsSigaction {
-    fn into(self) -> [u32; 7] {
+impl Into<[u32; 6]> for MipsSigaction {
+    fn into(self) -> [u32; 6] {
         unsafe { ::std::mem::transmute(self) }
     }
 }
@@ -111,11 +121,213 @@ fn translate_stat(stat: ::libc::stat) -> [u32; size_of::<MipsStat>() / 4] {
     unsafe { ::std::mem::transmute(s) }
 }
 
-fn translate_iovec(iovec_addr: u32, iovcnt: u32, memory: &mut Memory) -> Vec<Iovec> {
+/// Kernel `struct stat` of n64. n32 uses it for `newfstatat`.
+fn write_stat_n64(stat: &::libc::stat, address: u32, memory: &mut Memory) {
+    memory.write_block(address, &[0u8; 104]);
+    memory.write_word(address, stat.st_dev as u32);
+    memory.write_doubleword(address + 16, stat.st_ino);
+    memory.write_word(address + 24, stat.st_mode);
+    memory.write_word(address + 28, stat.st_nlink as u32);
+    memory.write_word(address + 32, stat.st_uid);
+    memory.write_word(address + 36, stat.st_gid);
+    memory.write_word(address + 40, stat.st_rdev as u32);
+    memory.write_doubleword(address + 56, stat.st_size as u64);
+    memory.write_word(address + 64, stat.st_atime as u32);
+    memory.write_word(address + 68, stat.st_atime_nsec as u32);
+    memory.write_word(address + 72, stat.st_mtime as u32);
+    memory.write_word(address + 76, stat.st_mtime_nsec as u32);
+    memory.write_word(address + 80, stat.st_ctime as u32);
+    memory.write_word(address + 84, stat.st_ctime_nsec as u32);
+    memory.write_word(address + 88, stat.st_blksize as u32);
+    memory.write_doubleword(address + 96, stat.st_blocks as u64);
+}
+
+/// Kernel `struct compat_stat` used by `stat`, `lstat` and `fstat` of n32
+fn write_stat_n32(stat: &::libc::stat, address: u32, memory: &mut Memory) {
+    memory.write_block(address, &[0u8; 144]);
+    memory.write_word(address, stat.st_dev as u32);
+    memory.write_word(address + 16, stat.st_ino as u32);
+    memory.write_word(address + 20, stat.st_mode);
+    memory.write_word(address + 24, stat.st_nlink as u32);
+    memory.write_word(address + 28, stat.st_uid);
+    memory.write_word(address + 32, stat.st_gid);
+    memory.write_word(address + 36, stat.st_rdev as u32);
+    memory.write_word(address + 48, stat.st_size as u32);
+    memory.write_word(address + 56, stat.st_atime as u32);
+    memory.write_word(address + 60, stat.st_atime_nsec as u32);
+    memory.write_word(address + 64, stat.st_mtime as u32);
+    memory.write_word(address + 68, stat.st_mtime_nsec as u32);
+    memory.write_word(address + 72, stat.st_ctime as u32);
+    memory.write_word(address + 76, stat.st_ctime_nsec as u32);
+    memory.write_word(address + 80, stat.st_blksize as u32);
+    memory.write_word(address + 84, stat.st_blocks as u32);
+}
+
+/// Reads `struct timespec`, both fields are `long`
+fn read_timespec(address: u32, memory: &Memory, abi: Abi) -> ::libc::timespec {
+    if abi == Abi::N64 {
+        ::libc::timespec {
+            tv_sec: memory.read_doubleword(address) as i64,
+            tv_nsec: memory.read_doubleword(address + 8) as i64,
+        }
+    } else {
+        ::libc::timespec {
+            tv_sec: memory.read_word(address) as i64,
+            tv_nsec: memory.read_word(address + 4) as i64,
+        }
+    }
+}
+
+fn write_timespec(address: u32, time: &::libc::timespec, memory: &mut Memory, abi: Abi) {
+    if abi == Abi::N64 {
+        memory.write_doubleword(address, time.tv_sec as u64);
+        memory.write_doubleword(address + 8, time.tv_nsec as u64);
+    } else {
+        memory.write_word(address, time.tv_sec as u32);
+        memory.write_word(address + 4, time.tv_nsec as u32);
+    }
+}
+
+/// Reads 128 bit kernel sigset into the glibc representation. n64 stores it as 64 bit words.
+fn read_sigset(address: u32, memory: &Memory, abi: Abi) -> [u32; 32] {
+    let mut sigset = [0u32; 32];
+    if abi == Abi::N64 {
+        for i in 0..2 {
+            let word = memory.read_doubleword(address + i * 8);
+            sigset[2 * i as usize] = word as u32;
+            sigset[2 * i as usize + 1] = (word >> 32) as u32;
+        }
+    } else {
+        for i in 0..4 {
+            sigset[i as usize] = memory.read_word(address + i * 4);
+        }
+    }
+    sigset
+}
+
+fn write_sigset(address: u32, sigset: &[u32; 32], memory: &mut Memory, abi: Abi) {
+    if abi == Abi::N64 {
+        for i in 0..2 {
+            let word = ((sigset[2 * i as usize + 1] as u64) << 32) | sigset[2 * i as usize] as u64;
+            memory.write_doubleword(address + i * 8, word);
+        }
+    } else {
+        for i in 0..4 {
+            memory.write_word(address + i * 4, sigset[i as usize]);
+        }
+    }
+}
+
+/// Host signal with the same meaning as the MIPS one. The standard signals are numbered
+/// differently on MIPS, real-time ones keep their numbers. SIGEMT has no host counterpart.
+pub fn host_signal(signum: u32) -> Option<i32> {
+    let signal = match signum {
+        1 => ::libc::SIGHUP,
+        2 => ::libc::SIGINT,
+        3 => ::libc::SIGQUIT,
+        4 => ::libc::SIGILL,
+        5 => ::libc::SIGTRAP,
+        6 => ::libc::SIGABRT,
+        8 => ::libc::SIGFPE,
+        9 => ::libc::SIGKILL,
+        10 => ::libc::SIGBUS,
+        11 => ::libc::SIGSEGV,
+        12 => ::libc::SIGSYS,
+        13 => ::libc::SIGPIPE,
+        14 => ::libc::SIGALRM,
+        15 => ::libc::SIGTERM,
+        16 => ::libc::SIGUSR1,
+        17 => ::libc::SIGUSR2,
+        18 => ::libc::SIGCHLD,
+        19 => ::libc::SIGPWR,
+        20 => ::libc::SIGWINCH,
+        21 => ::libc::SIGURG,
+        22 => ::libc::SIGIO,
+        23 => ::libc::SIGSTOP,
+        24 => ::libc::SIGTSTP,
+        25 => ::libc::SIGCONT,
+        26 => ::libc::SIGTTIN,
+        27 => ::libc::SIGTTOU,
+        28 => ::libc::SIGVTALRM,
+        29 => ::libc::SIGPROF,
+        30 => ::libc::SIGXCPU,
+        31 => ::libc::SIGXFSZ,
+        32..=64 => signum as i32,
+        _ => return None,
+    };
+    Some(signal)
+}
+
+/// MIPS signal with the same meaning as the host one, see `host_signal`
+pub fn guest_signal(signal: i32) -> Option<u32> {
+    (1..=64).find(|&signum| host_signal(signum) == Some(signal))
+}
+
+/// Moves the bits of the first 64 signals of a sigset to their numbers given by `translate`
+fn translate_sigset<F: Fn(u32) -> Option<u32>>(sigset: &[u32], translate: F) -> [u32; 32] {
+    let mut translated = [0u32; 32];
+    for signum in 1..=64 {
+        let bit = signum - 1;
+        if sigset[bit as usize / 32] & (1 << (bit % 32)) != 0 {
+            if let Some(signum) = translate(signum) {
+                let bit = signum - 1;
+                translated[bit as usize / 32] |= 1 << (bit % 32);
+            }
+        }
+    }
+    translated
+}
+
+fn host_sigset(sigset: &[u32]) -> [u32; 32] {
+    translate_sigset(sigset, |signum| host_signal(signum).map(|signal| signal as u32))
+}
+
+fn guest_sigset(sigset: &[u32]) -> [u32; 32] {
+    translate_sigset(sigset, |signal| guest_signal(signal as i32))
+}
+
+/// Reads `struct sigaction`. n64 has 64 bit handler and sigset, n32 uses the O32 layout.
+fn read_sigaction(address: u32, memory: &Memory, abi: Abi) -> MipsSigaction {
+    if abi == Abi::N64 {
+        let mask = [memory.read_doubleword(address + 16), memory.read_doubleword(address + 24)];
+        MipsSigaction {
+            sa_flags: memory.read_word(address),
+            __sa_handler: memory.read_doubleword(address + 8) as u32,
+            sa_mask: [mask[0] as u32, (mask[0] >> 32) as u32, mask[1] as u32, (mask[1] >> 32) as u32],
+        }
+    } else {
+        const SIGACTION_SIZE: usize = ::std::mem::size_of::<MipsSigaction>() / 4;
+        let mut sigaction = [0u32; SIGACTION_SIZE];
+        for i in 0..SIGACTION_SIZE as u32 {
+            sigaction[i as usize] = memory.read_word(address + i * 4);
+        }
+        MipsSigaction::from(sigaction)
+    }
+}
+
+fn write_sigaction(address: u32, sigaction: MipsSigaction, memory: &mut Memory, abi: Abi) {
+    if abi == Abi::N64 {
+        let mask = sigaction.sa_mask;
+        memory.write_word(address, sigaction.sa_flags);
+        memory.write_word(address + 4, 0);
+        memory.write_pointer(address + 8, sigaction.__sa_handler, abi);
+        memory.write_doubleword(address + 16, ((mask[1] as u64) << 32) | mask[0] as u64);
+        memory.write_doubleword(address + 24, ((mask[3] as u64) << 32) | mask[2] as u64);
+    } else {
+        let sigaction: [u32; 6] = sigaction.into();
+        for (i, word) in sigaction.iter().enumerate() {
+            memory.write_word(address + i as u32 * 4, *word);
+        }
+    }
+}
+
+/// Reads an array of `struct iovec`, it is two `long` values wide
+fn translate_iovec(iovec_addr: u32, iovcnt: u32, memory: &mut Memory, abi: Abi) -> Vec<Iovec> {
+    let size = abi.pointer_size();
     let mut iovec: Vec<Iovec> = Vec::with_capacity(iovcnt as usize);
     for i in 0..(iovcnt as u32) {
-        let addr = memory.read_word(iovec_addr + i * 8);
-        let len = memory.read_word(iovec_addr + i * 8 + 4);
+        let addr = memory.read_pointer(iovec_addr + i * 2 * size, abi);
+        let len = memory.read_pointer(iovec_addr + i * 2 * size + size, abi);
 
         iovec.push(Iovec {
             iov_base: addr,
@@ -125,11 +337,17 @@ fn translate_iovec(iovec_addr: u32, iovcnt: u32, memory: &mut Memory) -> Vec<Iov
     iovec
 }
 
-fn translate_iovec_libc(iovec_addr: u32, iovcnt: u32, memory: &mut Memory) -> Vec<::libc::iovec> {
+fn translate_iovec_libc(
+    iovec_addr: u32,
+    iovcnt: u32,
+    memory: &mut Memory,
+    abi: Abi,
+) -> Vec<::libc::iovec> {
+    let size = abi.pointer_size();
     let mut iovec: Vec<::libc::iovec> = Vec::with_capacity(iovcnt as usize);
     for i in 0..(iovcnt as u32) {
-        let addr = memory.read_word(iovec_addr + i * 8);
-        let len = memory.read_word(iovec_addr + i * 8 + 4);
+        let addr = memory.read_pointer(iovec_addr + i * 2 * size, abi);
+        let len = memory.read_pointer(iovec_addr + i * 2 * size + size, abi);
 
         iovec.push(::libc::iovec {
             iov_base: memory.translate_address_mut(addr) as *mut ::libc::c_void,
@@ -146,7 +364,6 @@ const SA_ONSTACK: u32 = 0x08000000;
 const SA_RESTART: u32 = 0x10000000;
 const SA_NODEFER: u32 = 0x40000000;
 const SA_RESETHAND: u32 = 0x80000000;
-const SA_RESTORER: u32 = 0x04000000;
 fn translate_signal_flags(mask: u32) -> i32 {
     let mut res = 0i32;
     if mask & SA_ONSTACK == SA_ONSTACK {
@@ -173,6 +390,20 @@ fn translate_signal_flags(mask: u32) -> i32 {
     res
 }
 
+/// Resolves an absolute path of the emulated program in the sysroot. Like with qemu-user, the path
+/// is used as it is, when the sysroot does not contain it.
+pub fn guest_path(sysroot: Option<&str>, path: &Path) -> PathBuf {
+    if let Some(sysroot) = sysroot {
+        if path.is_absolute() {
+            let resolved = Path::new(sysroot).join(path.strip_prefix("/").unwrap());
+            if resolved.symlink_metadata().is_ok() {
+                return resolved;
+            }
+        }
+    }
+    path.to_path_buf()
+}
+
 fn check_error<T: Default + Ord + ToPrimitive>(num: T) -> Result<u32, Error> {
     if num < T::default() {
         let e = Error::last_os_error();
@@ -183,25 +414,136 @@ fn check_error<T: Default + Ord + ToPrimitive>(num: T) -> Result<u32, Error> {
     }
 }
 
-fn read_argument_from_memory(argn: u32, registers: &RegisterFile, memory: &Memory) -> u32 {
+/// Reads arguments after the fourth. O32 passes them on the stack, n32 and n64 in registers
+/// a4-a7 (t0-t3 in O32 naming).
+fn read_argument_from_memory(
+    argn: u32,
+    registers: &RegisterFile,
+    memory: &Memory,
+    abi: Abi,
+) -> u32 {
     assert!(argn > 4);
+    if abi.is_64bit() {
+        return registers.read_register(argn + 3);
+    }
     let argn = argn - 1;
     memory.read_word(registers.read_register(STACK_POINTER) + 4 * argn)
 }
 
+/// Details of a signal passed to SA_SIGINFO handlers
+#[derive(Debug, Clone, Copy)]
+pub struct SignalInfo {
+    pub signo: u32,
+    pub code: u32,
+    pub address: u32,
+}
+
 pub struct System {
     config: CPUFlagsSyscalls,
+    abi: Abi,
     sigactions: HashMap<u32, MipsSigaction>,
+    mappings: Mappings,
+    /// synchronous signal being delivered by `force_signal`, its handler runs inside `raise`
+    forced_signal: Cell<Option<SignalInfo>>,
+    /// program counter of the code interrupted by `force_signal`, the handler can move it
+    forced_pc: Cell<Option<u32>>,
 }
 
 impl System {
-    pub fn new(config: CPUFlagsSyscalls) -> System {
+    pub fn new(config: CPUFlagsSyscalls, abi: Abi) -> System {
         System {
             config,
+            abi,
             sigactions: HashMap::new(),
+            mappings: Mappings::new(),
+            forced_signal: Cell::new(None),
+            forced_pc: Cell::new(None),
+        }
+    }
+
+    /// Reads a path argument and resolves it in the sysroot
+    fn read_path(&self, address: u32, memory: &Memory) -> CString {
+        let path = unsafe { CStr::from_ptr(memory.translate_address(address) as *const i8) };
+        let path = Path::new(OsStr::from_bytes(path.to_bytes()));
+        let sysroot = self.config.sysroot.as_deref();
+        CString::new(guest_path(sysroot, path).into_os_string().into_vec()).unwrap()
+    }
+
+    /// Writes the result of `stat`, `lstat` and `fstat` in the layout of the ABI
+    fn write_stat(&self, stat: ::libc::stat, address: u32, memory: &mut Memory) {
+        match self.abi {
+            Abi::O32 => {
+                let repr = translate_stat(stat);
+                for i in 0..40 {
+                    memory.write_word(address + i * 4, repr[i as usize]);
+                }
+            }
+            Abi::N32 => write_stat_n32(&stat, address, memory),
+            Abi::N64 => write_stat_n64(&stat, address, memory),
         }
     }
 
+    /// Checks the buffers, that the syscall reads or writes. The kernel fails with EFAULT instead
+    /// of faulting, when they are not accessible.
+    fn buffers_accessible(&self, syscall: &SyscallO32, args: [u32; 4], memory: &Memory) -> bool {
+        let [arg1, arg2, arg3, arg4] = args;
+        let abi = self.abi;
+        let stat_size = match abi {
+            Abi::O32 => 160,
+            Abi::N32 => 144,
+            Abi::N64 => 104,
+        };
+        // NULL is allowed for the optional pointers
+        let optional = |address: u32, len: u32| if address == 0 { 0 } else { len };
+        let buffers = match *syscall {
+            SyscallO32::NRUname => vec![(arg1, size_of::<::libc::utsname>() as u32, Access::Write)],
+            SyscallO32::NRWait4 => vec![
+                (arg2, optional(arg2, 4), Access::Write),
+                (arg4, optional(arg4, 18 * abi.pointer_size()), Access::Write),
+            ],
+            SyscallO32::NRRt_sigprocmask => vec![
+                (arg2, optional(arg2, 16), Access::Read),
+                (arg3, optional(arg3, 16), Access::Write),
+            ],
+            SyscallO32::NRStat | SyscallO32::NRLstat if abi.is_64bit() => {
+                vec![(arg2, stat_size, Access::Write)]
+            }
+            SyscallO32::NRStat64 |
+            SyscallO32::NRLstat64 |
+            SyscallO32::NRFstat64 |
+            SyscallO32::NRFstat => vec![(arg2, stat_size, Access::Write)],
+            SyscallO32::NRNewfstatat => vec![(arg3, 104, Access::Write)],
+            SyscallO32::NRClock_gettime => vec![(arg2, 2 * abi.pointer_size(), Access::Write)],
+            SyscallO32::NRWrite => vec![(arg2, arg3, Access::Read)],
+            SyscallO32::NRRead => vec![(arg2, arg3, Access::Write)],
+            SyscallO32::NRReadv | SyscallO32::NRWritev => {
+                let size = abi.pointer_size();
+                if memory.check_access(arg2, arg3.saturating_mul(2 * size), Access::Read).is_err() {
+                    return false;
+                }
+                let access = if *syscall == SyscallO32::NRReadv {
+                    Access::Write
+                } else {
+                    Access::Read
+                };
+                (0..arg3)
+                    .map(|i| {
+                        let address = memory.read_pointer(arg2 + i * 2 * size, abi);
+                        let len = memory.read_pointer(arg2 + i * 2 * size + size, abi);
+                        (address, len, access)
+                    })
+                    .collect()
+            }
+            SyscallO32::NR_llseek => vec![(arg4, 8, Access::Write)],
+            SyscallO32::NRGetcwd => vec![(arg1, arg2, Access::Write)],
+            SyscallO32::NRTime => vec![(arg1, optional(arg1, 4), Access::Write)],
+            _ => vec![],
+        };
+        buffers
+            .into_iter()
+            .all(|(address, len, access)| memory.check_access(address, len, access).is_ok())
+    }
+
     pub fn eval_syscall(
         &mut self,
         _inst: u32,
@@ -223,7 +565,12 @@ impl System {
         let arg3 = registers.read_register(6);
         let arg4 = registers.read_register(7);
 
-        let translated_syscall_number = translate_syscall_number(syscall_number);
+        let abi = self.abi;
+        let translated_syscall_number = match abi {
+            Abi::O32 => translate_syscall_number(syscall_number),
+            Abi::N32 => translate_syscall_number_n32(syscall_number),
+            Abi::N64 => translate_syscall_number_n64(syscall_number),
+        };
         let mut exit = CPUEvent::Nothing;
 
         if translated_syscall_number == SyscallO32::NRUnknown {
@@ -238,19 +585,26 @@ impl System {
             error!("Failed to translate syscall.");
             panic!("Unknown SYSCALL");
         } else {
+            let args = [arg1, arg2, arg3, arg4];
+            let accessible = self.buffers_accessible(&translated_syscall_number, args, memory);
             let result: Result<u32, Error> = match translated_syscall_number {
+                _ if !accessible => {
+                    itrace!("{:?} - bad address", translated_syscall_number);
+                    Err(Error::from_raw_os_error(::libc::EFAULT))
+                }
                 SyscallO32::NRBrk => {
-                    // TODO consider whether this should not be implemented differently
-                    itrace!("BRK (faked)");
-                    if arg1 == 0 {
-                        Ok(memory.get_program_break())
-                    } else {
-                        memory.update_program_break(arg1);
-                        Ok(arg1)
-                    }
+                    itrace!("BRK addr=0x{:x}", arg1);
+                    // like Linux, a failure is reported by returning the old break
+                    Ok(memory.set_program_break(arg1))
+                }
+                SyscallO32::NRCacheflush => {
+                    itrace!("CACHEFLUSH addr=0x{:x} bytes={}", arg1, arg2);
+                    memory.flush_code();
+                    Ok(0)
                 }
                 SyscallO32::NRSet_thread_area => {
-                    itrace!("SET_THREAD_AREA (ignored)");
+                    itrace!("SET_THREAD_AREA tp=0x{:x}", arg1);
+                    registers.set_user_local(arg1);
                     Ok(0)
                 }
                 SyscallO32::NRSet_tid_address => {
@@ -270,17 +624,10 @@ impl System {
                     // sigset is 1024bits wide = 128bytes (glibc)
                     let mut sigset = [0u32; 32];
                     if arg2 != 0 {
-                        for i in 0..32 {
-                            sigset[i as usize] = memory.read_word(arg2 + i * 4);
-                        }
+                        sigset = host_sigset(&read_sigset(arg2, memory, abi));
                     }
 
                     let mut oldsigset = [0u32; 32];
-                    if arg3 != 0 {
-                        for i in 0..32 {
-                            oldsigset[i as usize] = memory.read_word(arg3 + i * 4);
-                        }
-                    }
 
                     let result = unsafe {
                         ::libc::sigprocmask(
@@ -299,9 +646,7 @@ impl System {
                     };
 
                     if arg3 != 0 {
-                        for i in 0..32 {
-                            memory.write_word(arg3 + i * 4, sigset[i as usize]);
-                        }
+                        write_sigset(arg3, &guest_sigset(&oldsigset), memory, abi);
                     }
 
                     check_error(result)
@@ -316,38 +661,29 @@ impl System {
                     );
 
                     // read sigaction in argument
-                    // struct sigaction is 140bytes wide
-                    const SIGACTION_SIZE: usize = ::std::mem::size_of::<MipsSigaction>() / 4;
-                    let mut sigaction = [0u32; SIGACTION_SIZE];
-                    if arg2 != 0 {
-                        for i in 0..SIGACTION_SIZE as u32 {
-                            sigaction[i as usize] = memory.read_word(arg2 + i * 4);
-                        }
-                    }
+                    let sigaction = if arg2 != 0 {
+                        read_sigaction(arg2, memory, abi)
+                    } else {
+                        MipsSigaction::from([0u32; 6])
+                    };
 
                     // save it for futuru use and obtain previous value
-                    let mut oldsigaction = self.sigactions.insert(
-                        signum,
-                        MipsSigaction::from(sigaction),
-                    );
+                    let oldsigaction = self.sigactions.insert(signum, sigaction);
 
                     let result = self.reannounce_signal_handlers(signum);
 
                     // create old sigaction. First option is old stored, then modified result from system, then 0array as a fallback
-                    let oldsigaction: [u32; SIGACTION_SIZE] =
-                        if let Some(oldsigaction) = oldsigaction {
-                            oldsigaction.into()
-                        } else if let Ok(oldsigaction) = result {
-                            MipsSigaction::from(oldsigaction).into()
-                        } else {
-                            [0u32; SIGACTION_SIZE]
-                        };
+                    let oldsigaction = if let Some(oldsigaction) = oldsigaction {
+                        oldsigaction
+                    } else if let Ok(oldsigaction) = result {
+                        MipsSigaction::from(oldsigaction)
+                    } else {
+                        MipsSigaction::from([0u32; 6])
+                    };
 
                     // write it back into memory
                     if arg3 != 0 {
-                        for i in 0..SIGACTION_SIZE as u32 {
-                            memory.write_word(arg3 + i * 4, oldsigaction[i as usize]);
-                        }
+                        write_sigaction(arg3, oldsigaction, memory, abi);
                     }
 
                     if let Err(e) = result { Err(e) } else { Ok(0) }
@@ -394,6 +730,14 @@ impl System {
                     check_error(unsafe { ::libc::getpid() })
                     //Ok(0x4b)
                 }
+                SyscallO32::NRKill => {
+                    itrace!("KILL pid={} signal={}", arg1 as i32, arg2);
+                    // signal 0 only checks, that the process exists
+                    match if arg2 == 0 { Some(0) } else { host_signal(arg2) } {
+                        Some(signal) => check_error(unsafe { ::libc::kill(arg1 as i32, signal) }),
+                        None => Err(Error::from_raw_os_error(::libc::EINVAL)),
+                    }
+                }
                 SyscallO32::NRGetppid => {
                     itrace!("GETPPID");
 
@@ -412,65 +756,58 @@ impl System {
                     itrace!("WAIT4");
 
                     let pid = arg1;
-                    let wstatus = memory.translate_address_mut(arg2);
+                    let mut wstatus = 0i32;
                     let options = arg3;
                     let mut rusage = [0u64; 18];
 
                     let respis = unsafe {
                         ::libc::wait4(
                             pid as i32,
-                            wstatus as *mut i32,
+                            &mut wstatus,
                             options as i32,
                             rusage.as_mut_ptr() as *mut ::libc::rusage,
                         )
                     };
 
-                    // write back rusage
+                    // the status is in the guest byte order
+                    if arg2 != 0 && respis > 0 {
+                        memory.write_word(arg2, wstatus as u32);
+                    }
+
+                    // write back rusage, it consists of 18 longs in every ABI
                     if arg4 != 0 {
-                        memory.write_word(arg3 + 0 * 4, rusage[0] as u32);
-                        memory.write_word(arg3 + 1 * 4, rusage[1] as u32);
-                        for i in 1..18 {
-                            memory.write_word(arg3 + 2 * i * 4, (rusage[i as usize]) as u32);
-                            memory.write_word(
-                                arg3 + 2 * i * 4 + 1 * 4,
-                                (rusage[i as usize] >> 32) as u32,
-                            );
+                        let size = abi.pointer_size();
+                        for (i, value) in rusage.iter().enumerate() {
+                            let address = arg4 + i as u32 * size;
+                            if abi == Abi::N64 {
+                                memory.write_doubleword(address, *value);
+                            } else {
+                                memory.write_word(address, *value as u32);
+                            }
                         }
                     }
 
                     check_error(respis)
                 }
-                SyscallO32::NRStat64 => {
-                    let file =
-                        unsafe { CStr::from_ptr(memory.translate_address_mut(arg1) as *mut i8) };
+                SyscallO32::NRStat64 |
+                SyscallO32::NRStat if abi.is_64bit() => {
+                    let file = self.read_path(arg1, memory);
                     itrace!("STAT64 file={:?} struct_at=0x{:08x}", file, arg2,);
-                    let res = ::nix::sys::stat::stat(file);
+                    let res = ::nix::sys::stat::stat(file.as_c_str());
                     if let Ok(stat) = res {
-                        let addr = arg2;
-                        let repr = translate_stat(stat);
-
-                        for i in 0..40 {
-                            memory.write_word(addr + i * 4, repr[i as usize]);
-                        }
-
+                        self.write_stat(stat, arg2, memory);
                         Ok(0)
                     } else {
                         check_error(-1)
                     }
                 }
-                SyscallO32::NRLstat64 => {
-                    let file =
-                        unsafe { CStr::from_ptr(memory.translate_address_mut(arg1) as *mut i8) };
+                SyscallO32::NRLstat64 |
+                SyscallO32::NRLstat if abi.is_64bit() => {
+                    let file = self.read_path(arg1, memory);
                     itrace!("LSTAT64 file={:?} struct_at=0x{:08x}", file, arg2,);
-                    let res = ::nix::sys::stat::lstat(file);
+                    let res = ::nix::sys::stat::lstat(file.as_c_str());
                     if let Ok(stat) = res {
-                        let addr = arg2;
-                        let repr = translate_stat(stat);
-
-                        for i in 0..40 {
-                            memory.write_word(addr + i * 4, repr[i as usize]);
-                        }
-
+                        self.write_stat(stat, arg2, memory);
                         Ok(0)
                     } else {
                         check_error(-1)
@@ -481,18 +818,30 @@ impl System {
                     itrace!("FSTAT64 fd={} struct_at=0x{:08x}", arg1, arg2,);
                     let res = ::nix::sys::stat::fstat(arg1 as ::libc::c_int);
                     if let Ok(stat) = res {
-                        let addr = arg2;
-                        let repr = translate_stat(stat);
-
-                        for i in 0..40 {
-                            memory.write_word(addr + i * 4, repr[i as usize]);
-                        }
-
+                        self.write_stat(stat, arg2, memory);
                         Ok(0)
                     } else {
                         check_error(-1)
                     }
                 }
+                SyscallO32::NRNewfstatat => {
+                    let file = self.read_path(arg2, memory);
+                    let flags = arg4 as i32;
+                    itrace!(
+                        "NEWFSTATAT dirfd={} file={:?} struct_at=0x{:08x} flags=0x{:x}",
+                        arg1 as i32,
+                        file,
+                        arg3,
+                        flags
+                    );
+                    let mut stat: ::libc::stat = unsafe { ::std::mem::zeroed() };
+                    let res =
+                        unsafe { ::libc::fstatat(arg1 as i32, file.as_ptr(), &mut stat, flags) };
+                    if res == 0 {
+                        write_stat_n64(&stat, arg3, memory);
+                    }
+                    check_error(res)
+                }
                 SyscallO32::NRGettid => {
                     itrace!("GETTID");
 
@@ -508,21 +857,20 @@ impl System {
                     res
                 }
                 SyscallO32::NRExecve => {
-                    let filename =
-                        unsafe { CStr::from_ptr(memory.translate_address(arg1) as *const i8) };
+                    let filename = self.read_path(arg1, memory);
 
-                    fn f(i: u32, memory: &Memory) -> Vec<*const i8> {
+                    fn f(i: u32, memory: &Memory, abi: Abi) -> Vec<*const i8> {
                         (i..)
-                            .filter(|i| i % 4 == 0)
-                            .map(|i| memory.read_word(i))
+                            .step_by(abi.pointer_size() as usize)
+                            .map(|i| memory.read_pointer(i, abi))
                             .take_while(|i| *i != 0)
                             .map(|a| memory.translate_address(a) as *const i8)
                             .chain(::std::iter::once(0 as *const i8))
                             .collect()
                     }
 
-                    let argv = f(arg2, memory);
-                    let envp = f(arg3, memory);
+                    let argv = f(arg2, memory, abi);
+                    let envp = f(arg3, memory, abi);
 
                     let argv_str: Vec<&CStr> = argv.iter()
                         .take_while(|a| *a != &(0 as *const i8))
@@ -569,14 +917,12 @@ impl System {
                     let futex_op = arg2;
                     let val = arg3;
                     let timeout_ptr = arg4;
-                    let uaddr2_ptr =
-                        memory.translate_address(read_argument_from_memory(5, registers, memory));
-                    let val3 = read_argument_from_memory(6, registers, memory);
+                    let uaddr2_ptr = memory.translate_address(
+                        read_argument_from_memory(5, registers, memory, abi),
+                    );
+                    let val3 = read_argument_from_memory(6, registers, memory, abi);
 
-                    let timeout = ::libc::timespec {
-                        tv_sec: memory.read_word(timeout_ptr) as i64,
-                        tv_nsec: memory.read_word(timeout_ptr + 4) as i64,
-                    };
+                    let timeout = read_timespec(timeout_ptr, memory, abi);
 
                     check_error(unsafe {
                         ::libc::syscall(
@@ -602,8 +948,7 @@ impl System {
                         ::libc::clock_gettime(clockid as i32, &mut time as *mut ::libc::timespec)
                     };
 
-                    memory.write_word(arg2, time.tv_sec as u32);
-                    memory.write_word(arg2 + 4, time.tv_nsec as u32);
+                    write_timespec(arg2, &time, memory, abi);
 
                     check_error(res)
                 }
@@ -622,12 +967,8 @@ impl System {
                         flags ^= 0x2000;
                     }
 
-                    let (file, res) = unsafe {
-                        (
-                            CStr::from_ptr(memory.translate_address_mut(arg1) as *mut i8),
-                            ::libc::open(memory.translate_address_mut(arg1) as *mut i8, flags, arg3),
-                        )
-                    };
+                    let file = self.read_path(arg1, memory);
+                    let res = unsafe { ::libc::open(file.as_ptr(), flags, arg3) };
                     itrace!(
                         "OPEN file={:?} flags=0x{:08x} mode=0x{:x} res_fd={}",
                         file,
@@ -654,7 +995,7 @@ impl System {
 
                     let fd = arg1 as i32;
                     // This branch translates 32bit iovec array to native one and directly calls the kernel
-                    let mut iovec = translate_iovec_libc(arg2, arg3, memory);
+                    let mut iovec = translate_iovec_libc(arg2, arg3, memory, abi);
 
                     check_error(unsafe {
                         ::libc::writev(
@@ -675,7 +1016,7 @@ impl System {
                 SyscallO32::NRReadv => {
                     itrace!("READV (emulated)");
                     let fd = arg1 as i32;
-                    let mut iovec = translate_iovec(arg2, arg3, memory);
+                    let mut iovec = translate_iovec(arg2, arg3, memory, abi);
                     let total_size = iovec.iter().map(|iovec: &Iovec| iovec.iov_len).sum();
 
                     let mut buffer = vec![0u8; total_size];
@@ -732,7 +1073,7 @@ impl System {
                     let fd = arg1 as i32;
                     let offset: i64 = (((arg2 as u64) << 32) | (arg3 as u64)) as i64;
                     let result_pointer = arg4;
-                    let whence = read_argument_from_memory(5, registers, memory);
+                    let whence = read_argument_from_memory(5, registers, memory, abi);
 
                     let mut result = unsafe { ::libc::lseek(fd as i32, offset, whence as i32) };
 
@@ -744,6 +1085,16 @@ impl System {
                         check_error(result)
                     }
                 }
+                SyscallO32::NRLseek => {
+                    // the offset is `long`, 64 bits wide in n64
+                    let offset = if abi == Abi::N64 {
+                        registers.read_register64(5) as i64
+                    } else {
+                        arg2 as i32 as i64
+                    };
+                    itrace!("LSEEK fd={} offset={} whence={}", arg1 as i32, offset, arg3);
+                    check_error(unsafe { ::libc::lseek(arg1 as i32, offset, arg3 as i32) })
+                }
                 SyscallO32::NRGetcwd => {
                     let buf_addr = arg1;
                     let buf_size = arg2;
@@ -804,22 +1155,58 @@ impl System {
                     check_error(unsafe { ::libc::setuid(arg1 as ::libc::uid_t) })
                 }
                 SyscallO32::NRChdir => {
-                    let dir =
-                        unsafe { CStr::from_ptr(memory.translate_address(arg1) as *const i8) };
+                    let dir = self.read_path(arg1, memory);
                     itrace!("CHDIR {:?}", dir);
                     check_error(unsafe { ::libc::chdir(dir.as_ptr()) })
                 }
+                SyscallO32::NRMmap |
                 SyscallO32::NRMmap2 => {
-                    let addr = arg1;
-                    let len = arg2;
-
-                    itrace!("MMAP2 addr=0x{:x} len={}", addr, len);
-
-                    if len == 0 {
-                        Err(Error::from_raw_os_error(::libc::EINVAL))
+                    let fd = read_argument_from_memory(5, registers, memory, abi) as i32;
+                    let offset = read_argument_from_memory(6, registers, memory, abi) as i64;
+                    // MMAP2 takes the offset in pages, MMAP in bytes
+                    let offset = if translated_syscall_number == SyscallO32::NRMmap2 {
+                        offset * 4096
                     } else {
-                        panic!("MMAP2 syscall is not implemented!");
-                    }
+                        offset
+                    };
+                    itrace!(
+                        "MMAP addr=0x{:x} len={} prot=0x{:x} flags=0x{:x} fd={} offset=0x{:x}",
+                        arg1,
+                        arg2,
+                        arg3,
+                        arg4,
+                        fd,
+                        offset
+                    );
+                    self.mappings.mmap(memory, arg1, arg2, arg3, arg4, (fd, offset))
+                }
+                SyscallO32::NRMunmap => {
+                    itrace!("MUNMAP addr=0x{:x} len={}", arg1, arg2);
+                    self.mappings.munmap(memory, arg1, arg2)
+                }
+                SyscallO32::NRMprotect => {
+                    itrace!("MPROTECT addr=0x{:x} len={} prot=0x{:x}", arg1, arg2, arg3);
+                    self.mappings.mprotect(memory, arg1, arg2, arg3)
+                }
+                SyscallO32::NRMremap => {
+                    let new_address = read_argument_from_memory(5, registers, memory, abi);
+                    itrace!(
+                        "MREMAP addr=0x{:x} old_len={} new_len={} flags=0x{:x} new_addr=0x{:x}",
+                        arg1,
+                        arg2,
+                        arg3,
+                        arg4,
+                        new_address
+                    );
+                    self.mappings.mremap(memory, arg1, arg2, arg3, arg4, new_address)
+                }
+                SyscallO32::NRMsync => {
+                    itrace!("MSYNC addr=0x{:x} len={}", arg1, arg2);
+                    self.mappings.msync(memory, arg1, arg2)
+                }
+                SyscallO32::NRMadvise => {
+                    itrace!("MADVISE addr=0x{:x} len={} advice={}", arg1, arg2, arg3);
+                    self.mappings.madvise(memory, arg1, arg2, arg3)
                 }
                 SyscallO32::NRPipe => {
                     // this syscall ignores the ABI and returns values in registers V0 and V1. Don't ask why! No idea!
@@ -851,10 +1238,24 @@ impl System {
                 }
             };
 
+            // pointers into the emulated memory need the upper half of n64 addresses
+            let returns_pointer = matches!(
+                translated_syscall_number,
+                SyscallO32::NRBrk |
+                    SyscallO32::NRGetcwd |
+                    SyscallO32::NRMmap |
+                    SyscallO32::NRMmap2 |
+                    SyscallO32::NRMremap
+            );
+
             match result {
                 Ok(res) => {
                     debug!("Syscall result - SUCCESS - return_value=0x{:x}", res);
-                    registers.write_register(V0, res as u32);
+                    if returns_pointer {
+                        registers.write_address(V0, res);
+                    } else {
+                        registers.write_register(V0, res as u32);
+                    }
                     registers.write_register(A3, 0); // no error
                 }
                 Err(err) => {
@@ -871,30 +1272,39 @@ impl System {
     }
 
     fn reannounce_signal_handlers(&self, signum: u32) -> Result<SigAction, Error> {
+        // signals without host counterpart are never delivered, there is nothing to install
+        let signal = match host_signal(signum) {
+            Some(signal) => signal,
+            None => {
+                return Ok(SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty()))
+            }
+        };
         let sigact = self.sigactions.get(&signum);
         let action;
 
-        if let Some(sigact) = sigact {
-            let mut ss = [0u32; 32];
-            ss[0] = sigact.sa_mask[0];
-            ss[1] = sigact.sa_mask[1];
-            ss[2] = sigact.sa_mask[2];
-            ss[3] = sigact.sa_mask[3];
-            let sigset = unsafe { ::std::mem::transmute(ss) };
-            let mut flags = translate_signal_flags(sigact.sa_flags);
-
-            action = SigAction::new(
-                SigHandler::SigAction(signal_handler),
-                SaFlags::from_bits(flags).expect("invalid sigaction flags"),
-                sigset,
-            );
-        } else {
-            action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+        match sigact {
+            Some(sigact) if sigact.__sa_handler == SIG_IGN => {
+                action = SigAction::new(SigHandler::SigIgn, SaFlags::empty(), SigSet::empty());
+            }
+            Some(sigact) if sigact.__sa_handler != SIG_DFL => {
+                let ss = host_sigset(&sigact.sa_mask);
+                let sigset = unsafe { ::std::mem::transmute(ss) };
+                let mut flags = translate_signal_flags(sigact.sa_flags);
+
+                action = SigAction::new(
+                    SigHandler::SigAction(signal_handler),
+                    SaFlags::from_bits(flags).expect("invalid sigaction flags"),
+                    sigset,
+                );
+            }
+            _ => {
+                action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+            }
         }
 
         let res = unsafe {
             sigaction(
-                Signal::from_c_int(signum as i32).expect("invalid signal"),
+                Signal::from_c_int(signal).expect("invalid signal"),
                 &action,
             )
         };
@@ -904,6 +1314,50 @@ impl System {
             Err(check_error(-1i32).err().unwrap())
         }
     }
+
+    /// Sends a signal caused by synchronous exception to the emulated program. Like the kernel
+    /// does, the signal can't be ignored or blocked, the program is killed instead. In that case
+    /// the emulator dies the same way thanks to the default action of the host signal. Returns the
+    /// program counter to resume at, the handler can change `pc` of the faulting instruction.
+    pub fn force_signal(&self, info: SignalInfo, pc: u32) -> u32 {
+        let signum = info.signo;
+        let host_signum = host_signal(signum).expect("signal without host counterpart");
+        let signal = Signal::from_c_int(host_signum).expect("invalid signal");
+        let handled = match self.sigactions.get(&signum) {
+            Some(sigact) => sigact.__sa_handler != SIG_DFL && sigact.__sa_handler != SIG_IGN,
+            None => false,
+        };
+        let blocked = SigSet::thread_get_mask()
+            .map(|mask| mask.contains(signal))
+            .unwrap_or(false);
+
+        if !handled || blocked {
+            info!("Signal {} can't be handled by the program, terminating", signum);
+            let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+            let mut unblock = SigSet::empty();
+            unblock.add(signal);
+            unsafe {
+                sigaction(signal, &action).expect("Failed to restore default signal action");
+            }
+            unblock.thread_unblock().expect("Failed to unblock signal");
+        }
+
+        self.forced_signal.set(Some(info));
+        self.forced_pc.set(Some(pc));
+        unsafe {
+            ::libc::raise(host_signum);
+        }
+        self.forced_signal.set(None);
+        self.forced_pc.take().unwrap_or(pc)
+    }
+
+    /// Details of the signal, when it's the one sent by `force_signal`
+    pub fn take_forced_signal(&self, signum: u32) -> Option<SignalInfo> {
+        match self.forced_signal.get() {
+            Some(info) if info.signo == signum => self.forced_signal.take(),
+            _ => None,
+        }
+    }
 }
 
 const SIG_ERR: u32 = -1i32 as u32; /* Error return.  */
@@ -912,15 +1366,22 @@ const SIG_IGN: u32 = 1i32 as u32; /* Ignore signal.  */
 
 extern "C" fn signal_handler(
     signal: ::libc::c_int,
-    _: *mut ::libc::siginfo_t,
+    host_info: *mut ::libc::siginfo_t,
     _: *mut ::libc::c_void,
 ) {
     info!("Caught signal {}", signal);
-    let signal = signal as u32;
+    let signal = match guest_signal(signal) {
+        Some(signal) => signal,
+        None => {
+            warn!("Signal {} has no MIPS counterpart, ignoring!", signal);
+            return;
+        }
+    };
     let context = unsafe { ::cpu::control::EmulatorContext::get_mut_ref() };
     let sigaction = context.get_system().sigactions.get(&signal).map(
         |a| a.clone(),
     );
+    let forced = context.get_system().take_forced_signal(signal);
     if let Some(sigaction) = sigaction {
         let flags = sigaction.sa_flags;
 
@@ -932,10 +1393,24 @@ extern "C" fn signal_handler(
             panic!("signal error requested");
         }
 
-        if flags & SA_RESTORER == SA_RESTORER {
-            unimplemented!("sa_restorer signal handler");
-        } else if flags & SA_SIGINFO == SA_SIGINFO {
-            unimplemented!("Signal handler with siginfo");
+        if flags & SA_SIGINFO == SA_SIGINFO {
+            info!("Running signal handler with siginfo.");
+            // asynchronous signals carry just the code, their sender isn't translated
+            let info = forced.unwrap_or_else(|| SignalInfo {
+                signo: signal,
+                code: unsafe { (*host_info).si_code } as u32,
+                address: 0,
+            });
+            let pc = if forced.is_some() {
+                context.get_system().forced_pc.get()
+            } else {
+                None
+            };
+            let resumed = context.run_siginfo_handler(sigaction.__sa_handler, info, pc);
+            if resumed.is_some() {
+                context.get_system().forced_pc.set(resumed);
+            }
+            info!("End of signal handler!");
         } else {
             info!("Running simple signal handler with only one argument - signal number.");
             context.run_function(sigaction.__sa_handler, &[signal]);
@@ -945,3 +1420,84 @@ extern "C" fn signal_handler(
         warn!("No signal handler is specified!");
     }
 }
+
+#[test]
+fn test_buffers_accessible() {
+    use cpu::control::CPUFlags;
+    use memory::{Endianness, PROT_READ, PROT_WRITE};
+
+    let system = System::new(CPUFlags::default().syscalls_conf, Abi::O32);
+    let mut memory = Memory::new(Endianness::BigEndian);
+    memory.map(0x400000, 0x1000, PROT_READ);
+    assert!(system.buffers_accessible(&SyscallO32::NRWrite, [1, 0x400000, 0x1000, 0], &memory));
+    assert!(!system.buffers_accessible(&SyscallO32::NRWrite, [1, 0x400800, 0x1000, 0], &memory));
+    assert!(!system.buffers_accessible(&SyscallO32::NRRead, [0, 0x400000, 4, 0], &memory));
+    assert!(system.buffers_accessible(&SyscallO32::NRTime, [0, 0, 0, 0], &memory));
+    // the O32 rusage of wait4 is 72 bytes long
+    memory.map(0x10000000, 0x1000, PROT_READ | PROT_WRITE);
+    let wait4 = |rusage| {
+        system.buffers_accessible(&SyscallO32::NRWait4, [0, 0, 0, rusage], &memory)
+    };
+    assert!(wait4(0x10001000 - 72));
+    assert!(!wait4(0x10001000 - 68));
+}
+
+#[test]
+fn test_signal_numbers() {
+    // SIGBUS is 10 on MIPS and 7 on the host
+    assert_eq!(host_signal(10), Some(::libc::SIGBUS));
+    assert_eq!(guest_signal(::libc::SIGBUS), Some(10));
+    // SIGEMT
+    assert_eq!(host_signal(7), None);
+    assert_eq!(host_sigset(&[1 << 9, 0])[0], 1 << 6);
+    assert_eq!(guest_sigset(&[1 << 6, 0])[0], 1 << 9);
+}
+
+#[test]
+fn test_guest_path() {
+    use std::fs;
+
+    let sysroot = ::std::env::temp_dir().join(format!("rumipsem-sysroot-{}", ::std::process::id()));
+    fs::create_dir_all(sysroot.join("etc")).unwrap();
+    fs::write(sysroot.join("etc/hosts"), b"").unwrap();
+    let root = sysroot.to_str();
+    assert_eq!(guest_path(root, Path::new("/etc/hosts")), sysroot.join("etc/hosts"));
+    // missing in the sysroot, the host path is used
+    assert_eq!(guest_path(root, Path::new("/etc/missing")), Path::new("/etc/missing"));
+    // relative paths stay relative to the working directory
+    assert_eq!(guest_path(root, Path::new("etc/hosts")), Path::new("etc/hosts"));
+    assert_eq!(guest_path(None, Path::new("/etc/hosts")), Path::new("/etc/hosts"));
+    fs::remove_dir_all(sysroot).unwrap();
+}
+
+#[test]
+fn test_wait4() {
+    use cpu::control::CPUFlags;
+    use memory::{Endianness, PROT_READ, PROT_WRITE};
+
+    let mut system = System::new(CPUFlags::default().syscalls_conf, Abi::O32);
+    let mut registers = RegisterFile::new(0x7ff00000);
+    let mut memory = Memory::new(Endianness::BigEndian);
+    memory.map(0x10000000, 0x1000, PROT_READ | PROT_WRITE);
+    for i in 0..64 {
+        memory.write_word(0x10000100 + i * 4, 0xFFFF_FFFF);
+    }
+
+    let pid = unsafe { ::libc::fork() };
+    if pid == 0 {
+        unsafe { ::libc::_exit(3) };
+    }
+    // wait4(pid, status, 0, rusage)
+    registers.write_register(V0, 4114);
+    registers.write_register(4, pid as u32);
+    registers.write_register(5, 0x10000000);
+    registers.write_register(6, 0);
+    registers.write_register(7, 0x10000100);
+    system.eval_syscall(0x0000000C, &mut registers, &mut memory);
+    assert_eq!(registers.read_register(V0), pid as u32);
+    assert_eq!(memory.read_word(0x10000000), 3 << 8);
+    // the O32 rusage is 18 words long, ru_maxrss is the fifth one
+    let maxrss = memory.read_word(0x10000100 + 4 * 4);
+    assert!(maxrss != 0 && maxrss != 0xFFFF_FFFF);
+    assert_eq!(memory.read_word(0x10000100 + 18 * 4), 0xFFFF_FFFF);
+}
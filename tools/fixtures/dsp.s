@@ -0,0 +1,102 @@
+# DSP ASE revision 1 and 2 instructions for the disassembler fixture, see tools/objdump_fixture.sh
+	.set	noreorder
+	.text
+	.globl	__start
+__start:
+	addq.ph	$2, $4, $5
+	addq_s.ph	$2, $4, $5
+	addq_s.w	$2, $4, $5
+	addu.qb	$2, $4, $5
+	addu_s.qb	$2, $4, $5
+	subq_s.ph	$2, $4, $5
+	subu.qb	$2, $4, $5
+	addsc	$2, $4, $5
+	addwc	$2, $4, $5
+	modsub	$2, $4, $5
+	raddu.w.qb	$2, $4
+	absq_s.ph	$2, $5
+	absq_s.w	$2, $5
+	precrq.qb.ph	$2, $4, $5
+	precrq_rs.ph.w	$2, $4, $5
+	precrqu_s.qb.ph	$2, $4, $5
+	preceq.w.phl	$2, $5
+	precequ.ph.qbla	$2, $5
+	preceu.ph.qbr	$2, $5
+	shll.qb	$2, $5, 3
+	shllv_s.ph	$2, $5, $4
+	shrl.qb	$2, $5, 3
+	shra_r.w	$2, $5, 7
+	muleu_s.ph.qbl	$2, $4, $5
+	mulq_rs.ph	$2, $4, $5
+	muleq_s.w.phr	$2, $4, $5
+	dpau.h.qbl	$ac1, $4, $5
+	dpaq_s.w.ph	$ac2, $4, $5
+	dpsq_sa.l.w	$ac3, $4, $5
+	maq_s.w.phl	$ac1, $4, $5
+	maq_sa.w.phr	$ac1, $4, $5
+	mult	$ac1, $4, $5
+	maddu	$ac2, $4, $5
+	msub	$ac3, $4, $5
+	mfhi	$2, $ac1
+	mtlo	$4, $ac2
+	cmpu.eq.qb	$4, $5
+	cmpgu.lt.qb	$2, $4, $5
+	cmp.le.ph	$4, $5
+	pick.qb	$2, $4, $5
+	pick.ph	$2, $4, $5
+	packrl.ph	$2, $4, $5
+	extr.w	$2, $ac1, 5
+	extr_rs.w	$2, $ac1, 31
+	extrv_s.h	$2, $ac2, $4
+	extp	$2, $ac3, 7
+	extpdp	$2, $ac3, 7
+	shilo	$ac1, -3
+	shilov	$ac1, $4
+	mthlip	$4, $ac2
+	wrdsp	$4, 17
+	rddsp	$2, 63
+	insv	$2, $4
+	bitrev	$2, $4
+	repl.qb	$2, 0x7f
+	replv.ph	$2, $4
+	repl.ph	$2, -2
+	lbux	$2, $5($4)
+	lhx	$2, $5($4)
+	lwx	$2, $5($4)
+	bposge32	__start
+	nop
+	addu.ph	$2, $4, $5
+	subu_s.ph	$2, $4, $5
+	adduh_r.qb	$2, $4, $5
+	subuh.qb	$2, $4, $5
+	addqh_r.ph	$2, $4, $5
+	subqh.w	$2, $4, $5
+	mul.ph	$2, $4, $5
+	mul_s.ph	$2, $4, $5
+	mulq_s.w	$2, $4, $5
+	mulq_rs.w	$2, $4, $5
+	dpa.w.ph	$ac1, $4, $5
+	dps.w.ph	$ac1, $4, $5
+	dpax.w.ph	$ac2, $4, $5
+	dpsx.w.ph	$ac2, $4, $5
+	dpaqx_s.w.ph	$ac3, $4, $5
+	dpaqx_sa.w.ph	$ac3, $4, $5
+	dpsqx_s.w.ph	$ac3, $4, $5
+	dpsqx_sa.w.ph	$ac3, $4, $5
+	mulsa.w.ph	$ac1, $4, $5
+	precr.qb.ph	$2, $4, $5
+	precr_sra.ph.w	$2, $4, 7
+	precr_sra_r.ph.w	$2, $4, 7
+	append	$2, $4, 8
+	prepend	$2, $4, 8
+	balign	$2, $4, 3
+	absq_s.qb	$2, $5
+	cmpgdu.eq.qb	$2, $4, $5
+	cmpgdu.le.qb	$2, $4, $5
+	shra.qb	$2, $5, 3
+	shra_r.qb	$2, $5, 3
+	shrav_r.qb	$2, $5, $4
+	shrl.ph	$2, $5, 13
+	shrlv.ph	$2, $5, $4
+	jr	$ra
+	nop
@@ -0,0 +1,61 @@
+# Release 6 instructions for the disassembler fixture, see tools/objdump_fixture.sh
+	.set	noreorder
+	.text
+	.globl	__start
+__start:
+	addiupc	$2, -8
+	lwpc	$2, -8
+	auipc	$2, 1
+	aluipc	$2, 2
+	bitswap	$2, $4
+	align	$2, $4, $5, 1
+	lsa	$2, $4, $5, 3
+	mul	$2, $4, $5
+	muh	$2, $4, $5
+	mulu	$2, $4, $5
+	muhu	$2, $4, $5
+	div	$2, $4, $5
+	mod	$2, $4, $5
+	divu	$2, $4, $5
+	modu	$2, $4, $5
+	seleqz	$2, $4, $5
+	selnez	$2, $4, $5
+	clz	$2, $4
+	clo	$2, $4
+	ll	$2, 8($4)
+	sc	$2, -8($4)
+	jic	$4, 16
+	jialc	$4, 0
+	bc	__start
+	balc	__start
+	beqzc	$4, __start
+	bnezc	$4, __start
+	beqc	$4, $5, __start
+	bnec	$4, $5, __start
+	bltc	$4, $5, __start
+	bgec	$4, $5, __start
+	bltuc	$4, $5, __start
+	bgeuc	$4, $5, __start
+	bovc	$4, $5, __start
+	bnvc	$4, $5, __start
+	blezc	$4, __start
+	bgtzc	$4, __start
+	bltzc	$4, __start
+	bgezc	$4, __start
+	cmp.lt.d	$f16, $f2, $f6
+	cmp.ueq.s	$f16, $f2, $f6
+	bc1eqz	$f16, __start
+	nop
+	bc1nez	$f16, __start
+	nop
+	sel.d	$f0, $f2, $f4
+	maddf.s	$f0, $f2, $f4
+	msubf.d	$f0, $f2, $f4
+	min.d	$f0, $f2, $f4
+	max.s	$f0, $f2, $f4
+	mina.d	$f0, $f2, $f4
+	maxa.s	$f0, $f2, $f4
+	rint.d	$f0, $f2
+	class.s	$f0, $f2
+	jr	$ra
+	nop
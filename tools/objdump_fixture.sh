@@ -0,0 +1,26 @@
+#!/bin/bash
+# Regenerates the fixtures of the disassembler tests from GNU binutils, e.g. mips-linux-gnu-objdump
+# from the binutils-mips-linux-gnu package. Record the printed version in test_data/README.md.
+# The R6 and DSP fixtures are assembled from tools/fixtures/*.s and linked at 0x400000.
+
+set -e
+cd "$(dirname "$0")/.."
+
+CROSS="${CROSS:-mips-linux-gnu-}"
+OBJDUMP="${OBJDUMP:-${CROSS}objdump}"
+
+"$OBJDUMP" --version | head -n 1
+"$OBJDUMP" -d --start-address=0x4000d0 --stop-address=0x400200 mips_binaries/busybox-mipsel \
+    > test_data/busybox-mipsel.objdump
+
+fixture() {
+    local name="$1"
+    shift
+    "${CROSS}as" -EL "$@" -o "test_data/$name.o" "tools/fixtures/$name.s"
+    "${CROSS}ld" -EL -e __start -Ttext=0x400000 -o "test_data/$name.elf" "test_data/$name.o"
+    rm "test_data/$name.o"
+    "$OBJDUMP" -d "test_data/$name.elf" > "test_data/$name.objdump"
+}
+
+fixture r6 -mips32r6
+fixture dsp -mips32r2 -mdspr2